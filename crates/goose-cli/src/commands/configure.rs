@@ -3,7 +3,10 @@ use console::style;
 use goose::agents::{extension::Envs, ExtensionConfig};
 use goose::config::{Config, ConfigError, ExperimentManager, ExtensionEntry, ExtensionManager};
 use goose::message::Message;
-use goose::providers::{create, providers};
+use goose::providers::azure_auth::AzureAuthType;
+use goose::providers::base::ConfigKey;
+use goose::providers::ollama::OllamaProvider;
+use goose::providers::{create, list_models, providers};
 use mcp_core::Tool;
 use serde_json::{json, Value};
 use std::collections::HashMap;
@@ -204,92 +207,58 @@ pub async fn configure_provider_dialog() -> Result<bool, Box<dyn Error>> {
             continue;
         }
 
-        // First check if the value is set via environment variable
-        let from_env = std::env::var(&key.name).ok();
-
-        match from_env {
-            Some(env_value) => {
-                let _ =
-                    cliclack::log::info(format!("{} is set via environment variable", key.name));
-                if cliclack::confirm("Would you like to save this value to your keyring?")
-                    .initial_value(true)
-                    .interact()?
-                {
-                    if key.secret {
-                        config.set_secret(&key.name, Value::String(env_value))?;
-                    } else {
-                        config.set(&key.name, Value::String(env_value))?;
-                    }
-                    let _ = cliclack::log::info(format!("Saved {} to config file", key.name));
-                }
-            }
-            None => {
-                // No env var, check config/secret storage
-                let existing: Result<String, _> = if key.secret {
-                    config.get_secret(&key.name)
-                } else {
-                    config.get(&key.name)
-                };
-
-                match existing {
-                    Ok(_) => {
-                        let _ = cliclack::log::info(format!("{} is already configured", key.name));
-                        if cliclack::confirm("Would you like to update this value?").interact()? {
-                            let new_value: String = if key.secret {
-                                cliclack::password(format!("Enter new value for {}", key.name))
-                                    .mask('▪')
-                                    .interact()?
-                            } else {
-                                let mut input =
-                                    cliclack::input(format!("Enter new value for {}", key.name));
-                                if key.default.is_some() {
-                                    input = input.default_input(&key.default.clone().unwrap());
-                                }
-                                input.interact()?
-                            };
-
-                            if key.secret {
-                                config.set_secret(&key.name, Value::String(new_value))?;
-                            } else {
-                                config.set(&key.name, Value::String(new_value))?;
-                            }
-                        }
-                    }
-                    Err(_) => {
-                        let value: String = if key.secret {
-                            cliclack::password(format!(
-                                "Provider {} requires {}, please enter a value",
-                                provider_meta.display_name, key.name
-                            ))
-                            .mask('▪')
-                            .interact()?
-                        } else {
-                            let mut input = cliclack::input(format!(
-                                "Provider {} requires {}, please enter a value",
-                                provider_meta.display_name, key.name
-                            ));
-                            if key.default.is_some() {
-                                input = input.default_input(&key.default.clone().unwrap());
-                            }
-                            input.interact()?
-                        };
+        configure_provider_key(config, &provider_meta.display_name, key)?;
+    }
 
-                        if key.secret {
-                            config.set_secret(&key.name, Value::String(value))?;
-                        } else {
-                            config.set(&key.name, Value::String(value))?;
-                        }
-                    }
-                }
-            }
-        }
+    // Azure OpenAI needs different settings depending on how it authenticates
+    if provider_name == "azure_openai" {
+        configure_azure_auth(config, &provider_meta.display_name)?;
     }
 
     // Select model, defaulting to the provider's recommended model UNLESS there is an env override
     let default_model = std::env::var("GOOSE_MODEL").unwrap_or(provider_meta.default_model.clone());
-    let model: String = cliclack::input("Enter a model from that provider:")
-        .default_input(&default_model)
-        .interact()?;
+
+    // Offer the models the provider reports, falling back to typing one in
+    let spin = spinner();
+    spin.start("Fetching the available models...");
+    let models = match list_models(provider_name).await {
+        Ok(models) => {
+            spin.stop(format!("Found {} models", models.len()));
+            models
+        }
+        Err(e) => {
+            spin.stop(style(format!("Could not list models: {}", e)).dim());
+            Vec::new()
+        }
+    };
+
+    let model: String = if models.is_empty() {
+        cliclack::input("Enter a model from that provider:")
+            .default_input(&default_model)
+            .interact()?
+    } else {
+        let mut select =
+            cliclack::select("Which model should we use? (type to filter)").filter_mode();
+        for model in &models {
+            select = select.item(model.clone(), model, "");
+        }
+        select = select.item(String::new(), "Other", "enter a model name");
+        if models.contains(&default_model) {
+            select = select.initial_value(default_model.clone());
+        }
+        match select.interact()? {
+            other if other.is_empty() => cliclack::input("Enter a model from that provider:")
+                .default_input(&default_model)
+                .interact()?,
+            model => model,
+        }
+    };
+
+    // Local models have to be downloaded before they can be used
+    if provider_name == "ollama" && !ensure_ollama_model(&model).await? {
+        cliclack::outro(style("The model has not been pulled").on_red().white())?;
+        return Ok(false);
+    }
 
     // Test the configuration
     let spin = spinner();
@@ -337,6 +306,170 @@ pub async fn configure_provider_dialog() -> Result<bool, Box<dyn Error>> {
     }
 }
 
+/// Prompt for one provider setting, offering to keep a value from the environment or config
+fn configure_provider_key(
+    config: &Config,
+    provider_display_name: &str,
+    key: &ConfigKey,
+) -> Result<(), Box<dyn Error>> {
+    // First check if the value is set via environment variable
+    let from_env = std::env::var(&key.name).ok();
+
+    match from_env {
+        Some(env_value) => {
+            let _ = cliclack::log::info(format!("{} is set via environment variable", key.name));
+            if cliclack::confirm("Would you like to save this value to your keyring?")
+                .initial_value(true)
+                .interact()?
+            {
+                if key.secret {
+                    config.set_secret(&key.name, Value::String(env_value))?;
+                } else {
+                    config.set(&key.name, Value::String(env_value))?;
+                }
+                let _ = cliclack::log::info(format!("Saved {} to config file", key.name));
+            }
+        }
+        None => {
+            // No env var, check config/secret storage
+            let existing: Result<String, _> = if key.secret {
+                config.get_secret(&key.name)
+            } else {
+                config.get(&key.name)
+            };
+
+            match existing {
+                Ok(_) => {
+                    let _ = cliclack::log::info(format!("{} is already configured", key.name));
+                    if cliclack::confirm("Would you like to update this value?").interact()? {
+                        let new_value: String = if key.secret {
+                            cliclack::password(format!("Enter new value for {}", key.name))
+                                .mask('▪')
+                                .interact()?
+                        } else {
+                            let mut input =
+                                cliclack::input(format!("Enter new value for {}", key.name));
+                            if key.default.is_some() {
+                                input = input.default_input(&key.default.clone().unwrap());
+                            }
+                            input.interact()?
+                        };
+
+                        if key.secret {
+                            config.set_secret(&key.name, Value::String(new_value))?;
+                        } else {
+                            config.set(&key.name, Value::String(new_value))?;
+                        }
+                    }
+                }
+                Err(_) => {
+                    let value: String = if key.secret {
+                        cliclack::password(format!(
+                            "Provider {} requires {}, please enter a value",
+                            provider_display_name, key.name
+                        ))
+                        .mask('▪')
+                        .interact()?
+                    } else {
+                        let mut input = cliclack::input(format!(
+                            "Provider {} requires {}, please enter a value",
+                            provider_display_name, key.name
+                        ));
+                        if key.default.is_some() {
+                            input = input.default_input(&key.default.clone().unwrap());
+                        }
+                        input.interact()?
+                    };
+
+                    if key.secret {
+                        config.set_secret(&key.name, Value::String(value))?;
+                    } else {
+                        config.set(&key.name, Value::String(value))?;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Dialog for choosing how Azure OpenAI authenticates, then configuring that method's keys
+fn configure_azure_auth(
+    config: &Config,
+    provider_display_name: &str,
+) -> Result<(), Box<dyn Error>> {
+    let current: AzureAuthType = config
+        .get::<String>("AZURE_OPENAI_AUTH_TYPE")
+        .ok()
+        .and_then(|auth_type| auth_type.parse().ok())
+        .unwrap_or_default();
+
+    let items: Vec<(AzureAuthType, &str, &str)> = AzureAuthType::ALL
+        .iter()
+        .map(|auth_type| (*auth_type, auth_type.as_str(), auth_type.description()))
+        .collect();
+    let auth_type = cliclack::select("How should goose authenticate with Azure OpenAI?")
+        .initial_value(current)
+        .items(&items)
+        .interact()?;
+    config.set(
+        "AZURE_OPENAI_AUTH_TYPE",
+        Value::String(auth_type.as_str().to_string()),
+    )?;
+
+    for key in auth_type.config_keys() {
+        if key.required {
+            configure_provider_key(config, provider_display_name, &key)?;
+        }
+    }
+    Ok(())
+}
+
+/// Check the model has been pulled to the Ollama server, offering to pull it if not. Returns
+/// whether the model is available
+async fn ensure_ollama_model(model: &str) -> Result<bool, Box<dyn Error>> {
+    let ollama = OllamaProvider::from_env(goose::model::ModelConfig::new(model.to_string()))?;
+    if ollama.show_model().await?.is_some() {
+        return Ok(true);
+    }
+
+    if !cliclack::confirm(format!(
+        "{} hasn't been downloaded to Ollama yet. Pull it now?",
+        model
+    ))
+    .initial_value(true)
+    .interact()?
+    {
+        return Ok(false);
+    }
+
+    let spin = spinner();
+    spin.start(format!("Pulling {}...", model));
+    let result = ollama
+        .pull_model(|progress| match (progress.completed, progress.total) {
+            (Some(completed), Some(total)) if total > 0 => spin.set_message(format!(
+                "{} {:.0}% ({} of {} MB)",
+                progress.status,
+                completed as f64 * 100.0 / total as f64,
+                completed / 1_000_000,
+                total / 1_000_000
+            )),
+            _ => spin.set_message(&progress.status),
+        })
+        .await;
+    match result {
+        Ok(()) => {
+            spin.stop(format!("Pulled {}", model));
+            Ok(true)
+        }
+        Err(e) => {
+            spin.error(e.to_string());
+            Ok(false)
+        }
+    }
+}
+
 /// Configure extensions that can be used with goose
 /// Dialog for toggling which extensions are enabled/disabled
 pub fn toggle_extensions_dialog() -> Result<(), Box<dyn Error>> {
@@ -628,6 +761,11 @@ pub fn configure_settings_dialog() -> Result<(), Box<dyn Error>> {
             "tool_output",
             "Tool Output",
             "Show more or less tool output",
+        )
+        .item(
+            "generation",
+            "Generation Settings",
+            "Sampling, stop sequences, tool choice and reasoning effort",
         );
 
     // Conditionally add the "Toggle Experiment" option
@@ -648,6 +786,9 @@ pub fn configure_settings_dialog() -> Result<(), Box<dyn Error>> {
         "tool_output" => {
             configure_tool_output_dialog()?;
         }
+        "generation" => {
+            configure_generation_dialog()?;
+        }
         "experiment" => {
             toggle_experiments_dialog()?;
         }
@@ -732,6 +873,116 @@ pub fn configure_tool_output_dialog() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+pub fn configure_generation_dialog() -> Result<(), Box<dyn Error>> {
+    let config = Config::global();
+
+    let key = cliclack::select("Which generation setting would you like to configure?")
+        .item(
+            "GOOSE_TEMPERATURE",
+            "Temperature",
+            "Randomness of sampling, e.g. 0.7",
+        )
+        .item(
+            "GOOSE_TOP_P",
+            "Top P",
+            "Sample from tokens up to this cumulative probability",
+        )
+        .item(
+            "GOOSE_TOP_K",
+            "Top K",
+            "Sample from only this many of the likeliest tokens",
+        )
+        .item(
+            "GOOSE_STOP_SEQUENCES",
+            "Stop Sequences",
+            "Stop generating at this text",
+        )
+        .item(
+            "GOOSE_SEED",
+            "Seed",
+            "Sample deterministically, where supported",
+        )
+        .item(
+            "GOOSE_PRESENCE_PENALTY",
+            "Presence Penalty",
+            "Penalize tokens which have appeared",
+        )
+        .item(
+            "GOOSE_FREQUENCY_PENALTY",
+            "Frequency Penalty",
+            "Penalize tokens by how often they appeared",
+        )
+        .item(
+            "GOOSE_TOOL_CHOICE",
+            "Tool Choice",
+            "auto, required, none or the name of a tool",
+        )
+        .item(
+            "GOOSE_PARALLEL_TOOL_CALLS",
+            "Parallel Tool Calls",
+            "true or false",
+        )
+        .item(
+            "GOOSE_REASONING_EFFORT",
+            "Reasoning Effort",
+            "low, medium or high",
+        )
+        .item(
+            "GOOSE_THINKING_BUDGET",
+            "Thinking Budget",
+            "Tokens for extended thinking",
+        )
+        .interact()?;
+
+    if std::env::var(key).is_ok() {
+        let _ = cliclack::log::info(format!(
+            "Notice: {} environment variable is set and will override the configuration here.",
+            key
+        ));
+    }
+
+    let previous: Option<Value> = config.get(key).ok();
+    let mut input =
+        cliclack::input(format!("Enter a value for {} (empty to unset)", key)).required(false);
+    if let Some(previous) = &previous {
+        let previous = match previous {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        input = input.default_input(&previous);
+    }
+    let input: String = input.interact()?;
+    let input = input.trim();
+
+    if input.is_empty() {
+        config.delete(key)?;
+        cliclack::outro(format!("Unset {}", key))?;
+        return Ok(());
+    }
+
+    // Values are read the same way as the environment variable, and a single stop sequence
+    // doesn't need to be written as a list
+    let mut value: Value =
+        serde_json::from_str(input).unwrap_or_else(|_| Value::String(input.to_string()));
+    if key == "GOOSE_STOP_SEQUENCES" && !value.is_array() {
+        value = json!([input]);
+    }
+    config.set(key, value)?;
+
+    // Check the value parses before keeping it, so sessions don't fail to start later
+    let model = config.get("GOOSE_MODEL").unwrap_or_default();
+    if let Err(e) = goose::model::ModelConfig::new(model).with_configured_generation(config) {
+        match previous {
+            Some(previous) => config.set(key, previous)?,
+            None => config.delete(key)?,
+        }
+        return Err(format!("Invalid value for {}: {}", key, e).into());
+    }
+
+    cliclack::outro(format!("Set {} to {}", key, input))?;
+    Ok(())
+}
+
 /// Configure experiment features that can be used with goose
 /// Dialog for toggling which experiments are enabled/disabled
 pub fn toggle_experiments_dialog() -> Result<(), Box<dyn Error>> {
@@ -2,3 +2,4 @@ pub mod agent_version;
 pub mod configure;
 pub mod info;
 pub mod mcp;
+pub mod models;
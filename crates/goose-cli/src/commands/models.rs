@@ -0,0 +1,33 @@
+use anyhow::{anyhow, Result};
+use console::style;
+use goose::config::Config;
+use goose::providers::list_models;
+
+/// Print the models a provider offers, marking the one goose is configured to use
+pub async fn handle_models(provider: Option<String>) -> Result<()> {
+    let config = Config::global();
+    let configured_provider: Option<String> = config.get("GOOSE_PROVIDER").ok();
+    let provider = provider.or(configured_provider.clone()).ok_or_else(|| {
+        anyhow!("No provider is configured, run 'goose configure' or pass --provider")
+    })?;
+
+    let current_model: Option<String> = if configured_provider.as_ref() == Some(&provider) {
+        config.get("GOOSE_MODEL").ok()
+    } else {
+        None
+    };
+
+    let models = list_models(&provider).await?;
+    println!(
+        "{}",
+        style(format!("Models available from {}", provider)).bold()
+    );
+    for model in models {
+        if current_model.as_ref() == Some(&model) {
+            println!("  {} {}", model, style("(current)").green());
+        } else {
+            println!("  {}", model);
+        }
+    }
+    Ok(())
+}
@@ -5,6 +5,9 @@ use goose::providers::base::ProviderUsage;
 struct SessionLog {
     session_file: String,
     usage: Vec<ProviderUsage>,
+    /// Cost of the session in US dollars, when the pricing of the models used is known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    total_cost: Option<f64>,
 }
 
 pub fn log_usage(
@@ -12,8 +15,10 @@ pub fn log_usage(
     session_file: String,
     usage: Vec<ProviderUsage>,
 ) {
+    let costs: Vec<f64> = usage.iter().filter_map(|u| u.cost).collect();
     let log = SessionLog {
         session_file,
+        total_cost: (!costs.is_empty()).then(|| costs.iter().sum()),
         usage,
     };
 
@@ -83,8 +88,9 @@ mod tests {
             "path.txt".to_string(),
             vec![ProviderUsage::new(
                 "model".to_string(),
-                Usage::new(Some(10), Some(20), Some(30)),
-            )],
+                Usage::new(Some(10), Some(20), Some(30)).with_cache_tokens(None, Some(5)),
+            )
+            .with_cost(Some(0.25))],
         );
 
         // Check if log file exists and contains the expected content
@@ -97,7 +103,9 @@ mod tests {
         assert_eq!(log.usage[0].usage.input_tokens, Some(10));
         assert_eq!(log.usage[0].usage.output_tokens, Some(20));
         assert_eq!(log.usage[0].usage.total_tokens, Some(30));
+        assert_eq!(log.usage[0].usage.cache_read_input_tokens, Some(5));
         assert_eq!(log.usage[0].model, "model");
+        assert_eq!(log.total_cost, Some(0.25));
 
         // Remove the log file after test
         std::fs::remove_file(&log_file).ok();
@@ -2,13 +2,15 @@ use anyhow::Result;
 use clap::{CommandFactory, Parser, Subcommand};
 
 use console::style;
+use goose::agents::OutputSchema;
 use goose::config::Config;
 use goose_cli::commands::agent_version::AgentCommand;
 use goose_cli::commands::configure::handle_configure;
 use goose_cli::commands::info::handle_info;
 use goose_cli::commands::mcp::run_server;
+use goose_cli::commands::models::handle_models;
 use goose_cli::logging::setup_logging;
-use goose_cli::session::build_session;
+use goose_cli::session::{build_session, BudgetArgs, GenerationArgs};
 use std::io::{self, Read};
 
 #[derive(Parser)]
@@ -36,6 +38,19 @@ enum Command {
     #[command(about = "Run one of the mcp servers bundled with goose")]
     Mcp { name: String },
 
+    /// List the models a provider offers
+    #[command(about = "List the models available from a provider")]
+    Models {
+        /// Provider to list the models of
+        #[arg(
+            short,
+            long,
+            value_name = "PROVIDER",
+            help = "Provider to list models for (e.g., 'openai'), defaults to the configured one"
+        )]
+        provider: Option<String>,
+    },
+
     /// Start or resume interactive chat sessions
     #[command(
         about = "Start or resume interactive chat sessions",
@@ -80,6 +95,14 @@ enum Command {
             value_delimiter = ','
         )]
         builtin: Vec<String>,
+
+        /// Limits on each reply and the session's spending cap
+        #[command(flatten)]
+        budget: BudgetArgs,
+
+        /// Generation settings for this session
+        #[command(flatten)]
+        generation: GenerationArgs,
     },
 
     /// Execute commands from an instruction file
@@ -145,6 +168,23 @@ enum Command {
             value_delimiter = ','
         )]
         builtin: Vec<String>,
+
+        /// Limits on each reply and the session's spending cap
+        #[command(flatten)]
+        budget: BudgetArgs,
+
+        /// JSON Schema for the final output
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "Print only a final answer matching this JSON Schema",
+            long_help = "Path to a JSON Schema file. The final answer is produced as JSON matching the schema, retrying if the model's output doesn't match, and only that JSON is printed to stdout. Exits with an error if no valid output is produced."
+        )]
+        output_schema: Option<String>,
+
+        /// Generation settings for this session
+        #[command(flatten)]
+        generation: GenerationArgs,
     },
 
     /// List available agent versions
@@ -174,13 +214,21 @@ async fn main() -> Result<()> {
         Some(Command::Mcp { name }) => {
             let _ = run_server(&name).await;
         }
+        Some(Command::Models { provider }) => {
+            handle_models(provider).await?;
+            return Ok(());
+        }
         Some(Command::Session {
             name,
             resume,
             extension,
             builtin,
+            budget,
+            generation,
         }) => {
-            let mut session = build_session(name, resume, extension, builtin).await;
+            let budget = budget.budget();
+            let mut session =
+                build_session(name, resume, extension, builtin, budget, generation).await;
             setup_logging(session.session_file().file_stem().and_then(|s| s.to_str()))?;
             let _ = session.start().await;
             return Ok(());
@@ -192,7 +240,11 @@ async fn main() -> Result<()> {
             resume,
             extension,
             builtin,
+            budget,
+            output_schema,
+            generation,
         }) => {
+            let budget = budget.budget();
             // Validate that we have some input source
             if instructions.is_none() && input_text.is_none() {
                 eprintln!("Error: Must provide either --instructions or --text");
@@ -211,9 +263,23 @@ async fn main() -> Result<()> {
                     .expect("Failed to read from stdin");
                 stdin
             };
-            let mut session = build_session(name, resume, extension, builtin).await;
+            let output_schema = output_schema.map(|file_name| {
+                OutputSchema::from_file(std::path::Path::new(&file_name)).unwrap_or_else(|e| {
+                    eprintln!("Error: {:#}", e);
+                    std::process::exit(1);
+                })
+            });
+            let structured = output_schema.is_some();
+            let mut session =
+                build_session(name, resume, extension, builtin, budget, generation).await;
+            session.set_output_schema(output_schema).await;
             setup_logging(session.session_file().file_stem().and_then(|s| s.to_str()))?;
-            let _ = session.headless_start(contents.clone()).await;
+            let result = session.headless_start(contents.clone()).await;
+            // Scripts reading the JSON need to know when there is none
+            if let (true, Err(e)) = (structured, result) {
+                eprintln!("Error: {:#}", e);
+                std::process::exit(1);
+            }
             return Ok(());
         }
         Some(Command::Agents(cmd)) => {
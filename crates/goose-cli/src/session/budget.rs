@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use goose::agents::ReplyBudget;
+use goose::config::Config;
+
+/// Limits on each reply and the session's spending cap
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct BudgetArgs {
+    /// Maximum number of tool calling turns per reply
+    #[arg(
+        long,
+        value_name = "TURNS",
+        help = "Stop a reply after this many tool calling turns",
+        long_help = "Limit how many times the model can call tools while answering a single message. The agent stops with an explanation when the limit is reached."
+    )]
+    pub max_turns: Option<usize>,
+
+    /// Maximum tokens per reply
+    #[arg(
+        long,
+        value_name = "TOKENS",
+        help = "Stop a reply after using this many input and output tokens",
+        long_help = "Limit the total input and output tokens reported by the provider while answering a single message."
+    )]
+    pub max_tokens: Option<usize>,
+
+    /// Maximum wall-clock time per reply
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "Stop a reply after this many seconds",
+        long_help = "Limit the wall-clock time spent answering a single message. The check happens between turns, so a slow tool call can run past the limit."
+    )]
+    pub max_duration: Option<u64>,
+
+    /// Spending cap for the session in US dollars
+    #[arg(
+        long,
+        value_name = "USD",
+        help = "Stop the session once it has cost this many US dollars",
+        long_help = "Stop the agent once the estimated cost of the session reaches this many US dollars, based on the pricing of the model. Defaults to GOOSE_MAX_COST from the config."
+    )]
+    pub max_cost: Option<f64>,
+}
+
+impl BudgetArgs {
+    /// The budget with every limit given on the command line, and the spending cap from the
+    /// config when none was given
+    pub fn budget(self) -> ReplyBudget {
+        ReplyBudget::default()
+            .with_max_turns(self.max_turns)
+            .with_max_tokens(self.max_tokens)
+            .with_max_duration(self.max_duration.map(Duration::from_secs))
+            .with_max_cost(
+                self.max_cost
+                    .or_else(|| Config::global().get("GOOSE_MAX_COST").ok()),
+            )
+    }
+}
@@ -1,13 +1,18 @@
 use console::style;
 use goose::agents::extension::ExtensionError;
-use goose::agents::AgentFactory;
+use goose::agents::{AgentFactory, ModelRouter, ReplyBudget};
 use goose::config::{Config, ExtensionManager};
+use goose::providers::base::Provider;
+use goose::providers::cache::ResponseCacheProvider;
+use goose::providers::retry::{RetryConfig, RetryProvider};
 use mcp_client::transport::Error as McpClientError;
 use std::path::PathBuf;
 use std::process;
+use std::sync::Arc;
 
 use super::output;
 use super::storage;
+use super::GenerationArgs;
 use super::Session;
 
 pub async fn build_session(
@@ -15,6 +20,8 @@ pub async fn build_session(
     resume: bool,
     extensions: Vec<String>,
     builtins: Vec<String>,
+    budget: ReplyBudget,
+    generation: GenerationArgs,
 ) -> Session {
     // Load config and get provider/model
     let config = Config::global();
@@ -27,9 +34,31 @@ pub async fn build_session(
     let model: String = config
         .get("GOOSE_MODEL")
         .expect("No model configured. Run 'goose configure' first");
-    let model_config = goose::model::ModelConfig::new(model.clone());
-    let provider =
-        goose::providers::create(&provider_name, model_config).expect("Failed to create provider");
+    // Settings given for this session win over the configured ones
+    let model_config = goose::model::ModelConfig::new(model.clone())
+        .with_configured_generation(config)
+        .unwrap_or_else(|e| {
+            eprintln!("Invalid generation settings: {}", e);
+            process::exit(1);
+        });
+    let model_config = generation.apply(model_config);
+    let wrap = |provider| -> anyhow::Result<Box<dyn Provider + Send + Sync>> {
+        // Answer repeated requests from disk while developing, if GOOSE_RESPONSE_CACHE enables it
+        let provider = ResponseCacheProvider::wrap_if_enabled(provider)?;
+        // Retry transient provider errors, letting the user know why the reply is taking longer
+        Ok(Box::new(
+            RetryProvider::new(provider, RetryConfig::from_config())
+                .with_listener(Arc::new(output::render_retry)),
+        ))
+    };
+    let provider = goose::providers::create(&provider_name, model_config.clone())
+        .expect("Failed to create provider");
+    let provider = wrap(provider).expect("Failed to set up the response cache");
+    // Send some turns to other models, if GOOSE_MODEL_ROUTER sets up routing
+    let model_router = ModelRouter::from_config(&model_config, wrap).unwrap_or_else(|e| {
+        eprintln!("Invalid GOOSE_MODEL_ROUTER: {}", e);
+        process::exit(1);
+    });
 
     // Create the agent
     let agent_version: Option<String> = config.get("GOOSE_AGENT").ok();
@@ -38,6 +67,8 @@ pub async fn build_session(
         None => AgentFactory::create(AgentFactory::default_version(), provider),
     }
     .expect("Failed to create agent");
+    agent.set_budget(budget).await;
+    agent.set_model_router(model_router).await;
 
     // Setup extensions for the agent
     for extension in ExtensionManager::get_all().expect("should load extensions") {
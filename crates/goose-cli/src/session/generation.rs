@@ -0,0 +1,124 @@
+use goose::model::{ModelConfig, ReasoningEffort, ToolChoice};
+
+/// Generation settings for one session, overriding those in the config
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct GenerationArgs {
+    /// Sampling temperature
+    #[arg(
+        long,
+        value_name = "TEMPERATURE",
+        help = "Sampling temperature, overriding GOOSE_TEMPERATURE"
+    )]
+    pub temperature: Option<f32>,
+
+    /// Nucleus sampling
+    #[arg(
+        long,
+        value_name = "P",
+        help = "Sample from the likeliest tokens up to this cumulative probability"
+    )]
+    pub top_p: Option<f32>,
+
+    /// Top-k sampling
+    #[arg(
+        long,
+        value_name = "K",
+        help = "Sample from only this many of the likeliest tokens"
+    )]
+    pub top_k: Option<u32>,
+
+    /// Stop sequences
+    #[arg(
+        long = "stop",
+        value_name = "SEQUENCE",
+        help = "Stop generating at this sequence (can be specified multiple times)",
+        action = clap::ArgAction::Append
+    )]
+    pub stop_sequences: Vec<String>,
+
+    /// Sampling seed
+    #[arg(
+        long,
+        value_name = "SEED",
+        help = "Seed for providers which can sample deterministically"
+    )]
+    pub seed: Option<i64>,
+
+    /// Presence penalty
+    #[arg(
+        long,
+        value_name = "PENALTY",
+        help = "Penalize tokens which have already appeared"
+    )]
+    pub presence_penalty: Option<f32>,
+
+    /// Frequency penalty
+    #[arg(
+        long,
+        value_name = "PENALTY",
+        help = "Penalize tokens by how often they have appeared"
+    )]
+    pub frequency_penalty: Option<f32>,
+
+    /// Tool choice
+    #[arg(
+        long,
+        value_name = "CHOICE",
+        help = "Whether the model may call tools: auto, required, none or the name of a tool",
+        long_help = "Whether the model may, must or must not call tools. Use auto, required or none, or the name of a tool such as developer__shell to require calling it."
+    )]
+    pub tool_choice: Option<ToolChoice>,
+
+    /// Parallel tool calls
+    #[arg(
+        long,
+        value_name = "BOOL",
+        help = "Whether the model may call several tools at once (true or false)"
+    )]
+    pub parallel_tool_calls: Option<bool>,
+
+    /// Reasoning effort
+    #[arg(
+        long,
+        value_name = "EFFORT",
+        help = "Reasoning effort for reasoning models: low, medium or high"
+    )]
+    pub reasoning_effort: Option<ReasoningEffort>,
+}
+
+impl GenerationArgs {
+    /// The model config with every setting given on the command line applied
+    pub fn apply(self, mut model: ModelConfig) -> ModelConfig {
+        if self.temperature.is_some() {
+            model.temperature = self.temperature;
+        }
+        if self.top_p.is_some() {
+            model.top_p = self.top_p;
+        }
+        if self.top_k.is_some() {
+            model.top_k = self.top_k;
+        }
+        if !self.stop_sequences.is_empty() {
+            model.stop_sequences = self.stop_sequences;
+        }
+        if self.seed.is_some() {
+            model.seed = self.seed;
+        }
+        if self.presence_penalty.is_some() {
+            model.presence_penalty = self.presence_penalty;
+        }
+        if self.frequency_penalty.is_some() {
+            model.frequency_penalty = self.frequency_penalty;
+        }
+        if self.tool_choice.is_some() {
+            model.tool_choice = self.tool_choice;
+        }
+        if self.parallel_tool_calls.is_some() {
+            model.parallel_tool_calls = self.parallel_tool_calls;
+        }
+        if self.reasoning_effort.is_some() {
+            model.reasoning_effort = self.reasoning_effort;
+        }
+        model
+    }
+}
@@ -8,6 +8,10 @@ pub enum InputResult {
     AddExtension(String),
     AddBuiltin(String),
     ToggleTheme,
+    /// Send every turn to the named model, or go back to routing with None
+    SetModel(Option<String>),
+    /// Keep the latest message when the conversation is truncated
+    PinLast,
     Retry,
 }
 
@@ -61,6 +65,11 @@ fn handle_slash_command(input: &str) -> Option<InputResult> {
         "/t" => Some(InputResult::ToggleTheme),
         s if s.starts_with("/extension ") => Some(InputResult::AddExtension(s[11..].to_string())),
         s if s.starts_with("/builtin ") => Some(InputResult::AddBuiltin(s[9..].to_string())),
+        "/pin" => Some(InputResult::PinLast),
+        "/model auto" => Some(InputResult::SetModel(None)),
+        s if s.starts_with("/model ") => {
+            Some(InputResult::SetModel(Some(s[7..].trim().to_string())))
+        }
         _ => None,
     }
 }
@@ -72,6 +81,8 @@ fn print_help() {
 /t - Toggle Light/Dark/Ansi theme
 /extension <command> - Add a stdio extension (format: ENV1=val1 command args...)
 /builtin <names> - Add builtin extensions by name (comma-separated)
+/model <name> - Use this model of GOOSE_MODEL_ROUTER for every turn, or auto to route by its rules
+/pin - Keep the latest message when the conversation is truncated to fit the context window
 /? or /help - Display this help message
 
 Navigation:
@@ -127,6 +138,22 @@ mod tests {
             panic!("Expected AddBuiltin");
         }
 
+        // Test model command
+        assert!(matches!(
+            handle_slash_command("/model strong"),
+            Some(InputResult::SetModel(Some(name))) if name == "strong"
+        ));
+        assert!(matches!(
+            handle_slash_command("/model auto"),
+            Some(InputResult::SetModel(None))
+        ));
+
+        // Test pin command
+        assert!(matches!(
+            handle_slash_command("/pin"),
+            Some(InputResult::PinLast)
+        ));
+
         // Test unknown commands
         assert!(handle_slash_command("/unknown").is_none());
     }
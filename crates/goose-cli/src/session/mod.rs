@@ -1,16 +1,20 @@
+mod budget;
 mod builder;
+mod generation;
 mod input;
 mod output;
 mod prompt;
 mod storage;
 mod thinking;
 
+pub use budget::BudgetArgs;
 pub use builder::build_session;
+pub use generation::GenerationArgs;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use etcetera::choose_app_strategy;
 use goose::agents::extension::{Envs, ExtensionConfig};
-use goose::agents::Agent;
+use goose::agents::{Agent, AgentEvent, OutputSchema};
 use goose::message::{Message, MessageContent};
 use mcp_core::handler::ToolError;
 use rand::{distributions::Alphanumeric, Rng};
@@ -23,6 +27,7 @@ pub struct Session {
     agent: Box<dyn Agent>,
     messages: Vec<Message>,
     session_file: PathBuf,
+    output_schema: Option<OutputSchema>,
 }
 
 impl Session {
@@ -39,6 +44,7 @@ impl Session {
             agent,
             messages,
             session_file,
+            output_schema: None,
         }
     }
 
@@ -131,6 +137,7 @@ impl Session {
                     output::show_thinking();
                     self.process_agent_response().await?;
                     output::hide_thinking();
+                    output::render_cost(&self.agent.usage().await);
                 }
                 input::InputResult::Exit => break,
                 input::InputResult::AddExtension(cmd) => {
@@ -164,6 +171,17 @@ impl Session {
                     output::set_theme(new_theme);
                     continue;
                 }
+                input::InputResult::SetModel(model) => {
+                    match self.agent.set_model_override(model.clone()).await {
+                        Ok(_) => output::render_model_override(model.as_deref()),
+                        Err(e) => output::render_error(&e.to_string()),
+                    }
+                }
+                input::InputResult::PinLast => match self.pin_last_message() {
+                    Ok(true) => output::render_pinned(),
+                    Ok(false) => output::render_error("There is no message to pin yet"),
+                    Err(e) => output::render_error(&e.to_string()),
+                },
                 input::InputResult::Retry => continue,
             }
         }
@@ -171,6 +189,7 @@ impl Session {
         // Log usage and cleanup
         if let Ok(home_dir) = choose_app_strategy(crate::APP_STRATEGY.clone()) {
             let usage = self.agent.usage().await;
+            output::render_usage(&usage);
             log_usage(
                 home_dir,
                 self.session_file.to_string_lossy().to_string(),
@@ -188,23 +207,85 @@ impl Session {
         self.messages
             .push(Message::user().with_text(&initial_message));
         storage::persist_messages(&self.session_file, &self.messages)?;
-        self.process_agent_response().await?;
+        match self.output_schema.clone() {
+            Some(schema) => self.process_structured_response(&schema).await?,
+            None => self.process_agent_response().await?,
+        }
         Ok(())
     }
 
-    async fn process_agent_response(&mut self) -> Result<()> {
+    /// Pin the latest message so truncation keeps it, returning false if there are no messages
+    pub fn pin_last_message(&mut self) -> Result<bool> {
+        let Some(message) = self.messages.last_mut() else {
+            return Ok(false);
+        };
+        message.pinned = true;
+        storage::persist_messages(&self.session_file, &self.messages)?;
+        Ok(true)
+    }
+
+    /// Require the final answer of each reply to match the schema
+    pub async fn set_output_schema(&mut self, schema: Option<OutputSchema>) {
+        self.output_schema = schema.clone();
+        self.agent.set_output_schema(schema).await;
+    }
+
+    /// Run the reply without rendering anything, then print only the final JSON to stdout
+    async fn process_structured_response(&mut self, schema: &OutputSchema) -> Result<()> {
         let mut stream = self.agent.reply(&self.messages).await?;
 
+        use futures::StreamExt;
+        while let Some(message) = stream.next().await {
+            self.messages.push(message?);
+            storage::persist_messages(&self.session_file, &self.messages)?;
+        }
+
+        // The reply can also end early, with an explanation instead of the JSON, such as when
+        // it runs out of budget
+        let output = self
+            .messages
+            .last()
+            .filter(|message| message.role == mcp_core::role::Role::Assistant)
+            .map(|message| message.as_concat_text())
+            .unwrap_or_default();
+        let value = schema.validate(&output).map_err(|e| {
+            anyhow!(
+                "The reply did not end with output matching the schema: {}\n{}",
+                e,
+                output
+            )
+        })?;
+        println!("{}", serde_json::to_string_pretty(&value)?);
+        Ok(())
+    }
+
+    async fn process_agent_response(&mut self) -> Result<()> {
+        let mut stream = self.agent.stream_reply(&self.messages).await?;
+        // Whether text of the next assistant message has already been printed
+        let mut streamed = false;
+
         use futures::StreamExt;
         loop {
             tokio::select! {
                 result = stream.next() => {
                     match result {
-                        Some(Ok(message)) => {
+                        Some(Ok(AgentEvent::Partial(message))) => {
+                            if !streamed {
+                                output::hide_thinking();
+                                streamed = true;
+                            }
+                            output::render_partial(&message);
+                        }
+                        Some(Ok(AgentEvent::Message(message))) => {
                             self.messages.push(message.clone());
                             storage::persist_messages(&self.session_file, &self.messages)?;
                             output::hide_thinking();
-                            output::render_message(&message);
+                            if streamed {
+                                output::render_streamed_message(&message);
+                                streamed = false;
+                            } else {
+                                output::render_message(&message);
+                            }
                             output::show_thinking();
                         }
                         Some(Err(e)) => {
@@ -2,9 +2,12 @@ use bat::WrappingMode;
 use console::style;
 use goose::config::Config;
 use goose::message::{Message, MessageContent, ToolRequest, ToolResponse};
+use goose::providers::base::ProviderUsage;
+use goose::providers::retry::RetryNotice;
 use mcp_core::tool::ToolCall;
 use serde_json::Value;
 use std::cell::RefCell;
+use std::io::Write;
 use std::path::Path;
 
 // Re-export theme for use in main
@@ -97,9 +100,86 @@ pub fn render_message(message: &Message) {
             MessageContent::Image(image) => {
                 println!("Image: [data: {}, type: {}]", image.data, image.mime_type);
             }
+            MessageContent::Thinking(thinking) => render_thinking(&thinking.thinking),
+            MessageContent::RedactedThinking(_) => {
+                println!(
+                    "{}",
+                    style("Thinking redacted by the provider").dim().italic()
+                );
+            }
+        }
+    }
+    println!();
+}
+
+thread_local! {
+    /// Whether reasoning is shown in full, otherwise it is collapsed to a single line. Read once
+    /// rather than for every streamed chunk
+    static SHOW_FULL_THINKING: bool = Config::global()
+        .get::<bool>("GOOSE_CLI_SHOW_THINKING")
+        .unwrap_or(true);
+}
+
+fn show_full_thinking() -> bool {
+    SHOW_FULL_THINKING.with(|show| *show)
+}
+
+fn render_thinking(thinking: &str) {
+    if show_full_thinking() {
+        println!("{}", style(thinking).dim());
+    } else {
+        println!(
+            "{}",
+            style(format!(
+                "Thought for {} lines (set GOOSE_CLI_SHOW_THINKING to expand)",
+                thinking.lines().count()
+            ))
+            .dim()
+            .italic()
+        );
+    }
+    println!();
+}
+
+thread_local! {
+    static STREAMED_REASONING: RefCell<bool> = const { RefCell::new(false) };
+}
+
+/// Print assistant text and reasoning as it is generated, before the complete message is
+/// available
+pub fn render_partial(message: &Message) {
+    for content in &message.content {
+        match content {
+            MessageContent::Text(text) => {
+                // Separate the answer from the reasoning before it
+                if STREAMED_REASONING.with(|t| t.replace(false)) {
+                    print!("\n\n");
+                }
+                print!("{}", text.text);
+            }
+            MessageContent::Thinking(thinking) if show_full_thinking() => {
+                STREAMED_REASONING.with(|t| *t.borrow_mut() = true);
+                print!("{}", style(&thinking.thinking).dim());
+            }
+            _ => {}
         }
     }
+    let _ = std::io::stdout().flush();
+}
+
+/// Render a message whose text and reasoning were already printed with `render_partial`
+pub fn render_streamed_message(message: &Message) {
+    STREAMED_REASONING.with(|t| *t.borrow_mut() = false);
     println!();
+    let mut message = message.clone();
+    let show_thinking = show_full_thinking();
+    message.content.retain(|content| match content {
+        MessageContent::Text(_) => false,
+        // Collapsed reasoning isn't streamed, so it is summarized here instead
+        MessageContent::Thinking(_) => !show_thinking,
+        _ => true,
+    });
+    render_message(&message);
 }
 
 fn render_tool_request(req: &ToolRequest, theme: Theme) {
@@ -151,6 +231,79 @@ pub fn render_error(message: &str) {
     println!("\n  {} {}\n", style("error:").red().bold(), message);
 }
 
+pub fn render_retry(notice: &RetryNotice) {
+    hide_thinking();
+    println!(
+        "  {} provider request failed, retrying in {}s (attempt {}/{})",
+        style("warning:").yellow().bold(),
+        notice.delay.as_secs_f64().ceil() as u64,
+        notice.attempt + 1,
+        notice.max_attempts
+    );
+    println!("  {}", style(&notice.error).dim());
+    show_thinking();
+}
+
+/// Summarize the tokens used by each model in the session, including prompt cache hits
+pub fn render_usage(usage: &[ProviderUsage]) {
+    if usage.is_empty() {
+        return;
+    }
+    println!("\n{}", style("Token usage").bold());
+    for provider_usage in usage {
+        let usage = &provider_usage.usage;
+        let mut line = format!(
+            "  {}: {} input, {} output",
+            provider_usage.model,
+            usage.input_tokens.unwrap_or(0),
+            usage.output_tokens.unwrap_or(0)
+        );
+        if let Some(read) = usage.cache_read_input_tokens {
+            let input = usage.input_tokens.unwrap_or(0).max(1);
+            line.push_str(&format!(
+                ", {} read from cache ({:.0}% of input)",
+                read,
+                read as f64 * 100.0 / input as f64
+            ));
+        }
+        if let Some(created) = usage.cache_creation_input_tokens {
+            line.push_str(&format!(", {} written to cache", created));
+        }
+        if let Some(cost) = provider_usage.cost {
+            line.push_str(&format!(", ${:.4}", cost));
+        }
+        if let Some(cache) = provider_usage.response_cache {
+            line.push_str(&format!(
+                ", {} of {} replies from the response cache",
+                cache.hits,
+                cache.hits + cache.misses
+            ));
+        }
+        println!("{}", style(line).dim());
+    }
+    if let Some(cost) = total_cost(usage) {
+        println!("{}", style(format!("  Total cost: ${:.4}", cost)).dim());
+    }
+}
+
+/// Show the running cost of the session, if the pricing of any model used is known
+pub fn render_cost(usage: &[ProviderUsage]) {
+    if let Some(cost) = total_cost(usage) {
+        println!(
+            "{}",
+            style(format!("Session cost so far: ${:.4}", cost)).dim()
+        );
+    }
+}
+
+/// The summed cost of the calls which have one, or None if none do
+fn total_cost(usage: &[ProviderUsage]) -> Option<f64> {
+    usage
+        .iter()
+        .filter_map(|u| u.cost)
+        .fold(None, |total, cost| Some(total.unwrap_or(0.0) + cost))
+}
+
 pub fn render_extension_success(name: &str) {
     println!();
     println!(
@@ -173,6 +326,29 @@ pub fn render_extension_error(name: &str, error: &str) {
     println!();
 }
 
+pub fn render_pinned() {
+    println!(
+        "\n  {} the latest message, it will be kept when the conversation is truncated\n",
+        style("pinned").green()
+    );
+}
+
+pub fn render_model_override(model: Option<&str>) {
+    println!();
+    match model {
+        Some(model) => println!(
+            "  {} every turn to `{}`",
+            style("sending").green(),
+            style(model).cyan()
+        ),
+        None => println!(
+            "  {} turns by the GOOSE_MODEL_ROUTER rules",
+            style("routing").green()
+        ),
+    }
+    println!();
+}
+
 pub fn render_builtin_success(names: &str) {
     println!();
     println!(
@@ -5,11 +5,19 @@ use axum::{
     routing::{get, post},
     Json, Router,
 };
+use goose::agents::{AgentFactory, ModelRouter, ReplyBudget};
 use goose::config::Config;
-use goose::{agents::AgentFactory, model::ModelConfig, providers};
+use goose::providers::base::ProviderUsage;
+use goose::providers::cache::{ResponseCacheConfig, RESPONSE_CACHE_CONFIG_KEY};
+use goose::providers::retry::{RetryConfig, RetryProvider};
+use goose::{model::ModelConfig, providers};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::time::Duration;
+
+/// How long a provider gets to list its models in the background
+const MODEL_REFRESH_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Serialize)]
 struct VersionsResponse {
@@ -27,6 +35,25 @@ struct ExtendPromptResponse {
     success: bool,
 }
 
+#[derive(Deserialize)]
+struct SetBudgetRequest {
+    max_turns: Option<usize>,
+    max_tokens: Option<usize>,
+    max_duration_secs: Option<u64>,
+    max_cost: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct SetBudgetResponse {
+    success: bool,
+}
+
+#[derive(Serialize)]
+struct UsageResponse {
+    usage: Vec<ProviderUsage>,
+    total_cost: Option<f64>,
+}
+
 #[derive(Deserialize)]
 struct CreateAgentRequest {
     version: Option<String>,
@@ -95,6 +122,61 @@ async fn extend_prompt(
     }
 }
 
+async fn set_budget(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<SetBudgetRequest>,
+) -> Result<Json<SetBudgetResponse>, StatusCode> {
+    // Verify secret key
+    let secret_key = headers
+        .get("X-Secret-Key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if secret_key != state.secret_key {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let budget = ReplyBudget::default()
+        .with_max_turns(payload.max_turns)
+        .with_max_tokens(payload.max_tokens)
+        .with_max_duration(payload.max_duration_secs.map(Duration::from_secs))
+        .with_max_cost(payload.max_cost);
+
+    let mut agent = state.agent.lock().await;
+    if let Some(ref mut agent) = *agent {
+        agent.set_budget(budget).await;
+        Ok(Json(SetBudgetResponse { success: true }))
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+async fn get_usage(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<UsageResponse>, StatusCode> {
+    // Verify secret key
+    let secret_key = headers
+        .get("X-Secret-Key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if secret_key != state.secret_key {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let agent = state.agent.lock().await;
+    if let Some(ref agent) = *agent {
+        let usage = agent.usage().await;
+        let costs: Vec<f64> = usage.iter().filter_map(|u| u.cost).collect();
+        let total_cost = (!costs.is_empty()).then(|| costs.iter().sum());
+        Ok(Json(UsageResponse { usage, total_cost }))
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
 #[axum::debug_handler]
 async fn create_agent(
     State(state): State<AppState>,
@@ -124,15 +206,36 @@ async fn create_agent(
             .get("GOOSE_MODEL")
             .expect("Did not find a model on payload or in env")
     });
-    let model_config = ModelConfig::new(model);
-    let provider =
-        providers::create(&payload.provider, model_config).expect("Failed to create provider");
+    let model_config = ModelConfig::new(model)
+        .with_configured_generation(config)
+        .map_err(|e| {
+            tracing::error!("Invalid generation settings: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let provider = providers::create(&payload.provider, model_config.clone())
+        .expect("Failed to create provider");
+    // The response cache is a development tool for the CLI, never used to answer server requests
+    if ResponseCacheConfig::from_config().enabled {
+        tracing::warn!("{} is ignored by the server", RESPONSE_CACHE_CONFIG_KEY);
+    }
+    let provider = Box::new(RetryProvider::new(provider, RetryConfig::from_config()));
+    let model_router = ModelRouter::from_config(&model_config, |provider| {
+        Ok(Box::new(RetryProvider::new(
+            provider,
+            RetryConfig::from_config(),
+        )))
+    })
+    .map_err(|e| {
+        tracing::error!("Invalid GOOSE_MODEL_ROUTER: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
 
     let version = payload
         .version
         .unwrap_or_else(|| AgentFactory::default_version().to_string());
 
-    let new_agent = AgentFactory::create(&version, provider).expect("Failed to create agent");
+    let mut new_agent = AgentFactory::create(&version, provider).expect("Failed to create agent");
+    new_agent.set_model_router(model_router).await;
 
     let mut agent = state.agent.lock().await;
     *agent = Some(new_agent);
@@ -145,19 +248,41 @@ async fn list_providers() -> Json<Vec<ProviderList>> {
 
     let providers: HashMap<String, ProviderFile> =
         serde_json::from_str(contents).expect("Failed to parse providers_and_keys.json");
+    let interactive: HashSet<String> = providers::providers()
+        .into_iter()
+        .filter(|metadata| metadata.interactive_auth)
+        .map(|metadata| metadata.name)
+        .collect();
 
+    // Prefer the models the provider reported, but never wait on its API here. Providers which
+    // haven't been listed yet are refreshed in the background for later requests, except those
+    // whose auth could need the user
+    let mut refresh = Vec::new();
     let response: Vec<ProviderList> = providers
         .into_iter()
-        .map(|(id, provider)| ProviderList {
-            id,
-            details: ProviderDetails {
-                name: provider.name,
-                description: provider.description,
-                models: provider.models,
-                required_keys: provider.required_keys,
-            },
+        .map(|(id, provider)| {
+            let models = providers::cached_models(&id).unwrap_or_else(|| {
+                if !interactive.contains(&id) {
+                    refresh.push(id.clone());
+                }
+                provider.models
+            });
+            ProviderList {
+                id,
+                details: ProviderDetails {
+                    name: provider.name,
+                    description: provider.description,
+                    models,
+                    required_keys: provider.required_keys,
+                },
+            }
         })
         .collect();
+    tokio::spawn(futures::future::join_all(refresh.into_iter().map(
+        |id| async move {
+            let _ = tokio::time::timeout(MODEL_REFRESH_TIMEOUT, providers::list_models(&id)).await;
+        },
+    )));
 
     // Return the response as JSON.
     Json(response)
@@ -168,6 +293,8 @@ pub fn routes(state: AppState) -> Router {
         .route("/agent/versions", get(get_versions))
         .route("/agent/providers", get(list_providers))
         .route("/agent/prompt", post(extend_prompt))
+        .route("/agent/budget", post(set_budget))
+        .route("/agent/usage", get(get_usage))
         .route("/agent", post(create_agent))
         .with_state(state)
 }
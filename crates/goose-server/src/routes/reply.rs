@@ -8,6 +8,7 @@ use axum::{
 };
 use bytes::Bytes;
 use futures::{stream::StreamExt, Stream};
+use goose::agents::AgentEvent;
 use goose::message::{Message, MessageContent};
 
 use mcp_core::{content::Content, role::Role};
@@ -141,6 +142,17 @@ impl ProtocolFormatter {
         format!("0:{}\n", encoded_text)
     }
 
+    fn format_reasoning(text: &str) -> String {
+        // Reasoning starts with "g:"
+        let encoded_text = serde_json::to_string(text).unwrap_or_else(|_| String::new());
+        format!("g:{}\n", encoded_text)
+    }
+
+    fn format_redacted_reasoning(data: &str) -> String {
+        // Redacted reasoning starts with "i:"
+        format!("i:{}\n", json!({ "data": data }))
+    }
+
     fn format_tool_call(id: &str, name: &str, args: &Value) -> String {
         // Tool calls start with "9:"
         let tool_call = json!({
@@ -247,6 +259,14 @@ async fn stream_message(
                                 .await?;
                         }
                     }
+                    MessageContent::Thinking(thinking) => {
+                        tx.send(ProtocolFormatter::format_reasoning(&thinking.thinking))
+                            .await?;
+                    }
+                    MessageContent::RedactedThinking(redacted) => {
+                        tx.send(ProtocolFormatter::format_redacted_reasoning(&redacted.data))
+                            .await?;
+                    }
                     MessageContent::Image(_) => {
                         // TODO
                         continue;
@@ -262,6 +282,26 @@ async fn stream_message(
     Ok(())
 }
 
+/// Send text and reasoning as it is generated, ahead of the complete message
+async fn stream_partial(
+    message: Message,
+    tx: &mpsc::Sender<String>,
+) -> Result<(), mpsc::error::SendError<String>> {
+    for content in message.content {
+        match content {
+            MessageContent::Text(text) => {
+                tx.send(ProtocolFormatter::format_text(&text.text)).await?;
+            }
+            MessageContent::Thinking(thinking) => {
+                tx.send(ProtocolFormatter::format_reasoning(&thinking.thinking))
+                    .await?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
 async fn handler(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -308,7 +348,7 @@ async fn handler(
             }
         };
 
-        let mut stream = match agent.reply(&messages).await {
+        let mut stream = match agent.stream_reply(&messages).await {
             Ok(stream) => stream,
             Err(e) => {
                 tracing::error!("Failed to start reply stream: {}", e);
@@ -320,11 +360,27 @@ async fn handler(
             }
         };
 
+        // Whether text of the next assistant message has already been sent as it was generated
+        let mut streamed = false;
+
         loop {
             tokio::select! {
                 response = timeout(Duration::from_millis(500), stream.next()) => {
                     match response {
-                        Ok(Some(Ok(message))) => {
+                        Ok(Some(Ok(AgentEvent::Partial(message)))) => {
+                            streamed = true;
+                            if let Err(e) = stream_partial(message, &tx).await {
+                                tracing::error!("Error sending message through channel: {}", e);
+                                break;
+                            }
+                        }
+                        Ok(Some(Ok(AgentEvent::Message(mut message)))) => {
+                            if streamed && message.role == Role::Assistant {
+                                message.content.retain(|content| {
+                                    !matches!(content, MessageContent::Text(_) | MessageContent::Thinking(_))
+                                });
+                                streamed = false;
+                            }
                             if let Err(e) = stream_message(message, &tx).await {
                                 tracing::error!("Error sending message through channel: {}", e);
                                 let _ = tx.send(ProtocolFormatter::format_error(&e.to_string())).await;
@@ -438,44 +494,7 @@ pub fn routes(state: AppState) -> Router {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use goose::{
-        agents::AgentFactory,
-        model::ModelConfig,
-        providers::{
-            base::{Provider, ProviderUsage, Usage},
-            errors::ProviderError,
-        },
-    };
-    use mcp_core::tool::Tool;
-
-    // Mock Provider implementation for testing
-    #[derive(Clone)]
-    struct MockProvider {
-        model_config: ModelConfig,
-    }
-
-    #[async_trait::async_trait]
-    impl Provider for MockProvider {
-        fn metadata() -> goose::providers::base::ProviderMetadata {
-            goose::providers::base::ProviderMetadata::empty()
-        }
-
-        fn get_model_config(&self) -> ModelConfig {
-            self.model_config.clone()
-        }
-
-        async fn complete(
-            &self,
-            _system: &str,
-            _messages: &[Message],
-            _tools: &[Tool],
-        ) -> anyhow::Result<(Message, ProviderUsage), ProviderError> {
-            Ok((
-                Message::assistant().with_text("Mock response"),
-                ProviderUsage::new("mock".to_string(), Usage::default()),
-            ))
-        }
-    }
+    use goose::{agents::AgentFactory, providers::mock::MockProvider};
 
     #[test]
     fn test_convert_messages_user_only() {
@@ -568,10 +587,8 @@ mod tests {
         #[tokio::test]
         async fn test_ask_endpoint() {
             // Create a mock app state with mock provider
-            let mock_model_config = ModelConfig::new("test-model".to_string());
-            let mock_provider = Box::new(MockProvider {
-                model_config: mock_model_config,
-            });
+            let mock_provider =
+                Box::new(MockProvider::new("test-model").with_default_text("Mock response"));
             let agent = AgentFactory::create("reference", mock_provider).unwrap();
             let state = AppState {
                 config: Arc::new(Mutex::new(HashMap::new())), // Add this line
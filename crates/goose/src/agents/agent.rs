@@ -1,18 +1,56 @@
 use anyhow::Result;
 use async_trait::async_trait;
 use futures::stream::BoxStream;
+use futures::TryStreamExt;
 use serde_json::Value;
 
+use super::budget::ReplyBudget;
 use super::extension::{ExtensionConfig, ExtensionResult};
+use super::model_router::ModelRouter;
+use super::output_schema::OutputSchema;
 use crate::message::Message;
 use crate::providers::base::ProviderUsage;
 
+/// An update produced while the agent is replying
+#[derive(Debug, Clone)]
+pub enum AgentEvent {
+    /// A fragment of assistant text as it is generated. The complete assistant message, including
+    /// this text, follows as a `Message` event once the model finishes
+    Partial(Message),
+    /// A complete message to add to the conversation
+    Message(Message),
+}
+
+/// Keep only the complete messages of a streamed reply, for agents implementing `reply` with
+/// their `stream_reply`
+pub fn complete_messages(
+    events: BoxStream<'_, Result<AgentEvent>>,
+) -> BoxStream<'_, Result<Message>> {
+    Box::pin(events.try_filter_map(|event| async move {
+        Ok(match event {
+            AgentEvent::Message(message) => Some(message),
+            AgentEvent::Partial(_) => None,
+        })
+    }))
+}
+
 /// Core trait defining the behavior of an Agent
 #[async_trait]
 pub trait Agent: Send + Sync {
     /// Create a stream that yields each message as it's generated by the agent
     async fn reply(&self, messages: &[Message]) -> Result<BoxStream<'_, Result<Message>>>;
 
+    /// Like `reply`, but also yields partial text while the model is generating it
+    ///
+    /// Agents which can't stream use the default, which yields only complete messages
+    async fn stream_reply(
+        &self,
+        messages: &[Message],
+    ) -> Result<BoxStream<'_, Result<AgentEvent>>> {
+        let messages = self.reply(messages).await?;
+        Ok(Box::pin(messages.map_ok(AgentEvent::Message)))
+    }
+
     /// Add a new MCP client to the agent
     async fn add_extension(&mut self, config: ExtensionConfig) -> ExtensionResult<()>;
 
@@ -34,4 +72,37 @@ pub trait Agent: Send + Sync {
 
     /// Override the system prompt with custom text
     async fn override_system_prompt(&mut self, template: String);
+
+    /// Set the limits applied to each reply
+    ///
+    /// Agents which don't enforce limits ignore it
+    async fn set_budget(&mut self, _budget: ReplyBudget) {}
+
+    /// Require the final answer of each reply to be JSON matching the schema
+    ///
+    /// The reply then ends with an assistant message holding only the JSON, or with an error if
+    /// the model can't produce valid output. Agents without support for it log a warning and
+    /// reply as usual
+    async fn set_output_schema(&mut self, schema: Option<OutputSchema>) {
+        if schema.is_some() {
+            tracing::warn!("This agent does not support output schemas, replies are not validated");
+        }
+    }
+
+    /// Choose the model for each turn with the router, rather than always using the provider
+    ///
+    /// Agents without support for routing log a warning and keep using the provider
+    async fn set_model_router(&mut self, router: Option<ModelRouter>) {
+        if router.is_some() {
+            tracing::warn!("This agent does not support model routing, using the provider");
+        }
+    }
+
+    /// Send every turn to the named model of the router, or go back to its rules with None
+    ///
+    /// Fails if no router is set or it has no model by that name. Agents without support for
+    /// routing ignore it
+    async fn set_model_override(&self, _model: Option<String>) -> Result<()> {
+        Ok(())
+    }
 }
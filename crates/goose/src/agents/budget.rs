@@ -0,0 +1,180 @@
+//! Limits on how much work a single `Agent::reply` can do
+//!
+//! A model stuck in a tool calling loop will otherwise keep going until the process is killed,
+//! which is especially costly in headless runs. When a limit is reached the agent stops after
+//! the current turn and explains why in an assistant message.
+
+use std::time::{Duration, Instant};
+
+use crate::providers::base::Usage;
+
+/// Per session limits applied to each reply, all of which are unlimited by default
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplyBudget {
+    /// Maximum number of turns in which the model requested tools
+    pub max_turns: Option<usize>,
+    /// Maximum input plus output tokens reported by the provider
+    pub max_tokens: Option<usize>,
+    /// Maximum wall-clock time for the reply
+    pub max_duration: Option<Duration>,
+    /// Spending cap in US dollars for the whole session, including earlier replies
+    pub max_cost: Option<f64>,
+}
+
+impl ReplyBudget {
+    pub fn with_max_turns(mut self, max_turns: Option<usize>) -> Self {
+        self.max_turns = max_turns;
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: Option<usize>) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    pub fn with_max_duration(mut self, max_duration: Option<Duration>) -> Self {
+        self.max_duration = max_duration;
+        self
+    }
+
+    pub fn with_max_cost(mut self, max_cost: Option<f64>) -> Self {
+        self.max_cost = max_cost;
+        self
+    }
+}
+
+/// Tracks the consumption of a single reply against its budget
+#[derive(Debug)]
+pub struct BudgetTracker {
+    budget: ReplyBudget,
+    started: Instant,
+    turns: usize,
+    tokens: usize,
+    session_cost: f64,
+}
+
+impl BudgetTracker {
+    pub fn new(budget: ReplyBudget) -> Self {
+        Self {
+            budget,
+            started: Instant::now(),
+            turns: 0,
+            tokens: 0,
+            session_cost: 0.0,
+        }
+    }
+
+    /// Record a turn in which the model requested tools
+    pub fn record_turn(&mut self) {
+        self.turns += 1;
+    }
+
+    /// Record the tokens used by a single provider call
+    pub fn record_usage(&mut self, usage: &Usage) {
+        let tokens = match (usage.input_tokens, usage.output_tokens) {
+            (None, None) => usage.total_tokens.unwrap_or(0),
+            (input, output) => input.unwrap_or(0) + output.unwrap_or(0),
+        };
+        self.tokens += tokens.max(0) as usize;
+    }
+
+    /// Record the total cost of the session so far
+    pub fn record_cost(&mut self, session_cost: f64) {
+        self.session_cost = session_cost;
+    }
+
+    /// If any limit has been reached, returns the message explaining why the agent stopped
+    pub fn exceeded(&self) -> Option<String> {
+        self.exceeded_after(self.started.elapsed())
+    }
+
+    fn exceeded_after(&self, elapsed: Duration) -> Option<String> {
+        // Unlike the other limits this one persists, so another message won't help
+        if let Some(max_cost) = self.budget.max_cost {
+            if self.session_cost >= max_cost {
+                return Some(format!(
+                    "I've stopped working on this because the session reached its spending cap \
+                    of ${:.2} (${:.2} spent). Raise the cap or start a new session to continue.",
+                    max_cost, self.session_cost
+                ));
+            }
+        }
+
+        let budget = &self.budget;
+        let reason = match (budget.max_turns, budget.max_tokens, budget.max_duration) {
+            (Some(max), _, _) if self.turns >= max => {
+                format!("the limit of {} tool calling turns", max)
+            }
+            (_, Some(max), _) if self.tokens >= max => {
+                format!("the token limit ({} used of {})", self.tokens, max)
+            }
+            (_, _, Some(max)) if elapsed >= max => {
+                format!("the time limit of {}s", max.as_secs())
+            }
+            _ => return None,
+        };
+
+        Some(format!(
+            "I've stopped working on this because the reply reached {}. \
+            Send another message if you would like me to continue.",
+            reason
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlimited_budget() {
+        let mut tracker = BudgetTracker::new(ReplyBudget::default());
+        for _ in 0..100 {
+            tracker.record_turn();
+            tracker.record_usage(&Usage::new(Some(10_000), Some(1_000), Some(11_000)));
+        }
+        assert!(tracker.exceeded_after(Duration::from_secs(3600)).is_none());
+    }
+
+    #[test]
+    fn test_max_turns() {
+        let mut tracker = BudgetTracker::new(ReplyBudget::default().with_max_turns(Some(2)));
+        tracker.record_turn();
+        assert!(tracker.exceeded().is_none());
+        tracker.record_turn();
+        let message = tracker.exceeded().unwrap();
+        assert!(message.contains("2 tool calling turns"));
+    }
+
+    #[test]
+    fn test_max_tokens() {
+        let mut tracker = BudgetTracker::new(ReplyBudget::default().with_max_tokens(Some(100)));
+        tracker.record_usage(&Usage::new(Some(40), Some(10), Some(50)));
+        assert!(tracker.exceeded().is_none());
+
+        // Falls back to the total when the breakdown is missing
+        tracker.record_usage(&Usage::new(None, None, Some(60)));
+        let message = tracker.exceeded().unwrap();
+        assert!(message.contains("110 used of 100"));
+    }
+
+    #[test]
+    fn test_max_duration() {
+        let tracker = BudgetTracker::new(
+            ReplyBudget::default().with_max_duration(Some(Duration::from_secs(30))),
+        );
+        assert!(tracker.exceeded_after(Duration::from_secs(29)).is_none());
+        let message = tracker.exceeded_after(Duration::from_secs(30)).unwrap();
+        assert!(message.contains("time limit of 30s"));
+    }
+
+    #[test]
+    fn test_max_cost() {
+        let mut tracker = BudgetTracker::new(ReplyBudget::default().with_max_cost(Some(5.0)));
+        tracker.record_cost(4.99);
+        assert!(tracker.exceeded().is_none());
+        tracker.record_cost(5.01);
+        let message = tracker.exceeded().unwrap();
+        assert!(message.contains("spending cap of $5.00 ($5.01 spent)"));
+    }
+}
@@ -0,0 +1,150 @@
+//! Learned corrections for token estimates
+//!
+//! `TokenCounter` only has a few tokenizers, so its counts for other models are approximate.
+//! Comparing the estimate for each request with the input tokens the provider reports gives a
+//! ratio per model, which is kept between sessions so the agent can truncate before the
+//! provider rejects a request for being too long.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use etcetera::{choose_app_strategy, AppStrategy};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Estimates this small are mostly per-request overhead which the counter doesn't see
+const MIN_ESTIMATE: usize = 100;
+/// Samples outside this range come from a mismatched request rather than another tokenizer
+const MIN_SAMPLE: f32 = 0.25;
+const MAX_SAMPLE: f32 = 4.0;
+/// The least weight a new sample gets, so the ratio keeps following changes in the requests
+const MIN_SAMPLE_WEIGHT: f32 = 0.2;
+
+/// The ratio of reported to estimated input tokens for one model
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Calibration {
+    pub ratio: f32,
+    pub samples: u32,
+}
+
+/// Correction ratios by model name, saved to disk after every update
+#[derive(Debug, Default)]
+pub struct TokenCalibration {
+    /// Where the ratios are saved, none to keep them in memory only
+    path: Option<PathBuf>,
+    models: Mutex<HashMap<String, Calibration>>,
+}
+
+impl TokenCalibration {
+    /// Load the saved ratios from the goose data directory
+    ///
+    /// - macOS:   ~/Library/Application Support/goose/token_calibration.json
+    /// - Linux:   ~/.local/share/goose/token_calibration.json
+    /// - Windows: ~\AppData\Roaming\Block\goose\data\token_calibration.json
+    pub fn load() -> Self {
+        match choose_app_strategy(crate::config::APP_STRATEGY.clone()) {
+            Ok(strategy) => Self::at(strategy.in_data_dir("token_calibration.json")),
+            Err(e) => {
+                warn!("Token calibration will not be saved: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Load the ratios saved at the path, starting over if there are none or they are unreadable
+    pub fn at(path: PathBuf) -> Self {
+        let models = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        Self {
+            path: Some(path),
+            models: Mutex::new(models),
+        }
+    }
+
+    pub fn get(&self, model: &str) -> Option<Calibration> {
+        self.models.lock().unwrap().get(model).copied()
+    }
+
+    /// What to multiply an estimate for the model by, 1 until it has been calibrated
+    pub fn ratio(&self, model: &str) -> f32 {
+        self.get(model).map_or(1.0, |calibration| calibration.ratio)
+    }
+
+    /// Learn from a request which was estimated at `estimated` tokens and reported as `actual`
+    pub fn record(&self, model: &str, estimated: usize, actual: usize) {
+        if estimated < MIN_ESTIMATE || actual == 0 {
+            return;
+        }
+        let sample = actual as f32 / estimated as f32;
+        if !(MIN_SAMPLE..=MAX_SAMPLE).contains(&sample) {
+            warn!(
+                "Ignoring token estimate of {} for {} which was reported as {}",
+                estimated, model, actual
+            );
+            return;
+        }
+
+        let mut models = self.models.lock().unwrap();
+        let calibration = models.entry(model.to_string()).or_insert(Calibration {
+            ratio: sample,
+            samples: 0,
+        });
+        // An average of the samples so far, until it becomes a moving average
+        let weight = (1.0 / (calibration.samples + 1) as f32).max(MIN_SAMPLE_WEIGHT);
+        calibration.ratio += weight * (sample - calibration.ratio);
+        calibration.samples += 1;
+
+        if let Err(e) = self.save(&models) {
+            warn!("Failed to save the token calibration: {}", e);
+        }
+    }
+
+    fn save(&self, models: &HashMap<String, Calibration>) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(models)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_averages_samples() {
+        let calibration = TokenCalibration::default();
+        assert_eq!(calibration.ratio("claude"), 1.0);
+
+        calibration.record("claude", 1000, 1200);
+        calibration.record("claude", 1000, 1100);
+        let learned = calibration.get("claude").unwrap();
+        assert!((learned.ratio - 1.15).abs() < 1e-4, "{:?}", learned);
+        assert_eq!(learned.samples, 2);
+
+        // Tiny requests and implausible reports are ignored
+        calibration.record("claude", 10, 40);
+        calibration.record("claude", 1000, 10_000);
+        assert_eq!(calibration.get("claude"), Some(learned));
+        assert_eq!(calibration.ratio("gpt-4o"), 1.0);
+    }
+
+    #[test]
+    fn test_ratios_persist() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("goose/token_calibration.json");
+
+        TokenCalibration::at(path.clone()).record("claude", 1000, 1200);
+        let reloaded = TokenCalibration::at(path);
+        assert!((reloaded.ratio("claude") - 1.2).abs() < 1e-4);
+        Ok(())
+    }
+}
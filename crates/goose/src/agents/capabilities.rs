@@ -6,11 +6,17 @@ use std::sync::Arc;
 use std::sync::LazyLock;
 use std::time::Duration;
 use tokio::sync::Mutex;
-use tracing::{debug, instrument};
+use tracing::{debug, instrument, warn};
 
 use super::extension::{ExtensionConfig, ExtensionError, ExtensionInfo, ExtensionResult};
+use super::model_router::ModelRouter;
+use crate::message::{Message, MessageContent};
+use crate::model::ModelConfig;
 use crate::prompt_template::{load_prompt, load_prompt_file};
-use crate::providers::base::{Provider, ProviderUsage};
+use crate::providers::base::{CompletionChunk, CompletionStream, Provider, ProviderUsage};
+use crate::providers::errors::ProviderError;
+use crate::providers::pricing;
+use crate::providers::utils::system_prompt_as_user_text;
 use mcp_client::client::{ClientCapabilities, ClientInfo, McpClient, McpClientTrait};
 use mcp_client::transport::{SseTransport, StdioTransport, Transport};
 use mcp_core::{Content, Tool, ToolCall, ToolError, ToolResult};
@@ -29,6 +35,7 @@ pub struct Capabilities {
     instructions: HashMap<String, String>,
     resource_capable_extensions: HashSet<String>,
     provider: Box<dyn Provider>,
+    model_router: Option<ModelRouter>,
     provider_usage: Mutex<Vec<ProviderUsage>>,
     system_prompt_override: Option<String>,
     system_prompt_extensions: Vec<String>,
@@ -81,6 +88,14 @@ fn normalize(input: String) -> String {
     result.to_lowercase()
 }
 
+/// Sum counts which not every response reports, keeping None when neither did
+fn sum_optional<T: std::ops::Add<Output = T> + Default>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (None, None) => None,
+        (a, b) => Some(a.unwrap_or_default() + b.unwrap_or_default()),
+    }
+}
+
 impl Capabilities {
     /// Create a new Capabilities with the specified provider
     pub fn new(provider: Box<dyn Provider>) -> Self {
@@ -89,6 +104,7 @@ impl Capabilities {
             instructions: HashMap::new(),
             resource_capable_extensions: HashSet::new(),
             provider,
+            model_router: None,
             provider_usage: Mutex::new(Vec::new()),
             system_prompt_override: None,
             system_prompt_extensions: Vec::new(),
@@ -183,12 +199,89 @@ impl Capabilities {
         &*self.provider
     }
 
+    /// Route each turn to one of several models, or only use the provider with None
+    pub fn set_model_router(&mut self, router: Option<ModelRouter>) {
+        self.model_router = router;
+    }
+
+    pub fn model_router(&self) -> Option<&ModelRouter> {
+        self.model_router.as_ref()
+    }
+
+    /// The config of the model which will answer the next turn of the conversation
+    pub fn turn_model_config(&self, messages: &[Message]) -> ModelConfig {
+        self.routed_provider(messages).get_model_config()
+    }
+
+    /// The provider for the next turn of the conversation
+    fn routed_provider(&self, messages: &[Message]) -> &dyn Provider {
+        self.model_router
+            .as_ref()
+            .and_then(|router| router.select(messages))
+            .unwrap_or(&*self.provider)
+    }
+
+    /// Stream a completion from the provider, first adapting the request to what the model can
+    /// do so that it fails here with a clear reason rather than as a rejected API call
+    pub async fn stream_completion(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<CompletionStream, ProviderError> {
+        let provider = self.routed_provider(messages);
+        let model_config = provider.get_model_config();
+        let (system, messages, tools) = adapt_to_model(&model_config, system, messages, tools);
+
+        if model_config.capabilities.streaming {
+            provider.stream(&system, &messages, &tools).await
+        } else {
+            let (message, usage) = provider.complete(&system, &messages, &tools).await?;
+            Ok(Box::pin(futures::stream::once(async move {
+                Ok(CompletionChunk::Done(message, usage))
+            })))
+        }
+    }
+
+    /// Complete a final answer as JSON matching the schema, adapting the request to the model
+    /// as for any other completion
+    pub async fn complete_structured(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let provider = self.routed_provider(messages);
+        let model_config = provider.get_model_config();
+        let (system, messages, _) = adapt_to_model(&model_config, system, messages, &[]);
+        provider
+            .complete_structured(&system, &messages, schema)
+            .await
+    }
+
     /// Record provider usage
     // TODO consider moving this off to the provider or as a form of logging
     pub async fn record_usage(&self, usage: ProviderUsage) {
+        let usage = match usage.cost {
+            Some(_) => usage,
+            None => {
+                let cost = pricing::registry().cost(&usage);
+                usage.with_cost(cost)
+            }
+        };
         self.provider_usage.lock().await.push(usage);
     }
 
+    /// Total cost in US dollars of the provider calls with known pricing
+    pub async fn total_cost(&self) -> f64 {
+        self.provider_usage
+            .lock()
+            .await
+            .iter()
+            .filter_map(|usage| usage.cost)
+            .sum()
+    }
+
     /// Get aggregated usage statistics
     pub async fn remove_extension(&mut self, name: &str) -> ExtensionResult<()> {
         let sanitized_name = normalize(name.to_string());
@@ -205,11 +298,11 @@ impl Capabilities {
 
     pub async fn get_usage(&self) -> Vec<ProviderUsage> {
         let provider_usage = self.provider_usage.lock().await.clone();
-        let mut usage_map: HashMap<String, ProviderUsage> = HashMap::new();
+        let mut usage_map: HashMap<(Option<String>, String), ProviderUsage> = HashMap::new();
 
         provider_usage.iter().for_each(|usage| {
             usage_map
-                .entry(usage.model.clone())
+                .entry((usage.provider.clone(), usage.model.clone()))
                 .and_modify(|e| {
                     e.usage.input_tokens = Some(
                         e.usage.input_tokens.unwrap_or(0) + usage.usage.input_tokens.unwrap_or(0),
@@ -220,6 +313,16 @@ impl Capabilities {
                     e.usage.total_tokens = Some(
                         e.usage.total_tokens.unwrap_or(0) + usage.usage.total_tokens.unwrap_or(0),
                     );
+                    e.usage.cache_creation_input_tokens = sum_optional(
+                        e.usage.cache_creation_input_tokens,
+                        usage.usage.cache_creation_input_tokens,
+                    );
+                    e.usage.cache_read_input_tokens = sum_optional(
+                        e.usage.cache_read_input_tokens,
+                        usage.usage.cache_read_input_tokens,
+                    );
+                    e.cost = sum_optional(e.cost, usage.cost);
+                    e.response_cache = sum_optional(e.response_cache, usage.response_cache);
                 })
                 .or_insert_with(|| usage.clone());
         });
@@ -546,13 +649,72 @@ impl Capabilities {
     }
 }
 
+/// Rewrite a request to fit the model's capabilities: tools are withheld from models which can't
+/// call them, images are replaced with a note for models which can't see them, and the system
+/// prompt is moved into the first user message for models which don't accept one
+fn adapt_to_model(
+    model_config: &ModelConfig,
+    system: &str,
+    messages: &[Message],
+    tools: &[Tool],
+) -> (String, Vec<Message>, Vec<Tool>) {
+    let capabilities = &model_config.capabilities;
+    let model = &model_config.model_name;
+    let mut system = system.to_string();
+    let mut messages = messages.to_vec();
+    let mut tools = tools.to_vec();
+
+    if !capabilities.tool_calling && !tools.is_empty() {
+        warn!(
+            "{} does not support tool calling, sending the request without tools",
+            model
+        );
+        tools.clear();
+        system.push_str(&format!(
+            "\n\nNo tools are available because {} does not support tool calling. If a request \
+            needs tools, tell the user to switch to a model which supports them.",
+            model
+        ));
+    }
+
+    if !capabilities.images {
+        let note = format!(
+            "[An image was removed because {} does not accept images]",
+            model
+        );
+        for message in &mut messages {
+            for content in &mut message.content {
+                match content {
+                    MessageContent::Image(_) => *content = MessageContent::text(note.clone()),
+                    MessageContent::ToolResponse(response) => {
+                        if let Ok(result) = &mut response.tool_result {
+                            for item in result.iter_mut() {
+                                if matches!(item, Content::Image(_)) {
+                                    *item = Content::text(note.clone());
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    if !capabilities.system_prompt && !system.is_empty() {
+        messages = system_prompt_as_user_text(&system, &messages);
+        system = String::new();
+    }
+
+    (system, messages, tools)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::message::Message;
-    use crate::model::ModelConfig;
-    use crate::providers::base::{Provider, ProviderMetadata, ProviderUsage, Usage};
-    use crate::providers::errors::ProviderError;
+    use crate::model::ModelCapabilities;
+    use crate::providers::base::Usage;
+    use crate::providers::mock::MockProvider;
     use mcp_client::client::Error;
     use mcp_client::client::McpClientTrait;
     use mcp_core::protocol::{
@@ -560,35 +722,6 @@ mod tests {
     };
     use serde_json::json;
 
-    // Mock Provider implementation for testing
-    #[derive(Clone)]
-    struct MockProvider {
-        model_config: ModelConfig,
-    }
-
-    #[async_trait::async_trait]
-    impl Provider for MockProvider {
-        fn metadata() -> ProviderMetadata {
-            ProviderMetadata::empty()
-        }
-
-        fn get_model_config(&self) -> ModelConfig {
-            self.model_config.clone()
-        }
-
-        async fn complete(
-            &self,
-            _system: &str,
-            _messages: &[Message],
-            _tools: &[Tool],
-        ) -> anyhow::Result<(Message, ProviderUsage), ProviderError> {
-            Ok((
-                Message::assistant().with_text("Mock response"),
-                ProviderUsage::new("mock".to_string(), Usage::default()),
-            ))
-        }
-    }
-
     struct MockClient {}
 
     #[async_trait::async_trait]
@@ -632,9 +765,9 @@ mod tests {
         let mock_model_config =
             ModelConfig::new("test-model".to_string()).with_context_limit(200_000.into());
 
-        let mut capabilities = Capabilities::new(Box::new(MockProvider {
-            model_config: mock_model_config,
-        }));
+        let mut capabilities = Capabilities::new(Box::new(
+            MockProvider::new("test-model").with_model_config(mock_model_config),
+        ));
 
         // Add some mock clients
         capabilities.clients.insert(
@@ -681,9 +814,9 @@ mod tests {
         let mock_model_config =
             ModelConfig::new("test-model".to_string()).with_context_limit(200_000.into());
 
-        let mut capabilities = Capabilities::new(Box::new(MockProvider {
-            model_config: mock_model_config,
-        }));
+        let mut capabilities = Capabilities::new(Box::new(
+            MockProvider::new("test-model").with_model_config(mock_model_config),
+        ));
 
         // Add some mock clients
         capabilities.clients.insert(
@@ -766,4 +899,77 @@ mod tests {
         let result = capabilities.dispatch_tool_call(invalid_tool_call).await;
         assert!(matches!(result.err().unwrap(), ToolError::NotFound(_)));
     }
+
+    #[tokio::test]
+    async fn test_get_usage_sums_cache_tokens() {
+        let capabilities = Capabilities::new(Box::new(MockProvider::new("test-model")));
+
+        let usage = Usage::new(Some(100), Some(10), Some(110));
+        capabilities
+            .record_usage(ProviderUsage::new(
+                "mock".to_string(),
+                usage.clone().with_cache_tokens(Some(80), None),
+            ))
+            .await;
+        capabilities
+            .record_usage(ProviderUsage::new(
+                "mock".to_string(),
+                usage.clone().with_cache_tokens(None, Some(80)),
+            ))
+            .await;
+        capabilities
+            .record_usage(ProviderUsage::new("other".to_string(), usage))
+            .await;
+
+        let totals = capabilities.get_usage().await;
+        let mock = totals.iter().find(|u| u.model == "mock").unwrap();
+        assert_eq!(mock.usage.input_tokens, Some(200));
+        assert_eq!(mock.usage.cache_creation_input_tokens, Some(80));
+        assert_eq!(mock.usage.cache_read_input_tokens, Some(80));
+        let other = totals.iter().find(|u| u.model == "other").unwrap();
+        assert_eq!(other.usage.cache_read_input_tokens, None);
+    }
+
+    #[test]
+    fn test_adapt_to_model() {
+        let model_config =
+            ModelConfig::new("text-only".to_string()).with_capabilities(ModelCapabilities {
+                tool_calling: false,
+                images: false,
+                system_prompt: false,
+                ..ModelCapabilities::default()
+            });
+        let messages = vec![Message::user()
+            .with_text("What is in this picture?")
+            .with_image("abcd", "image/png")];
+        let tools = vec![Tool::new("test__tool", "A tool", json!({}))];
+
+        let (system, messages, tools) =
+            adapt_to_model(&model_config, "You are goose", &messages, &tools);
+
+        assert!(system.is_empty());
+        assert!(tools.is_empty());
+        let content = &messages[0].content;
+        assert_eq!(content.len(), 2);
+        let prompt = content[0].as_text().unwrap();
+        assert!(prompt.starts_with("You are goose"));
+        assert!(prompt.contains("does not support tool calling"));
+        assert!(prompt.ends_with("What is in this picture?"));
+        assert!(content[1]
+            .as_text()
+            .unwrap()
+            .contains("does not accept images"));
+
+        // Requests which fit the model are left alone
+        let model_config = ModelConfig::new("gpt-4o".to_string());
+        let (system, messages, tools) = adapt_to_model(
+            &model_config,
+            "You are goose",
+            &[Message::user().with_image("abcd", "image/png")],
+            &[Tool::new("test__tool", "A tool", json!({}))],
+        );
+        assert_eq!(system, "You are goose");
+        assert_eq!(tools.len(), 1);
+        assert!(matches!(messages[0].content[0], MessageContent::Image(_)));
+    }
 }
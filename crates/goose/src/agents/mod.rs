@@ -1,11 +1,21 @@
 mod agent;
+mod budget;
+mod calibration;
 mod capabilities;
 pub mod extension;
 mod factory;
+mod model_router;
+mod output_schema;
 mod reference;
+mod tool_router;
 mod truncate;
 
-pub use agent::Agent;
+pub use agent::{complete_messages, Agent, AgentEvent};
+pub use budget::ReplyBudget;
+pub use calibration::{Calibration, TokenCalibration};
 pub use capabilities::Capabilities;
 pub use extension::ExtensionConfig;
 pub use factory::{register_agent, AgentFactory};
+pub use model_router::{ModelRoute, ModelRouter, ModelRouterConfig, RoutingRule, DEFAULT_ROUTE};
+pub use output_schema::{OutputSchema, MAX_OUTPUT_ATTEMPTS};
+pub use tool_router::ToolRouter;
@@ -0,0 +1,385 @@
+//! Per-turn model selection, so routine turns can go to a cheap fast model while harder ones
+//! go to a stronger model
+//!
+//! The agent's own provider is the route named `default`, and further models are configured
+//! under GOOSE_MODEL_ROUTER along with rules which are checked in order before every provider
+//! call. The first rule whose conditions all hold picks the model, otherwise the default route
+//! is used:
+//!
+//! ```yaml
+//! GOOSE_MODEL_ROUTER:
+//!   models:
+//!     fast:
+//!       provider: openai
+//!       model: gpt-4o-mini
+//!   default: fast
+//!   rules:
+//!     - model: default      # the configured GOOSE_PROVIDER and GOOSE_MODEL
+//!       after_tool_error: true
+//!     - model: default
+//!       min_chars: 2000
+//!     - model: default
+//!       tools: [developer__text_editor]
+//! ```
+//!
+//! A model chosen with `set_override` is used for every turn until the override is cleared.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::config::{Config, ConfigError};
+use crate::message::{Message, MessageContent};
+use crate::model::ModelConfig;
+use crate::providers::base::Provider;
+use mcp_core::role::Role;
+
+/// The name of the route to the agent's own provider
+pub const DEFAULT_ROUTE: &str = "default";
+
+/// A model the router can send turns to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelRoute {
+    pub provider: String,
+    /// Model for this provider, defaults to the provider's default model
+    pub model: Option<String>,
+}
+
+/// Conditions for sending a turn to a model, every condition which is set has to hold
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoutingRule {
+    /// The route to use when the rule matches
+    pub model: String,
+    /// The user's latest message is at least this many characters long
+    #[serde(default)]
+    pub min_chars: Option<usize>,
+    /// The user's latest message is at most this many characters long
+    #[serde(default)]
+    pub max_chars: Option<usize>,
+    /// A tool call in the previous turn failed
+    #[serde(default)]
+    pub after_tool_error: bool,
+    /// The previous turn called one of these tools, given by full tool name or extension name
+    #[serde(default)]
+    pub tools: Vec<String>,
+}
+
+/// The GOOSE_MODEL_ROUTER config section
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelRouterConfig {
+    #[serde(default)]
+    pub models: BTreeMap<String, ModelRoute>,
+    /// The route used when no rule matches, the agent's own provider if not set
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub rules: Vec<RoutingRule>,
+}
+
+type RoutedProvider = Box<dyn Provider + Send + Sync>;
+
+/// Picks which model answers each turn
+pub struct ModelRouter {
+    models: Vec<(String, RoutedProvider)>,
+    default: String,
+    rules: Vec<RoutingRule>,
+    override_model: Mutex<Option<String>>,
+}
+
+impl ModelRouter {
+    pub fn new(
+        models: Vec<(String, RoutedProvider)>,
+        default: Option<String>,
+        rules: Vec<RoutingRule>,
+    ) -> Result<Self> {
+        if models.iter().any(|(name, _)| name == DEFAULT_ROUTE) {
+            bail!(
+                "The model name {} is reserved for the configured provider",
+                DEFAULT_ROUTE
+            );
+        }
+        let router = Self {
+            models,
+            default: default.unwrap_or_else(|| DEFAULT_ROUTE.to_string()),
+            rules,
+            override_model: Mutex::new(None),
+        };
+        for name in std::iter::once(&router.default).chain(router.rules.iter().map(|r| &r.model)) {
+            router.check_route(name)?;
+        }
+        Ok(router)
+    }
+
+    /// Build the router from GOOSE_MODEL_ROUTER, routing is only enabled if it is set. A
+    /// setting which can't be read is an error rather than turning routing off
+    ///
+    /// Each model is created with the generation settings of `model`, and passed through `wrap`
+    /// so it can be given the same retries and caching as the agent's own provider.
+    pub fn from_config(
+        model: &ModelConfig,
+        wrap: impl Fn(RoutedProvider) -> Result<RoutedProvider>,
+    ) -> Result<Option<Self>> {
+        let config = match Config::global().get::<ModelRouterConfig>("GOOSE_MODEL_ROUTER") {
+            Ok(config) => config,
+            Err(ConfigError::NotFound(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let models = config
+            .models
+            .into_iter()
+            .map(|(name, route)| {
+                let model_name = match route.model {
+                    Some(model_name) => model_name,
+                    None => crate::providers::default_model(&route.provider)?,
+                };
+                let model_config = ModelConfig::new(model_name).with_generation_settings_of(model);
+                let provider = crate::providers::create(&route.provider, model_config)?;
+                Ok((name, wrap(provider)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Self::new(models, config.default, config.rules).map(Some)
+    }
+
+    fn check_route(&self, name: &str) -> Result<()> {
+        if name != DEFAULT_ROUTE && !self.models.iter().any(|(model, _)| model == name) {
+            bail!(
+                "Unknown model {}, expected one of: {}",
+                name,
+                self.route_names().join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// The names of every route, starting with the agent's own provider
+    pub fn route_names(&self) -> Vec<String> {
+        std::iter::once(DEFAULT_ROUTE.to_string())
+            .chain(self.models.iter().map(|(name, _)| name.clone()))
+            .collect()
+    }
+
+    /// Use the named model for every turn, or go back to the rules with None
+    pub fn set_override(&self, name: Option<String>) -> Result<()> {
+        if let Some(name) = &name {
+            self.check_route(name)?;
+        }
+        *self.override_model.lock().unwrap() = name;
+        Ok(())
+    }
+
+    /// The name of the route for the next turn of the conversation
+    pub fn route(&self, messages: &[Message]) -> String {
+        if let Some(name) = self.override_model.lock().unwrap().clone() {
+            return name;
+        }
+        let turn = Turn::of(messages);
+        self.rules
+            .iter()
+            .find(|rule| turn.matches(rule))
+            .map_or_else(|| self.default.clone(), |rule| rule.model.clone())
+    }
+
+    /// The provider for the next turn, None when that is the agent's own provider
+    pub fn select(&self, messages: &[Message]) -> Option<&dyn Provider> {
+        let name = self.route(messages);
+        debug!("Routing the turn to {}", name);
+        self.models
+            .iter()
+            .find(|(model, _)| *model == name)
+            .map(|(_, provider)| provider.as_ref() as &dyn Provider)
+    }
+}
+
+/// What the rules look at in a conversation
+struct Turn<'a> {
+    request_chars: usize,
+    tool_failed: bool,
+    tools_called: Vec<&'a str>,
+}
+
+impl<'a> Turn<'a> {
+    fn of(messages: &'a [Message]) -> Self {
+        // The user's request, rather than the tool responses which follow it
+        let request_chars = messages
+            .iter()
+            .rev()
+            .filter(|message| message.role == Role::User)
+            .map(|message| message.as_concat_text())
+            .find(|text| !text.is_empty())
+            .map_or(0, |text| text.chars().count());
+
+        let tool_failed = messages.last().is_some_and(|message| {
+            message.content.iter().any(|content| {
+                matches!(content, MessageContent::ToolResponse(response) if response.tool_result.is_err())
+            })
+        });
+
+        // Tool calls are always answered, so the call is just before the latest message
+        let tools_called = messages
+            .iter()
+            .rev()
+            .nth(1)
+            .map(|message| {
+                message
+                    .content
+                    .iter()
+                    .filter_map(|content| content.as_tool_request())
+                    .filter_map(|request| request.tool_call.as_ref().ok())
+                    .map(|call| call.name.as_str())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            request_chars,
+            tool_failed,
+            tools_called,
+        }
+    }
+
+    fn matches(&self, rule: &RoutingRule) -> bool {
+        let called = |wanted: &String| {
+            self.tools_called.iter().any(|name| {
+                *name == wanted
+                    || name
+                        .split_once("__")
+                        .is_some_and(|(extension, _)| extension == wanted)
+            })
+        };
+        rule.min_chars.is_none_or(|min| self.request_chars >= min)
+            && rule.max_chars.is_none_or(|max| self.request_chars <= max)
+            && (!rule.after_tool_error || self.tool_failed)
+            && (rule.tools.is_empty() || rule.tools.iter().any(called))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::ToolRequest;
+    use crate::providers::mock::MockProvider;
+    use mcp_core::tool::ToolCall;
+    use mcp_core::ToolError;
+    use serde_json::json;
+
+    fn router(rules: Vec<RoutingRule>) -> ModelRouter {
+        let models: Vec<(String, RoutedProvider)> = vec![
+            ("fast".to_string(), Box::new(MockProvider::new("fast"))),
+            ("strong".to_string(), Box::new(MockProvider::new("strong"))),
+        ];
+        ModelRouter::new(models, Some("fast".to_string()), rules).unwrap()
+    }
+
+    fn rule(model: &str) -> RoutingRule {
+        RoutingRule {
+            model: model.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn after_tool_call(
+        name: &str,
+        result: Result<Vec<mcp_core::Content>, ToolError>,
+    ) -> Vec<Message> {
+        vec![
+            Message::user().with_text("Fix the bug"),
+            Message::assistant().with_content(MessageContent::ToolRequest(ToolRequest {
+                id: "1".to_string(),
+                tool_call: Ok(ToolCall::new(name, json!({}))),
+            })),
+            Message::user().with_tool_response("1", result),
+        ]
+    }
+
+    #[test]
+    fn test_route_by_rules() {
+        let router = router(vec![
+            RoutingRule {
+                after_tool_error: true,
+                ..rule("strong")
+            },
+            RoutingRule {
+                min_chars: Some(20),
+                ..rule("strong")
+            },
+            RoutingRule {
+                tools: vec!["developer".to_string()],
+                ..rule(DEFAULT_ROUTE)
+            },
+        ]);
+
+        assert_eq!(router.route(&[Message::user().with_text("Hi")]), "fast");
+        assert_eq!(
+            router.route(&[Message::user().with_text("Refactor the whole parser module")]),
+            "strong"
+        );
+        assert_eq!(
+            router.route(&after_tool_call(
+                "developer__shell",
+                Err(ToolError::ExecutionError("failed".to_string()))
+            )),
+            "strong"
+        );
+        assert_eq!(
+            router.route(&after_tool_call("developer__shell", Ok(vec![]))),
+            DEFAULT_ROUTE
+        );
+        assert_eq!(
+            router.route(&after_tool_call("github__search", Ok(vec![]))),
+            "fast"
+        );
+    }
+
+    #[test]
+    fn test_override() {
+        let router = router(vec![]);
+        let messages = [Message::user().with_text("Hi")];
+        assert!(router.select(&messages).is_some());
+
+        router
+            .set_override(Some(DEFAULT_ROUTE.to_string()))
+            .unwrap();
+        assert!(router.select(&messages).is_none());
+        assert!(router.set_override(Some("huge".to_string())).is_err());
+
+        router.set_override(None).unwrap();
+        assert_eq!(router.route(&messages), "fast");
+    }
+
+    #[test]
+    fn test_unknown_models_are_rejected() {
+        let error = ModelRouter::new(vec![], None, vec![rule("strong")])
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Unknown model strong, expected one of: default"
+        );
+
+        let models: Vec<(String, RoutedProvider)> =
+            vec![(DEFAULT_ROUTE.to_string(), Box::new(MockProvider::new("x")))];
+        assert!(ModelRouter::new(models, None, vec![]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_config_is_an_error() {
+        // A misspelled rule condition would otherwise be dropped and the rule always match
+        std::env::set_var(
+            "GOOSE_MODEL_ROUTER",
+            r#"{"models": {"fast": {"provider": "ollama"}}, "rules": [{"model": "fast", "max_char": 200}]}"#,
+        );
+        let result = ModelRouter::from_config(&ModelConfig::new("x".to_string()), Ok);
+        std::env::remove_var("GOOSE_MODEL_ROUTER");
+        let error = result
+            .err()
+            .expect("the config should be rejected")
+            .to_string();
+        assert!(error.contains("max_char"), "{}", error);
+    }
+}
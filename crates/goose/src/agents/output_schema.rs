@@ -0,0 +1,217 @@
+//! Structured final output: a JSON Schema which the agent's final answer must match
+//!
+//! Once the model stops calling tools, the agent asks it for the answer again in the
+//! provider's structured output mode and validates the result, feeding any violations back to
+//! the model for another attempt.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use jsonschema::JSONSchema;
+use serde_json::Value;
+use tracing::warn;
+
+use super::capabilities::Capabilities;
+use crate::message::Message;
+
+/// How many times the model may answer before an invalid output is an error
+pub const MAX_OUTPUT_ATTEMPTS: usize = 3;
+
+/// A compiled JSON Schema for the final output
+#[derive(Clone)]
+pub struct OutputSchema {
+    schema: Value,
+    validator: Arc<JSONSchema>,
+}
+
+impl std::fmt::Debug for OutputSchema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OutputSchema")
+            .field("schema", &self.schema)
+            .finish()
+    }
+}
+
+impl OutputSchema {
+    pub fn new(schema: Value) -> Result<Self> {
+        let validator =
+            JSONSchema::compile(&schema).map_err(|e| anyhow!("Invalid JSON Schema: {}", e))?;
+        Ok(Self {
+            validator: Arc::new(validator),
+            schema,
+        })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let schema = serde_json::from_str(&text)
+            .with_context(|| format!("{} is not valid JSON", path.display()))?;
+        Self::new(schema).with_context(|| format!("Failed to load {}", path.display()))
+    }
+
+    pub fn schema(&self) -> &Value {
+        &self.schema
+    }
+
+    /// Parse the model's output and check it against the schema, describing every violation
+    ///
+    /// A code fence around the JSON is tolerated, since models add one even when asked not to
+    pub fn validate(&self, output: &str) -> Result<Value, String> {
+        let output = strip_code_fence(output);
+        let value: Value =
+            serde_json::from_str(output).map_err(|e| format!("The output is not JSON: {}", e))?;
+        if let Err(errors) = self.validator.validate(&value) {
+            let errors: Vec<String> = errors
+                .map(|error| {
+                    let path = error.instance_path.to_string();
+                    if path.is_empty() {
+                        error.to_string()
+                    } else {
+                        format!("{}: {}", path, error)
+                    }
+                })
+                .collect();
+            return Err(errors.join("\n"));
+        }
+        Ok(value)
+    }
+}
+
+fn strip_code_fence(output: &str) -> &str {
+    let trimmed = output.trim();
+    let Some(rest) = trimmed.strip_prefix("```") else {
+        return trimmed;
+    };
+    // Skip the language tag, if any
+    let rest = rest.split_once('\n').map_or("", |(_, body)| body);
+    rest.strip_suffix("```").unwrap_or(rest).trim()
+}
+
+/// Ask the model for its final answer as JSON matching the schema, retrying with the validation
+/// errors until it matches or `MAX_OUTPUT_ATTEMPTS` is reached
+pub(crate) async fn complete_output(
+    capabilities: &Capabilities,
+    schema: &OutputSchema,
+    system: &str,
+    messages: &[Message],
+) -> Result<Value> {
+    let mut messages = messages.to_vec();
+    // End on a user turn, since some APIs take a trailing assistant message as the start of
+    // the answer
+    messages.push(
+        Message::user()
+            .with_text("Now give your final answer as JSON matching the required JSON Schema."),
+    );
+    let mut errors = String::new();
+    for attempt in 1..=MAX_OUTPUT_ATTEMPTS {
+        let (response, usage) = capabilities
+            .complete_structured(system, &messages, schema.schema())
+            .await?;
+        capabilities.record_usage(usage).await;
+
+        match schema.validate(&response.as_concat_text()) {
+            Ok(value) => return Ok(value),
+            Err(violations) => {
+                warn!(
+                    "Output attempt {}/{} does not match the schema: {}",
+                    attempt, MAX_OUTPUT_ATTEMPTS, violations
+                );
+                messages.push(response);
+                messages.push(Message::user().with_text(format!(
+                    "That output does not match the required JSON Schema:\n{}\n\nRespond again \
+                    with only the corrected JSON.",
+                    violations
+                )));
+                errors = violations;
+            }
+        }
+    }
+    bail!(
+        "The final output did not match the output schema after {} attempts:\n{}",
+        MAX_OUTPUT_ATTEMPTS,
+        errors
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::mock::MockProvider;
+    use serde_json::json;
+
+    fn answer_schema() -> OutputSchema {
+        OutputSchema::new(json!({
+            "type": "object",
+            "properties": {"answer": {"type": "integer"}},
+            "required": ["answer"]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate() {
+        let schema = answer_schema();
+        assert_eq!(
+            schema.validate(r#"{"answer": 4}"#),
+            Ok(json!({"answer": 4}))
+        );
+        assert_eq!(
+            schema.validate("```json\n{\"answer\": 4}\n```"),
+            Ok(json!({"answer": 4}))
+        );
+
+        let error = schema.validate(r#"{"answer": "four"}"#).unwrap_err();
+        assert!(error.starts_with("/answer: "), "{}", error);
+        assert!(schema.validate("{}").unwrap_err().contains("answer"));
+        assert!(schema
+            .validate("four")
+            .unwrap_err()
+            .starts_with("The output is not JSON"));
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        let error = OutputSchema::new(json!({"type": "nonsense"})).unwrap_err();
+        assert!(error.to_string().starts_with("Invalid JSON Schema"));
+    }
+
+    #[tokio::test]
+    async fn test_complete_output_retries_with_violations() -> Result<()> {
+        let provider = MockProvider::new("mock")
+            .with_text(r#"{"answer": "four"}"#)
+            .with_text(r#"{"answer": 4}"#);
+        let requests = provider.requests();
+        let capabilities = Capabilities::new(Box::new(provider));
+        let messages = vec![Message::user().with_text("What is 2 + 2?")];
+
+        let value = complete_output(&capabilities, &answer_schema(), "system", &messages).await?;
+        assert_eq!(value, json!({"answer": 4}));
+
+        assert_eq!(requests.len(), 2);
+        assert!(requests.get(0).unwrap().system.contains("JSON Schema"));
+        let feedback = requests
+            .last()
+            .unwrap()
+            .messages
+            .last()
+            .unwrap()
+            .as_concat_text();
+        assert!(feedback.contains("/answer"), "{}", feedback);
+        assert_eq!(capabilities.get_usage().await.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_complete_output_gives_up() {
+        let provider = MockProvider::new("mock").with_default_text("not json");
+        let capabilities = Capabilities::new(Box::new(provider));
+        let messages = vec![Message::user().with_text("What is 2 + 2?")];
+
+        let error = complete_output(&capabilities, &answer_schema(), "system", &messages)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("after 3 attempts"));
+    }
+}
@@ -2,15 +2,19 @@
 /// It makes no attempt to handle context limits, and cannot read resources
 use async_trait::async_trait;
 use futures::stream::BoxStream;
+use futures::StreamExt;
 use tokio::sync::Mutex;
 use tracing::{debug, instrument};
 
-use super::Agent;
+use super::{complete_messages, Agent, AgentEvent};
+use crate::agents::budget::{BudgetTracker, ReplyBudget};
 use crate::agents::capabilities::Capabilities;
 use crate::agents::extension::{ExtensionConfig, ExtensionResult};
+use crate::agents::model_router::ModelRouter;
+use crate::agents::output_schema::{complete_output, OutputSchema};
 use crate::message::{Message, ToolRequest};
-use crate::providers::base::Provider;
 use crate::providers::base::ProviderUsage;
+use crate::providers::base::{CompletionChunk, Provider};
 use crate::register_agent;
 use crate::token_counter::TokenCounter;
 use indoc::indoc;
@@ -21,6 +25,8 @@ use serde_json::{json, Value};
 pub struct ReferenceAgent {
     capabilities: Mutex<Capabilities>,
     _token_counter: TokenCounter,
+    budget: ReplyBudget,
+    output_schema: Option<OutputSchema>,
 }
 
 impl ReferenceAgent {
@@ -29,6 +35,8 @@ impl ReferenceAgent {
         Self {
             capabilities: Mutex::new(Capabilities::new(provider)),
             _token_counter: token_counter,
+            budget: ReplyBudget::default(),
+            output_schema: None,
         }
     }
 }
@@ -61,11 +69,18 @@ impl Agent for ReferenceAgent {
         Ok(Value::Null)
     }
 
-    #[instrument(skip(self, messages), fields(user_message))]
     async fn reply(
         &self,
         messages: &[Message],
     ) -> anyhow::Result<BoxStream<'_, anyhow::Result<Message>>> {
+        Ok(complete_messages(self.stream_reply(messages).await?))
+    }
+
+    #[instrument(skip(self, messages), fields(user_message))]
+    async fn stream_reply(
+        &self,
+        messages: &[Message],
+    ) -> anyhow::Result<BoxStream<'_, anyhow::Result<AgentEvent>>> {
         let mut messages = messages.to_vec();
         let reply_span = tracing::Span::current();
         let mut capabilities = self.capabilities.lock().await;
@@ -116,6 +131,9 @@ impl Agent for ReferenceAgent {
         }
 
         let system_prompt = capabilities.get_system_prompt().await;
+        let mut budget = BudgetTracker::new(self.budget.clone());
+        budget.record_cost(capabilities.total_cost().await);
+        let output_schema = self.output_schema.clone();
 
         // Set the user_message field in the span instead of creating a new event
         if let Some(content) = messages
@@ -129,16 +147,38 @@ impl Agent for ReferenceAgent {
         Ok(Box::pin(async_stream::try_stream! {
             let _reply_guard = reply_span.enter();
             loop {
-                // Get completion from provider
-                let (response, usage) = capabilities.provider().complete(
+                if let Some(reason) = budget.exceeded() {
+                    yield AgentEvent::Message(Message::assistant().with_text(reason));
+                    break;
+                }
+
+                // Stream the completion from the provider, forwarding text as it arrives
+                let mut completion = capabilities.stream_completion(
                     &system_prompt,
                     &messages,
                     &tools,
                 ).await?;
+                let mut result = None;
+                while let Some(chunk) = completion.next().await {
+                    match chunk? {
+                        CompletionChunk::Text(text) => {
+                            yield AgentEvent::Partial(Message::assistant().with_text(text));
+                        }
+                        CompletionChunk::Thinking(thinking) => {
+                            yield AgentEvent::Partial(Message::assistant().with_thinking(thinking, None));
+                        }
+                        CompletionChunk::ToolCall { .. } => {}
+                        CompletionChunk::Done(message, usage) => result = Some((message, usage)),
+                    }
+                }
+                let (response, usage) = result
+                    .ok_or_else(|| anyhow::anyhow!("The provider stream ended without a response"))?;
+                budget.record_usage(&usage.usage);
                 capabilities.record_usage(usage).await;
+                budget.record_cost(capabilities.total_cost().await);
 
                 // Yield the assistant's response
-                yield response.clone();
+                yield AgentEvent::Message(response.clone());
 
                 tokio::task::yield_now().await;
 
@@ -149,6 +189,11 @@ impl Agent for ReferenceAgent {
                     .collect();
 
                 if tool_requests.is_empty() {
+                    if let Some(schema) = &output_schema {
+                        messages.push(response.clone());
+                        let output = complete_output(&capabilities, schema, &system_prompt, &messages).await?;
+                        yield AgentEvent::Message(Message::assistant().with_text(output.to_string()));
+                    }
                     break;
                 }
 
@@ -172,10 +217,12 @@ impl Agent for ReferenceAgent {
                     );
                 }
 
-                yield message_tool_response.clone();
+                yield AgentEvent::Message(message_tool_response.clone());
 
                 messages.push(response);
                 messages.push(message_tool_response);
+
+                budget.record_turn();
             }
         }))
     }
@@ -194,6 +241,29 @@ impl Agent for ReferenceAgent {
         let mut capabilities = self.capabilities.lock().await;
         capabilities.set_system_prompt_override(template);
     }
+
+    async fn set_budget(&mut self, budget: ReplyBudget) {
+        self.budget = budget;
+    }
+
+    async fn set_output_schema(&mut self, schema: Option<OutputSchema>) {
+        self.output_schema = schema;
+    }
+
+    async fn set_model_router(&mut self, router: Option<ModelRouter>) {
+        let mut capabilities = self.capabilities.lock().await;
+        capabilities.set_model_router(router);
+    }
+
+    async fn set_model_override(&self, model: Option<String>) -> anyhow::Result<()> {
+        let capabilities = self.capabilities.lock().await;
+        match capabilities.model_router() {
+            Some(router) => router.set_override(model),
+            None => Err(anyhow::anyhow!(
+                "Model routing is not configured, set up GOOSE_MODEL_ROUTER to use it"
+            )),
+        }
+    }
 }
 
 register_agent!("reference", ReferenceAgent);
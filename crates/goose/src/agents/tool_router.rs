@@ -0,0 +1,331 @@
+//! Relevance-based tool selection for agents with many extensions enabled
+//!
+//! Sending every tool on every provider call costs tokens and tends to confuse smaller models.
+//! The router ranks tools against the recent conversation with BM25 over tool names and
+//! descriptions, and only the top-N (plus pinned tools) are sent. The model can discover the
+//! remaining tools through the `platform__search_tools` meta-tool.
+
+use std::collections::{HashMap, HashSet};
+
+use indoc::indoc;
+use mcp_core::{Content, Tool, ToolError, ToolResult};
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::message::Message;
+
+pub const SEARCH_TOOLS_TOOL_NAME: &str = "platform__search_tools";
+
+/// Number of recent messages used to build the ranking query
+const QUERY_MESSAGE_WINDOW: usize = 4;
+/// Default number of results returned by the search meta-tool
+const DEFAULT_SEARCH_LIMIT: usize = 5;
+
+// Standard BM25 parameters
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+
+/// Selects the subset of tools sent to the provider on each turn
+#[derive(Debug, Clone)]
+pub struct ToolRouter {
+    top_n: usize,
+    pinned: HashSet<String>,
+}
+
+impl ToolRouter {
+    /// Create a router which keeps the `top_n` most relevant tools plus any pinned tools
+    ///
+    /// Pinned entries match either a full tool name (`developer__shell`) or an extension
+    /// name (`developer`), in which case every tool from that extension is kept.
+    pub fn new(top_n: usize, pinned: Vec<String>) -> Self {
+        Self {
+            top_n,
+            pinned: pinned.into_iter().collect(),
+        }
+    }
+
+    /// Build the router from config, routing is only enabled if GOOSE_TOOL_ROUTER_TOP_N is set
+    pub fn from_config() -> Option<Self> {
+        let config = Config::global();
+        let top_n: usize = config.get("GOOSE_TOOL_ROUTER_TOP_N").ok()?;
+        let pinned: Vec<String> = config.get("GOOSE_TOOL_ROUTER_PINNED").unwrap_or_default();
+        Some(Self::new(top_n, pinned))
+    }
+
+    fn is_pinned(&self, tool: &Tool) -> bool {
+        // Platform tools are cheap and always useful, so they are never routed away
+        if tool.name.starts_with("platform__") || self.pinned.contains(&tool.name) {
+            return true;
+        }
+        tool.name
+            .split_once("__")
+            .is_some_and(|(extension, _)| self.pinned.contains(extension))
+    }
+
+    /// Select the tools to send for the current conversation
+    ///
+    /// The original ordering of the tools is preserved, and the search meta-tool is appended
+    /// whenever some tools were left out.
+    pub fn select(&self, tools: &[Tool], messages: &[Message]) -> Vec<Tool> {
+        let (pinned, candidates): (Vec<&Tool>, Vec<&Tool>) =
+            tools.iter().partition(|tool| self.is_pinned(tool));
+
+        if candidates.len() <= self.top_n {
+            return tools.to_vec();
+        }
+
+        let query = messages
+            .iter()
+            .rev()
+            .take(QUERY_MESSAGE_WINDOW)
+            .map(|message| message.as_concat_text())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let selected: HashSet<&str> = rank_tools(&candidates, &query)
+            .into_iter()
+            .take(self.top_n)
+            .map(|index| candidates[index].name.as_str())
+            .chain(pinned.iter().map(|tool| tool.name.as_str()))
+            .collect();
+
+        let mut result: Vec<Tool> = tools
+            .iter()
+            .filter(|tool| selected.contains(tool.name.as_str()))
+            .cloned()
+            .collect();
+        result.push(Self::search_tool());
+        result
+    }
+
+    /// Find tools matching the arguments of a `platform__search_tools` call
+    pub fn search(&self, tools: &[Tool], arguments: &Value) -> ToolResult<Vec<Tool>> {
+        let query = arguments
+            .get("query")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::InvalidParameters("Missing 'query' parameter".to_string()))?;
+        let limit = arguments
+            .get("limit")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as usize)
+            .unwrap_or(DEFAULT_SEARCH_LIMIT);
+
+        let candidates: Vec<&Tool> = tools
+            .iter()
+            .filter(|tool| tool.name != SEARCH_TOOLS_TOOL_NAME)
+            .collect();
+
+        Ok(rank_tools(&candidates, query)
+            .into_iter()
+            .take(limit)
+            .map(|index| candidates[index].clone())
+            .collect())
+    }
+
+    /// Describe the tools found by a search so the model knows what it can now call
+    pub fn describe(tools: &[Tool]) -> Vec<Content> {
+        if tools.is_empty() {
+            return vec![Content::text("No matching tools found.")];
+        }
+
+        let listing = tools
+            .iter()
+            .map(|tool| format!("- {}: {}", tool.name, tool.description.trim()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        vec![Content::text(format!(
+            "The following tools are now available to call:\n{}",
+            listing
+        ))]
+    }
+
+    /// The meta-tool which lets the model discover tools that were not selected
+    pub fn search_tool() -> Tool {
+        Tool::new(
+            SEARCH_TOOLS_TOOL_NAME.to_string(),
+            indoc! {r#"
+                Search for additional tools by keyword.
+
+                Only the tools most relevant to the conversation are shown to you. If you need a
+                capability that none of your current tools provide, search for it here. Matching
+                tools become available to call in your next step.
+            "#}
+            .to_string(),
+            json!({
+                "type": "object",
+                "required": ["query"],
+                "properties": {
+                    "query": {"type": "string", "description": "Keywords describing the capability you need"},
+                    "limit": {"type": "integer", "description": "Maximum number of tools to return, default 5"}
+                }
+            }),
+        )
+    }
+}
+
+/// Split text into lowercase alphanumeric terms, so `developer__shell` becomes [developer, shell]
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| term.to_lowercase())
+        .collect()
+}
+
+/// Rank tools against the query with BM25, returning indices of tools with a positive score
+/// from most to least relevant
+fn rank_tools(tools: &[&Tool], query: &str) -> Vec<usize> {
+    let documents: Vec<Vec<String>> = tools
+        .iter()
+        .map(|tool| tokenize(&format!("{} {}", tool.name, tool.description)))
+        .collect();
+    let query_terms: HashSet<String> = tokenize(query).into_iter().collect();
+
+    if documents.is_empty() || query_terms.is_empty() {
+        return Vec::new();
+    }
+
+    let doc_count = documents.len() as f32;
+    let avg_len = documents.iter().map(|d| d.len()).sum::<usize>() as f32 / doc_count;
+
+    // Number of documents containing each query term
+    let doc_freq: HashMap<&str, usize> = query_terms
+        .iter()
+        .map(|term| {
+            let count = documents.iter().filter(|doc| doc.contains(term)).count();
+            (term.as_str(), count)
+        })
+        .collect();
+
+    let mut scored: Vec<(usize, f32)> = documents
+        .iter()
+        .enumerate()
+        .map(|(index, doc)| {
+            let len_norm = 1.0 - BM25_B + BM25_B * doc.len() as f32 / avg_len.max(1.0);
+            let score = query_terms
+                .iter()
+                .map(|term| {
+                    let tf = doc.iter().filter(|t| *t == term).count() as f32;
+                    if tf == 0.0 {
+                        return 0.0;
+                    }
+                    let df = doc_freq[term.as_str()] as f32;
+                    let idf = ((doc_count - df + 0.5) / (df + 0.5) + 1.0).ln();
+                    idf * tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * len_norm)
+                })
+                .sum();
+            (index, score)
+        })
+        .filter(|(_, score)| *score > 0.0)
+        .collect();
+
+    // Stable sort keeps the original ordering for ties
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.into_iter().map(|(index, _)| index).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(name: &str, description: &str) -> Tool {
+        Tool::new(
+            name,
+            description,
+            json!({"type": "object", "properties": {}}),
+        )
+    }
+
+    fn sample_tools() -> Vec<Tool> {
+        vec![
+            tool("developer__shell", "Execute a command in the shell"),
+            tool("developer__text_editor", "View and edit text files"),
+            tool(
+                "github__create_issue",
+                "Create an issue in a GitHub repository",
+            ),
+            tool(
+                "github__list_pull_requests",
+                "List pull requests in a GitHub repository",
+            ),
+            tool("slack__post_message", "Post a message to a Slack channel"),
+            tool("jira__search_issues", "Search Jira issues with JQL"),
+            tool(
+                "platform__read_resource",
+                "Read a resource from an extension",
+            ),
+        ]
+    }
+
+    fn names(tools: &[Tool]) -> Vec<&str> {
+        tools.iter().map(|t| t.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_select_keeps_all_tools_under_limit() {
+        let router = ToolRouter::new(10, vec![]);
+        let tools = sample_tools();
+        let messages = vec![Message::user().with_text("post to slack")];
+
+        let selected = router.select(&tools, &messages);
+        assert_eq!(names(&selected), names(&tools));
+    }
+
+    #[test]
+    fn test_select_ranks_relevant_tools() {
+        let router = ToolRouter::new(1, vec![]);
+        let tools = sample_tools();
+        let messages =
+            vec![Message::user().with_text("Please post a message in the slack channel")];
+
+        let selected = router.select(&tools, &messages);
+        assert_eq!(
+            names(&selected),
+            vec![
+                "slack__post_message",
+                "platform__read_resource",
+                SEARCH_TOOLS_TOOL_NAME
+            ]
+        );
+    }
+
+    #[test]
+    fn test_select_keeps_pinned_tools() {
+        let router = ToolRouter::new(
+            1,
+            vec!["developer".to_string(), "jira__search_issues".to_string()],
+        );
+        let tools = sample_tools();
+        let messages = vec![Message::user().with_text("open a github issue")];
+
+        let selected = router.select(&tools, &messages);
+        let selected = names(&selected);
+        assert!(selected.contains(&"developer__shell"));
+        assert!(selected.contains(&"developer__text_editor"));
+        assert!(selected.contains(&"jira__search_issues"));
+        assert!(selected.contains(&"github__create_issue"));
+        assert!(!selected.contains(&"slack__post_message"));
+        assert!(selected.contains(&SEARCH_TOOLS_TOOL_NAME));
+    }
+
+    #[test]
+    fn test_search_tools() {
+        let router = ToolRouter::new(1, vec![]);
+        let tools = sample_tools();
+
+        let found = router
+            .search(
+                &tools,
+                &json!({"query": "github pull requests", "limit": 1}),
+            )
+            .unwrap();
+        assert_eq!(names(&found), vec!["github__list_pull_requests"]);
+
+        let found = router
+            .search(&tools, &json!({"query": "kubernetes"}))
+            .unwrap();
+        assert!(found.is_empty());
+
+        let result = router.search(&tools, &json!({}));
+        assert!(matches!(result, Err(ToolError::InvalidParameters(_))));
+    }
+}
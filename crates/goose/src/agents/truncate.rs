@@ -2,30 +2,43 @@
 /// It makes no attempt to handle context limits, and cannot read resources
 use async_trait::async_trait;
 use futures::stream::BoxStream;
+use futures::StreamExt;
 use tokio::sync::Mutex;
 use tracing::{debug, error, instrument, warn};
 
-use super::Agent;
+use super::{complete_messages, Agent, AgentEvent};
+use crate::agents::budget::{BudgetTracker, ReplyBudget};
+use crate::agents::calibration::TokenCalibration;
 use crate::agents::capabilities::Capabilities;
 use crate::agents::extension::{ExtensionConfig, ExtensionResult};
+use crate::agents::model_router::ModelRouter;
+use crate::agents::output_schema::{complete_output, OutputSchema};
+use crate::agents::tool_router::{ToolRouter, SEARCH_TOOLS_TOOL_NAME};
 use crate::message::{Message, ToolRequest};
-use crate::providers::base::Provider;
+use crate::model::ModelConfig;
 use crate::providers::base::ProviderUsage;
+use crate::providers::base::{CompletionChunk, Provider};
 use crate::providers::errors::ProviderError;
 use crate::register_agent;
 use crate::token_counter::TokenCounter;
-use crate::truncate::{truncate_messages, OldestFirstTruncation};
+use crate::truncate::{truncate_messages, TruncationStrategy, TruncationStrategyName};
 use indoc::indoc;
 use mcp_core::tool::Tool;
 use serde_json::{json, Value};
 
 const MAX_TRUNCATION_ATTEMPTS: usize = 3;
 const ESTIMATE_FACTOR_DECAY: f32 = 0.9;
+/// Truncate before calling the model once a calibrated estimate reaches this share of its context
+const PROACTIVE_TRUNCATION_THRESHOLD: f32 = 0.95;
 
 /// Truncate implementation of an Agent
 pub struct TruncateAgent {
     capabilities: Mutex<Capabilities>,
     token_counter: TokenCounter,
+    calibration: TokenCalibration,
+    truncation: Box<dyn TruncationStrategy + Send + Sync>,
+    budget: ReplyBudget,
+    output_schema: Option<OutputSchema>,
 }
 
 impl TruncateAgent {
@@ -34,30 +47,49 @@ impl TruncateAgent {
         Self {
             capabilities: Mutex::new(Capabilities::new(provider)),
             token_counter,
+            calibration: TokenCalibration::load(),
+            truncation: TruncationStrategyName::from_config().strategy(),
+            budget: ReplyBudget::default(),
+            output_schema: None,
         }
     }
 
+    /// Use these learned token estimate corrections instead of the saved ones
+    #[cfg(test)]
+    fn with_calibration(mut self, calibration: TokenCalibration) -> Self {
+        self.calibration = calibration;
+        self
+    }
+
+    /// Whether the request is expected to be too long for the model, going by its calibrated
+    /// ratio. Models which haven't been calibrated yet are left to the provider to reject
+    fn exceeds_context(&self, model_config: &ModelConfig, estimate: usize) -> bool {
+        self.calibration
+            .get(&model_config.model_name)
+            .is_some_and(|calibration| {
+                estimate as f32 * calibration.ratio
+                    > model_config.context_limit() as f32 * PROACTIVE_TRUNCATION_THRESHOLD
+            })
+    }
+
     /// Truncates the messages to fit within the model's context window
     /// Ensures the last message is a user message and removes tool call-response pairs
-    async fn truncate_messages(
+    fn truncate_messages(
         &self,
         messages: &mut Vec<Message>,
         estimate_factor: f32,
         system_prompt: &str,
         tools: &mut Vec<Tool>,
+        model_config: &ModelConfig,
     ) -> anyhow::Result<()> {
         // Model's actual context limit
-        let context_limit = self
-            .capabilities
-            .lock()
-            .await
-            .provider()
-            .get_model_config()
-            .context_limit();
+        let context_limit = model_config.context_limit();
 
         // Our conservative estimate of the **target** context limit
-        // Our token count is an estimate since model providers often don't provide the tokenizer (eg. Claude)
-        let context_limit = (context_limit as f32 * estimate_factor) as usize;
+        // Our token count is an estimate since model providers often don't provide the tokenizer
+        // (eg. Claude), so it is also corrected by what the provider has reported for this model
+        let ratio = self.calibration.ratio(&model_config.model_name);
+        let context_limit = (context_limit as f32 * estimate_factor / ratio) as usize;
 
         // Take into account the system prompt, and our tools input and subtract that from the
         // remaining context limit
@@ -88,7 +120,7 @@ impl TruncateAgent {
             messages,
             &mut token_counts,
             context_limit,
-            &OldestFirstTruncation,
+            self.truncation.as_ref(),
         )
     }
 }
@@ -121,11 +153,18 @@ impl Agent for TruncateAgent {
         Ok(Value::Null)
     }
 
-    #[instrument(skip(self, messages), fields(user_message))]
     async fn reply(
         &self,
         messages: &[Message],
     ) -> anyhow::Result<BoxStream<'_, anyhow::Result<Message>>> {
+        Ok(complete_messages(self.stream_reply(messages).await?))
+    }
+
+    #[instrument(skip(self, messages), fields(user_message))]
+    async fn stream_reply(
+        &self,
+        messages: &[Message],
+    ) -> anyhow::Result<BoxStream<'_, anyhow::Result<AgentEvent>>> {
         let mut messages = messages.to_vec();
         let reply_span = tracing::Span::current();
         let mut capabilities = self.capabilities.lock().await;
@@ -177,7 +216,17 @@ impl Agent for TruncateAgent {
             tools.push(list_resources_tool);
         }
 
+        // With many extensions enabled, only send the tools most relevant to the conversation
+        let tool_router = ToolRouter::from_config();
+        let all_tools = tools.clone();
+        if let Some(router) = &tool_router {
+            tools = router.select(&all_tools, &messages);
+        }
+
         let system_prompt = capabilities.get_system_prompt().await;
+        let mut budget = BudgetTracker::new(self.budget.clone());
+        budget.record_cost(capabilities.total_cost().await);
+        let output_schema = self.output_schema.clone();
 
         // Set the user_message field in the span instead of creating a new event
         if let Some(content) = messages
@@ -191,20 +240,68 @@ impl Agent for TruncateAgent {
         Ok(Box::pin(async_stream::try_stream! {
             let _reply_guard = reply_span.enter();
             loop {
-                // Attempt to get completion from provider
-                match capabilities.provider().complete(
-                    &system_prompt,
-                    &messages,
-                    &tools,
-                ).await {
+                // Stop cleanly rather than calling the model again once over budget
+                if let Some(reason) = budget.exceeded() {
+                    warn!("Reply budget exceeded: {}", reason);
+                    yield AgentEvent::Message(Message::assistant().with_text(reason));
+                    break;
+                }
+
+                // Truncate ahead of time once the estimate is known to be reliable for this model,
+                // rather than waiting for the provider to reject the request
+                let model_config = capabilities.turn_model_config(&messages);
+                let mut estimate = self.token_counter.count_chat_tokens(&system_prompt, &messages, &tools);
+                if self.exceeds_context(&model_config, estimate) {
+                    warn!("Estimated {} tokens for {}, truncating before the request", estimate, model_config.model_name);
+                    if let Err(err) = self.truncate_messages(&mut messages, ESTIMATE_FACTOR_DECAY, &system_prompt, &mut tools, &model_config) {
+                        yield AgentEvent::Message(Message::assistant().with_text(format!("Error: Unable to truncate messages to stay within context limit. \n\nRan into this error: {}.\n\nPlease start a new session with fresh context and try again.", err)));
+                        break;
+                    }
+                    estimate = self.token_counter.count_chat_tokens(&system_prompt, &messages, &tools);
+                }
+
+                // Attempt to stream the completion from the provider, forwarding text as it arrives
+                let mut result = Err(ProviderError::ExecutionError(
+                    "The provider stream ended without a response".to_string(),
+                ));
+                match capabilities.stream_completion(&system_prompt, &messages, &tools).await {
+                    Ok(mut completion) => {
+                        while let Some(chunk) = completion.next().await {
+                            match chunk {
+                                Ok(CompletionChunk::Text(text)) => {
+                                    yield AgentEvent::Partial(Message::assistant().with_text(text));
+                                }
+                                Ok(CompletionChunk::Thinking(thinking)) => {
+                                    yield AgentEvent::Partial(Message::assistant().with_thinking(thinking, None));
+                                }
+                                Ok(CompletionChunk::ToolCall { .. }) => {}
+                                Ok(CompletionChunk::Done(message, usage)) => {
+                                    result = Ok((message, usage));
+                                }
+                                Err(e) => {
+                                    result = Err(e);
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => result = Err(e),
+                }
+
+                match result {
                     Ok((response, usage)) => {
+                        if let Some(actual) = usage.usage.input_tokens {
+                            self.calibration.record(&model_config.model_name, estimate, actual.max(0) as usize);
+                        }
+                        budget.record_usage(&usage.usage);
                         capabilities.record_usage(usage).await;
+                        budget.record_cost(capabilities.total_cost().await);
 
                         // Reset truncation attempt
                         truncation_attempt = 0;
 
                         // Yield the assistant's response
-                        yield response.clone();
+                        yield AgentEvent::Message(response.clone());
 
                         tokio::task::yield_now().await;
 
@@ -215,19 +312,51 @@ impl Agent for TruncateAgent {
                             .collect();
 
                         if tool_requests.is_empty() {
+                            if let Some(schema) = &output_schema {
+                                messages.push(response.clone());
+                                let output = complete_output(&capabilities, schema, &system_prompt, &messages).await?;
+                                yield AgentEvent::Message(Message::assistant().with_text(output.to_string()));
+                            }
                             break;
                         }
 
-                        // Then dispatch each in parallel
+                        // Then dispatch each in parallel. Tool searches are answered by the router
+                        // since only the agent knows about the tools that were not sent
+                        let mut discovered_tools = Vec::new();
                         let futures: Vec<_> = tool_requests
                             .iter()
                             .filter_map(|request| request.tool_call.clone().ok())
-                            .map(|tool_call| capabilities.dispatch_tool_call(tool_call))
+                            .map(|tool_call| {
+                                let search_result = match &tool_router {
+                                    Some(router) if tool_call.name == SEARCH_TOOLS_TOOL_NAME => {
+                                        let result = router.search(&all_tools, &tool_call.arguments);
+                                        if let Ok(found) = &result {
+                                            discovered_tools.extend(found.iter().cloned());
+                                        }
+                                        Some(result.map(|found| ToolRouter::describe(&found)))
+                                    }
+                                    _ => None,
+                                };
+                                let capabilities = &capabilities;
+                                async move {
+                                    match search_result {
+                                        Some(result) => result,
+                                        None => capabilities.dispatch_tool_call(tool_call).await,
+                                    }
+                                }
+                            })
                             .collect();
 
                         // Process all the futures in parallel but wait until all are finished
                         let outputs = futures::future::join_all(futures).await;
 
+                        // Make any tools found through search available for the next turn
+                        for tool in discovered_tools {
+                            if !tools.iter().any(|t| t.name == tool.name) {
+                                tools.push(tool);
+                            }
+                        }
+
                         // Create a message with the responses
                         let mut message_tool_response = Message::user();
                         // Now combine these into MessageContent::ToolResponse using the original ID
@@ -238,17 +367,19 @@ impl Agent for TruncateAgent {
                             );
                         }
 
-                        yield message_tool_response.clone();
+                        yield AgentEvent::Message(message_tool_response.clone());
 
                         messages.push(response);
                         messages.push(message_tool_response);
+
+                        budget.record_turn();
                     },
                     Err(ProviderError::ContextLengthExceeded(_)) => {
                         if truncation_attempt >= MAX_TRUNCATION_ATTEMPTS {
                             // Create an error message & terminate the stream
                             // the previous message would have been a user message (e.g. before any tool calls, this is just after the input message.
                             // at the start of a loop after a tool call, it would be after a tool_use assistant followed by a tool_result user)
-                            yield Message::assistant().with_text("Error: Context length exceeds limits even after multiple attempts to truncate. Please start a new session with fresh context and try again.");
+                            yield AgentEvent::Message(Message::assistant().with_text("Error: Context length exceeds limits even after multiple attempts to truncate. Please start a new session with fresh context and try again."));
                             break;
                         }
 
@@ -259,25 +390,18 @@ impl Agent for TruncateAgent {
                         // Estimate factor decays like this over time: 0.9, 0.81, 0.729, ...
                         let estimate_factor: f32 = ESTIMATE_FACTOR_DECAY.powi(truncation_attempt as i32);
 
-                        // release the lock before truncation to prevent deadlock
-                        drop(capabilities);
-
-                        if let Err(err) = self.truncate_messages(&mut messages, estimate_factor, &system_prompt, &mut tools).await {
-                            yield Message::assistant().with_text(format!("Error: Unable to truncate messages to stay within context limit. \n\nRan into this error: {}.\n\nPlease start a new session with fresh context and try again.", err));
+                        if let Err(err) = self.truncate_messages(&mut messages, estimate_factor, &system_prompt, &mut tools, &model_config) {
+                            yield AgentEvent::Message(Message::assistant().with_text(format!("Error: Unable to truncate messages to stay within context limit. \n\nRan into this error: {}.\n\nPlease start a new session with fresh context and try again.", err)));
                             break;
                         }
 
-
-                        // Re-acquire the lock
-                        capabilities = self.capabilities.lock().await;
-
                         // Retry the loop after truncation
                         continue;
                     },
                     Err(e) => {
                         // Create an error message & terminate the stream
                         error!("Error: {}", e);
-                        yield Message::assistant().with_text(format!("Ran into this error: {e}.\n\nPlease retry if you think this is a transient or recoverable error."));
+                        yield AgentEvent::Message(Message::assistant().with_text(format!("Ran into this error: {e}.\n\nPlease retry if you think this is a transient or recoverable error.")));
                         break;
                     }
                 }
@@ -302,6 +426,241 @@ impl Agent for TruncateAgent {
         let mut capabilities = self.capabilities.lock().await;
         capabilities.set_system_prompt_override(template);
     }
+
+    async fn set_budget(&mut self, budget: ReplyBudget) {
+        self.budget = budget;
+    }
+
+    async fn set_output_schema(&mut self, schema: Option<OutputSchema>) {
+        self.output_schema = schema;
+    }
+
+    async fn set_model_router(&mut self, router: Option<ModelRouter>) {
+        let mut capabilities = self.capabilities.lock().await;
+        capabilities.set_model_router(router);
+    }
+
+    async fn set_model_override(&self, model: Option<String>) -> anyhow::Result<()> {
+        let capabilities = self.capabilities.lock().await;
+        match capabilities.model_router() {
+            Some(router) => router.set_override(model),
+            None => Err(anyhow::anyhow!(
+                "Model routing is not configured, set up GOOSE_MODEL_ROUTER to use it"
+            )),
+        }
+    }
 }
 
 register_agent!("truncate", TruncateAgent);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::{RoutingRule, DEFAULT_ROUTE};
+    use crate::message::MessageContent;
+    use crate::providers::base::Usage;
+    use crate::providers::mock::MockProvider;
+    use futures::TryStreamExt;
+
+    #[tokio::test]
+    async fn test_reply_loop_with_mock_provider() -> anyhow::Result<()> {
+        let provider = MockProvider::new("mock")
+            .with_tool_request("developer__shell", json!({"command": "ls"}))
+            .with_error(ProviderError::ContextLengthExceeded("too long".to_string()))
+            .with_text("There is nothing here")
+            .expect(|request| assert!(request.system.contains("Goose")));
+        let requests = provider.requests();
+        let agent = TruncateAgent::new(Box::new(provider));
+
+        let messages: Vec<Message> = agent
+            .reply(&[Message::user().with_text("What's in this folder?")])
+            .await?
+            .try_collect()
+            .await?;
+
+        // The unknown tool is answered with an error, and the request is retried after truncating
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].is_tool_call());
+        assert!(matches!(
+            &messages[1].content[0],
+            MessageContent::ToolResponse(response) if response.tool_result.is_err()
+        ));
+        assert_eq!(messages[2].as_concat_text(), "There is nothing here");
+
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests.get(1).unwrap().messages.len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_forced_tool_choice_only_applies_until_a_tool_is_called() -> anyhow::Result<()> {
+        let model = ModelConfig::new("mock".to_string())
+            .with_tool_choice(Some(crate::model::ToolChoice::Required));
+        let provider = MockProvider::new("mock")
+            .with_model_config(model.clone())
+            .with_tool_request("developer__shell", json!({"command": "ls"}))
+            .with_text("There is nothing here");
+        let requests = provider.requests();
+        let agent = TruncateAgent::new(Box::new(provider));
+
+        let messages: Vec<Message> = agent
+            .reply(&[Message::user().with_text("What's in this folder?")])
+            .await?
+            .try_collect()
+            .await?;
+        assert_eq!(
+            messages.last().unwrap().as_concat_text(),
+            "There is nothing here"
+        );
+
+        // Format each turn's request as a provider would
+        let tools = [Tool::new("developer__shell", "Run a command", json!({}))];
+        let tool_choices: Vec<Value> = requests
+            .all()
+            .iter()
+            .map(|request| {
+                let payload = crate::providers::formats::openai::create_request(
+                    &model,
+                    &request.system,
+                    &request.messages,
+                    &tools,
+                    &crate::providers::utils::ImageFormat::OpenAi,
+                )
+                .unwrap();
+                payload["tool_choice"].clone()
+            })
+            .collect();
+        assert_eq!(tool_choices, vec![json!("required"), Value::Null]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reply_with_output_schema() -> anyhow::Result<()> {
+        let provider = MockProvider::new("mock")
+            .with_text("There are two files")
+            .with_text(r#"{"files": "two"}"#)
+            .with_text(r#"{"files": 2}"#);
+        let requests = provider.requests();
+        let mut agent = TruncateAgent::new(Box::new(provider));
+        let schema = json!({
+            "type": "object",
+            "properties": {"files": {"type": "integer"}},
+            "required": ["files"]
+        });
+        agent
+            .set_output_schema(Some(OutputSchema::new(schema)?))
+            .await;
+
+        let messages: Vec<Message> = agent
+            .reply(&[Message::user().with_text("How many files are there?")])
+            .await?
+            .try_collect()
+            .await?;
+
+        // The invalid output is retried, and the reply ends with only the valid JSON
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].as_concat_text(), r#"{"files":2}"#);
+        assert_eq!(requests.len(), 3);
+        assert!(requests.last().unwrap().tools.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reply_with_model_router() -> anyhow::Result<()> {
+        let strong = MockProvider::new("strong").with_text("The tool is missing");
+        let strong_requests = strong.requests();
+        let fast = MockProvider::new("fast")
+            .with_tool_request("developer__shell", json!({"command": "ls"}));
+        let fast_requests = fast.requests();
+
+        let mut agent = TruncateAgent::new(Box::new(strong));
+        let rules = vec![RoutingRule {
+            model: DEFAULT_ROUTE.to_string(),
+            after_tool_error: true,
+            ..Default::default()
+        }];
+        let router = ModelRouter::new(
+            vec![("fast".to_string(), Box::new(fast))],
+            Some("fast".to_string()),
+            rules,
+        )?;
+        agent.set_model_router(Some(router)).await;
+
+        let messages: Vec<Message> = agent
+            .reply(&[Message::user().with_text("What's in this folder?")])
+            .await?
+            .try_collect()
+            .await?;
+
+        // The unknown tool fails, so the next turn goes to the stronger model
+        assert_eq!(messages.len(), 3);
+        assert_eq!(fast_requests.len(), 1);
+        assert_eq!(strong_requests.len(), 1);
+        let mut models: Vec<String> = agent.usage().await.into_iter().map(|u| u.model).collect();
+        models.sort();
+        assert_eq!(models, vec!["fast", "strong"]);
+
+        assert!(agent
+            .set_model_override(Some("huge".to_string()))
+            .await
+            .is_err());
+        agent.set_model_override(Some("fast".to_string())).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_calibration_is_learned_from_usage() -> anyhow::Result<()> {
+        let provider = MockProvider::new("mock").with_reply(
+            Message::assistant().with_text("Hello"),
+            Usage::new(Some(500), Some(1), None),
+        );
+        let agent =
+            TruncateAgent::new(Box::new(provider)).with_calibration(TokenCalibration::default());
+
+        let _: Vec<Message> = agent
+            .reply(&[Message::user().with_text("Hi")])
+            .await?
+            .try_collect()
+            .await?;
+
+        let calibration = agent.calibration.get("mock").unwrap();
+        assert_eq!(calibration.samples, 1);
+        assert!(calibration.ratio > 1.0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_truncates_before_exceeding_calibrated_context() -> anyhow::Result<()> {
+        let provider = MockProvider::new("mock")
+            .with_model_config(
+                ModelConfig::new("mock".to_string()).with_context_limit(Some(12_000)),
+            )
+            .with_text("Hello")
+            .expect(|request| {
+                let texts: Vec<String> = request
+                    .messages
+                    .iter()
+                    .map(|m| m.as_concat_text())
+                    .collect();
+                assert_eq!(texts, vec!["Hi", "That is a lot of words", "Thanks"]);
+            });
+        let calibration = TokenCalibration::default();
+        calibration.record("mock", 1000, 1000);
+        let agent = TruncateAgent::new(Box::new(provider)).with_calibration(calibration);
+
+        let messages = vec![
+            Message::user().with_text("Hi"),
+            Message::assistant().with_text("Hello, what can I do?"),
+            Message::user().with_text("word ".repeat(20_000)),
+            Message::assistant().with_text("That is a lot of words"),
+            Message::user().with_text("Thanks"),
+        ];
+        let reply: Vec<Message> = agent.reply(&messages).await?.try_collect().await?;
+
+        // The long message was dropped, keeping the first one, without first being rejected by
+        // the provider
+        assert_eq!(reply.len(), 1);
+        assert_eq!(reply[0].as_concat_text(), "Hello");
+        Ok(())
+    }
+}
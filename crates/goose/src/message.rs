@@ -25,6 +25,22 @@ pub struct ToolResponse {
     pub tool_result: ToolResult<Vec<Content>>,
 }
 
+/// Reasoning the model produced before its answer
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ThinkingContent {
+    pub thinking: String,
+    /// Signature which some APIs (e.g. Anthropic) require to accept the thinking back in the
+    /// conversation history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// Reasoning which the provider returned encrypted, only useful to send back to that provider
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RedactedThinkingContent {
+    pub data: String,
+}
+
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 /// Content passed inside a message, which can be both simple content and tool content
 pub enum MessageContent {
@@ -32,6 +48,8 @@ pub enum MessageContent {
     Image(ImageContent),
     ToolRequest(ToolRequest),
     ToolResponse(ToolResponse),
+    Thinking(ThinkingContent),
+    RedactedThinking(RedactedThinkingContent),
 }
 
 impl MessageContent {
@@ -64,6 +82,17 @@ impl MessageContent {
         })
     }
 
+    pub fn thinking<S: Into<String>>(thinking: S, signature: Option<String>) -> Self {
+        MessageContent::Thinking(ThinkingContent {
+            thinking: thinking.into(),
+            signature,
+        })
+    }
+
+    pub fn redacted_thinking<S: Into<String>>(data: S) -> Self {
+        MessageContent::RedactedThinking(RedactedThinkingContent { data: data.into() })
+    }
+
     pub fn as_tool_request(&self) -> Option<&ToolRequest> {
         if let MessageContent::ToolRequest(ref tool_request) = self {
             Some(tool_request)
@@ -102,6 +131,14 @@ impl MessageContent {
             _ => None,
         }
     }
+
+    /// Get the reasoning if this is a ThinkingContent variant
+    pub fn as_thinking(&self) -> Option<&str> {
+        match self {
+            MessageContent::Thinking(thinking) => Some(&thinking.thinking),
+            _ => None,
+        }
+    }
 }
 
 impl From<Content> for MessageContent {
@@ -123,6 +160,9 @@ pub struct Message {
     pub role: Role,
     pub created: i64,
     pub content: Vec<MessageContent>,
+    /// Pinned messages are kept when the conversation is truncated
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
 }
 
 impl Message {
@@ -132,6 +172,7 @@ impl Message {
             role: Role::User,
             created: Utc::now().timestamp(),
             content: Vec::new(),
+            pinned: false,
         }
     }
 
@@ -141,6 +182,7 @@ impl Message {
             role: Role::Assistant,
             created: Utc::now().timestamp(),
             content: Vec::new(),
+            pinned: false,
         }
     }
 
@@ -150,6 +192,12 @@ impl Message {
         self
     }
 
+    /// Keep the message when the conversation is truncated
+    pub fn with_pinned(mut self, pinned: bool) -> Self {
+        self.pinned = pinned;
+        self
+    }
+
     /// Add text content to the message
     pub fn with_text<S: Into<String>>(self, text: S) -> Self {
         self.with_content(MessageContent::text(text))
@@ -160,6 +208,16 @@ impl Message {
         self.with_content(MessageContent::image(data, mime_type))
     }
 
+    /// Add thinking content to the message
+    pub fn with_thinking<S: Into<String>>(self, thinking: S, signature: Option<String>) -> Self {
+        self.with_content(MessageContent::thinking(thinking, signature))
+    }
+
+    /// Add redacted thinking content to the message
+    pub fn with_redacted_thinking<S: Into<String>>(self, data: S) -> Self {
+        self.with_content(MessageContent::redacted_thinking(data))
+    }
+
     /// Add a tool request to the message
     pub fn with_tool_request<S: Into<String>>(
         self,
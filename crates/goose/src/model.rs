@@ -1,10 +1,208 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::config::{Config, ConfigError};
 
 const DEFAULT_CONTEXT_LIMIT: usize = 128_000;
 
 // Tokenizer names, used to infer from model name
 pub const GPT_4O_TOKENIZER: &str = "Xenova--gpt-4o";
 pub const CLAUDE_TOKENIZER: &str = "Xenova--claude-tokenizer";
+// Not embedded, these are loaded from GOOSE_TOKENIZER_DIR or the tokenizer cache, and GPT-4o's
+// is used when they aren't there
+pub const LLAMA_TOKENIZER: &str = "Xenova--llama3-tokenizer";
+pub const QWEN_TOKENIZER: &str = "Qwen--Qwen2.5-Coder-32B-Instruct";
+pub const MISTRAL_TOKENIZER: &str = "Xenova--mistral-tokenizer-v3";
+// Gemini's tokenizer isn't published, Gemma shares its vocabulary
+pub const GEMINI_TOKENIZER: &str = "Xenova--gemma-tokenizer";
+
+/// What a model can do, so agents can adapt requests instead of failing at the API
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelCapabilities {
+    /// Whether the model accepts tool definitions and makes tool calls
+    pub tool_calling: bool,
+    /// Whether the model accepts images in messages and tool results
+    pub images: bool,
+    /// Whether the model accepts a separate system prompt
+    pub system_prompt: bool,
+    /// Whether the model can stream its response
+    pub streaming: bool,
+    /// Whether the model can be constrained to produce JSON
+    pub json_mode: bool,
+    /// The context window in tokens, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<usize>,
+    /// The most tokens the model can generate in one response, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<i32>,
+}
+
+impl Default for ModelCapabilities {
+    /// Unknown models are assumed to support everything but JSON mode, which is how they were
+    /// treated before capabilities were tracked
+    fn default() -> Self {
+        Self {
+            tool_calling: true,
+            images: true,
+            system_prompt: true,
+            streaming: true,
+            json_mode: false,
+            context_window: None,
+            max_output_tokens: None,
+        }
+    }
+}
+
+impl ModelCapabilities {
+    /// The bundled capabilities of a model
+    ///
+    /// Entries are keyed by the start of a model name and the longest one which matches wins, so
+    /// `claude-3-5-sonnet` also describes `claude-3-5-sonnet-20241022` rather than `claude-3`.
+    /// Provider namespaces such as `models/`, `anthropic.` or `databricks-` are ignored.
+    pub fn for_model(model_name: &str) -> Self {
+        static BUNDLED: OnceLock<HashMap<String, ModelCapabilities>> = OnceLock::new();
+        let bundled = BUNDLED.get_or_init(|| {
+            serde_json::from_str(include_str!("model_capabilities.json"))
+                .expect("the bundled model capabilities are valid")
+        });
+
+        let model_name = base_model_name(model_name);
+        bundled
+            .iter()
+            .filter(|(name, _)| model_name.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, capabilities)| capabilities.clone())
+            .unwrap_or_default()
+    }
+
+    /// What both models can do, for when requests could go to either
+    pub fn intersect(&self, other: &Self) -> Self {
+        fn min<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+
+        Self {
+            tool_calling: self.tool_calling && other.tool_calling,
+            images: self.images && other.images,
+            system_prompt: self.system_prompt && other.system_prompt,
+            streaming: self.streaming && other.streaming,
+            json_mode: self.json_mode && other.json_mode,
+            context_window: min(self.context_window, other.context_window),
+            max_output_tokens: min(self.max_output_tokens, other.max_output_tokens),
+        }
+    }
+}
+
+/// The model name without the namespaces providers add to it, e.g. `claude-3-5-sonnet-20241022-v2:0`
+/// for `us.anthropic.claude-3-5-sonnet-20241022-v2:0`
+fn base_model_name(model_name: &str) -> &str {
+    let mut name = model_name.rsplit('/').next().unwrap_or(model_name);
+    name = name.strip_prefix("databricks-").unwrap_or(name);
+    // Bedrock prefixes a region and a vendor, which unlike model names have no digits
+    while let Some((prefix, rest)) = name.split_once('.') {
+        if prefix.is_empty() || !prefix.chars().all(|c| c.is_ascii_alphabetic()) {
+            break;
+        }
+        name = rest;
+    }
+    name
+}
+
+/// Whether the model may, must or must not call tools
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ToolChoice {
+    /// The model decides, the default of every provider
+    Auto,
+    /// The model has to call at least one tool
+    Required,
+    /// The model can't call tools, though they are still described to it
+    None,
+    /// The model has to call the named tool
+    Tool(String),
+}
+
+impl fmt::Display for ToolChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToolChoice::Auto => write!(f, "auto"),
+            ToolChoice::Required => write!(f, "required"),
+            ToolChoice::None => write!(f, "none"),
+            ToolChoice::Tool(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Parses `auto`, `required` or `none`, and takes anything else as the name of a tool
+impl FromStr for ToolChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "" => Err("The tool choice can't be empty".to_string()),
+            "auto" => Ok(ToolChoice::Auto),
+            "required" => Ok(ToolChoice::Required),
+            "none" => Ok(ToolChoice::None),
+            name => Ok(ToolChoice::Tool(name.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for ToolChoice {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<ToolChoice> for String {
+    fn from(choice: ToolChoice) -> Self {
+        choice.to_string()
+    }
+}
+
+/// How much reasoning models think before answering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReasoningEffort::Low => "low",
+            ReasoningEffort::Medium => "medium",
+            ReasoningEffort::High => "high",
+        }
+    }
+}
+
+impl FromStr for ReasoningEffort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low" => Ok(ReasoningEffort::Low),
+            "medium" => Ok(ReasoningEffort::Medium),
+            "high" => Ok(ReasoningEffort::High),
+            _ => Err(format!(
+                "Unknown reasoning effort {}, expected low, medium or high",
+                s
+            )),
+        }
+    }
+}
 
 /// Configuration for model-specific settings and limits
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +219,40 @@ pub struct ModelConfig {
     pub temperature: Option<f32>,
     /// Optional maximum tokens to generate
     pub max_tokens: Option<i32>,
+    /// Optional token budget for extended thinking, which enables it on models that support it
+    #[serde(default)]
+    pub thinking_budget: Option<i32>,
+    /// Optional nucleus sampling, the cumulative probability of the tokens sampled from
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    /// Optional limit on how many of the likeliest tokens are sampled from
+    #[serde(default)]
+    pub top_k: Option<u32>,
+    /// Sequences which end generation when the model produces them
+    #[serde(default)]
+    pub stop_sequences: Vec<String>,
+    /// Optional seed for providers which can sample deterministically
+    #[serde(default)]
+    pub seed: Option<i64>,
+    /// Optional penalty on tokens which have appeared at all, to encourage new topics
+    #[serde(default)]
+    pub presence_penalty: Option<f32>,
+    /// Optional penalty on tokens by how often they have appeared, to discourage repetition
+    #[serde(default)]
+    pub frequency_penalty: Option<f32>,
+    /// Whether the model may, must or must not call tools, left to the provider if unset
+    #[serde(default)]
+    pub tool_choice: Option<ToolChoice>,
+    /// Whether the model may call several tools in one response, left to the provider if unset
+    #[serde(default)]
+    pub parallel_tool_calls: Option<bool>,
+    /// Reasoning effort for models which take one, overriding a `-low`, `-medium` or `-high`
+    /// suffix on the model name
+    #[serde(default)]
+    pub reasoning_effort: Option<ReasoningEffort>,
+    /// What the model can do, looked up from its name unless the provider knows better
+    #[serde(default)]
+    pub capabilities: ModelCapabilities,
 }
 
 impl ModelConfig {
@@ -28,48 +260,51 @@ impl ModelConfig {
     ///
     /// The context limit is set with the following precedence:
     /// 1. Explicit context_limit if provided in config
-    /// 2. Model-specific default based on model name
+    /// 2. The context window from the model's capabilities
     /// 3. Global default (128_000) (in get_context_limit)
     pub fn new(model_name: String) -> Self {
-        let context_limit = Self::get_model_specific_limit(&model_name);
         let tokenizer_name = Self::infer_tokenizer_name(&model_name);
+        let capabilities = ModelCapabilities::for_model(&model_name);
 
         Self {
             model_name,
             tokenizer_name: tokenizer_name.to_string(),
-            context_limit,
+            context_limit: None,
             temperature: None,
             max_tokens: None,
+            thinking_budget: None,
+            top_p: None,
+            top_k: None,
+            stop_sequences: Vec::new(),
+            seed: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            tool_choice: None,
+            parallel_tool_calls: None,
+            reasoning_effort: None,
+            capabilities,
         }
     }
 
-    fn infer_tokenizer_name(model_name: &str) -> &'static str {
-        if model_name.contains("claude") {
+    pub fn infer_tokenizer_name(model_name: &str) -> &'static str {
+        let name = model_name.to_lowercase();
+        let is = |families: &[&str]| families.iter().any(|family| name.contains(family));
+        if is(&["claude"]) {
             CLAUDE_TOKENIZER
+        } else if is(&["llama"]) {
+            LLAMA_TOKENIZER
+        } else if is(&["qwen", "qwq"]) {
+            QWEN_TOKENIZER
+        } else if is(&["mistral", "mixtral", "codestral", "pixtral"]) {
+            MISTRAL_TOKENIZER
+        } else if is(&["gemini", "gemma"]) {
+            GEMINI_TOKENIZER
         } else {
             // Default tokenizer
             GPT_4O_TOKENIZER
         }
     }
 
-    /// Get model-specific context limit based on model name
-    fn get_model_specific_limit(model_name: &str) -> Option<usize> {
-        // Implement some sensible defaults
-        match model_name {
-            // OpenAI models, https://platform.openai.com/docs/models#models-overview
-            name if name.contains("gpt-4o") => Some(128_000),
-            name if name.contains("gpt-4-turbo") => Some(128_000),
-
-            // Anthropic models, https://docs.anthropic.com/en/docs/about-claude/models
-            name if name.contains("claude-3") => Some(200_000),
-
-            // Meta Llama models, https://github.com/meta-llama/llama-models/tree/main?tab=readme-ov-file#llama-models-1
-            name if name.contains("llama3.2") => Some(128_000),
-            name if name.contains("llama3.3") => Some(128_000),
-            _ => None,
-        }
-    }
-
     /// Set an explicit context limit
     pub fn with_context_limit(mut self, limit: Option<usize>) -> Self {
         // Default is None and therefore DEFAULT_CONTEXT_LIMIT, only set
@@ -93,6 +328,144 @@ impl ModelConfig {
         self
     }
 
+    /// Set the extended thinking budget
+    pub fn with_thinking_budget(mut self, budget: Option<i32>) -> Self {
+        self.thinking_budget = budget;
+        self
+    }
+
+    /// Set nucleus sampling
+    pub fn with_top_p(mut self, top_p: Option<f32>) -> Self {
+        self.top_p = top_p;
+        self
+    }
+
+    /// Set top-k sampling
+    pub fn with_top_k(mut self, top_k: Option<u32>) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    /// Set the stop sequences
+    pub fn with_stop_sequences(mut self, stop_sequences: Vec<String>) -> Self {
+        self.stop_sequences = stop_sequences;
+        self
+    }
+
+    /// Set the sampling seed
+    pub fn with_seed(mut self, seed: Option<i64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Set the presence penalty
+    pub fn with_presence_penalty(mut self, penalty: Option<f32>) -> Self {
+        self.presence_penalty = penalty;
+        self
+    }
+
+    /// Set the frequency penalty
+    pub fn with_frequency_penalty(mut self, penalty: Option<f32>) -> Self {
+        self.frequency_penalty = penalty;
+        self
+    }
+
+    /// Set whether the model may, must or must not call tools
+    pub fn with_tool_choice(mut self, tool_choice: Option<ToolChoice>) -> Self {
+        self.tool_choice = tool_choice;
+        self
+    }
+
+    /// Set whether the model may call several tools at once
+    pub fn with_parallel_tool_calls(mut self, parallel: Option<bool>) -> Self {
+        self.parallel_tool_calls = parallel;
+        self
+    }
+
+    /// Set the reasoning effort
+    pub fn with_reasoning_effort(mut self, effort: Option<ReasoningEffort>) -> Self {
+        self.reasoning_effort = effort;
+        self
+    }
+
+    /// Copy the generation settings of another config, for the same request to another model
+    pub fn with_generation_settings_of(self, other: &ModelConfig) -> Self {
+        Self {
+            temperature: other.temperature,
+            max_tokens: other.max_tokens,
+            thinking_budget: other.thinking_budget,
+            top_p: other.top_p,
+            top_k: other.top_k,
+            stop_sequences: other.stop_sequences.clone(),
+            seed: other.seed,
+            presence_penalty: other.presence_penalty,
+            frequency_penalty: other.frequency_penalty,
+            tool_choice: other.tool_choice.clone(),
+            parallel_tool_calls: other.parallel_tool_calls,
+            reasoning_effort: other.reasoning_effort,
+            ..self
+        }
+    }
+
+    /// Apply the generation settings from the config file, or from the environment variables of
+    /// the same names, such as GOOSE_TEMPERATURE or GOOSE_STOP_SEQUENCES='["END"]'
+    ///
+    /// Settings which aren't configured are left as they are, and settings which can't be
+    /// parsed are an error rather than silently ignored.
+    pub fn with_configured_generation(mut self, config: &Config) -> Result<Self, ConfigError> {
+        fn get<T: for<'de> Deserialize<'de>>(
+            config: &Config,
+            key: &str,
+        ) -> Result<Option<T>, ConfigError> {
+            match config.get(key) {
+                Ok(value) => Ok(Some(value)),
+                Err(ConfigError::NotFound(_)) => Ok(None),
+                Err(e) => Err(e),
+            }
+        }
+
+        if let Some(temperature) = get(config, "GOOSE_TEMPERATURE")? {
+            self.temperature = Some(temperature);
+        }
+        if let Some(budget) = get(config, "GOOSE_THINKING_BUDGET")? {
+            self.thinking_budget = Some(budget);
+        }
+        if let Some(top_p) = get(config, "GOOSE_TOP_P")? {
+            self.top_p = Some(top_p);
+        }
+        if let Some(top_k) = get(config, "GOOSE_TOP_K")? {
+            self.top_k = Some(top_k);
+        }
+        if let Some(stop_sequences) = get(config, "GOOSE_STOP_SEQUENCES")? {
+            self.stop_sequences = stop_sequences;
+        }
+        if let Some(seed) = get(config, "GOOSE_SEED")? {
+            self.seed = Some(seed);
+        }
+        if let Some(penalty) = get(config, "GOOSE_PRESENCE_PENALTY")? {
+            self.presence_penalty = Some(penalty);
+        }
+        if let Some(penalty) = get(config, "GOOSE_FREQUENCY_PENALTY")? {
+            self.frequency_penalty = Some(penalty);
+        }
+        if let Some(tool_choice) = get(config, "GOOSE_TOOL_CHOICE")? {
+            self.tool_choice = Some(tool_choice);
+        }
+        if let Some(parallel) = get(config, "GOOSE_PARALLEL_TOOL_CALLS")? {
+            self.parallel_tool_calls = Some(parallel);
+        }
+        if let Some(effort) = get(config, "GOOSE_REASONING_EFFORT")? {
+            self.reasoning_effort = Some(effort);
+        }
+        Ok(self)
+    }
+
+    /// Replace the capabilities looked up from the model name
+    pub fn with_capabilities(mut self, capabilities: ModelCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
     // Get the tokenizer name
     pub fn tokenizer_name(&self) -> &str {
         &self.tokenizer_name
@@ -101,7 +474,9 @@ impl ModelConfig {
     /// Get the context_limit for the current model
     /// If none are defined, use the DEFAULT_CONTEXT_LIMIT
     pub fn context_limit(&self) -> usize {
-        self.context_limit.unwrap_or(DEFAULT_CONTEXT_LIMIT)
+        self.context_limit
+            .or(self.capabilities.context_window)
+            .unwrap_or(DEFAULT_CONTEXT_LIMIT)
     }
 }
 
@@ -128,6 +503,20 @@ mod tests {
         assert_eq!(config.context_limit(), DEFAULT_CONTEXT_LIMIT);
     }
 
+    #[test]
+    fn test_infer_tokenizer_name() {
+        let tokenizer = |name: &str| ModelConfig::new(name.to_string()).tokenizer_name;
+        assert_eq!(tokenizer("claude-3-5-sonnet-latest"), CLAUDE_TOKENIZER);
+        assert_eq!(
+            tokenizer("meta-llama/Llama-3.3-70B-Instruct"),
+            LLAMA_TOKENIZER
+        );
+        assert_eq!(tokenizer("qwen2.5-coder:32b"), QWEN_TOKENIZER);
+        assert_eq!(tokenizer("codestral-latest"), MISTRAL_TOKENIZER);
+        assert_eq!(tokenizer("gemini-2.0-flash"), GEMINI_TOKENIZER);
+        assert_eq!(tokenizer("gpt-4o"), GPT_4O_TOKENIZER);
+    }
+
     #[test]
     fn test_model_config_settings() {
         let config = ModelConfig::new("test-model".to_string())
@@ -139,4 +528,75 @@ mod tests {
         assert_eq!(config.max_tokens, Some(1000));
         assert_eq!(config.context_limit, Some(50_000));
     }
+
+    #[test]
+    fn test_capabilities_for_model() {
+        let mini = ModelCapabilities::for_model("o1-mini-2024-09-12");
+        assert!(!mini.tool_calling);
+        assert!(!mini.system_prompt);
+
+        // The longest matching entry wins over shorter ones like "claude-3"
+        let sonnet = ModelCapabilities::for_model("anthropic.claude-3-5-sonnet-20241022-v2:0");
+        assert_eq!(sonnet.max_output_tokens, Some(8192));
+        assert_eq!(sonnet.context_window, Some(200_000));
+        let sonnet = ModelCapabilities::for_model("us.anthropic.claude-3-7-sonnet-20250219-v1:0");
+        assert_eq!(sonnet.max_output_tokens, Some(64000));
+        let flash = ModelCapabilities::for_model("models/gemini-2.0-flash");
+        assert_eq!(flash.context_window, Some(1_048_576));
+        assert_eq!(
+            ModelCapabilities::for_model("llama3.2-vision:11b"),
+            ModelCapabilities::for_model("llama3.2-vision")
+        );
+
+        // Names are only matched from the start, not anywhere inside another model's name
+        assert_eq!(
+            ModelCapabilities::for_model("my-o1-finetune"),
+            ModelCapabilities::default()
+        );
+        assert_eq!(
+            ModelCapabilities::for_model("unknown-model"),
+            ModelCapabilities::default()
+        );
+    }
+
+    #[test]
+    fn test_capabilities_intersect() {
+        let vision = ModelCapabilities::for_model("llama3.2-vision");
+        let text = ModelCapabilities::for_model("llama3.2");
+        let both = vision.intersect(&text);
+
+        assert!(!both.tool_calling);
+        assert!(!both.images);
+        assert_eq!(both.context_window, Some(128_000));
+        assert_eq!(both.max_output_tokens, None);
+    }
+
+    #[test]
+    fn test_configured_generation() -> Result<(), ConfigError> {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let config = Config::new(file.path(), "test")?;
+        config.set("GOOSE_TOP_P", serde_json::json!(0.9))?;
+        config.set("GOOSE_STOP_SEQUENCES", serde_json::json!(["END"]))?;
+        config.set("GOOSE_TOOL_CHOICE", serde_json::json!("developer__shell"))?;
+        config.set("GOOSE_REASONING_EFFORT", serde_json::json!("high"))?;
+
+        let model = ModelConfig::new("o3-mini".to_string())
+            .with_seed(Some(7))
+            .with_configured_generation(&config)?;
+        assert_eq!(model.top_p, Some(0.9));
+        assert_eq!(model.stop_sequences, vec!["END"]);
+        assert_eq!(
+            model.tool_choice,
+            Some(ToolChoice::Tool("developer__shell".to_string()))
+        );
+        assert_eq!(model.reasoning_effort, Some(ReasoningEffort::High));
+        // Settings which aren't configured are kept
+        assert_eq!(model.seed, Some(7));
+
+        config.set("GOOSE_REASONING_EFFORT", serde_json::json!("extreme"))?;
+        assert!(ModelConfig::new("o3-mini".to_string())
+            .with_configured_generation(&config)
+            .is_err());
+        Ok(())
+    }
 }
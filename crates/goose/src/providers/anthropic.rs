@@ -1,15 +1,21 @@
 use anyhow::Result;
 use async_trait::async_trait;
-use reqwest::{Client, StatusCode};
-use serde_json::Value;
+use reqwest::{Client, Response, StatusCode};
+use serde_json::{json, Value};
 use std::time::Duration;
 
-use super::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage};
+use super::base::{
+    complete_structured_with_prompt, CompletionStream, ConfigKey, Provider, ProviderMetadata,
+    ProviderUsage,
+};
 use super::errors::ProviderError;
-use super::formats::anthropic::{create_request, get_usage, response_to_message};
-use super::utils::{emit_debug_trace, get_model};
+use super::formats::anthropic::{
+    create_request, get_usage, response_to_message, MessageStreamAccumulator,
+};
+use super::streaming::{response_error, stream_response};
+use super::utils::{emit_debug_trace, get_model, get_model_names, get_retry_after};
 use crate::message::Message;
-use crate::model::ModelConfig;
+use crate::model::{ModelConfig, ToolChoice};
 use mcp_core::tool::Tool;
 
 pub const ANTHROPIC_DEFAULT_MODEL: &str = "claude-3-5-sonnet-latest";
@@ -19,6 +25,9 @@ pub const ANTHROPIC_KNOWN_MODELS: &[&str] = &[
     "claude-3-opus-latest",
 ];
 
+/// The tool which structured output is forced through
+const FINAL_OUTPUT_TOOL: &str = "final_output";
+
 pub const ANTHROPIC_DOC_URL: &str = "https://docs.anthropic.com/en/docs/about-claude/models";
 
 #[derive(serde::Serialize)]
@@ -57,7 +66,7 @@ impl AnthropicProvider {
         })
     }
 
-    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+    async fn send(&self, payload: &Value) -> Result<Response, ProviderError> {
         let base_url = url::Url::parse(&self.host)
             .map_err(|e| ProviderError::RequestFailed(format!("Invalid base URL: {e}")))?;
         let url = base_url.join("v1/messages").map_err(|e| {
@@ -69,11 +78,20 @@ impl AnthropicProvider {
             .post(url)
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", "2023-06-01")
-            .json(&payload)
+            .json(payload)
             .send()
             .await?;
+        Ok(response)
+    }
+
+    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+        let response = self.send(&payload).await?;
+        Self::handle_response(response).await
+    }
 
+    async fn handle_response(response: Response) -> Result<Value, ProviderError> {
         let status = response.status();
+        let retry_delay = get_retry_after(response.headers());
         let payload: Option<Value> = response.json().await.ok();
 
         // https://docs.anthropic.com/en/api/errors
@@ -99,7 +117,10 @@ impl AnthropicProvider {
                 Err(ProviderError::RequestFailed(format!("Request failed with status: {}. Message: {}", status, error_msg)))
             }
             StatusCode::TOO_MANY_REQUESTS => {
-                Err(ProviderError::RateLimitExceeded(format!("{:?}", payload)))
+                Err(ProviderError::RateLimitExceeded {
+                    details: format!("{:?}", payload),
+                    retry_delay,
+                })
             }
             StatusCode::INTERNAL_SERVER_ERROR | StatusCode::SERVICE_UNAVAILABLE => {
                 Err(ProviderError::ServerError(format!("{:?}", payload)))
@@ -143,6 +164,25 @@ impl Provider for AnthropicProvider {
         self.model.clone()
     }
 
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        let base_url = url::Url::parse(&self.host)
+            .map_err(|e| ProviderError::RequestFailed(format!("Invalid base URL: {e}")))?;
+        let mut url = base_url.join("v1/models").map_err(|e| {
+            ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
+        })?;
+        url.query_pairs_mut().append_pair("limit", "1000");
+
+        let response = self
+            .client
+            .get(url)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .send()
+            .await?;
+        let response = Self::handle_response(response).await?;
+        get_model_names(&response, "data", "id")
+    }
+
     #[tracing::instrument(
         skip(self, system, messages, tools),
         fields(model_config, input, output, input_tokens, output_tokens, total_tokens)
@@ -164,6 +204,146 @@ impl Provider for AnthropicProvider {
 
         let model = get_model(&response);
         emit_debug_trace(self, &payload, &response, &usage);
-        Ok((message, ProviderUsage::new(model, usage)))
+        Ok((
+            message,
+            ProviderUsage::new(model, usage).with_provider("anthropic"),
+        ))
+    }
+
+    /// Forces a call to a tool whose input schema is the output schema, as the API has no JSON
+    /// mode. Tool inputs have to be objects, so other schemas are asked for in the prompt.
+    /// Thinking can't be combined with a forced tool call, so it is off for this request
+    async fn complete_structured(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        if !self.model.capabilities.tool_calling || schema.get("type") != Some(&json!("object")) {
+            return complete_structured_with_prompt(self, system, messages, schema).await;
+        }
+        let model = self
+            .model
+            .clone()
+            .with_tool_choice(Some(ToolChoice::Tool(FINAL_OUTPUT_TOOL.to_string())))
+            .with_parallel_tool_calls(None)
+            .with_thinking_budget(None);
+        let tool = Tool::new(
+            FINAL_OUTPUT_TOOL,
+            "Give the final answer, in the requested format",
+            schema.clone(),
+        );
+        let payload = create_request(&model, system, messages, &[tool])?;
+
+        let response = self.post(payload.clone()).await?;
+        let message = response_to_message(response.clone())?;
+        let usage = get_usage(&response)?;
+        emit_debug_trace(self, &payload, &response, &usage);
+
+        let output = message
+            .content
+            .iter()
+            .filter_map(|content| content.as_tool_request())
+            .find_map(|request| match &request.tool_call {
+                Ok(call) if call.name == FINAL_OUTPUT_TOOL => Some(call.arguments.to_string()),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                ProviderError::RequestFailed(format!(
+                    "The model did not call the {} tool",
+                    FINAL_OUTPUT_TOOL
+                ))
+            })?;
+        Ok((
+            Message::assistant().with_text(output),
+            ProviderUsage::new(get_model(&response), usage).with_provider("anthropic"),
+        ))
+    }
+
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<CompletionStream, ProviderError> {
+        let mut payload = create_request(&self.model, system, messages, tools)?;
+        payload["stream"] = json!(true);
+
+        let response = self.send(&payload).await?;
+        if !response.status().is_success() {
+            return Err(response_error(Self::handle_response(response)).await);
+        }
+
+        let model_config = self.model.clone();
+        Ok(stream_response(
+            response,
+            MessageStreamAccumulator::default(),
+            move |response| {
+                let message = response_to_message(response.clone())?;
+                let usage = get_usage(&response)?;
+                let model = get_model(&response);
+                emit_debug_trace(&model_config, &payload, &response, &usage);
+                Ok((
+                    message,
+                    ProviderUsage::new(model, usage).with_provider("anthropic"),
+                ))
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_complete_structured_forces_tool_call() -> Result<()> {
+        let schema = json!({"type": "object", "properties": {"files": {"type": "integer"}}});
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .and(body_partial_json(json!({
+                "tools": [{"name": "final_output", "input_schema": schema}],
+                "tool_choice": {"type": "tool", "name": "final_output"}
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-3-5-sonnet-latest",
+                "content": [
+                    {"type": "tool_use", "id": "toolu_1", "name": "final_output", "input": {"files": 2}}
+                ],
+                "stop_reason": "tool_use",
+                "usage": {"input_tokens": 20, "output_tokens": 5}
+            })))
+            .mount(&server)
+            .await;
+
+        let provider = AnthropicProvider {
+            client: Client::new(),
+            host: server.uri(),
+            api_key: "key".to_string(),
+            model: ModelConfig::new(ANTHROPIC_DEFAULT_MODEL.to_string())
+                .with_thinking_budget(Some(2048)),
+        };
+        let (message, usage) = provider
+            .complete_structured(
+                "system",
+                &[Message::user().with_text("How many files?")],
+                &schema,
+            )
+            .await?;
+        assert_eq!(message.as_concat_text(), r#"{"files":2}"#);
+        assert!(!message.is_tool_call());
+        assert_eq!(usage.usage.input_tokens, Some(20));
+
+        // A forced tool call is rejected with thinking enabled
+        let requests = server.received_requests().await.unwrap();
+        let request: Value = serde_json::from_slice(&requests[0].body)?;
+        assert!(request.get("thinking").is_none());
+        Ok(())
     }
 }
@@ -4,10 +4,19 @@ use reqwest::Client;
 use serde_json::Value;
 use std::time::Duration;
 
-use super::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage, Usage};
+use super::azure_auth::AzureAuth;
+use super::base::{
+    complete_structured_with_prompt, ConfigKey, Embeddings, Provider, ProviderMetadata,
+    ProviderUsage, Usage,
+};
 use super::errors::ProviderError;
-use super::formats::openai::{create_request, get_usage, response_to_message};
-use super::utils::{emit_debug_trace, get_model, handle_response_openai_compat, ImageFormat};
+use super::formats::openai::{
+    create_embeddings_request, create_request, embeddings_from_response, get_usage,
+    response_to_message, set_response_schema,
+};
+use super::utils::{
+    embed_in_batches, emit_debug_trace, get_model, handle_response_openai_compat, ImageFormat,
+};
 use crate::message::Message;
 use crate::model::ModelConfig;
 use mcp_core::tool::Tool;
@@ -17,14 +26,19 @@ pub const AZURE_DOC_URL: &str =
     "https://learn.microsoft.com/en-us/azure/ai-services/openai/concepts/models";
 pub const AZURE_DEFAULT_API_VERSION: &str = "2024-10-21";
 pub const AZURE_OPENAI_KNOWN_MODELS: &[&str] = &["gpt-4o", "gpt-4o-mini", "gpt-4"];
+/// The most inputs Azure OpenAI accepts in one embeddings request
+const AZURE_EMBEDDING_BATCH_SIZE: usize = 2048;
 
 #[derive(Debug, serde::Serialize)]
 pub struct AzureProvider {
     #[serde(skip)]
     client: Client,
     endpoint: String,
-    api_key: String,
+    #[serde(skip)]
+    auth: AzureAuth,
     deployment_name: String,
+    /// Deployment of an embedding model, which Azure serves separately from the chat model
+    embedding_deployment_name: Option<String>,
     api_version: String,
     model: ModelConfig,
 }
@@ -39,9 +53,11 @@ impl Default for AzureProvider {
 impl AzureProvider {
     pub fn from_env(model: ModelConfig) -> Result<Self> {
         let config = crate::config::Config::global();
-        let api_key: String = config.get_secret("AZURE_OPENAI_API_KEY")?;
+        let auth = AzureAuth::from_env()?;
         let endpoint: String = config.get("AZURE_OPENAI_ENDPOINT")?;
         let deployment_name: String = config.get("AZURE_OPENAI_DEPLOYMENT_NAME")?;
+        let embedding_deployment_name: Option<String> =
+            config.get("AZURE_OPENAI_EMBEDDING_DEPLOYMENT_NAME").ok();
         let api_version: String = config
             .get("AZURE_OPENAI_API_VERSION")
             .unwrap_or_else(|_| AZURE_DEFAULT_API_VERSION.to_string());
@@ -53,33 +69,65 @@ impl AzureProvider {
         Ok(Self {
             client,
             endpoint,
-            api_key,
+            auth,
             deployment_name,
+            embedding_deployment_name,
             api_version,
             model,
         })
     }
 
-    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+    async fn post(
+        &self,
+        deployment_name: &str,
+        operation: &str,
+        payload: &Value,
+    ) -> Result<Value, ProviderError> {
         let mut base_url = url::Url::parse(&self.endpoint)
             .map_err(|e| ProviderError::RequestFailed(format!("Invalid base URL: {e}")))?;
 
         base_url.set_path(&format!(
-            "openai/deployments/{}/chat/completions",
-            self.deployment_name
+            "openai/deployments/{}/{}",
+            deployment_name, operation
         ));
         base_url.set_query(Some(&format!("api-version={}", self.api_version)));
 
+        let (auth_header, auth_value) = self.auth.header().await?;
         let response: reqwest::Response = self
             .client
             .post(base_url)
-            .header("api-key", &self.api_key)
-            .json(&payload)
+            .header(auth_header, auth_value)
+            .json(payload)
             .send()
             .await?;
 
         handle_response_openai_compat(response).await
     }
+
+    async fn complete_request(
+        &self,
+        payload: Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let response = self
+            .post(&self.deployment_name, "chat/completions", &payload)
+            .await?;
+
+        let message = response_to_message(response.clone())?;
+        let usage = match get_usage(&response) {
+            Ok(usage) => usage,
+            Err(ProviderError::UsageError(e)) => {
+                tracing::debug!("Failed to get usage data: {}", e);
+                Usage::default()
+            }
+            Err(e) => return Err(e),
+        };
+        let model = get_model(&response);
+        emit_debug_trace(self, &payload, &response, &usage);
+        Ok((
+            message,
+            ProviderUsage::new(model, usage).with_provider("azure_openai"),
+        ))
+    }
 }
 
 #[async_trait]
@@ -96,7 +144,6 @@ impl Provider for AzureProvider {
                 .collect(),
             AZURE_DOC_URL,
             vec![
-                ConfigKey::new("AZURE_OPENAI_API_KEY", true, true, None),
                 ConfigKey::new("AZURE_OPENAI_ENDPOINT", true, false, None),
                 ConfigKey::new(
                     "AZURE_OPENAI_DEPLOYMENT_NAME",
@@ -110,6 +157,12 @@ impl Provider for AzureProvider {
                     false,
                     Some("Azure OpenAI API version, default: 2024-10-21"),
                 ),
+                ConfigKey::new("AZURE_OPENAI_AUTH_TYPE", false, false, Some("api_key")),
+                ConfigKey::new("AZURE_OPENAI_API_KEY", false, true, None),
+                ConfigKey::new("AZURE_TENANT_ID", false, false, None),
+                ConfigKey::new("AZURE_CLIENT_ID", false, false, None),
+                ConfigKey::new("AZURE_CLIENT_SECRET", false, true, None),
+                ConfigKey::new("AZURE_OPENAI_EMBEDDING_DEPLOYMENT_NAME", false, false, None),
             ],
         )
     }
@@ -118,6 +171,30 @@ impl Provider for AzureProvider {
         self.model.clone()
     }
 
+    async fn embed(&self, texts: &[String]) -> Result<Embeddings, ProviderError> {
+        let deployment_name = self.embedding_deployment_name.as_deref().ok_or_else(|| {
+            ProviderError::NotSupported(
+                "set AZURE_OPENAI_EMBEDDING_DEPLOYMENT_NAME to the deployment of an embedding model"
+                    .to_string(),
+            )
+        })?;
+
+        let (vectors, usage) = embed_in_batches(texts, AZURE_EMBEDDING_BATCH_SIZE, |batch| {
+            let payload = create_embeddings_request(deployment_name, &batch);
+            async move {
+                let response = self.post(deployment_name, "embeddings", &payload).await?;
+                embeddings_from_response(&response)
+            }
+        })
+        .await?;
+
+        Ok(Embeddings {
+            vectors,
+            usage: ProviderUsage::new(deployment_name.to_string(), usage)
+                .with_provider("azure_openai"),
+        })
+    }
+
     #[tracing::instrument(
         skip(self, system, messages, tools),
         fields(model_config, input, output, input_tokens, output_tokens, total_tokens)
@@ -129,19 +206,76 @@ impl Provider for AzureProvider {
         tools: &[Tool],
     ) -> Result<(Message, ProviderUsage), ProviderError> {
         let payload = create_request(&self.model, system, messages, tools, &ImageFormat::OpenAi)?;
-        let response = self.post(payload.clone()).await?;
+        self.complete_request(payload).await
+    }
 
-        let message = response_to_message(response.clone())?;
-        let usage = match get_usage(&response) {
-            Ok(usage) => usage,
-            Err(ProviderError::UsageError(e)) => {
-                tracing::debug!("Failed to get usage data: {}", e);
-                Usage::default()
-            }
-            Err(e) => return Err(e),
-        };
-        let model = get_model(&response);
-        emit_debug_trace(self, &payload, &response, &usage);
-        Ok((message, ProviderUsage::new(model, usage)))
+    /// Uses the json_schema response format on models with JSON mode
+    async fn complete_structured(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        if !self.model.capabilities.json_mode {
+            return complete_structured_with_prompt(self, system, messages, schema).await;
+        }
+        let mut payload = create_request(&self.model, system, messages, &[], &ImageFormat::OpenAi)?;
+        set_response_schema(&mut payload, schema);
+        self.complete_request(payload).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::azure_auth::AzureCredentials;
+    use serde_json::json;
+    use wiremock::matchers::{header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn provider(endpoint: String, embedding_deployment_name: Option<&str>) -> AzureProvider {
+        AzureProvider {
+            client: Client::new(),
+            endpoint,
+            auth: AzureAuth::new(AzureCredentials::ApiKey("key".to_string())),
+            deployment_name: "gpt-4o".to_string(),
+            embedding_deployment_name: embedding_deployment_name.map(String::from),
+            api_version: AZURE_DEFAULT_API_VERSION.to_string(),
+            model: ModelConfig::new("gpt-4o".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embed() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/openai/deployments/embeddings-small/embeddings"))
+            .and(query_param("api-version", AZURE_DEFAULT_API_VERSION))
+            .and(header("api-key", "key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": [
+                    {"index": 1, "embedding": [0.0, 1.0]},
+                    {"index": 0, "embedding": [1.0, 0.0]}
+                ],
+                "usage": {"prompt_tokens": 4, "total_tokens": 4}
+            })))
+            .mount(&server)
+            .await;
+
+        let provider = provider(server.uri(), Some("embeddings-small"));
+        let embeddings = provider
+            .embed(&["first".to_string(), "second".to_string()])
+            .await?;
+        assert_eq!(embeddings.vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert_eq!(embeddings.usage.model, "embeddings-small");
+        assert_eq!(embeddings.usage.usage.input_tokens, Some(4));
+
+        // Without an embedding deployment there is nothing to call
+        let provider = self::provider(server.uri(), None);
+        assert!(matches!(
+            provider.embed(&["first".to_string()]).await,
+            Err(ProviderError::NotSupported(_))
+        ));
+        Ok(())
     }
 }
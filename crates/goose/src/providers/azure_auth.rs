@@ -0,0 +1,390 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use super::base::ConfigKey;
+use super::errors::ProviderError;
+use crate::config::Config;
+
+pub const AZURE_DEFAULT_AUTHORITY_HOST: &str = "https://login.microsoftonline.com";
+pub const AZURE_IMDS_ENDPOINT: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+pub const AZURE_COGNITIVE_SERVICES_RESOURCE: &str = "https://cognitiveservices.azure.com";
+
+/// Tokens are renewed this long before they expire, so a request never carries a stale one
+const TOKEN_REFRESH_MARGIN: chrono::Duration = chrono::Duration::minutes(5);
+
+/// How requests to Azure OpenAI are authenticated, set through AZURE_OPENAI_AUTH_TYPE
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AzureAuthType {
+    /// The resource's api key, sent in the api-key header
+    #[default]
+    ApiKey,
+    /// An Entra ID service principal, exchanging its client secret for tokens
+    ClientCredentials,
+    /// The identity of the machine goose runs on, through the instance metadata service
+    ManagedIdentity,
+}
+
+impl AzureAuthType {
+    pub const ALL: [AzureAuthType; 3] = [
+        AzureAuthType::ApiKey,
+        AzureAuthType::ClientCredentials,
+        AzureAuthType::ManagedIdentity,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AzureAuthType::ApiKey => "api_key",
+            AzureAuthType::ClientCredentials => "client_credentials",
+            AzureAuthType::ManagedIdentity => "managed_identity",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            AzureAuthType::ApiKey => "API key of the Azure OpenAI resource",
+            AzureAuthType::ClientCredentials => "Entra ID service principal with a client secret",
+            AzureAuthType::ManagedIdentity => "Managed identity of this machine",
+        }
+    }
+
+    /// The settings needed by this kind of authentication
+    pub fn config_keys(&self) -> Vec<ConfigKey> {
+        match self {
+            AzureAuthType::ApiKey => vec![ConfigKey::new("AZURE_OPENAI_API_KEY", true, true, None)],
+            AzureAuthType::ClientCredentials => vec![
+                ConfigKey::new("AZURE_TENANT_ID", true, false, None),
+                ConfigKey::new("AZURE_CLIENT_ID", true, false, None),
+                ConfigKey::new("AZURE_CLIENT_SECRET", true, true, None),
+                ConfigKey::new(
+                    "AZURE_AUTHORITY_HOST",
+                    false,
+                    false,
+                    Some(AZURE_DEFAULT_AUTHORITY_HOST),
+                ),
+            ],
+            AzureAuthType::ManagedIdentity => vec![
+                ConfigKey::new("AZURE_CLIENT_ID", false, false, None),
+                ConfigKey::new(
+                    "AZURE_MANAGED_IDENTITY_ENDPOINT",
+                    false,
+                    false,
+                    Some(AZURE_IMDS_ENDPOINT),
+                ),
+            ],
+        }
+    }
+}
+
+impl std::str::FromStr for AzureAuthType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|auth_type| auth_type.as_str() == s.to_lowercase())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown Azure auth type {}, expected api_key, client_credentials or managed_identity",
+                    s
+                )
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum AzureCredentials {
+    ApiKey(String),
+    ClientCredentials {
+        authority_host: String,
+        tenant_id: String,
+        client_id: String,
+        client_secret: String,
+    },
+    ManagedIdentity {
+        endpoint: String,
+        client_id: Option<String>,
+        /// Sent as X-IDENTITY-HEADER, which App Service style identity endpoints require
+        identity_header: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct CachedToken {
+    access_token: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Produces the auth header for Azure OpenAI requests, fetching and caching Entra ID
+/// tokens when an api key isn't used
+#[derive(Debug)]
+pub struct AzureAuth {
+    client: Client,
+    credentials: AzureCredentials,
+    token: Mutex<Option<CachedToken>>,
+}
+
+impl AzureAuth {
+    pub fn new(credentials: AzureCredentials) -> Self {
+        Self {
+            client: Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .unwrap_or_default(),
+            credentials,
+            token: Mutex::new(None),
+        }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let config = Config::global();
+        let auth_type = match config.get::<String>("AZURE_OPENAI_AUTH_TYPE") {
+            Ok(auth_type) => auth_type.parse()?,
+            Err(_) => AzureAuthType::default(),
+        };
+
+        let credentials = match auth_type {
+            AzureAuthType::ApiKey => {
+                AzureCredentials::ApiKey(config.get_secret("AZURE_OPENAI_API_KEY")?)
+            }
+            AzureAuthType::ClientCredentials => AzureCredentials::ClientCredentials {
+                authority_host: config
+                    .get("AZURE_AUTHORITY_HOST")
+                    .unwrap_or_else(|_| AZURE_DEFAULT_AUTHORITY_HOST.to_string()),
+                tenant_id: config.get("AZURE_TENANT_ID")?,
+                client_id: config.get("AZURE_CLIENT_ID")?,
+                client_secret: config.get_secret("AZURE_CLIENT_SECRET")?,
+            },
+            AzureAuthType::ManagedIdentity => AzureCredentials::ManagedIdentity {
+                // App Service and Functions publish their identity endpoint in the environment
+                endpoint: config
+                    .get("AZURE_MANAGED_IDENTITY_ENDPOINT")
+                    .or_else(|_| std::env::var("IDENTITY_ENDPOINT"))
+                    .unwrap_or_else(|_| AZURE_IMDS_ENDPOINT.to_string()),
+                client_id: config.get("AZURE_CLIENT_ID").ok(),
+                identity_header: std::env::var("IDENTITY_HEADER").ok(),
+            },
+        };
+
+        Ok(Self::new(credentials))
+    }
+
+    /// The header name and value which authenticate a request
+    pub async fn header(&self) -> Result<(&'static str, String), ProviderError> {
+        if let AzureCredentials::ApiKey(api_key) = &self.credentials {
+            return Ok(("api-key", api_key.clone()));
+        }
+
+        let mut cached = self.token.lock().await;
+        if let Some(token) = cached.as_ref() {
+            if token.expires_at - TOKEN_REFRESH_MARGIN > Utc::now() {
+                return Ok(("Authorization", format!("Bearer {}", token.access_token)));
+            }
+        }
+
+        let token = self.fetch_token().await?;
+        let header = format!("Bearer {}", token.access_token);
+        *cached = Some(token);
+        Ok(("Authorization", header))
+    }
+
+    async fn fetch_token(&self) -> Result<CachedToken, ProviderError> {
+        let request = match &self.credentials {
+            AzureCredentials::ApiKey(_) => unreachable!("api keys don't need a token"),
+            AzureCredentials::ClientCredentials {
+                authority_host,
+                tenant_id,
+                client_id,
+                client_secret,
+            } => {
+                let url = format!(
+                    "{}/{}/oauth2/v2.0/token",
+                    authority_host.trim_end_matches('/'),
+                    tenant_id
+                );
+                let scope = format!("{}/.default", AZURE_COGNITIVE_SERVICES_RESOURCE);
+                self.client.post(url).form(&[
+                    ("grant_type", "client_credentials"),
+                    ("client_id", client_id),
+                    ("client_secret", client_secret),
+                    ("scope", &scope),
+                ])
+            }
+            AzureCredentials::ManagedIdentity {
+                endpoint,
+                client_id,
+                identity_header,
+            } => {
+                let api_version = if identity_header.is_some() {
+                    "2019-08-01"
+                } else {
+                    "2018-02-01"
+                };
+                let mut query = vec![
+                    ("api-version", api_version),
+                    ("resource", AZURE_COGNITIVE_SERVICES_RESOURCE),
+                ];
+                if let Some(client_id) = client_id {
+                    query.push(("client_id", client_id));
+                }
+                let mut request = self
+                    .client
+                    .get(endpoint)
+                    .query(&query)
+                    .header("Metadata", "true");
+                if let Some(identity_header) = identity_header {
+                    request = request.header("X-IDENTITY-HEADER", identity_header);
+                }
+                request
+            }
+        };
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(ProviderError::Authentication(format!(
+                "Failed to get an Entra ID token ({}): {}",
+                status, body
+            )));
+        }
+
+        let body: Value = response.json().await?;
+        parse_token(&body)
+    }
+}
+
+/// Token endpoints disagree on whether times are numbers or strings, and managed identity
+/// endpoints may only send an absolute expiry
+fn parse_token(body: &Value) -> Result<CachedToken, ProviderError> {
+    let access_token = body
+        .get("access_token")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| {
+            ProviderError::Authentication("access_token not found in token response".to_string())
+        })?
+        .to_string();
+
+    let seconds = |key: &str| {
+        body.get(key).and_then(|v| match v {
+            Value::Number(n) => n.as_i64(),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        })
+    };
+    let expires_at = match (seconds("expires_in"), seconds("expires_on")) {
+        (Some(expires_in), _) => Utc::now() + chrono::Duration::seconds(expires_in),
+        (None, Some(expires_on)) => DateTime::from_timestamp(expires_on, 0).unwrap_or_default(),
+        (None, None) => Utc::now() + chrono::Duration::hours(1),
+    };
+
+    Ok(CachedToken {
+        access_token,
+        expires_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{body_string_contains, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_client_credentials_token_is_cached() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/my-tenant/oauth2/v2.0/token"))
+            .and(body_string_contains("grant_type=client_credentials"))
+            .and(body_string_contains("client_secret=shh"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "token_type": "Bearer",
+                "expires_in": 3599,
+                "access_token": "sp-token"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let auth = AzureAuth::new(AzureCredentials::ClientCredentials {
+            authority_host: server.uri(),
+            tenant_id: "my-tenant".to_string(),
+            client_id: "my-client".to_string(),
+            client_secret: "shh".to_string(),
+        });
+
+        for _ in 0..2 {
+            let (name, value) = auth.header().await?;
+            assert_eq!(name, "Authorization");
+            assert_eq!(value, "Bearer sp-token");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_managed_identity_token_is_refreshed_near_expiry() -> Result<()> {
+        let server = MockServer::start().await;
+        // IMDS reports expiry as strings, and this token is already inside the refresh margin
+        Mock::given(method("GET"))
+            .and(path("/metadata/identity/oauth2/token"))
+            .and(header("Metadata", "true"))
+            .and(query_param("resource", AZURE_COGNITIVE_SERVICES_RESOURCE))
+            .and(query_param("client_id", "user-assigned"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "mi-token",
+                "expires_in": "60",
+                "expires_on": "1700000000"
+            })))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let auth = AzureAuth::new(AzureCredentials::ManagedIdentity {
+            endpoint: format!("{}/metadata/identity/oauth2/token", server.uri()),
+            client_id: Some("user-assigned".to_string()),
+            identity_header: None,
+        });
+
+        assert_eq!(auth.header().await?.1, "Bearer mi-token");
+        assert_eq!(auth.header().await?.1, "Bearer mi-token");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_token_failure_is_an_authentication_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(401).set_body_json(json!({"error": "invalid_client"})),
+            )
+            .mount(&server)
+            .await;
+
+        let auth = AzureAuth::new(AzureCredentials::ClientCredentials {
+            authority_host: server.uri(),
+            tenant_id: "my-tenant".to_string(),
+            client_id: "my-client".to_string(),
+            client_secret: "wrong".to_string(),
+        });
+
+        let error = auth.header().await.unwrap_err();
+        assert!(matches!(error, ProviderError::Authentication(_)));
+        assert!(error.to_string().contains("invalid_client"));
+    }
+
+    #[test]
+    fn test_parse_auth_type() -> Result<()> {
+        assert_eq!(
+            "managed_identity".parse::<AzureAuthType>()?,
+            AzureAuthType::ManagedIdentity
+        );
+        assert!("password".parse::<AzureAuthType>().is_err());
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
 use anyhow::Result;
+use futures::stream::BoxStream;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 use super::errors::ProviderError;
 use crate::message::Message;
@@ -17,13 +19,16 @@ pub struct ProviderMetadata {
     pub description: String,
     /// The default/recommended model for this provider
     pub default_model: String,
-    /// A list of currently known models
-    /// TODO: eventually query the apis directly
+    /// A list of currently known models, used when the provider can't list them itself
     pub known_models: Vec<String>,
     /// Link to the docs where models can be found
     pub model_doc_link: String,
     /// Required configuration keys
     pub config_keys: Vec<ConfigKey>,
+    /// Whether authenticating may need the user, such as a browser sign in, so the provider
+    /// shouldn't be contacted in the background
+    #[serde(default)]
+    pub interactive_auth: bool,
 }
 
 impl ProviderMetadata {
@@ -44,9 +49,15 @@ impl ProviderMetadata {
             known_models,
             model_doc_link: model_doc_link.to_string(),
             config_keys,
+            interactive_auth: false,
         }
     }
 
+    pub fn with_interactive_auth(mut self) -> Self {
+        self.interactive_auth = true;
+        self
+    }
+
     pub fn empty() -> Self {
         Self {
             name: "".to_string(),
@@ -56,6 +67,7 @@ impl ProviderMetadata {
             known_models: vec![],
             model_doc_link: "".to_string(),
             config_keys: vec![],
+            interactive_auth: false,
         }
     }
 }
@@ -83,19 +95,78 @@ impl ConfigKey {
 pub struct ProviderUsage {
     pub model: String,
     pub usage: Usage,
+    /// The provider which answered, set when it could have been one of several
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Cost in US dollars, when the model's pricing is known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    /// Lookups in the development response cache, when it is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_cache: Option<ResponseCacheStats>,
+}
+
+/// How many calls were answered from the response cache, and how many went to the provider
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseCacheStats {
+    pub hits: u32,
+    pub misses: u32,
+}
+
+impl std::ops::Add for ResponseCacheStats {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            hits: self.hits + other.hits,
+            misses: self.misses + other.misses,
+        }
+    }
 }
 
 impl ProviderUsage {
     pub fn new(model: String, usage: Usage) -> Self {
-        Self { model, usage }
+        Self {
+            model,
+            usage,
+            provider: None,
+            cost: None,
+            response_cache: None,
+        }
+    }
+
+    pub fn with_provider(mut self, provider: &str) -> Self {
+        self.provider = Some(provider.to_string());
+        self
+    }
+
+    pub fn with_cost(mut self, cost: Option<f64>) -> Self {
+        self.cost = cost;
+        self
+    }
+
+    /// Count this call as answered from the response cache, or not
+    pub fn with_response_cache(mut self, hit: bool) -> Self {
+        self.response_cache = Some(ResponseCacheStats {
+            hits: hit as u32,
+            misses: !hit as u32,
+        });
+        self
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Usage {
+    /// All input tokens, including those written to or read from the prompt cache
     pub input_tokens: Option<i32>,
     pub output_tokens: Option<i32>,
     pub total_tokens: Option<i32>,
+    /// Input tokens written to the prompt cache, if the provider reports them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation_input_tokens: Option<i32>,
+    /// Input tokens read from the prompt cache, if the provider reports them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_input_tokens: Option<i32>,
 }
 
 impl Usage {
@@ -108,12 +179,53 @@ impl Usage {
             input_tokens,
             output_tokens,
             total_tokens,
+            cache_creation_input_tokens: None,
+            cache_read_input_tokens: None,
         }
     }
+
+    /// Set the prompt cache token counts
+    pub fn with_cache_tokens(
+        mut self,
+        cache_creation_input_tokens: Option<i32>,
+        cache_read_input_tokens: Option<i32>,
+    ) -> Self {
+        self.cache_creation_input_tokens = cache_creation_input_tokens;
+        self.cache_read_input_tokens = cache_read_input_tokens;
+        self
+    }
+}
+
+/// A piece of a streamed completion
+#[derive(Debug, Clone)]
+pub enum CompletionChunk {
+    /// Text generated since the previous chunk
+    Text(String),
+    /// Reasoning generated since the previous chunk, for models with extended thinking
+    Thinking(String),
+    /// A fragment of a tool call. The id and name arrive with the first fragment for each index,
+    /// followed by pieces of the JSON arguments
+    ToolCall {
+        index: usize,
+        id: Option<String>,
+        name: Option<String>,
+        arguments: String,
+    },
+    /// The complete response, always the last chunk of a successful stream
+    Done(Message, ProviderUsage),
 }
 
+pub type CompletionStream = BoxStream<'static, Result<CompletionChunk, ProviderError>>;
+
 use async_trait::async_trait;
 
+/// Vectors for a batch of texts, in the order the texts were given
+#[derive(Debug, Clone)]
+pub struct Embeddings {
+    pub vectors: Vec<Vec<f32>>,
+    pub usage: ProviderUsage,
+}
+
 /// Base trait for AI providers (OpenAI, Anthropic, etc)
 #[async_trait]
 pub trait Provider: Send + Sync {
@@ -142,16 +254,113 @@ pub trait Provider: Send + Sync {
         tools: &[Tool],
     ) -> Result<(Message, ProviderUsage), ProviderError>;
 
+    /// Generate the next message like `complete`, yielding text and tool calls as they arrive
+    ///
+    /// Providers which can't stream use the default, which yields the whole response at once
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<CompletionStream, ProviderError> {
+        let (message, usage) = self.complete(system, messages, tools).await?;
+        Ok(Box::pin(futures::stream::once(async move {
+            Ok(CompletionChunk::Done(message, usage))
+        })))
+    }
+
+    /// Generate a final answer constrained to JSON matching `schema`, without calling tools
+    ///
+    /// Returns an assistant message whose text is the JSON. Providers with a structured output
+    /// mode use it, the default asks for the JSON in the system prompt. Either way the caller
+    /// still has to validate the result, since not every mode guarantees it matches
+    async fn complete_structured(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        complete_structured_with_prompt(self, system, messages, schema).await
+    }
+
+    /// List the models this provider can serve, by asking its API
+    ///
+    /// Providers which can't list their models return an empty list, and callers fall back to
+    /// `ProviderMetadata::known_models`
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        Ok(Vec::new())
+    }
+
+    /// Embed texts as vectors for similarity search, using the provider's embedding model
+    ///
+    /// The model can be chosen with GOOSE_EMBEDDING_MODEL. Providers without an embeddings API
+    /// return `ProviderError::NotSupported`
+    async fn embed(&self, _texts: &[String]) -> Result<Embeddings, ProviderError> {
+        Err(ProviderError::NotSupported(
+            "this provider does not offer embeddings".to_string(),
+        ))
+    }
+
     /// Get the model config from the provider
     fn get_model_config(&self) -> ModelConfig;
 }
 
+/// Ask for JSON matching the schema through the system prompt, for models without a structured
+/// output mode
+///
+/// No tools are offered, so earlier tool calls are sent as text, since APIs such as Anthropic
+/// and Bedrock reject tool content in a request without tools
+pub async fn complete_structured_with_prompt<P: Provider + ?Sized>(
+    provider: &P,
+    system: &str,
+    messages: &[Message],
+    schema: &Value,
+) -> Result<(Message, ProviderUsage), ProviderError> {
+    let system = format!(
+        "{}\n\nRespond with only a JSON value matching this JSON Schema, without any other text \
+        or code fences:\n{}",
+        system,
+        serde_json::to_string_pretty(schema).unwrap_or_default()
+    );
+    let messages = super::utils::tool_messages_as_text(messages);
+    provider.complete(&system, &messages, &[]).await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use serde_json::json;
 
+    #[tokio::test]
+    async fn test_structured_prompt_sends_tool_calls_as_text() -> Result<()> {
+        let provider = crate::providers::mock::MockProvider::new("mock").with_text("{}");
+        let messages = [
+            Message::user().with_text("How many files?"),
+            Message::assistant().with_tool_request(
+                "1",
+                Ok(mcp_core::tool::ToolCall::new(
+                    "developer__shell",
+                    json!({"command": "ls"}),
+                )),
+            ),
+            Message::user()
+                .with_tool_response("1", Ok(vec![mcp_core::content::Content::text("a.rs b.rs")])),
+        ];
+        complete_structured_with_prompt(&provider, "system", &messages, &json!({"type": "object"}))
+            .await?;
+
+        let request = provider.requests().last().unwrap();
+        assert_eq!(request.messages.len(), 3);
+        assert!(request
+            .messages
+            .iter()
+            .flat_map(|message| &message.content)
+            .all(|content| content.as_text().is_some()));
+        assert!(request.messages[2].as_concat_text().contains("a.rs b.rs"));
+        Ok(())
+    }
+
     #[test]
     fn test_usage_creation() {
         let usage = Usage::new(Some(10), Some(20), Some(30));
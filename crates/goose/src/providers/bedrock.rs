@@ -1,18 +1,22 @@
 use anyhow::Result;
 use async_trait::async_trait;
 use aws_sdk_bedrockruntime::operation::converse::ConverseError;
+use aws_sdk_bedrockruntime::operation::invoke_model::InvokeModelError;
+use aws_sdk_bedrockruntime::primitives::Blob;
 use aws_sdk_bedrockruntime::{types as bedrock, Client};
 use mcp_core::Tool;
+use serde_json::{json, Value};
 
-use super::base::{Provider, ProviderMetadata, ProviderUsage};
+use super::base::{Embeddings, Provider, ProviderMetadata, ProviderUsage, Usage};
 use super::errors::ProviderError;
 use crate::message::Message;
 use crate::model::ModelConfig;
-use crate::providers::utils::emit_debug_trace;
+use crate::providers::utils::{embed_in_batches, embedding_model, emit_debug_trace, get_embedding};
 
 // Import the migrated helper functions from providers/formats/bedrock.rs
 use super::formats::bedrock::{
-    from_bedrock_message, from_bedrock_usage, to_bedrock_message, to_bedrock_tool_config,
+    from_bedrock_message, from_bedrock_usage, to_bedrock_inference_config, to_bedrock_message,
+    to_bedrock_tool_config,
 };
 
 pub const BEDROCK_DOC_LINK: &str =
@@ -24,6 +28,10 @@ pub const BEDROCK_KNOWN_MODELS: &[&str] = &[
     "anthropic.claude-3-5-sonnet-20241022-v2:0",
 ];
 
+pub const BEDROCK_DEFAULT_EMBEDDING_MODEL: &str = "amazon.titan-embed-text-v2:0";
+/// The most texts a Cohere embedding model accepts in one call, Titan takes only one
+const BEDROCK_COHERE_EMBEDDING_BATCH_SIZE: usize = 96;
+
 #[derive(Debug, serde::Serialize)]
 pub struct BedrockProvider {
     #[serde(skip)]
@@ -38,6 +46,73 @@ impl BedrockProvider {
 
         Ok(Self { client, model })
     }
+
+    async fn invoke(&self, model_id: &str, body: &Value) -> Result<Value, ProviderError> {
+        let response = self
+            .client
+            .invoke_model()
+            .model_id(model_id)
+            .content_type("application/json")
+            .accept("application/json")
+            .body(Blob::new(body.to_string()))
+            .send()
+            .await
+            .map_err(|err| match err.into_service_error() {
+                InvokeModelError::AccessDeniedException(err) => {
+                    ProviderError::Authentication(format!("Failed to call Bedrock: {:?}", err))
+                }
+                InvokeModelError::ThrottlingException(err) => ProviderError::RateLimitExceeded {
+                    details: format!("Failed to call Bedrock: {:?}", err),
+                    retry_delay: None,
+                },
+                InvokeModelError::ValidationException(err) => {
+                    ProviderError::RequestFailed(format!("Failed to call Bedrock: {:?}", err))
+                }
+                err => ProviderError::ServerError(format!("Failed to call Bedrock: {:?}", err)),
+            })?;
+
+        serde_json::from_slice(response.body().as_ref()).map_err(|e| {
+            ProviderError::RequestFailed(format!("Invalid response from Bedrock: {e}"))
+        })
+    }
+
+    /// Titan models embed one text per call and report its token count
+    async fn embed_titan(
+        &self,
+        model_id: &str,
+        texts: Vec<String>,
+    ) -> Result<(Vec<Vec<f32>>, Usage), ProviderError> {
+        let mut vectors = Vec::with_capacity(texts.len());
+        let mut input_tokens = 0;
+        for text in texts {
+            let response = self.invoke(model_id, &json!({"inputText": text})).await?;
+            vectors.push(get_embedding(&response["embedding"])?);
+            input_tokens += response["inputTextTokenCount"].as_i64().unwrap_or(0) as i32;
+        }
+        Ok((
+            vectors,
+            Usage::new(Some(input_tokens), None, Some(input_tokens)),
+        ))
+    }
+
+    /// Cohere models take a batch of texts but don't report usage
+    async fn embed_cohere(
+        &self,
+        model_id: &str,
+        texts: Vec<String>,
+    ) -> Result<(Vec<Vec<f32>>, Usage), ProviderError> {
+        let body = json!({"texts": texts, "input_type": "search_document"});
+        let response = self.invoke(model_id, &body).await?;
+        let vectors = response["embeddings"]
+            .as_array()
+            .ok_or_else(|| {
+                ProviderError::RequestFailed("No embeddings in the Bedrock response".to_string())
+            })?
+            .iter()
+            .map(get_embedding)
+            .collect::<Result<_, _>>()?;
+        Ok((vectors, Usage::default()))
+    }
 }
 
 impl Default for BedrockProvider {
@@ -65,6 +140,26 @@ impl Provider for BedrockProvider {
         self.model.clone()
     }
 
+    async fn embed(&self, texts: &[String]) -> Result<Embeddings, ProviderError> {
+        let model_id = embedding_model(BEDROCK_DEFAULT_EMBEDDING_MODEL);
+        let (vectors, usage) = if model_id.starts_with("cohere.") {
+            embed_in_batches(texts, BEDROCK_COHERE_EMBEDDING_BATCH_SIZE, |batch| {
+                self.embed_cohere(&model_id, batch)
+            })
+            .await?
+        } else {
+            embed_in_batches(texts, texts.len(), |batch| {
+                self.embed_titan(&model_id, batch)
+            })
+            .await?
+        };
+
+        Ok(Embeddings {
+            vectors,
+            usage: ProviderUsage::new(model_id, usage).with_provider("bedrock"),
+        })
+    }
+
     #[tracing::instrument(
         skip(self, system, messages, tools),
         fields(model_config, input, output, input_tokens, output_tokens, total_tokens)
@@ -90,8 +185,9 @@ impl Provider for BedrockProvider {
             ));
 
         if !tools.is_empty() {
-            request = request.tool_config(to_bedrock_tool_config(tools)?);
+            request = request.tool_config(to_bedrock_tool_config(messages, tools, &self.model)?);
         }
+        request = request.set_inference_config(to_bedrock_inference_config(&self.model)?);
 
         let response = request.send().await;
 
@@ -102,9 +198,10 @@ impl Provider for BedrockProvider {
                     ConverseError::AccessDeniedException(err) => {
                         ProviderError::Authentication(format!("Failed to call Bedrock: {:?}", err))
                     }
-                    ConverseError::ThrottlingException(err) => ProviderError::RateLimitExceeded(
-                        format!("Failed to call Bedrock: {:?}", err),
-                    ),
+                    ConverseError::ThrottlingException(err) => ProviderError::RateLimitExceeded {
+                        details: format!("Failed to call Bedrock: {:?}", err),
+                        retry_delay: None,
+                    },
                     ConverseError::ValidationException(err)
                         if err
                             .message()
@@ -156,7 +253,97 @@ impl Provider for BedrockProvider {
             &usage,
         );
 
-        let provider_usage = ProviderUsage::new(model_name.to_string(), usage);
+        let provider_usage =
+            ProviderUsage::new(model_name.to_string(), usage).with_provider("bedrock");
         Ok((message, provider_usage))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ToolChoice;
+    use aws_sdk_bedrockruntime::config::{Credentials, Region};
+    use wiremock::matchers::{body_json, body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn provider(server: &MockServer, model: ModelConfig) -> BedrockProvider {
+        let config = aws_sdk_bedrockruntime::Config::builder()
+            .behavior_version_latest()
+            .region(Region::new("us-east-1"))
+            .endpoint_url(server.uri())
+            .credentials_provider(Credentials::new("key", "secret", None, None, "test"))
+            .build();
+        BedrockProvider {
+            client: Client::from_conf(config),
+            model,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_complete_sends_generation_settings() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(json!({
+                "inferenceConfig": {"maxTokens": 100, "topP": 0.5, "stopSequences": ["END"]},
+                "toolConfig": {"toolChoice": {"any": {}}}
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "output": {"message": {"role": "assistant", "content": [{"text": "Hi"}]}},
+                "stopReason": "end_turn",
+                "usage": {"inputTokens": 5, "outputTokens": 1, "totalTokens": 6},
+                "metrics": {"latencyMs": 1}
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let model = ModelConfig::new(BEDROCK_DEFAULT_MODEL.to_string())
+            .with_max_tokens(Some(100))
+            .with_top_p(Some(0.5))
+            .with_stop_sequences(vec!["END".to_string()])
+            .with_tool_choice(Some(ToolChoice::Required));
+        let provider = provider(&server, model.clone());
+        let tools = [Tool::new("developer__shell", "Run a command", json!({}))];
+        let messages = [Message::user().with_text("Hello")];
+        let (message, _) = provider.complete("system", &messages, &tools).await?;
+        assert_eq!(message.as_concat_text(), "Hi");
+
+        // Settings Bedrock has no equivalent for fail before anything is sent
+        let provider = self::provider(&server, model.with_seed(Some(7)));
+        assert!(provider
+            .complete("system", &messages, &tools)
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_embed_with_titan() -> Result<()> {
+        let server = MockServer::start().await;
+        for (text, embedding, tokens) in [("first", [1.0, 0.0], 2), ("second", [0.0, 1.0], 3)] {
+            Mock::given(method("POST"))
+                .and(path(format!(
+                    "/model/{}/invoke",
+                    BEDROCK_DEFAULT_EMBEDDING_MODEL.replace(':', "%3A")
+                )))
+                .and(body_json(json!({"inputText": text})))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "embedding": embedding,
+                    "inputTextTokenCount": tokens
+                })))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let provider = provider(&server, ModelConfig::new(BEDROCK_DEFAULT_MODEL.to_string()));
+        let embeddings = provider
+            .embed(&["first".to_string(), "second".to_string()])
+            .await?;
+        assert_eq!(embeddings.vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert_eq!(embeddings.usage.model, BEDROCK_DEFAULT_EMBEDDING_MODEL);
+        assert_eq!(embeddings.usage.usage.input_tokens, Some(5));
+        Ok(())
+    }
+}
@@ -0,0 +1,438 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use etcetera::{choose_app_strategy, AppStrategy};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::base::{
+    CompletionChunk, CompletionStream, Embeddings, Provider, ProviderMetadata, ProviderUsage,
+};
+use super::errors::ProviderError;
+use super::replay::{normalize_request, normalize_structured_request, request_hash};
+use crate::config::Config;
+use crate::message::Message;
+use crate::model::ModelConfig;
+use mcp_core::tool::Tool;
+
+pub const RESPONSE_CACHE_CONFIG_KEY: &str = "GOOSE_RESPONSE_CACHE";
+
+fn default_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_max_entries() -> usize {
+    1000
+}
+
+fn default_max_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+/// The GOOSE_RESPONSE_CACHE settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseCacheConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// How long a response is reused
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+    /// Total size of the cached responses on disk
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+    /// Where responses are stored, defaults to goose's cache directory
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for ResponseCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: default_ttl_secs(),
+            max_entries: default_max_entries(),
+            max_bytes: default_max_bytes(),
+            dir: None,
+        }
+    }
+}
+
+impl ResponseCacheConfig {
+    pub fn from_config() -> Self {
+        Config::global()
+            .get(RESPONSE_CACHE_CONFIG_KEY)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    created: DateTime<Utc>,
+    message: Message,
+    usage: ProviderUsage,
+}
+
+/// Responses stored as one JSON file per request
+#[derive(Debug)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+    max_entries: usize,
+    max_bytes: u64,
+}
+
+impl ResponseCache {
+    pub fn new(config: &ResponseCacheConfig) -> Result<Self> {
+        let dir = match &config.dir {
+            Some(dir) => dir.clone(),
+            // - macOS:   ~/Library/Caches/goose/responses
+            // - Linux:   ~/.cache/goose/responses
+            // - Windows: ~\AppData\Local\Block\goose\cache\responses
+            None => {
+                choose_app_strategy(crate::config::APP_STRATEGY.clone())?.in_cache_dir("responses")
+            }
+        };
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            ttl: Duration::from_secs(config.ttl_secs),
+            max_entries: config.max_entries,
+            max_bytes: config.max_bytes,
+        })
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    fn is_expired(&self, created: DateTime<Utc>) -> bool {
+        Utc::now()
+            .signed_duration_since(created)
+            .to_std()
+            .is_ok_and(|age| age > self.ttl)
+    }
+
+    pub fn get(&self, key: &str) -> Option<(Message, ProviderUsage)> {
+        let path = self.path(key);
+        let entry: CacheEntry = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+        if self.is_expired(entry.created) {
+            let _ = fs::remove_file(path);
+            return None;
+        }
+        Some((entry.message, entry.usage))
+    }
+
+    pub fn put(&self, key: &str, message: &Message, usage: &ProviderUsage) -> Result<()> {
+        let entry = CacheEntry {
+            created: Utc::now(),
+            message: message.clone(),
+            usage: usage.clone(),
+        };
+        fs::write(self.path(key), serde_json::to_string(&entry)?)?;
+        self.prune()
+    }
+
+    /// Remove expired responses, then the oldest ones until the cache is within its limits
+    fn prune(&self) -> Result<()> {
+        let mut entries = Vec::new();
+        for file in fs::read_dir(&self.dir)? {
+            let file = file?;
+            let metadata = file.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let modified: DateTime<Utc> = metadata.modified()?.into();
+            if self.is_expired(modified) {
+                fs::remove_file(file.path())?;
+            } else {
+                entries.push((modified, metadata.len(), file.path()));
+            }
+        }
+
+        entries.sort_by_key(|(modified, _, _)| *modified);
+        let mut total_bytes: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        let mut count = entries.len();
+        for (_, len, path) in entries {
+            if count <= self.max_entries && total_bytes <= self.max_bytes {
+                break;
+            }
+            fs::remove_file(path)?;
+            count -= 1;
+            total_bytes -= len;
+        }
+        Ok(())
+    }
+}
+
+/// A provider which answers repeated requests from an on-disk cache, for iterating on prompts
+/// and extensions without paying for the same conversation prefix again
+///
+/// Only goose's CLI applies it, and only when enabled in config, so the server never serves
+/// cached responses:
+///
+/// ```yaml
+/// GOOSE_RESPONSE_CACHE:
+///   enabled: true
+///   ttl_secs: 3600
+///   max_entries: 500
+/// ```
+pub struct ResponseCacheProvider {
+    inner: Box<dyn Provider + Send + Sync>,
+    cache: Arc<ResponseCache>,
+}
+
+impl ResponseCacheProvider {
+    pub fn new(inner: Box<dyn Provider + Send + Sync>, cache: ResponseCache) -> Self {
+        Self {
+            inner,
+            cache: Arc::new(cache),
+        }
+    }
+
+    /// Wrap the provider if GOOSE_RESPONSE_CACHE enables the cache
+    pub fn wrap_if_enabled(
+        inner: Box<dyn Provider + Send + Sync>,
+    ) -> Result<Box<dyn Provider + Send + Sync>> {
+        let config = ResponseCacheConfig::from_config();
+        if !config.enabled {
+            return Ok(inner);
+        }
+        let cache = ResponseCache::new(&config)?;
+        tracing::info!("Caching provider responses in {}", cache.dir().display());
+        Ok(Box::new(Self::new(inner, cache)))
+    }
+
+    /// Requests are the same if they'd get the same answer, apart from message timestamps,
+    /// tool call ids and the date in the system prompt
+    fn key(&self, request: Value) -> String {
+        let model = self.inner.get_model_config();
+        let request = json!({
+            "model": model.model_name,
+            "temperature": model.temperature,
+            "max_tokens": model.max_tokens,
+            "thinking_budget": model.thinking_budget,
+            "top_p": model.top_p,
+            "top_k": model.top_k,
+            "stop_sequences": model.stop_sequences,
+            "seed": model.seed,
+            "presence_penalty": model.presence_penalty,
+            "frequency_penalty": model.frequency_penalty,
+            "tool_choice": model.tool_choice,
+            "parallel_tool_calls": model.parallel_tool_calls,
+            "reasoning_effort": model.reasoning_effort,
+            "request": request,
+        });
+        request_hash(&request)
+    }
+
+    fn hit(&self, key: &str) -> Option<(Message, ProviderUsage)> {
+        let (message, usage) = self.cache.get(key)?;
+        tracing::debug!("Response cache hit for {}", key);
+        // Nothing was spent on a cached answer
+        Some((
+            message,
+            usage.with_cost(Some(0.0)).with_response_cache(true),
+        ))
+    }
+}
+
+#[async_trait]
+impl Provider for ResponseCacheProvider {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::empty()
+    }
+
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let key = self.key(normalize_request(system, messages, tools));
+        if let Some(hit) = self.hit(&key) {
+            return Ok(hit);
+        }
+
+        let (message, usage) = self.inner.complete(system, messages, tools).await?;
+        if let Err(e) = self.cache.put(&key, &message, &usage) {
+            tracing::warn!("Failed to cache the response: {}", e);
+        }
+        Ok((message, usage.with_response_cache(false)))
+    }
+
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<CompletionStream, ProviderError> {
+        let key = self.key(normalize_request(system, messages, tools));
+        if let Some((message, usage)) = self.hit(&key) {
+            return Ok(Box::pin(futures::stream::once(async move {
+                Ok(CompletionChunk::Done(message, usage))
+            })));
+        }
+
+        let stream = self.inner.stream(system, messages, tools).await?;
+        let cache = self.cache.clone();
+        Ok(Box::pin(stream.map_ok(move |chunk| match chunk {
+            CompletionChunk::Done(message, usage) => {
+                if let Err(e) = cache.put(&key, &message, &usage) {
+                    tracing::warn!("Failed to cache the response: {}", e);
+                }
+                CompletionChunk::Done(message, usage.with_response_cache(false))
+            }
+            chunk => chunk,
+        })))
+    }
+
+    async fn complete_structured(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let key = self.key(normalize_structured_request(system, messages, schema));
+        if let Some(hit) = self.hit(&key) {
+            return Ok(hit);
+        }
+
+        let (message, usage) = self
+            .inner
+            .complete_structured(system, messages, schema)
+            .await?;
+        if let Err(e) = self.cache.put(&key, &message, &usage) {
+            tracing::warn!("Failed to cache the response: {}", e);
+        }
+        Ok((message, usage.with_response_cache(false)))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        self.inner.list_models().await
+    }
+
+    /// Embeddings aren't cached, only completions
+    async fn embed(&self, texts: &[String]) -> Result<Embeddings, ProviderError> {
+        self.inner.embed(texts).await
+    }
+
+    fn get_model_config(&self) -> ModelConfig {
+        self.inner.get_model_config()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::base::{ResponseCacheStats, Usage};
+    use crate::providers::mock::MockProvider;
+
+    fn cache(dir: &tempfile::TempDir, max_entries: usize) -> Result<ResponseCache> {
+        ResponseCache::new(&ResponseCacheConfig {
+            enabled: true,
+            max_entries,
+            dir: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_repeated_requests_are_cached() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let inner = MockProvider::new("gpt-4o")
+            .with_reply(
+                Message::assistant().with_text("Hi there"),
+                Usage::new(Some(100), Some(5), Some(105)),
+            )
+            .with_text("Something else");
+        let requests = inner.requests();
+        let provider = ResponseCacheProvider::new(Box::new(inner), cache(&dir, 10)?);
+
+        let (_, usage) = provider
+            .complete(
+                "Today is 2025-01-01 10:00:00",
+                &[Message::user().with_text("Hello")],
+                &[],
+            )
+            .await?;
+        assert_eq!(
+            usage.response_cache,
+            Some(ResponseCacheStats { hits: 0, misses: 1 })
+        );
+
+        // The same conversation later in the day is answered from disk
+        let (message, usage) = provider
+            .complete(
+                "Today is 2025-01-01 16:30:00",
+                &[Message::user().with_text("Hello")],
+                &[],
+            )
+            .await?;
+        assert_eq!(message.as_concat_text(), "Hi there");
+        assert_eq!(
+            usage.response_cache,
+            Some(ResponseCacheStats { hits: 1, misses: 0 })
+        );
+        assert_eq!(usage.usage.input_tokens, Some(100));
+        assert_eq!(usage.cost, Some(0.0));
+        assert_eq!(requests.len(), 1);
+
+        let chunks: Vec<_> = provider
+            .stream(
+                "Today is 2025-01-02 09:00:00",
+                &[Message::user().with_text("Hello")],
+                &[],
+            )
+            .await?
+            .try_collect()
+            .await?;
+        assert!(matches!(
+            chunks.last(),
+            Some(CompletionChunk::Done(_, usage)) if usage.response_cache.unwrap().hits == 1
+        ));
+
+        // A different conversation goes to the provider
+        let (message, _) = provider
+            .complete("", &[Message::user().with_text("Goodbye")], &[])
+            .await?;
+        assert_eq!(message.as_concat_text(), "Something else");
+        assert_eq!(requests.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_limits() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = cache(&dir, 2)?;
+        let message = Message::assistant().with_text("Hi");
+        let usage = ProviderUsage::new("gpt-4o".to_string(), Usage::default());
+
+        for key in ["a", "b", "c"] {
+            cache.put(key, &message, &usage)?;
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_some());
+        assert!(cache.get("c").is_some());
+
+        let expired = ResponseCache::new(&ResponseCacheConfig {
+            ttl_secs: 0,
+            dir: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        })?;
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(expired.get("c").is_none());
+        Ok(())
+    }
+}
@@ -1,15 +1,22 @@
 use anyhow::Result;
 use async_trait::async_trait;
-use reqwest::{Client, StatusCode};
+use reqwest::{Client, Response, StatusCode};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 use std::time::Duration;
 
-use super::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage, Usage};
+use super::base::{
+    CompletionStream, ConfigKey, Embeddings, Provider, ProviderMetadata, ProviderUsage, Usage,
+};
 use super::errors::ProviderError;
-use super::formats::openai::{create_request, get_usage, response_to_message};
-use super::oauth;
-use super::utils::{get_model, ImageFormat};
+use super::formats::openai::{
+    create_request, embeddings_from_response, get_usage, response_to_message, ChatStreamAccumulator,
+};
+use super::oauth::{self, OAuthGrant};
+use super::streaming::{response_error, stream_response};
+use super::utils::{
+    embed_in_batches, embedding_model, get_model, get_model_names, get_retry_after, ImageFormat,
+};
 use crate::config::ConfigError;
 use crate::message::Message;
 use crate::model::ModelConfig;
@@ -18,7 +25,9 @@ use url::Url;
 
 const DEFAULT_CLIENT_ID: &str = "databricks-cli";
 const DEFAULT_REDIRECT_URL: &str = "http://localhost:8020";
-const DEFAULT_SCOPES: &[&str] = &["all-apis"];
+// Databricks only issues a refresh token when offline_access is requested, without one the
+// user has to sign in again whenever the access token expires
+const DEFAULT_SCOPES: &[&str] = &["all-apis", "offline_access"];
 
 pub const DATABRICKS_DEFAULT_MODEL: &str = "databricks-meta-llama-3-3-70b-instruct";
 // Databricks can passthrough to a wide range of models, we only provide the default
@@ -29,6 +38,10 @@ pub const DATABRICKS_KNOWN_MODELS: &[&str] = &[
     "databricks-mixtral-8x7b-instruct",
 ];
 
+pub const DATABRICKS_DEFAULT_EMBEDDING_MODEL: &str = "databricks-gte-large-en";
+/// The most inputs the Foundation Model API takes in one embeddings request
+const DATABRICKS_EMBEDDING_BATCH_SIZE: usize = 150;
+
 pub const DATABRICKS_DOC_URL: &str =
     "https://docs.databricks.com/en/generative-ai/external-models/index.html";
 
@@ -40,6 +53,8 @@ pub enum DatabricksAuth {
         client_id: String,
         redirect_url: String,
         scopes: Vec<String>,
+        #[serde(default)]
+        grant: OAuthGrant,
     },
 }
 
@@ -51,6 +66,27 @@ impl DatabricksAuth {
             client_id: DEFAULT_CLIENT_ID.to_string(),
             redirect_url: DEFAULT_REDIRECT_URL.to_string(),
             scopes: DEFAULT_SCOPES.iter().map(|s| s.to_string()).collect(),
+            grant: OAuthGrant::default(),
+        }
+    }
+
+    /// Sign in with a device code instead of a browser redirect, for headless machines
+    pub fn with_grant(self, grant: OAuthGrant) -> Self {
+        match self {
+            Self::OAuth {
+                host,
+                client_id,
+                redirect_url,
+                scopes,
+                ..
+            } => Self::OAuth {
+                host,
+                client_id,
+                redirect_url,
+                scopes,
+                grant,
+            },
+            token => token,
         }
     }
     pub fn token(token: String) -> Self {
@@ -111,10 +147,14 @@ impl DatabricksProvider {
             });
         }
 
-        // Otherwise use Oauth flow
+        // Otherwise use Oauth flow, with a device code when configured for headless machines
+        let grant = match config.get::<String>("DATABRICKS_OAUTH_FLOW") {
+            Ok(flow) => flow.parse()?,
+            Err(_) => OAuthGrant::default(),
+        };
         Ok(Self {
             client,
-            auth: DatabricksAuth::oauth(host.clone()),
+            auth: DatabricksAuth::oauth(host.clone()).with_grant(grant),
             host,
             model,
             image_format: ImageFormat::OpenAi,
@@ -129,18 +169,20 @@ impl DatabricksProvider {
                 client_id,
                 redirect_url,
                 scopes,
+                grant,
             } => {
                 let token =
-                    oauth::get_oauth_token_async(host, client_id, redirect_url, scopes).await?;
+                    oauth::get_oauth_token_async(host, client_id, redirect_url, scopes, *grant)
+                        .await?;
                 Ok(format!("Bearer {}", token))
             }
         }
     }
 
-    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+    async fn send(&self, endpoint: &str, payload: &Value) -> Result<Response, ProviderError> {
         let base_url = Url::parse(&self.host)
             .map_err(|e| ProviderError::RequestFailed(format!("Invalid base URL: {e}")))?;
-        let path = format!("serving-endpoints/{}/invocations", self.model.model_name);
+        let path = format!("serving-endpoints/{}/invocations", endpoint);
         let url = base_url.join(&path).map_err(|e| {
             ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
         })?;
@@ -150,11 +192,20 @@ impl DatabricksProvider {
             .client
             .post(url)
             .header("Authorization", auth_header)
-            .json(&payload)
+            .json(payload)
             .send()
             .await?;
+        Ok(response)
+    }
 
+    async fn post(&self, endpoint: &str, payload: Value) -> Result<Value, ProviderError> {
+        let response = self.send(endpoint, &payload).await?;
+        Self::handle_response(response).await
+    }
+
+    async fn handle_response(response: Response) -> Result<Value, ProviderError> {
         let status = response.status();
+        let retry_delay = get_retry_after(response.headers());
         let payload: Option<Value> = response.json().await.ok();
 
         match status {
@@ -199,7 +250,10 @@ impl DatabricksProvider {
                 Err(ProviderError::RequestFailed(format!("Request failed with status: {}. Message: {}", status, error_msg)))
             }
             StatusCode::TOO_MANY_REQUESTS => {
-                Err(ProviderError::RateLimitExceeded(format!("{:?}", payload)))
+                Err(ProviderError::RateLimitExceeded {
+                    details: format!("{:?}", payload),
+                    retry_delay,
+                })
             }
             StatusCode::INTERNAL_SERVER_ERROR | StatusCode::SERVICE_UNAVAILABLE => {
                 Err(ProviderError::ServerError(format!("{:?}", payload)))
@@ -230,14 +284,53 @@ impl Provider for DatabricksProvider {
             vec![
                 ConfigKey::new("DATABRICKS_HOST", true, false, None),
                 ConfigKey::new("DATABRICKS_TOKEN", false, true, None),
+                ConfigKey::new("DATABRICKS_OAUTH_FLOW", false, false, Some("browser")),
             ],
         )
+        .with_interactive_auth()
     }
 
     fn get_model_config(&self) -> ModelConfig {
         self.model.clone()
     }
 
+    /// Lists the serving endpoints in the workspace, which are used as model names
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        let base_url = Url::parse(&self.host)
+            .map_err(|e| ProviderError::RequestFailed(format!("Invalid base URL: {e}")))?;
+        let url = base_url.join("api/2.0/serving-endpoints").map_err(|e| {
+            ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
+        })?;
+
+        let auth_header = self.ensure_auth_header().await?;
+        let response = self
+            .client
+            .get(url)
+            .header("Authorization", auth_header)
+            .send()
+            .await?;
+        let response = Self::handle_response(response).await?;
+        get_model_names(&response, "endpoints", "name")
+    }
+
+    /// Embeds through a serving endpoint, which answers in the OpenAI format
+    async fn embed(&self, texts: &[String]) -> Result<Embeddings, ProviderError> {
+        let endpoint = embedding_model(DATABRICKS_DEFAULT_EMBEDDING_MODEL);
+        let (vectors, usage) = embed_in_batches(texts, DATABRICKS_EMBEDDING_BATCH_SIZE, |batch| {
+            let endpoint = &endpoint;
+            async move {
+                let response = self.post(endpoint, json!({"input": batch})).await?;
+                embeddings_from_response(&response)
+            }
+        })
+        .await?;
+
+        Ok(Embeddings {
+            vectors,
+            usage: ProviderUsage::new(endpoint, usage).with_provider("databricks"),
+        })
+    }
+
     #[tracing::instrument(
         skip(self, system, messages, tools),
         fields(model_config, input, output, input_tokens, output_tokens, total_tokens)
@@ -255,7 +348,7 @@ impl Provider for DatabricksProvider {
             .expect("payload should have model key")
             .remove("model");
 
-        let response = self.post(payload.clone()).await?;
+        let response = self.post(&self.model.model_name, payload.clone()).await?;
 
         // Parse response
         let message = response_to_message(response.clone())?;
@@ -270,6 +363,90 @@ impl Provider for DatabricksProvider {
         let model = get_model(&response);
         super::utils::emit_debug_trace(self, &payload, &response, &usage);
 
-        Ok((message, ProviderUsage::new(model, usage)))
+        Ok((
+            message,
+            ProviderUsage::new(model, usage).with_provider("databricks"),
+        ))
+    }
+
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<CompletionStream, ProviderError> {
+        let mut payload = create_request(&self.model, system, messages, tools, &self.image_format)?;
+        payload
+            .as_object_mut()
+            .expect("payload should have model key")
+            .remove("model");
+        payload["stream"] = json!(true);
+
+        let response = self.send(&self.model.model_name, &payload).await?;
+        if !response.status().is_success() {
+            return Err(response_error(Self::handle_response(response)).await);
+        }
+
+        let model_config = self.model.clone();
+        Ok(stream_response(
+            response,
+            ChatStreamAccumulator::default(),
+            move |response| {
+                let message = response_to_message(response.clone())?;
+                let usage = get_usage(&response).unwrap_or_else(|e| {
+                    tracing::debug!("Failed to get usage data: {}", e);
+                    Usage::default()
+                });
+                let model = get_model(&response);
+                super::utils::emit_debug_trace(&model_config, &payload, &response, &usage);
+                Ok((
+                    message,
+                    ProviderUsage::new(model, usage).with_provider("databricks"),
+                ))
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_embed() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(format!(
+                "/serving-endpoints/{}/invocations",
+                DATABRICKS_DEFAULT_EMBEDDING_MODEL
+            )))
+            .and(header("Authorization", "Bearer token"))
+            .and(body_json(json!({"input": ["first", "second"]})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "data": [
+                    {"object": "embedding", "index": 0, "embedding": [1.0, 0.0]},
+                    {"object": "embedding", "index": 1, "embedding": [0.0, 1.0]}
+                ],
+                "usage": {"prompt_tokens": 4, "total_tokens": 4}
+            })))
+            .mount(&server)
+            .await;
+
+        let provider = DatabricksProvider {
+            client: Client::new(),
+            host: server.uri(),
+            auth: DatabricksAuth::token("token".to_string()),
+            model: ModelConfig::new(DATABRICKS_DEFAULT_MODEL.to_string()),
+            image_format: ImageFormat::OpenAi,
+        };
+        let embeddings = provider
+            .embed(&["first".to_string(), "second".to_string()])
+            .await?;
+        assert_eq!(embeddings.vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert_eq!(embeddings.usage.usage.total_tokens, Some(4));
+        Ok(())
     }
 }
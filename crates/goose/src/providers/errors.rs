@@ -1,6 +1,9 @@
 use reqwest::StatusCode;
+use std::time::Duration;
 use thiserror::Error;
 
+use super::formats::UnsupportedSettings;
+
 #[derive(Error, Debug)]
 pub enum ProviderError {
     #[error("Authentication error: {0}")]
@@ -9,8 +12,12 @@ pub enum ProviderError {
     #[error("Context length exceeded: {0}")]
     ContextLengthExceeded(String),
 
-    #[error("Rate limit exceeded: {0}")]
-    RateLimitExceeded(String),
+    #[error("Rate limit exceeded: {details}")]
+    RateLimitExceeded {
+        details: String,
+        /// How long the provider asked us to wait, from the Retry-After header
+        retry_delay: Option<Duration>,
+    },
 
     #[error("Server error: {0}")]
     ServerError(String),
@@ -23,10 +30,16 @@ pub enum ProviderError {
 
     #[error("Usage data error: {0}")]
     UsageError(String),
+
+    #[error("Not supported: {0}")]
+    NotSupported(String),
 }
 
 impl From<anyhow::Error> for ProviderError {
     fn from(error: anyhow::Error) -> Self {
+        if error.is::<UnsupportedSettings>() {
+            return ProviderError::NotSupported(error.to_string());
+        }
         ProviderError::ExecutionError(error.to_string())
     }
 }
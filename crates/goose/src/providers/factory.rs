@@ -4,17 +4,33 @@ use super::{
     base::{Provider, ProviderMetadata},
     bedrock::BedrockProvider,
     databricks::DatabricksProvider,
+    fallback::{FallbackProvider, FALLBACK_PROVIDER_NAME},
     google::GoogleProvider,
     groq::GroqProvider,
     ollama::OllamaProvider,
     openai::OpenAiProvider,
+    openai_compatible::OpenAiCompatibleProvider,
     openrouter::OpenRouterProvider,
+    replay::{ReplayProvider, REPLAY_PROVIDER_NAME},
+    toolshim::ToolShimProvider,
 };
 use crate::model::ModelConfig;
 use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
+/// How long a provider's model listing is reused before asking its API again
+const MODEL_LIST_TTL: Duration = Duration::from_secs(10 * 60);
+/// Listings are only a convenience, so don't wait long on a provider which isn't answering
+const MODEL_LIST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Model listings by provider name, with when they were fetched
+type ModelListCache = Mutex<HashMap<String, (Instant, Vec<String>)>>;
+
+/// The built in providers, followed by any OpenAI compatible servers registered in config
 pub fn providers() -> Vec<ProviderMetadata> {
-    vec![
+    let mut providers = vec![
         AnthropicProvider::metadata(),
         AzureProvider::metadata(),
         BedrockProvider::metadata(),
@@ -24,10 +40,45 @@ pub fn providers() -> Vec<ProviderMetadata> {
         OllamaProvider::metadata(),
         OpenAiProvider::metadata(),
         OpenRouterProvider::metadata(),
-    ]
+    ];
+    // A broken entry is reported when the provider is created, the built in ones still work
+    let mut instances: Vec<_> = match OpenAiCompatibleProvider::instances() {
+        Ok(instances) => instances.into_iter().collect(),
+        Err(e) => {
+            tracing::warn!("{}", e);
+            Vec::new()
+        }
+    };
+    instances.sort_by(|(a, _), (b, _)| a.cmp(b));
+    providers.extend(
+        instances
+            .iter()
+            .filter(|(name, _)| !providers.iter().any(|p| &p.name == name))
+            .map(|(name, config)| OpenAiCompatibleProvider::instance_metadata(name, config))
+            .collect::<Vec<_>>(),
+    );
+    providers
+}
+
+/// The default model of a provider, by its name
+pub fn default_model(name: &str) -> Result<String> {
+    providers()
+        .into_iter()
+        .find(|metadata| metadata.name == name)
+        .map(|metadata| metadata.default_model)
+        .ok_or_else(|| anyhow::anyhow!("Unknown provider: {}", name))
 }
 
+/// Create a provider by name, emulating tool calls if GOOSE_TOOLSHIM enables it for the model
 pub fn create(name: &str, model: ModelConfig) -> Result<Box<dyn Provider + Send + Sync>> {
+    ToolShimProvider::wrap_if_enabled(create_provider(name, model)?)
+}
+
+/// Create a provider by name without the tool call shim
+pub(super) fn create_provider(
+    name: &str,
+    model: ModelConfig,
+) -> Result<Box<dyn Provider + Send + Sync>> {
     match name {
         "openai" => Ok(Box::new(OpenAiProvider::from_env(model)?)),
         "anthropic" => Ok(Box::new(AnthropicProvider::from_env(model)?)),
@@ -38,6 +89,59 @@ pub fn create(name: &str, model: ModelConfig) -> Result<Box<dyn Provider + Send
         "ollama" => Ok(Box::new(OllamaProvider::from_env(model)?)),
         "openrouter" => Ok(Box::new(OpenRouterProvider::from_env(model)?)),
         "google" => Ok(Box::new(GoogleProvider::from_env(model)?)),
+        FALLBACK_PROVIDER_NAME => Ok(Box::new(FallbackProvider::from_env(model)?)),
+        REPLAY_PROVIDER_NAME => Ok(Box::new(ReplayProvider::from_env(model)?)),
+        name if OpenAiCompatibleProvider::instances()?.contains_key(name) => {
+            Ok(Box::new(OpenAiCompatibleProvider::from_env(name, model)?))
+        }
         _ => Err(anyhow::anyhow!("Unknown provider: {}", name)),
     }
 }
+
+fn model_list_cache() -> &'static ModelListCache {
+    static CACHE: OnceLock<ModelListCache> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+/// The last listing of a provider's models, even if it is out of date, without asking its API
+pub fn cached_models(name: &str) -> Option<Vec<String>> {
+    model_list_cache()
+        .lock()
+        .unwrap()
+        .get(name)
+        .map(|(_, models)| models.clone())
+}
+
+/// The models a provider offers, asked of its API using the configured credentials
+///
+/// Listings are cached for a while. Providers which can't list their models return their known
+/// models, while failures such as missing credentials are returned as errors.
+pub async fn list_models(name: &str) -> Result<Vec<String>> {
+    let cache = model_list_cache();
+
+    if let Some((fetched, models)) = cache.lock().unwrap().get(name) {
+        if fetched.elapsed() < MODEL_LIST_TTL {
+            return Ok(models.clone());
+        }
+    }
+
+    let metadata = providers()
+        .into_iter()
+        .find(|metadata| metadata.name == name)
+        .ok_or_else(|| anyhow::anyhow!("Unknown provider: {}", name))?;
+    let provider = create(name, ModelConfig::new(metadata.default_model))?;
+    let models = tokio::time::timeout(MODEL_LIST_TIMEOUT, provider.list_models())
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out listing the models of {}", name))??;
+    let models = if models.is_empty() {
+        metadata.known_models
+    } else {
+        models
+    };
+
+    cache
+        .lock()
+        .unwrap()
+        .insert(name.to_string(), (Instant::now(), models.clone()));
+    Ok(models)
+}
@@ -0,0 +1,358 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::future::Future;
+
+use super::base::{
+    CompletionChunk, CompletionStream, Embeddings, Provider, ProviderMetadata, ProviderUsage,
+};
+use super::errors::ProviderError;
+use crate::config::Config;
+use crate::message::Message;
+use crate::model::ModelConfig;
+use mcp_core::tool::Tool;
+
+pub const FALLBACK_PROVIDER_NAME: &str = "fallback";
+
+/// One entry in the GOOSE_FALLBACK_PROVIDERS chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FallbackEntry {
+    pub provider: String,
+    /// Model for this provider, defaults to the provider's default model
+    pub model: Option<String>,
+}
+
+/// A provider which tries an ordered chain of providers, failing over to the next entry when
+/// one is unavailable
+///
+/// Configured with `GOOSE_PROVIDER: fallback` and a list of entries, for example
+///
+/// ```yaml
+/// GOOSE_FALLBACK_PROVIDERS:
+///   - provider: anthropic
+///     model: claude-3-5-sonnet-latest
+///   - provider: bedrock
+///   - provider: openai
+///     model: gpt-4o
+/// ```
+pub struct FallbackProvider {
+    chain: Vec<(String, Box<dyn Provider + Send + Sync>)>,
+}
+
+impl FallbackProvider {
+    pub fn new(chain: Vec<(String, Box<dyn Provider + Send + Sync>)>) -> Result<Self> {
+        if chain.is_empty() {
+            return Err(anyhow::anyhow!(
+                "The fallback provider needs at least one entry"
+            ));
+        }
+        Ok(Self { chain })
+    }
+
+    pub fn from_env(model: ModelConfig) -> Result<Self> {
+        let entries: Vec<FallbackEntry> = Config::global()
+            .get("GOOSE_FALLBACK_PROVIDERS")
+            .map_err(|_| {
+                anyhow::anyhow!("GOOSE_FALLBACK_PROVIDERS must be set to use the fallback provider")
+            })?;
+
+        let chain = entries
+            .into_iter()
+            .map(|entry| {
+                if entry.provider == FALLBACK_PROVIDER_NAME {
+                    return Err(anyhow::anyhow!("Fallback providers cannot be nested"));
+                }
+                let model_name = match entry.model {
+                    Some(model_name) => model_name,
+                    None => super::default_model(&entry.provider)?,
+                };
+                let config = ModelConfig::new(model_name).with_generation_settings_of(&model);
+                let provider = super::create(&entry.provider, config)?;
+                Ok((entry.provider, provider))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Self::new(chain)
+    }
+
+    /// Make a call to each provider in turn until one succeeds, returning its result and the
+    /// name of the provider which answered
+    ///
+    /// Only the errors which `should_fail_over` allows move on to the next provider, any other
+    /// error is returned straight away.
+    async fn with_failover<'a, T, F, Fut>(&'a self, call: F) -> Result<(&'a str, T), ProviderError>
+    where
+        F: Fn(&'a (dyn Provider + Send + Sync)) -> Fut,
+        Fut: Future<Output = Result<T, ProviderError>>,
+    {
+        let mut last_error = None;
+        for (name, provider) in &self.chain {
+            match call(provider.as_ref()).await {
+                Ok(result) => return Ok((name, result)),
+                Err(error) if should_fail_over(&error) => {
+                    tracing::warn!("Provider {} failed, trying the next one: {}", name, error);
+                    last_error = Some(error);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Err(last_error.expect("the chain is never empty"))
+    }
+}
+
+/// Errors which mean the provider is unavailable or can't handle the request, such as a
+/// generation setting it has no equivalent for, rather than a problem with the request itself
+fn should_fail_over(error: &ProviderError) -> bool {
+    matches!(
+        error,
+        ProviderError::ServerError(_)
+            | ProviderError::RateLimitExceeded { .. }
+            | ProviderError::Authentication(_)
+            | ProviderError::NotSupported(_)
+    )
+}
+
+#[async_trait]
+impl Provider for FallbackProvider {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::empty()
+    }
+
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let (name, (message, usage)) = self
+            .with_failover(|provider| provider.complete(system, messages, tools))
+            .await?;
+        Ok((message, usage.with_provider(name)))
+    }
+
+    /// Fails over only while establishing the stream, errors mid-stream are returned as is
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<CompletionStream, ProviderError> {
+        let (name, stream) = self
+            .with_failover(|provider| provider.stream(system, messages, tools))
+            .await?;
+        let name = name.to_string();
+        Ok(Box::pin(stream.map_ok(move |chunk| match chunk {
+            CompletionChunk::Done(message, usage) => {
+                CompletionChunk::Done(message, usage.with_provider(&name))
+            }
+            chunk => chunk,
+        })))
+    }
+
+    async fn complete_structured(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let (name, (message, usage)) = self
+            .with_failover(|provider| provider.complete_structured(system, messages, schema))
+            .await?;
+        Ok((message, usage.with_provider(name)))
+    }
+
+    /// The models of the primary provider
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        self.chain[0].1.list_models().await
+    }
+
+    /// Embeds with the first provider in the chain which offers embeddings. Errors don't fail
+    /// over, since vectors from different models can't be compared with each other
+    async fn embed(&self, texts: &[String]) -> Result<Embeddings, ProviderError> {
+        let mut last_error = None;
+        for (name, provider) in &self.chain {
+            match provider.embed(texts).await {
+                Ok(mut embeddings) => {
+                    embeddings.usage = embeddings.usage.with_provider(name);
+                    return Ok(embeddings);
+                }
+                Err(error @ ProviderError::NotSupported(_)) => last_error = Some(error),
+                Err(error) => return Err(error),
+            }
+        }
+        Err(last_error.expect("the chain is never empty"))
+    }
+
+    /// The primary provider's config, limited to the smallest context window in the chain and to
+    /// the capabilities every model shares, so requests still fit after failing over
+    fn get_model_config(&self) -> ModelConfig {
+        let configs: Vec<ModelConfig> = self
+            .chain
+            .iter()
+            .map(|(_, provider)| provider.get_model_config())
+            .collect();
+        let context_limit = configs.iter().map(|config| config.context_limit()).min();
+        let capabilities = configs[1..]
+            .iter()
+            .fold(configs[0].capabilities.clone(), |shared, config| {
+                shared.intersect(&config.capabilities)
+            });
+        configs[0]
+            .clone()
+            .with_context_limit(context_limit)
+            .with_capabilities(capabilities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::mock::MockProvider;
+
+    #[tokio::test]
+    async fn test_fails_over_to_next_provider() -> Result<()> {
+        let anthropic = MockProvider::new("claude-3-5-sonnet")
+            .with_error(ProviderError::ServerError("overloaded".to_string()));
+        let bedrock = MockProvider::new("claude-3-5-sonnet-bedrock").with_error(
+            ProviderError::RateLimitExceeded {
+                details: "slow down".to_string(),
+                retry_delay: None,
+            },
+        );
+        let openai = MockProvider::new("gpt-4o").with_text("Mock response");
+        let openai_requests = openai.requests();
+        let provider = FallbackProvider::new(vec![
+            ("anthropic".to_string(), Box::new(anthropic)),
+            ("bedrock".to_string(), Box::new(bedrock)),
+            ("openai".to_string(), Box::new(openai)),
+        ])?;
+
+        let (_, usage) = provider.complete("", &[], &[]).await?;
+        assert_eq!(usage.provider.as_deref(), Some("openai"));
+        assert_eq!(usage.model, "gpt-4o");
+        assert_eq!(openai_requests.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_on_unsupported_settings() -> Result<()> {
+        let unsupported: ProviderError =
+            crate::providers::formats::reject_settings("Google", &[("seed", true)])
+                .unwrap_err()
+                .into();
+        assert!(matches!(unsupported, ProviderError::NotSupported(_)));
+
+        let google = MockProvider::new("gemini-2.0-flash").with_error(unsupported);
+        let openai = MockProvider::new("gpt-4o").with_text("Mock response");
+        let provider = FallbackProvider::new(vec![
+            ("google".to_string(), Box::new(google)),
+            ("openai".to_string(), Box::new(openai)),
+        ])?;
+
+        let (_, usage) = provider.complete("", &[], &[]).await?;
+        assert_eq!(usage.provider.as_deref(), Some("openai"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_does_not_fail_over_on_request_errors() -> Result<()> {
+        let anthropic = MockProvider::new("claude-3-5-sonnet")
+            .with_error(ProviderError::ContextLengthExceeded("too long".to_string()));
+        let openai = MockProvider::new("gpt-4o").with_text("Mock response");
+        let openai_requests = openai.requests();
+        let provider = FallbackProvider::new(vec![
+            ("anthropic".to_string(), Box::new(anthropic)),
+            ("openai".to_string(), Box::new(openai)),
+        ])?;
+
+        let result = provider.complete("", &[], &[]).await;
+        assert!(matches!(
+            result,
+            Err(ProviderError::ContextLengthExceeded(_))
+        ));
+        assert!(openai_requests.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_returns_last_error_when_all_fail() -> Result<()> {
+        let anthropic = MockProvider::new("claude-3-5-sonnet")
+            .with_error(ProviderError::ServerError("overloaded".to_string()));
+        let openai = MockProvider::new("gpt-4o")
+            .with_error(ProviderError::Authentication("bad key".to_string()));
+        let provider = FallbackProvider::new(vec![
+            ("anthropic".to_string(), Box::new(anthropic)),
+            ("openai".to_string(), Box::new(openai)),
+        ])?;
+
+        let result = provider.complete("", &[], &[]).await;
+        assert!(matches!(result, Err(ProviderError::Authentication(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_fails_over_and_tags_provider() -> Result<()> {
+        let anthropic = MockProvider::new("claude-3-5-sonnet")
+            .with_error(ProviderError::ServerError("overloaded".to_string()));
+        let openai = MockProvider::new("gpt-4o").with_text("Mock response");
+        let provider = FallbackProvider::new(vec![
+            ("anthropic".to_string(), Box::new(anthropic)),
+            ("openai".to_string(), Box::new(openai)),
+        ])?;
+
+        let chunks: Vec<_> = provider.stream("", &[], &[]).await?.try_collect().await?;
+        match chunks.last() {
+            Some(CompletionChunk::Done(_, usage)) => {
+                assert_eq!(usage.provider.as_deref(), Some("openai"))
+            }
+            _ => panic!("expected the stream to finish with a complete message"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_model_config_uses_smallest_context_limit() -> Result<()> {
+        // 128k is also the default limit, so neither model uses it
+        let mock = |name: &str, limit: usize| {
+            Box::new(MockProvider::new(name).with_model_config(
+                ModelConfig::new(name.to_string()).with_context_limit(Some(limit)),
+            )) as Box<dyn Provider + Send + Sync>
+        };
+        let provider = FallbackProvider::new(vec![
+            ("anthropic".to_string(), mock("claude-3-5-sonnet", 200_000)),
+            ("openai".to_string(), mock("gpt-4o", 32_000)),
+        ])?;
+
+        let config = provider.get_model_config();
+        assert_eq!(config.model_name, "claude-3-5-sonnet");
+        assert_eq!(config.context_limit(), 32_000);
+        assert!(config.capabilities.images);
+
+        // Whichever position the smaller model is in
+        let provider = FallbackProvider::new(vec![
+            ("openai".to_string(), mock("gpt-4o", 32_000)),
+            ("anthropic".to_string(), mock("claude-3-5-sonnet", 200_000)),
+        ])?;
+        assert_eq!(provider.get_model_config().context_limit(), 32_000);
+
+        // Capabilities are limited to what every model in the chain supports
+        let provider = FallbackProvider::new(vec![
+            (
+                "anthropic".to_string(),
+                Box::new(MockProvider::new("claude-3-5-sonnet")),
+            ),
+            (
+                "ollama".to_string(),
+                Box::new(MockProvider::new("llama3.3")),
+            ),
+        ])?;
+        let config = provider.get_model_config();
+        assert!(!config.capabilities.images);
+        assert!(config.capabilities.tool_calling);
+
+        assert!(FallbackProvider::new(vec![]).is_err());
+        Ok(())
+    }
+}
@@ -1,12 +1,14 @@
 use crate::message::{Message, MessageContent};
-use crate::model::ModelConfig;
-use crate::providers::base::Usage;
+use crate::model::{ModelConfig, ToolChoice};
+use crate::providers::base::{CompletionChunk, Usage};
 use crate::providers::errors::ProviderError;
+use crate::providers::formats::{reject_settings, tool_choice};
+use crate::providers::streaming::{SseEvent, StreamAccumulator};
 use anyhow::{anyhow, Result};
 use mcp_core::content::Content;
 use mcp_core::role::Role;
 use mcp_core::tool::{Tool, ToolCall};
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
 use std::collections::HashSet;
 
 /// Convert internal Message format to Anthropic's API message specification
@@ -57,6 +59,22 @@ pub fn format_messages(messages: &[Message]) -> Vec<Value> {
                         }));
                     }
                 }
+                MessageContent::Thinking(thinking) => {
+                    // Thinking without a signature came from another provider and would be rejected
+                    if let Some(signature) = &thinking.signature {
+                        content.push(json!({
+                            "type": "thinking",
+                            "thinking": thinking.thinking,
+                            "signature": signature
+                        }));
+                    }
+                }
+                MessageContent::RedactedThinking(redacted) => {
+                    content.push(json!({
+                        "type": "redacted_thinking",
+                        "data": redacted.data
+                    }));
+                }
                 MessageContent::Image(_) => continue, // Anthropic doesn't support image content yet
             }
         }
@@ -176,6 +194,24 @@ pub fn response_to_message(response: Value) -> Result<Message> {
                 let tool_call = ToolCall::new(name, input.clone());
                 message = message.with_tool_request(id, Ok(tool_call));
             }
+            Some("thinking") => {
+                let thinking = block
+                    .get("thinking")
+                    .and_then(|t| t.as_str())
+                    .ok_or_else(|| anyhow!("Missing thinking content"))?;
+                let signature = block
+                    .get("signature")
+                    .and_then(|s| s.as_str())
+                    .map(String::from);
+                message = message.with_thinking(thinking, signature);
+            }
+            Some("redacted_thinking") => {
+                let data = block
+                    .get("data")
+                    .and_then(|d| d.as_str())
+                    .ok_or_else(|| anyhow!("Missing redacted_thinking data"))?;
+                message = message.with_redacted_thinking(data);
+            }
             _ => continue,
         }
     }
@@ -213,7 +249,17 @@ pub fn get_usage(data: &Value) -> Result<Usage> {
 
         let total_tokens = output_tokens.map(|o| total_input_tokens as i32 + o);
 
-        Ok(Usage::new(input_tokens, output_tokens, total_tokens))
+        let cache_creation_input_tokens = usage
+            .get("cache_creation_input_tokens")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as i32);
+        let cache_read_input_tokens = usage
+            .get("cache_read_input_tokens")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as i32);
+
+        Ok(Usage::new(input_tokens, output_tokens, total_tokens)
+            .with_cache_tokens(cache_creation_input_tokens, cache_read_input_tokens))
     } else {
         tracing::debug!(
             "Failed to get usage data: {}",
@@ -239,11 +285,26 @@ pub fn create_request(
     if anthropic_messages.is_empty() {
         return Err(anyhow!("No valid messages to send to Anthropic API"));
     }
+    // Thinking is set with a budget rather than an effort
+    reject_settings(
+        "Anthropic",
+        &[
+            ("seed", model_config.seed.is_some()),
+            ("presence_penalty", model_config.presence_penalty.is_some()),
+            (
+                "frequency_penalty",
+                model_config.frequency_penalty.is_some(),
+            ),
+            ("reasoning_effort", model_config.reasoning_effort.is_some()),
+        ],
+    )?;
+
+    let (max_tokens, thinking_budget) = output_limits(model_config);
 
     let mut payload = json!({
         "model": model_config.model_name,
         "messages": anthropic_messages,
-        "max_tokens": model_config.max_tokens.unwrap_or(4096)
+        "max_tokens": max_tokens
     });
 
     // Add system message if present
@@ -260,10 +321,47 @@ pub fn create_request(
             .as_object_mut()
             .unwrap()
             .insert("tools".to_string(), json!(tool_specs));
+
+        // Parallel tool use is switched off through the tool choice, which can't be `none`
+        let mut choice = match tool_choice(model_config, messages, tools)? {
+            Some(ToolChoice::Auto) => Some(json!({"type": "auto"})),
+            Some(ToolChoice::Required) => Some(json!({"type": "any"})),
+            Some(ToolChoice::None) => Some(json!({"type": "none"})),
+            Some(ToolChoice::Tool(name)) => Some(json!({"type": "tool", "name": name})),
+            None => None,
+        };
+        if let Some(parallel) = model_config.parallel_tool_calls {
+            let choice = choice.get_or_insert_with(|| json!({"type": "auto"}));
+            if choice["type"] != "none" {
+                choice["disable_parallel_tool_use"] = json!(!parallel);
+            }
+        }
+        if let Some(choice) = choice {
+            payload["tool_choice"] = choice;
+        }
+    }
+
+    let settings = payload.as_object_mut().unwrap();
+    if let Some(top_p) = model_config.top_p {
+        settings.insert("top_p".to_string(), json!(top_p));
+    }
+    if let Some(top_k) = model_config.top_k {
+        settings.insert("top_k".to_string(), json!(top_k));
+    }
+    if !model_config.stop_sequences.is_empty() {
+        settings.insert(
+            "stop_sequences".to_string(),
+            json!(model_config.stop_sequences),
+        );
     }
 
-    // Add temperature if specified
-    if let Some(temp) = model_config.temperature {
+    if let Some(budget) = thinking_budget {
+        payload.as_object_mut().unwrap().insert(
+            "thinking".to_string(),
+            json!({"type": "enabled", "budget_tokens": budget}),
+        );
+    } else if let Some(temp) = model_config.temperature {
+        // Temperature can't be changed with thinking enabled, so it is only added without it
         payload
             .as_object_mut()
             .unwrap()
@@ -273,6 +371,160 @@ pub fn create_request(
     Ok(payload)
 }
 
+/// The max_tokens and thinking budget to request, keeping max_tokens above the budget as the
+/// API requires, since max_tokens includes the thinking
+fn output_limits(model_config: &ModelConfig) -> (i32, Option<i32>) {
+    let mut max_tokens = model_config.max_tokens.unwrap_or(4096);
+    let mut budget = model_config.thinking_budget;
+    // Leave room for the answer itself
+    if let Some(budget) = budget {
+        if max_tokens <= budget {
+            max_tokens += budget;
+        }
+    }
+    // Asking for more than the model can produce is rejected outright
+    if let Some(limit) = model_config.capabilities.max_output_tokens {
+        max_tokens = max_tokens.min(limit);
+        if let Some(thinking) = budget.filter(|&thinking| thinking >= max_tokens) {
+            tracing::warn!(
+                "The thinking budget of {} doesn't fit in {}'s {} output tokens, reducing it",
+                thinking,
+                model_config.model_name,
+                limit
+            );
+            budget = Some(max_tokens / 2);
+        }
+    }
+    (max_tokens, budget)
+}
+
+/// Accumulates streamed message events into a complete Messages API response
+#[derive(Debug, Default)]
+pub struct MessageStreamAccumulator {
+    model: Option<String>,
+    /// Content blocks by index, with tool inputs kept as partial JSON until finished
+    blocks: Vec<Value>,
+    partial_json: Vec<String>,
+    usage: Map<String, Value>,
+}
+
+impl StreamAccumulator for MessageStreamAccumulator {
+    fn push(&mut self, event: &SseEvent) -> Result<Vec<CompletionChunk>, ProviderError> {
+        let data: Value = serde_json::from_str(&event.data).map_err(|e| {
+            ProviderError::RequestFailed(format!("Invalid stream event: {}: {}", e, event.data))
+        })?;
+
+        let mut chunks = Vec::new();
+        match data.get("type").and_then(|t| t.as_str()) {
+            Some("message_start") => {
+                let message = &data["message"];
+                self.model = message["model"].as_str().map(String::from);
+                if let Some(usage) = message["usage"].as_object() {
+                    self.usage.extend(usage.clone());
+                }
+            }
+            Some("content_block_start") => {
+                let index = data["index"].as_u64().unwrap_or(0) as usize;
+                let block = data["content_block"].clone();
+                if self.blocks.len() <= index {
+                    self.blocks.resize(index + 1, Value::Null);
+                    self.partial_json.resize(index + 1, String::new());
+                }
+                if block["type"] == "tool_use" {
+                    chunks.push(CompletionChunk::ToolCall {
+                        index,
+                        id: block["id"].as_str().map(String::from),
+                        name: block["name"].as_str().map(String::from),
+                        arguments: String::new(),
+                    });
+                }
+                self.blocks[index] = block;
+            }
+            Some("content_block_delta") => {
+                let index = data["index"].as_u64().unwrap_or(0) as usize;
+                let delta = &data["delta"];
+                let Some(block) = self.blocks.get_mut(index) else {
+                    return Ok(chunks);
+                };
+                match delta["type"].as_str() {
+                    Some("text_delta") => {
+                        let text = delta["text"].as_str().unwrap_or_default();
+                        let existing = block["text"].as_str().unwrap_or_default();
+                        block["text"] = json!(format!("{}{}", existing, text));
+                        chunks.push(CompletionChunk::Text(text.to_string()));
+                    }
+                    Some("thinking_delta") => {
+                        let thinking = delta["thinking"].as_str().unwrap_or_default();
+                        let existing = block["thinking"].as_str().unwrap_or_default();
+                        block["thinking"] = json!(format!("{}{}", existing, thinking));
+                        chunks.push(CompletionChunk::Thinking(thinking.to_string()));
+                    }
+                    Some("signature_delta") => {
+                        block["signature"] = delta["signature"].clone();
+                    }
+                    Some("input_json_delta") => {
+                        let partial = delta["partial_json"].as_str().unwrap_or_default();
+                        self.partial_json[index].push_str(partial);
+                        chunks.push(CompletionChunk::ToolCall {
+                            index,
+                            id: None,
+                            name: None,
+                            arguments: partial.to_string(),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            Some("message_delta") => {
+                if let Some(usage) = data["usage"].as_object() {
+                    self.usage.extend(usage.clone());
+                }
+            }
+            Some("error") => {
+                let message = data["error"]["message"]
+                    .as_str()
+                    .unwrap_or("Unknown error")
+                    .to_string();
+                return Err(match data["error"]["type"].as_str() {
+                    Some("rate_limit_error") => ProviderError::RateLimitExceeded {
+                        details: message,
+                        retry_delay: None,
+                    },
+                    _ => ProviderError::ServerError(message),
+                });
+            }
+            // ping, content_block_stop and message_stop carry nothing we need
+            _ => {}
+        }
+        Ok(chunks)
+    }
+
+    fn finish(self) -> Value {
+        let content: Vec<Value> = self
+            .blocks
+            .into_iter()
+            .zip(self.partial_json)
+            .filter(|(block, _)| !block.is_null())
+            .map(|(mut block, partial_json)| {
+                if block["type"] == "tool_use" {
+                    block["input"] = if partial_json.is_empty() {
+                        json!({})
+                    } else {
+                        serde_json::from_str(&partial_json).unwrap_or(json!(partial_json))
+                    };
+                }
+                block
+            })
+            .collect();
+
+        json!({
+            "model": self.model.unwrap_or_default(),
+            "content": content,
+            "usage": self.usage,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -311,6 +563,8 @@ mod tests {
         assert_eq!(usage.input_tokens, Some(24)); // 12 + 12 + 0
         assert_eq!(usage.output_tokens, Some(15));
         assert_eq!(usage.total_tokens, Some(39)); // 24 + 15
+        assert_eq!(usage.cache_creation_input_tokens, Some(12));
+        assert_eq!(usage.cache_read_input_tokens, Some(0));
 
         Ok(())
     }
@@ -433,4 +687,166 @@ mod tests {
         assert_eq!(spec_array[0]["text"], system);
         assert!(spec_array[0].get("cache_control").is_some());
     }
+
+    #[test]
+    fn test_stream_accumulator() -> Result<()> {
+        let events = [
+            json!({"type": "message_start", "message": {"model": "claude-3-5-sonnet-latest", "usage": {"input_tokens": 10, "output_tokens": 1}}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "ping"}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Listing "}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "files"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "developer__shell", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"command\": \"l"}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "s\"}"}}),
+            json!({"type": "content_block_stop", "index": 1}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 15}}),
+            json!({"type": "message_stop"}),
+        ];
+
+        let mut accumulator = MessageStreamAccumulator::default();
+        let mut text = String::new();
+        for event in events {
+            let event = SseEvent {
+                event: event["type"].as_str().map(String::from),
+                data: event.to_string(),
+            };
+            for chunk in accumulator.push(&event)? {
+                if let CompletionChunk::Text(delta) = chunk {
+                    text.push_str(&delta);
+                }
+            }
+        }
+        assert_eq!(text, "Listing files");
+
+        let response = accumulator.finish();
+        let message = response_to_message(response.clone())?;
+        assert_eq!(message.as_concat_text(), "Listing files");
+        let request = message.content[1].as_tool_request().unwrap();
+        let tool_call = request.tool_call.as_ref().unwrap();
+        assert_eq!(request.id, "toolu_1");
+        assert_eq!(tool_call.arguments, json!({"command": "ls"}));
+
+        let usage = get_usage(&response)?;
+        assert_eq!(usage.input_tokens, Some(10));
+        assert_eq!(usage.output_tokens, Some(15));
+        Ok(())
+    }
+
+    #[test]
+    fn test_thinking_round_trip() -> Result<()> {
+        let response = json!({
+            "content": [
+                {"type": "thinking", "thinking": "The user wants a greeting", "signature": "sig_1"},
+                {"type": "redacted_thinking", "data": "encrypted"},
+                {"type": "text", "text": "Hello!"}
+            ],
+            "model": "claude-3-7-sonnet-latest",
+            "usage": {"input_tokens": 10, "output_tokens": 20}
+        });
+
+        let message = response_to_message(response)?;
+        assert_eq!(
+            message.content[0].as_thinking(),
+            Some("The user wants a greeting")
+        );
+        assert_eq!(message.as_concat_text(), "Hello!");
+
+        // Thinking without a signature, e.g. from another provider, is not sent back
+        let messages = vec![
+            Message::user().with_text("Hi"),
+            message.with_thinking("unsigned", None),
+        ];
+        let spec = format_messages(&messages);
+        let content = spec[1]["content"].as_array().unwrap();
+        assert_eq!(content.len(), 3);
+        assert_eq!(content[0]["type"], "thinking");
+        assert_eq!(content[0]["signature"], "sig_1");
+        assert_eq!(content[1]["type"], "redacted_thinking");
+        assert_eq!(content[1]["data"], "encrypted");
+        assert_eq!(content[2]["type"], "text");
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_request_with_thinking_budget() -> Result<()> {
+        let model_config = ModelConfig::new("claude-3-7-sonnet-latest".to_string())
+            .with_temperature(Some(0.5))
+            .with_thinking_budget(Some(8000));
+        let messages = vec![Message::user().with_text("Hello")];
+
+        let payload = create_request(&model_config, "system", &messages, &[])?;
+        assert_eq!(payload["thinking"]["type"], "enabled");
+        assert_eq!(payload["thinking"]["budget_tokens"], 8000);
+        assert_eq!(payload["max_tokens"], 12096);
+        assert!(payload.get("temperature").is_none());
+
+        // A budget beyond what the model can produce is cut down to leave room for the answer
+        let model_config = model_config.with_thinking_budget(Some(64000));
+        let payload = create_request(&model_config, "system", &messages, &[])?;
+        assert_eq!(payload["max_tokens"], 64000);
+        assert_eq!(payload["thinking"]["budget_tokens"], 32000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_request_generation_settings() -> Result<()> {
+        let tools = [Tool::new("developer__shell", "Run a command", json!({}))];
+        let messages = vec![Message::user().with_text("Hello")];
+        let model_config = ModelConfig::new("claude-3-5-sonnet-latest".to_string())
+            .with_top_k(Some(40))
+            .with_stop_sequences(vec!["END".to_string()])
+            .with_tool_choice(Some(ToolChoice::Required))
+            .with_parallel_tool_calls(Some(false));
+
+        let payload = create_request(&model_config, "system", &messages, &tools)?;
+        assert_eq!(payload["top_k"], 40);
+        assert_eq!(payload["stop_sequences"], json!(["END"]));
+        assert_eq!(
+            payload["tool_choice"],
+            json!({"type": "any", "disable_parallel_tool_use": true})
+        );
+
+        let model_config = model_config.with_seed(Some(7));
+        let error = create_request(&model_config, "system", &messages, &tools).unwrap_err();
+        assert!(error.to_string().contains("seed"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_accumulator_thinking() -> Result<()> {
+        let events = [
+            json!({"type": "message_start", "message": {"model": "claude-3-7-sonnet-latest", "usage": {"input_tokens": 10}}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "thinking", "thinking": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "Let me "}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "think"}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "signature_delta", "signature": "sig_1"}}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "text_delta", "text": "Done"}}),
+        ];
+
+        let mut accumulator = MessageStreamAccumulator::default();
+        let mut thinking = String::new();
+        for event in events {
+            let event = SseEvent {
+                event: event["type"].as_str().map(String::from),
+                data: event.to_string(),
+            };
+            for chunk in accumulator.push(&event)? {
+                if let CompletionChunk::Thinking(delta) = chunk {
+                    thinking.push_str(&delta);
+                }
+            }
+        }
+        assert_eq!(thinking, "Let me think");
+
+        let message = response_to_message(accumulator.finish())?;
+        assert_eq!(
+            message.content[0],
+            MessageContent::thinking("Let me think", Some("sig_1".to_string()))
+        );
+        assert_eq!(message.as_concat_text(), "Done");
+        Ok(())
+    }
 }
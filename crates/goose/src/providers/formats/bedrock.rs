@@ -9,7 +9,9 @@ use mcp_core::{Content, ResourceContents, Role, Tool, ToolCall, ToolError, ToolR
 use serde_json::Value;
 
 use super::super::base::Usage;
+use super::{reject_settings, tool_choice};
 use crate::message::{Message, MessageContent};
+use crate::model::{ModelConfig, ToolChoice};
 
 pub fn to_bedrock_message(message: &Message) -> Result<bedrock::Message> {
     bedrock::Message::builder()
@@ -18,6 +20,13 @@ pub fn to_bedrock_message(message: &Message) -> Result<bedrock::Message> {
             message
                 .content
                 .iter()
+                // Reasoning from other providers can't be sent back, Bedrock doesn't need it
+                .filter(|content| {
+                    !matches!(
+                        content,
+                        MessageContent::Thinking(_) | MessageContent::RedactedThinking(_)
+                    )
+                })
                 .map(to_bedrock_message_content)
                 .collect::<Result<_>>()?,
         ))
@@ -31,6 +40,9 @@ pub fn to_bedrock_message_content(content: &MessageContent) -> Result<bedrock::C
         MessageContent::Image(_) => {
             bail!("Image content is not supported by Bedrock provider yet")
         }
+        MessageContent::Thinking(_) | MessageContent::RedactedThinking(_) => {
+            bail!("Thinking content is not supported by Bedrock provider yet")
+        }
         MessageContent::ToolRequest(tool_req) => {
             let tool_use_id = tool_req.id.to_string();
             let tool_use = if let Ok(call) = tool_req.tool_call.as_ref() {
@@ -91,14 +103,74 @@ pub fn to_bedrock_role(role: &Role) -> bedrock::ConversationRole {
     }
 }
 
-pub fn to_bedrock_tool_config(tools: &[Tool]) -> Result<bedrock::ToolConfiguration> {
+pub fn to_bedrock_tool_config(
+    messages: &[Message],
+    tools: &[Tool],
+    model_config: &ModelConfig,
+) -> Result<bedrock::ToolConfiguration> {
+    let tool_choice = match tool_choice(model_config, messages, tools)? {
+        Some(ToolChoice::Auto) => Some(bedrock::ToolChoice::Auto(
+            bedrock::AutoToolChoice::builder().build(),
+        )),
+        Some(ToolChoice::Required) => Some(bedrock::ToolChoice::Any(
+            bedrock::AnyToolChoice::builder().build(),
+        )),
+        Some(ToolChoice::Tool(name)) => Some(bedrock::ToolChoice::Tool(
+            bedrock::SpecificToolChoice::builder().name(name).build()?,
+        )),
+        Some(ToolChoice::None) => bail!("The Bedrock API does not support a tool choice of none"),
+        None => None,
+    };
+
     Ok(bedrock::ToolConfiguration::builder()
         .set_tools(Some(
             tools.iter().map(to_bedrock_tool).collect::<Result<_>>()?,
         ))
+        .set_tool_choice(tool_choice)
         .build()?)
 }
 
+/// The sampling settings the Converse API has in common across models, none if nothing is set
+pub fn to_bedrock_inference_config(
+    model_config: &ModelConfig,
+) -> Result<Option<bedrock::InferenceConfiguration>> {
+    reject_settings(
+        "Bedrock",
+        &[
+            ("top_k", model_config.top_k.is_some()),
+            ("seed", model_config.seed.is_some()),
+            ("presence_penalty", model_config.presence_penalty.is_some()),
+            (
+                "frequency_penalty",
+                model_config.frequency_penalty.is_some(),
+            ),
+            (
+                "parallel_tool_calls",
+                model_config.parallel_tool_calls.is_some(),
+            ),
+            ("reasoning_effort", model_config.reasoning_effort.is_some()),
+        ],
+    )?;
+
+    if model_config.temperature.is_none()
+        && model_config.max_tokens.is_none()
+        && model_config.top_p.is_none()
+        && model_config.stop_sequences.is_empty()
+    {
+        return Ok(None);
+    }
+    let stop_sequences =
+        (!model_config.stop_sequences.is_empty()).then(|| model_config.stop_sequences.clone());
+    Ok(Some(
+        bedrock::InferenceConfiguration::builder()
+            .set_max_tokens(model_config.max_tokens)
+            .set_temperature(model_config.temperature)
+            .set_top_p(model_config.top_p)
+            .set_stop_sequences(stop_sequences)
+            .build(),
+    ))
+}
+
 pub fn to_bedrock_tool(tool: &Tool) -> Result<bedrock::Tool> {
     Ok(bedrock::Tool::ToolSpec(
         bedrock::ToolSpecification::builder()
@@ -186,6 +258,7 @@ pub fn from_bedrock_message(message: &bedrock::Message) -> Result<Message> {
         role,
         content,
         created,
+        pinned: false,
     })
 }
 
@@ -239,11 +312,12 @@ pub fn from_bedrock_role(role: &bedrock::ConversationRole) -> Result<Role> {
 }
 
 pub fn from_bedrock_usage(usage: &bedrock::TokenUsage) -> Usage {
-    Usage {
-        input_tokens: Some(usage.input_tokens),
-        output_tokens: Some(usage.output_tokens),
-        total_tokens: Some(usage.total_tokens),
-    }
+    // Cache token counts aren't part of TokenUsage in this version of the SDK
+    Usage::new(
+        Some(usage.input_tokens),
+        Some(usage.output_tokens),
+        Some(usage.total_tokens),
+    )
 }
 
 pub fn from_bedrock_json(document: &Document) -> Result<Value> {
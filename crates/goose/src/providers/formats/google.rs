@@ -1,7 +1,9 @@
 use crate::message::{Message, MessageContent};
-use crate::model::ModelConfig;
-use crate::providers::base::Usage;
+use crate::model::{ModelConfig, ToolChoice};
+use crate::providers::base::{CompletionChunk, Usage};
 use crate::providers::errors::ProviderError;
+use crate::providers::formats::{reject_settings, tool_choice};
+use crate::providers::streaming::{SseEvent, StreamAccumulator};
 use crate::providers::utils::{is_valid_function_name, sanitize_function_name};
 use anyhow::Result;
 use mcp_core::content::Content;
@@ -114,23 +116,11 @@ pub fn format_tools(tools: &[Tool]) -> Vec<Value> {
                 .unwrap()
                 .clone();
             if !tool_input_schema_properties.is_empty() {
-                let accepted_tool_schema_attributes = vec![
-                    "type".to_string(),
-                    "format".to_string(),
-                    "description".to_string(),
-                    "nullable".to_string(),
-                    "enum".to_string(),
-                    "maxItems".to_string(),
-                    "minItems".to_string(),
-                    "properties".to_string(),
-                    "required".to_string(),
-                    "items".to_string(),
-                ];
                 parameters.insert(
                     "parameters".to_string(),
                     json!(process_map(
                         tool_input_schema,
-                        &accepted_tool_schema_attributes,
+                        &accepted_schema_attributes(),
                         None
                     )),
                 );
@@ -140,6 +130,25 @@ pub fn format_tools(tools: &[Tool]) -> Vec<Value> {
         .collect()
 }
 
+/// The JSON Schema attributes which Google's OpenAPI schema subset accepts
+fn accepted_schema_attributes() -> Vec<String> {
+    [
+        "type",
+        "format",
+        "description",
+        "nullable",
+        "enum",
+        "maxItems",
+        "minItems",
+        "properties",
+        "required",
+        "items",
+    ]
+    .iter()
+    .map(|key| key.to_string())
+    .collect()
+}
+
 /// Process a JSON map to filter out unsupported attributes
 fn process_map(
     map: &Map<String, Value>,
@@ -192,6 +201,7 @@ pub fn response_to_message(response: Value) -> Result<Message> {
             role,
             created,
             content,
+            pinned: false,
         });
     }
     let candidate = candidate.unwrap();
@@ -203,7 +213,11 @@ pub fn response_to_message(response: Value) -> Result<Message> {
 
     for part in parts {
         if let Some(text) = part.get("text").and_then(|v| v.as_str()) {
-            content.push(MessageContent::text(text.to_string()));
+            if part.get("thought").and_then(|t| t.as_bool()) == Some(true) {
+                content.push(MessageContent::thinking(text, None));
+            } else {
+                content.push(MessageContent::text(text.to_string()));
+            }
         } else if let Some(function_call) = part.get("functionCall") {
             let id: String = rand::thread_rng()
                 .sample_iter(&Alphanumeric)
@@ -235,6 +249,7 @@ pub fn response_to_message(response: Value) -> Result<Message> {
         role,
         created,
         content,
+        pinned: false,
     })
 }
 
@@ -253,7 +268,12 @@ pub fn get_usage(data: &Value) -> Result<Usage> {
             .get("totalTokenCount")
             .and_then(|v| v.as_u64())
             .map(|v| v as i32);
-        Ok(Usage::new(input_tokens, output_tokens, total_tokens))
+        let cache_read_input_tokens = usage_meta_data
+            .get("cachedContentTokenCount")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as i32);
+        Ok(Usage::new(input_tokens, output_tokens, total_tokens)
+            .with_cache_tokens(None, cache_read_input_tokens))
     } else {
         tracing::debug!(
             "Failed to get usage data: {}",
@@ -271,6 +291,18 @@ pub fn create_request(
     messages: &[Message],
     tools: &[Tool],
 ) -> Result<Value> {
+    // Thinking is set with a budget rather than an effort
+    reject_settings(
+        "Google",
+        &[
+            (
+                "parallel_tool_calls",
+                model_config.parallel_tool_calls.is_some(),
+            ),
+            ("reasoning_effort", model_config.reasoning_effort.is_some()),
+        ],
+    )?;
+
     let mut payload = Map::new();
     payload.insert(
         "system_instruction".to_string(),
@@ -283,6 +315,18 @@ pub fn create_request(
             json!({"functionDeclarations": format_tools(tools)}),
         );
     }
+    if let Some(choice) = tool_choice(model_config, messages, tools)? {
+        let config = match choice {
+            ToolChoice::Auto => json!({"mode": "AUTO"}),
+            ToolChoice::Required => json!({"mode": "ANY"}),
+            ToolChoice::None => json!({"mode": "NONE"}),
+            ToolChoice::Tool(name) => json!({"mode": "ANY", "allowedFunctionNames": [name]}),
+        };
+        payload.insert(
+            "toolConfig".to_string(),
+            json!({"functionCallingConfig": config}),
+        );
+    }
     let mut generation_config = Map::new();
     if let Some(temp) = model_config.temperature {
         generation_config.insert("temperature".to_string(), json!(temp));
@@ -290,6 +334,33 @@ pub fn create_request(
     if let Some(tokens) = model_config.max_tokens {
         generation_config.insert("maxOutputTokens".to_string(), json!(tokens));
     }
+    if let Some(top_p) = model_config.top_p {
+        generation_config.insert("topP".to_string(), json!(top_p));
+    }
+    if let Some(top_k) = model_config.top_k {
+        generation_config.insert("topK".to_string(), json!(top_k));
+    }
+    if !model_config.stop_sequences.is_empty() {
+        generation_config.insert(
+            "stopSequences".to_string(),
+            json!(model_config.stop_sequences),
+        );
+    }
+    if let Some(seed) = model_config.seed {
+        generation_config.insert("seed".to_string(), json!(seed));
+    }
+    if let Some(penalty) = model_config.presence_penalty {
+        generation_config.insert("presencePenalty".to_string(), json!(penalty));
+    }
+    if let Some(penalty) = model_config.frequency_penalty {
+        generation_config.insert("frequencyPenalty".to_string(), json!(penalty));
+    }
+    if let Some(budget) = model_config.thinking_budget {
+        generation_config.insert(
+            "thinkingConfig".to_string(),
+            json!({"thinkingBudget": budget, "includeThoughts": true}),
+        );
+    }
     if !generation_config.is_empty() {
         payload.insert("generationConfig".to_string(), json!(generation_config));
     }
@@ -297,6 +368,110 @@ pub fn create_request(
     Ok(Value::Object(payload))
 }
 
+/// Constrain a request to respond with JSON matching the schema
+///
+/// Attributes outside Google's schema subset are dropped, so the response may still need
+/// validating against the full schema
+pub fn set_response_schema(payload: &mut Value, schema: &Value) {
+    let mut generation_config = payload
+        .get("generationConfig")
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+    generation_config.insert("responseMimeType".to_string(), json!("application/json"));
+    if let Some(schema) = schema.as_object() {
+        generation_config.insert(
+            "responseSchema".to_string(),
+            process_map(schema, &accepted_schema_attributes(), None),
+        );
+    }
+    payload["generationConfig"] = Value::Object(generation_config);
+}
+
+/// Accumulates streamed generateContent responses into a single response
+///
+/// Each event is a full response holding only the newly generated parts, so consecutive text
+/// parts are merged while function calls are kept as they arrive.
+#[derive(Debug, Default)]
+pub struct ContentStreamAccumulator {
+    parts: Vec<Value>,
+    usage_metadata: Option<Value>,
+    model_version: Option<String>,
+}
+
+impl StreamAccumulator for ContentStreamAccumulator {
+    fn push(&mut self, event: &SseEvent) -> Result<Vec<CompletionChunk>, ProviderError> {
+        let data: Value = serde_json::from_str(&event.data).map_err(|e| {
+            ProviderError::RequestFailed(format!("Invalid stream event: {}: {}", e, event.data))
+        })?;
+
+        if let Some(error) = data.get("error") {
+            let message = error
+                .get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or("Unknown error");
+            return Err(ProviderError::ServerError(message.to_string()));
+        }
+        if let Some(usage) = data.get("usageMetadata") {
+            self.usage_metadata = Some(usage.clone());
+        }
+        if let Some(model) = data.get("modelVersion").and_then(|m| m.as_str()) {
+            self.model_version = Some(model.to_string());
+        }
+
+        let mut chunks = Vec::new();
+        let parts = data["candidates"][0]["content"]["parts"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        for part in parts {
+            if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                let thought = part.get("thought").and_then(|t| t.as_bool()) == Some(true);
+                chunks.push(if thought {
+                    CompletionChunk::Thinking(text.to_string())
+                } else {
+                    CompletionChunk::Text(text.to_string())
+                });
+                match self.parts.last_mut() {
+                    Some(last)
+                        if last.get("text").is_some()
+                            && last.get("thought").is_some() == thought =>
+                    {
+                        let existing = last["text"].as_str().unwrap_or_default();
+                        last["text"] = json!(format!("{}{}", existing, text));
+                    }
+                    _ if thought => self.parts.push(json!({"text": text, "thought": true})),
+                    _ => self.parts.push(json!({"text": text})),
+                }
+            } else {
+                if let Some(function_call) = part.get("functionCall") {
+                    chunks.push(CompletionChunk::ToolCall {
+                        index: self.parts.len(),
+                        id: None,
+                        name: function_call["name"].as_str().map(String::from),
+                        arguments: function_call["args"].to_string(),
+                    });
+                }
+                self.parts.push(part);
+            }
+        }
+        Ok(chunks)
+    }
+
+    fn finish(self) -> Value {
+        let mut response = json!({
+            "candidates": [{"content": {"role": "model", "parts": self.parts}}]
+        });
+        if let Some(usage) = self.usage_metadata {
+            response["usageMetadata"] = usage;
+        }
+        if let Some(model) = self.model_version {
+            response["modelVersion"] = json!(model);
+        }
+        response
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -306,6 +481,7 @@ mod tests {
         Message {
             role,
             created: 0,
+            pinned: false,
             content: vec![MessageContent::text(text.to_string())],
         }
     }
@@ -314,6 +490,7 @@ mod tests {
         Message {
             role: Role::User,
             created: 0,
+            pinned: false,
             content: vec![MessageContent::tool_request(id.to_string(), Ok(tool_call))],
         }
     }
@@ -322,6 +499,7 @@ mod tests {
         Message {
             role: Role::Assistant,
             created: 0,
+            pinned: false,
             content: vec![MessageContent::tool_response(
                 id.to_string(),
                 Ok(tool_response),
@@ -345,13 +523,15 @@ mod tests {
             "usageMetadata": {
                 "promptTokenCount": 1,
                 "candidatesTokenCount": 2,
-                "totalTokenCount": 3
+                "totalTokenCount": 3,
+                "cachedContentTokenCount": 1
             }
         });
         let usage = get_usage(&data).unwrap();
         assert_eq!(usage.input_tokens, Some(1));
         assert_eq!(usage.output_tokens, Some(2));
         assert_eq!(usage.total_tokens, Some(3));
+        assert_eq!(usage.cache_read_input_tokens, Some(1));
     }
 
     #[test]
@@ -486,6 +666,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_response_to_message_with_thought_part() {
+        let response = json!({
+            "candidates": [{
+                "content": {
+                    "parts": [
+                        {"text": "Working out a greeting", "thought": true},
+                        {"text": "Hello, world!"}
+                    ]
+                }
+            }]
+        });
+        let message = response_to_message(response).unwrap();
+        assert_eq!(
+            message.content[0].as_thinking(),
+            Some("Working out a greeting")
+        );
+        assert_eq!(message.as_concat_text(), "Hello, world!");
+    }
+
     #[test]
     fn test_response_to_message_with_invalid_function_name() {
         let response = json!({
@@ -536,4 +736,98 @@ mod tests {
             panic!("Expected valid tool request");
         }
     }
+
+    #[test]
+    fn test_stream_accumulator() -> anyhow::Result<()> {
+        let events = [
+            json!({"candidates": [{"content": {"role": "model", "parts": [{"text": "Checking "}]}}], "modelVersion": "gemini-2.0-flash"}),
+            json!({"candidates": [{"content": {"role": "model", "parts": [{"text": "now"}]}}]}),
+            json!({"candidates": [{"content": {"role": "model", "parts": [
+                {"functionCall": {"name": "developer__shell", "args": {"command": "ls"}}}
+            ]}}], "usageMetadata": {"promptTokenCount": 10, "candidatesTokenCount": 5, "totalTokenCount": 15}}),
+        ];
+
+        let mut accumulator = ContentStreamAccumulator::default();
+        let mut text = String::new();
+        for event in events {
+            let event = SseEvent {
+                event: None,
+                data: event.to_string(),
+            };
+            for chunk in accumulator.push(&event)? {
+                if let CompletionChunk::Text(delta) = chunk {
+                    text.push_str(&delta);
+                }
+            }
+        }
+        assert_eq!(text, "Checking now");
+
+        let response = accumulator.finish();
+        assert_eq!(response["modelVersion"], "gemini-2.0-flash");
+        let message = response_to_message(response.clone())?;
+        assert_eq!(message.content.len(), 2);
+        assert_eq!(message.as_concat_text(), "Checking now");
+        let tool_call = message.content[1]
+            .as_tool_request()
+            .unwrap()
+            .tool_call
+            .as_ref()
+            .unwrap();
+        assert_eq!(tool_call.arguments, json!({"command": "ls"}));
+        assert_eq!(get_usage(&response)?.total_tokens, Some(15));
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_request_generation_settings() -> anyhow::Result<()> {
+        let tools = [Tool::new("developer__shell", "Run a command", json!({}))];
+        let model_config = ModelConfig::new("gemini-2.0-flash".to_string())
+            .with_top_p(Some(0.5))
+            .with_seed(Some(7))
+            .with_presence_penalty(Some(0.5))
+            .with_tool_choice(Some(ToolChoice::Tool("developer__shell".to_string())));
+
+        let payload = create_request(&model_config, "system", &[], &tools)?;
+        let generation_config = &payload["generationConfig"];
+        assert_eq!(generation_config["topP"], 0.5);
+        assert_eq!(generation_config["seed"], 7);
+        assert_eq!(generation_config["presencePenalty"], 0.5);
+        assert_eq!(
+            payload["toolConfig"]["functionCallingConfig"],
+            json!({"mode": "ANY", "allowedFunctionNames": ["developer__shell"]})
+        );
+
+        let model_config = model_config.with_parallel_tool_calls(Some(false));
+        assert!(create_request(&model_config, "system", &[], &tools).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_response_schema() -> Result<()> {
+        let model_config = ModelConfig::new("gemini-2.0-flash".to_string()).with_top_k(Some(40));
+        let mut payload = create_request(&model_config, "system", &[], &[])?;
+        set_response_schema(
+            &mut payload,
+            &json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "object",
+                "properties": {"files": {"type": "integer", "minimum": 0}},
+                "additionalProperties": false
+            }),
+        );
+
+        // Unsupported attributes are dropped and the other settings are kept
+        assert_eq!(
+            payload["generationConfig"],
+            json!({
+                "topK": 40,
+                "responseMimeType": "application/json",
+                "responseSchema": {
+                    "type": "object",
+                    "properties": {"files": {"type": "integer"}}
+                }
+            })
+        );
+        Ok(())
+    }
 }
@@ -1,4 +1,73 @@
 pub mod anthropic;
 pub mod bedrock;
 pub mod google;
+pub mod ollama;
 pub mod openai;
+
+use crate::message::Message;
+use crate::model::{ModelConfig, ToolChoice};
+use anyhow::{anyhow, Result};
+use mcp_core::role::Role;
+use mcp_core::tool::Tool;
+
+/// Generation settings which an API has no equivalent for, which is reported as
+/// `ProviderError::NotSupported` so a fallback chain can move on to another provider
+#[derive(Debug, thiserror::Error)]
+#[error("The {api} API does not support {settings}, unset it to use this provider")]
+pub struct UnsupportedSettings {
+    pub api: String,
+    pub settings: String,
+}
+
+/// Fail a request which sets generation settings the API has no equivalent for, rather than
+/// sending it without them and leaving the user to wonder why they had no effect
+pub fn reject_settings(api: &str, settings: &[(&str, bool)]) -> Result<()> {
+    let set: Vec<&str> = settings
+        .iter()
+        .filter(|(_, is_set)| *is_set)
+        .map(|(name, _)| *name)
+        .collect();
+    if set.is_empty() {
+        return Ok(());
+    }
+    Err(UnsupportedSettings {
+        api: api.to_string(),
+        settings: set.join(", "),
+    }
+    .into())
+}
+
+/// The tool choice to send with a request, none when there are no tools to choose from
+///
+/// A choice which forces a tool call only applies until the model has called a tool since the
+/// user's latest request, after which it is left to the provider so the model can answer.
+pub fn tool_choice<'a>(
+    model_config: &'a ModelConfig,
+    messages: &[Message],
+    tools: &[Tool],
+) -> Result<Option<&'a ToolChoice>> {
+    if tools.is_empty() {
+        return Ok(None);
+    }
+    let forced = matches!(
+        model_config.tool_choice,
+        Some(ToolChoice::Required | ToolChoice::Tool(_))
+    );
+    let called_tool = messages
+        .iter()
+        .rev()
+        .take_while(|message| !(message.role == Role::User && message.has_only_text_content()))
+        .any(|message| message.is_tool_response());
+    if forced && called_tool {
+        return Ok(None);
+    }
+    if let Some(ToolChoice::Tool(name)) = &model_config.tool_choice {
+        if !tools.iter().any(|tool| &tool.name == name) {
+            return Err(anyhow!(
+                "The tool choice requires calling {}, which is not one of the available tools",
+                name
+            ));
+        }
+    }
+    Ok(model_config.tool_choice.as_ref())
+}
@@ -0,0 +1,392 @@
+use crate::message::Message;
+use crate::model::{ModelConfig, ToolChoice};
+use crate::providers::base::CompletionChunk;
+use crate::providers::errors::ProviderError;
+use crate::providers::formats::openai::{self, ChatStreamAccumulator};
+use crate::providers::formats::{reject_settings, tool_choice};
+use crate::providers::streaming::{SseEvent, StreamAccumulator};
+use crate::providers::utils::ImageFormat;
+use anyhow::Result;
+use mcp_core::tool::Tool;
+use serde_json::{json, Map, Value};
+
+/// Request settings which Ollama takes as model options, by their name in an OpenAI request
+const OPTIONS: &[(&str, &str)] = &[
+    ("temperature", "temperature"),
+    ("top_p", "top_p"),
+    ("max_tokens", "num_predict"),
+    ("stop", "stop"),
+    ("seed", "seed"),
+    ("presence_penalty", "presence_penalty"),
+    ("frequency_penalty", "frequency_penalty"),
+];
+
+/// Build a request for Ollama's native chat API
+///
+/// Unlike its OpenAI compatible endpoint, the native API takes the model's options with each
+/// request, so `num_ctx` sets the context window the server loads the model with. The request
+/// is built by the OpenAI formatter and then converted, so both share the message handling.
+pub fn create_request(
+    model_config: &ModelConfig,
+    system: &str,
+    messages: &[Message],
+    tools: &[Tool],
+    num_ctx: Option<usize>,
+) -> Result<Value> {
+    let choice = tool_choice(model_config, messages, tools)?;
+    reject_settings(
+        "Ollama",
+        &[
+            (
+                "tool_choice",
+                matches!(choice, Some(ToolChoice::Required | ToolChoice::Tool(_))),
+            ),
+            (
+                "parallel_tool_calls",
+                model_config.parallel_tool_calls == Some(false),
+            ),
+        ],
+    )?;
+
+    // top_k has no OpenAI equivalent, it is added to the options below
+    let openai_config = model_config.clone().with_top_k(None);
+    let request = openai::create_request(
+        &openai_config,
+        system,
+        messages,
+        tools,
+        &ImageFormat::OpenAi,
+    )?;
+
+    let messages: Vec<Value> = request["messages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(to_native_message)
+        .collect();
+    let mut payload = json!({
+        "model": request["model"],
+        "messages": messages,
+        "stream": false,
+    });
+    if let Some(tools) = request.get("tools") {
+        if !matches!(choice, Some(ToolChoice::None)) {
+            payload["tools"] = tools.clone();
+        }
+    }
+
+    let mut options = Map::new();
+    for (from, to) in OPTIONS {
+        if let Some(value) = request.get(*from) {
+            options.insert(to.to_string(), value.clone());
+        }
+    }
+    if let Some(top_k) = model_config.top_k {
+        options.insert("top_k".to_string(), json!(top_k));
+    }
+    if let Some(num_ctx) = num_ctx {
+        options.insert("num_ctx".to_string(), json!(num_ctx));
+    }
+    if !options.is_empty() {
+        payload["options"] = Value::Object(options);
+    }
+    Ok(payload)
+}
+
+/// Convert a message of an OpenAI request to the native format, which takes its text as a
+/// single string, images as base64 data and tool call arguments as objects
+fn to_native_message(message: &Value) -> Value {
+    let mut text = Vec::new();
+    let mut images = Vec::new();
+    match &message["content"] {
+        Value::String(content) => text.push(content.as_str()),
+        Value::Array(parts) => {
+            for part in parts {
+                if let Some(part_text) = part.get("text").and_then(|t| t.as_str()) {
+                    text.push(part_text);
+                } else if let Some(url) = part["image_url"]["url"].as_str() {
+                    // Images are formatted as data URLs, e.g. "data:image/png;base64,..."
+                    if let Some((_, data)) = url.split_once(";base64,") {
+                        images.push(data);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+
+    let mut native = json!({
+        "role": message["role"],
+        "content": text.join("\n"),
+    });
+    if !images.is_empty() {
+        native["images"] = json!(images);
+    }
+    if let Some(tool_calls) = message.get("tool_calls").and_then(|t| t.as_array()) {
+        let tool_calls: Vec<Value> = tool_calls
+            .iter()
+            .map(|tool_call| {
+                let arguments = tool_call["function"]["arguments"]
+                    .as_str()
+                    .and_then(|arguments| serde_json::from_str::<Value>(arguments).ok())
+                    .unwrap_or_else(|| json!({}));
+                json!({
+                    "function": {
+                        "name": tool_call["function"]["name"],
+                        "arguments": arguments,
+                    }
+                })
+            })
+            .collect();
+        native["tool_calls"] = json!(tool_calls);
+    }
+    native
+}
+
+/// Convert the tool calls of a native message to the OpenAI format, numbering them from `first`
+///
+/// Older Ollama versions don't give tool calls an id, so one is made up for them.
+fn to_openai_tool_calls(message: &Value, first: usize) -> Vec<Value> {
+    message["tool_calls"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(index, tool_call)| {
+            let id = tool_call["id"]
+                .as_str()
+                .map(String::from)
+                .unwrap_or_else(|| format!("call_{}", nanoid::nanoid!(12)));
+            let arguments = match &tool_call["function"]["arguments"] {
+                Value::String(arguments) => arguments.clone(),
+                Value::Null => "{}".to_string(),
+                arguments => arguments.to_string(),
+            };
+            json!({
+                "index": first + index,
+                "id": id,
+                "type": "function",
+                "function": {"name": tool_call["function"]["name"], "arguments": arguments}
+            })
+        })
+        .collect()
+}
+
+/// The usage of a native response, in the OpenAI format, if the server reported it
+fn to_openai_usage(response: &Value) -> Option<Value> {
+    let input = response.get("prompt_eval_count");
+    let output = response.get("eval_count");
+    if input.is_none() && output.is_none() {
+        return None;
+    }
+    Some(json!({"prompt_tokens": input, "completion_tokens": output}))
+}
+
+/// Convert a native chat response to an OpenAI chat completion, so it can be read by the
+/// OpenAI format's `response_to_message` and `get_usage`
+pub fn to_chat_completion(response: &Value) -> Value {
+    let message = &response["message"];
+    let mut converted = json!({"role": "assistant"});
+    if let Some(content) = message["content"].as_str().filter(|c| !c.is_empty()) {
+        converted["content"] = json!(content);
+    }
+    if let Some(thinking) = message["thinking"].as_str().filter(|t| !t.is_empty()) {
+        converted["reasoning"] = json!(thinking);
+    }
+    let tool_calls = to_openai_tool_calls(message, 0);
+    if !tool_calls.is_empty() {
+        converted["tool_calls"] = json!(tool_calls);
+    }
+
+    let mut completion = json!({
+        "model": response["model"],
+        "choices": [{"index": 0, "message": converted}],
+    });
+    if let Some(usage) = to_openai_usage(response) {
+        completion["usage"] = usage;
+    }
+    completion
+}
+
+/// Accumulates the lines of a native chat stream into an OpenAI chat completion
+#[derive(Debug, Default)]
+pub struct OllamaStreamAccumulator {
+    inner: ChatStreamAccumulator,
+    /// Tool calls arrive whole, so each line's are numbered after those already seen
+    tool_calls: usize,
+}
+
+impl StreamAccumulator for OllamaStreamAccumulator {
+    fn push(&mut self, event: &SseEvent) -> Result<Vec<CompletionChunk>, ProviderError> {
+        let line: Value = serde_json::from_str(&event.data).map_err(|e| {
+            ProviderError::RequestFailed(format!("Invalid stream chunk: {}: {}", e, event.data))
+        })?;
+        if let Some(error) = line.get("error").and_then(|e| e.as_str()) {
+            return Err(ProviderError::ServerError(error.to_string()));
+        }
+
+        let message = &line["message"];
+        let tool_calls = to_openai_tool_calls(message, self.tool_calls);
+        self.tool_calls += tool_calls.len();
+        let mut delta = json!({
+            "content": message["content"],
+            "reasoning": message["thinking"],
+        });
+        if !tool_calls.is_empty() {
+            delta["tool_calls"] = json!(tool_calls);
+        }
+
+        let mut chunk = json!({
+            "model": line["model"],
+            "choices": [{"index": 0, "delta": delta}],
+        });
+        if line["done"].as_bool() == Some(true) {
+            if let Some(usage) = to_openai_usage(&line) {
+                chunk["usage"] = usage;
+            }
+        }
+        self.inner.push_chunk(&chunk)
+    }
+
+    fn finish(self) -> Value {
+        self.inner.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::formats::openai::{get_usage, response_to_message};
+    use mcp_core::ToolCall;
+
+    #[test]
+    fn test_create_request() -> Result<()> {
+        let model_config = ModelConfig::new("qwen2.5".to_string())
+            .with_temperature(Some(0.5))
+            .with_max_tokens(Some(100))
+            .with_top_k(Some(40));
+        let tool = Tool::new(
+            "developer__shell",
+            "Run a command",
+            json!({"type": "object"}),
+        );
+        let messages = [
+            Message::user()
+                .with_text("What is in this image?")
+                .with_image("aGVsbG8=", "image/png"),
+            Message::assistant().with_tool_request(
+                "call_1",
+                Ok(ToolCall::new("developer__shell", json!({"command": "ls"}))),
+            ),
+        ];
+        let request = create_request(&model_config, "system", &messages, &[tool], Some(8192))?;
+
+        assert_eq!(request["stream"], json!(false));
+        assert_eq!(
+            request["options"],
+            json!({"temperature": 0.5, "num_predict": 100, "top_k": 40, "num_ctx": 8192})
+        );
+        assert_eq!(request["tools"][0]["function"]["name"], "developer__shell");
+        assert_eq!(request["messages"][0]["role"], "system");
+        assert_eq!(request["messages"][1]["images"], json!(["aGVsbG8="]));
+        assert_eq!(
+            request["messages"][2]["tool_calls"][0]["function"]["arguments"],
+            json!({"command": "ls"})
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_forced_tool_choice_is_rejected() {
+        let model_config =
+            ModelConfig::new("qwen2.5".to_string()).with_tool_choice(Some(ToolChoice::Required));
+        let tool = Tool::new(
+            "developer__shell",
+            "Run a command",
+            json!({"type": "object"}),
+        );
+        let messages = [Message::user().with_text("Hi")];
+        let error = create_request(&model_config, "", &messages, &[tool], None).unwrap_err();
+        assert!(error
+            .downcast_ref::<super::super::UnsupportedSettings>()
+            .is_some());
+    }
+
+    #[test]
+    fn test_response_to_message() -> Result<()> {
+        let response = json!({
+            "model": "qwen2.5",
+            "message": {
+                "role": "assistant",
+                "content": "",
+                "tool_calls": [{"function": {"name": "developer__shell", "arguments": {"command": "ls"}}}]
+            },
+            "done": true,
+            "prompt_eval_count": 12,
+            "eval_count": 8
+        });
+        let completion = to_chat_completion(&response);
+        let message = response_to_message(completion.clone())?;
+        assert_eq!(message.content.len(), 1);
+        let request = message.content[0].as_tool_request().unwrap();
+        assert!(request.id.starts_with("call_"));
+        assert_eq!(
+            request.tool_call.as_ref().unwrap().arguments,
+            json!({"command": "ls"})
+        );
+        assert_eq!(get_usage(&completion)?.total_tokens, Some(20));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_accumulator() -> Result<()> {
+        let mut accumulator = OllamaStreamAccumulator::default();
+        let lines = [
+            json!({"model": "qwen2.5", "message": {"role": "assistant", "content": "Let me "}, "done": false}),
+            json!({"model": "qwen2.5", "message": {"role": "assistant", "content": "check."}, "done": false}),
+            json!({"model": "qwen2.5", "message": {"role": "assistant", "content": "", "tool_calls": [
+                {"function": {"name": "developer__shell", "arguments": {"command": "ls"}}}
+            ]}, "done": false}),
+            json!({"model": "qwen2.5", "message": {"role": "assistant", "content": ""}, "done": true,
+                "prompt_eval_count": 12, "eval_count": 8}),
+        ];
+
+        let mut text = String::new();
+        for line in lines {
+            let event = SseEvent {
+                event: None,
+                data: line.to_string(),
+            };
+            for chunk in accumulator.push(&event)? {
+                if let CompletionChunk::Text(delta) = chunk {
+                    text.push_str(&delta);
+                }
+            }
+        }
+        assert_eq!(text, "Let me check.");
+
+        let response = accumulator.finish();
+        let message = response_to_message(response.clone())?;
+        assert_eq!(message.as_concat_text(), "Let me check.");
+        let request = message.content[1].as_tool_request().unwrap();
+        assert_eq!(
+            request.tool_call.as_ref().unwrap().arguments,
+            json!({"command": "ls"})
+        );
+        assert_eq!(get_usage(&response)?.total_tokens, Some(20));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_error() {
+        let mut accumulator = OllamaStreamAccumulator::default();
+        let event = SseEvent {
+            event: None,
+            data: json!({"error": "model runner has unexpectedly stopped"}).to_string(),
+        };
+        assert!(matches!(
+            accumulator.push(&event),
+            Err(ProviderError::ServerError(_))
+        ));
+    }
+}
@@ -1,10 +1,12 @@
 use crate::message::{Message, MessageContent};
-use crate::model::ModelConfig;
-use crate::providers::base::Usage;
+use crate::model::{ModelConfig, ToolChoice};
+use crate::providers::base::{CompletionChunk, Usage};
 use crate::providers::errors::ProviderError;
+use crate::providers::formats::{reject_settings, tool_choice};
+use crate::providers::streaming::{SseEvent, StreamAccumulator};
 use crate::providers::utils::{
-    convert_image, detect_image_path, is_valid_function_name, load_image_file,
-    sanitize_function_name, ImageFormat,
+    convert_image, detect_image_path, get_embedding, is_valid_function_name, load_image_file,
+    sanitize_function_name, system_prompt_as_user_text, ImageFormat,
 };
 use anyhow::{anyhow, Error};
 use mcp_core::ToolError;
@@ -140,6 +142,10 @@ pub fn format_messages(messages: &[Message], image_format: &ImageFormat) -> Vec<
                     // Handle direct image content
                     converted["content"] = json!([convert_image(image, image_format)]);
                 }
+                MessageContent::Thinking(_) | MessageContent::RedactedThinking(_) => {
+                    // Reasoning is not sent back, some APIs (e.g. DeepSeek) reject it in requests
+                    continue;
+                }
             }
         }
 
@@ -184,6 +190,17 @@ pub fn response_to_message(response: Value) -> anyhow::Result<Message> {
     let original = response["choices"][0]["message"].clone();
     let mut content = Vec::new();
 
+    // Reasoning models served through compatible APIs (e.g. DeepSeek, OpenRouter) return their
+    // reasoning alongside the content
+    if let Some(reasoning) = original
+        .get("reasoning_content")
+        .or_else(|| original.get("reasoning"))
+        .and_then(|r| r.as_str())
+        .filter(|r| !r.is_empty())
+    {
+        content.push(MessageContent::thinking(reasoning, None));
+    }
+
     if let Some(text) = original.get("content") {
         if let Some(text_str) = text.as_str() {
             content.push(MessageContent::text(text_str));
@@ -238,9 +255,49 @@ pub fn response_to_message(response: Value) -> anyhow::Result<Message> {
         role: Role::Assistant,
         created: chrono::Utc::now().timestamp(),
         content,
+        pinned: false,
+    })
+}
+
+pub fn create_embeddings_request(model: &str, texts: &[String]) -> Value {
+    json!({
+        "model": model,
+        "input": texts,
     })
 }
 
+/// The vectors of an embeddings response, ordered like the inputs, and the tokens used
+pub fn embeddings_from_response(response: &Value) -> Result<(Vec<Vec<f32>>, Usage), ProviderError> {
+    let data = response
+        .get("data")
+        .and_then(|d| d.as_array())
+        .ok_or_else(|| {
+            ProviderError::RequestFailed("No data in embeddings response".to_string())
+        })?;
+
+    let mut indexed = data
+        .iter()
+        .enumerate()
+        .map(|(position, item)| {
+            let index = item
+                .get("index")
+                .and_then(|i| i.as_u64())
+                .map_or(position, |i| i as usize);
+            let embedding = item.get("embedding").ok_or_else(|| {
+                ProviderError::RequestFailed("No embedding in embeddings response".to_string())
+            })?;
+            Ok((index, get_embedding(embedding)?))
+        })
+        .collect::<Result<Vec<_>, ProviderError>>()?;
+    indexed.sort_by_key(|(index, _)| *index);
+
+    let usage = get_usage(response).unwrap_or_default();
+    Ok((
+        indexed.into_iter().map(|(_, vector)| vector).collect(),
+        usage,
+    ))
+}
+
 pub fn get_usage(data: &Value) -> Result<Usage, ProviderError> {
     let usage = data
         .get("usage")
@@ -265,7 +322,15 @@ pub fn get_usage(data: &Value) -> Result<Usage, ProviderError> {
             _ => None,
         });
 
-    Ok(Usage::new(input_tokens, output_tokens, total_tokens))
+    // OpenAI caches prompts automatically, so only reads from the cache are reported
+    let cache_read_input_tokens = usage
+        .get("prompt_tokens_details")
+        .and_then(|d| d.get("cached_tokens"))
+        .and_then(|v| v.as_i64())
+        .map(|v| v as i32);
+
+    Ok(Usage::new(input_tokens, output_tokens, total_tokens)
+        .with_cache_tokens(None, cache_read_input_tokens))
 }
 
 /// Validates and fixes tool schemas to ensure they have proper parameter structure.
@@ -321,30 +386,55 @@ pub fn create_request(
     tools: &[Tool],
     image_format: &ImageFormat,
 ) -> anyhow::Result<Value, Error> {
-    if model_config.model_name.starts_with("o1-mini") {
+    if !tools.is_empty() && !model_config.capabilities.tool_calling {
         return Err(anyhow!(
-            "o1-mini model is not currently supported since Goose uses tool calling and o1-mini does not support it. Please use o1 or o3 models instead."
+            "{} does not support tool calling, choose a model which does to use extensions",
+            model_config.model_name
         ));
     }
 
     let is_o1 = model_config.model_name.starts_with("o1");
     let is_o3 = model_config.model_name.starts_with("o3");
 
-    // Only extract reasoning effort for O1/O3 models
+    reject_settings("OpenAI", &[("top_k", model_config.top_k.is_some())])?;
+    if is_o1 || is_o3 {
+        reject_settings(
+            "OpenAI reasoning model",
+            &[
+                ("top_p", model_config.top_p.is_some()),
+                ("presence_penalty", model_config.presence_penalty.is_some()),
+                (
+                    "frequency_penalty",
+                    model_config.frequency_penalty.is_some(),
+                ),
+            ],
+        )?;
+    } else if model_config.reasoning_effort.is_some() {
+        return Err(anyhow!(
+            "{} does not take a reasoning effort, only o1 and o3 models do",
+            model_config.model_name
+        ));
+    }
+
+    // Only extract reasoning effort for O1/O3 models, where an explicit setting wins over the
+    // suffix on the model name
     let (model_name, reasoning_effort) = if is_o1 || is_o3 {
         let parts: Vec<&str> = model_config.model_name.split('-').collect();
         let last_part = parts.last().unwrap();
 
-        match *last_part {
+        let (model_name, suffix_effort) = match *last_part {
             "low" | "medium" | "high" => {
                 let base_name = parts[..parts.len() - 1].join("-");
                 (base_name, Some(last_part.to_string()))
             }
-            _ => (
-                model_config.model_name.to_string(),
-                Some("medium".to_string()),
-            ),
-        }
+            _ => (model_config.model_name.to_string(), None),
+        };
+        let effort = model_config
+            .reasoning_effort
+            .map(|effort| effort.as_str().to_string())
+            .or(suffix_effort)
+            .unwrap_or_else(|| "medium".to_string());
+        (model_name, Some(effort))
     } else {
         // For non-O family models, use the model name as is and no reasoning effort
         (model_config.model_name.to_string(), None)
@@ -355,6 +445,14 @@ pub fn create_request(
         "content": system
     });
 
+    // Models without system prompt support get it at the start of the conversation instead
+    let folded;
+    let messages = if model_config.capabilities.system_prompt || system.is_empty() {
+        messages
+    } else {
+        folded = system_prompt_as_user_text(system, messages);
+        &folded
+    };
     let messages_spec = format_messages(messages, image_format);
     let mut tools_spec = if !tools.is_empty() {
         format_tools(tools)?
@@ -365,7 +463,10 @@ pub fn create_request(
     // Validate tool schemas
     validate_tool_schemas(&mut tools_spec);
 
-    let mut messages_array = vec![system_message];
+    let mut messages_array = Vec::new();
+    if model_config.capabilities.system_prompt {
+        messages_array.push(system_message);
+    }
     messages_array.extend(messages_spec);
 
     let mut payload = json!({
@@ -385,6 +486,18 @@ pub fn create_request(
             .as_object_mut()
             .unwrap()
             .insert("tools".to_string(), json!(tools_spec));
+        if let Some(choice) = tool_choice(model_config, messages, tools)? {
+            let choice = match choice {
+                ToolChoice::Auto => json!("auto"),
+                ToolChoice::Required => json!("required"),
+                ToolChoice::None => json!("none"),
+                ToolChoice::Tool(name) => json!({"type": "function", "function": {"name": name}}),
+            };
+            payload["tool_choice"] = choice;
+        }
+        if let Some(parallel) = model_config.parallel_tool_calls {
+            payload["parallel_tool_calls"] = json!(parallel);
+        }
     }
     // o1, o3 models currently don't support temperature
     if !is_o1 && !is_o3 {
@@ -408,12 +521,179 @@ pub fn create_request(
             .unwrap()
             .insert(key.to_string(), json!(tokens));
     }
+
+    let settings = payload.as_object_mut().unwrap();
+    if let Some(top_p) = model_config.top_p {
+        settings.insert("top_p".to_string(), json!(top_p));
+    }
+    if !model_config.stop_sequences.is_empty() {
+        settings.insert("stop".to_string(), json!(model_config.stop_sequences));
+    }
+    if let Some(seed) = model_config.seed {
+        settings.insert("seed".to_string(), json!(seed));
+    }
+    if let Some(penalty) = model_config.presence_penalty {
+        settings.insert("presence_penalty".to_string(), json!(penalty));
+    }
+    if let Some(penalty) = model_config.frequency_penalty {
+        settings.insert("frequency_penalty".to_string(), json!(penalty));
+    }
     Ok(payload)
 }
 
+/// Constrain a chat request to respond with JSON matching the schema
+///
+/// Strict mode is left off, since it only accepts schemas which close every object and require
+/// every property
+pub fn set_response_schema(payload: &mut Value, schema: &Value) {
+    payload["response_format"] = json!({
+        "type": "json_schema",
+        "json_schema": {"name": "final_output", "schema": schema, "strict": false}
+    });
+}
+
+/// Accumulates streamed chat completion chunks into a complete chat completion response
+#[derive(Debug, Default)]
+pub struct ChatStreamAccumulator {
+    model: Option<String>,
+    content: String,
+    reasoning: String,
+    /// (id, name, arguments) for each tool call, by index
+    tool_calls: Vec<(String, String, String)>,
+    usage: Option<Value>,
+}
+
+impl ChatStreamAccumulator {
+    /// Process one chat completion chunk, for APIs whose chunks are converted to this format
+    pub fn push_chunk(&mut self, chunk: &Value) -> Result<Vec<CompletionChunk>, ProviderError> {
+        if let Some(error) = chunk.get("error") {
+            let message = error
+                .get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or("Unknown error");
+            return Err(ProviderError::ServerError(message.to_string()));
+        }
+
+        if let Some(model) = chunk.get("model").and_then(|m| m.as_str()) {
+            self.model = Some(model.to_string());
+        }
+        if let Some(usage) = chunk.get("usage").filter(|u| !u.is_null()) {
+            self.usage = Some(usage.clone());
+        }
+
+        let mut chunks = Vec::new();
+        let Some(delta) = chunk["choices"].get(0).and_then(|c| c.get("delta")) else {
+            return Ok(chunks);
+        };
+
+        if let Some(reasoning) = delta
+            .get("reasoning_content")
+            .or_else(|| delta.get("reasoning"))
+            .and_then(|r| r.as_str())
+        {
+            if !reasoning.is_empty() {
+                self.reasoning.push_str(reasoning);
+                chunks.push(CompletionChunk::Thinking(reasoning.to_string()));
+            }
+        }
+
+        if let Some(text) = delta.get("content").and_then(|c| c.as_str()) {
+            if !text.is_empty() {
+                self.content.push_str(text);
+                chunks.push(CompletionChunk::Text(text.to_string()));
+            }
+        }
+
+        for tool_call in delta
+            .get("tool_calls")
+            .and_then(|t| t.as_array())
+            .into_iter()
+            .flatten()
+        {
+            let index = tool_call
+                .get("index")
+                .and_then(|i| i.as_u64())
+                .map(|i| i as usize)
+                .unwrap_or(self.tool_calls.len().saturating_sub(1));
+            if self.tool_calls.len() <= index {
+                self.tool_calls.resize(index + 1, Default::default());
+            }
+
+            let id = tool_call.get("id").and_then(|i| i.as_str());
+            let name = tool_call["function"].get("name").and_then(|n| n.as_str());
+            let arguments = tool_call["function"]
+                .get("arguments")
+                .and_then(|a| a.as_str())
+                .unwrap_or_default();
+
+            let entry = &mut self.tool_calls[index];
+            if let Some(id) = id {
+                entry.0 = id.to_string();
+            }
+            if let Some(name) = name {
+                entry.1.push_str(name);
+            }
+            entry.2.push_str(arguments);
+
+            chunks.push(CompletionChunk::ToolCall {
+                index,
+                id: id.map(String::from),
+                name: name.map(String::from),
+                arguments: arguments.to_string(),
+            });
+        }
+
+        Ok(chunks)
+    }
+}
+
+impl StreamAccumulator for ChatStreamAccumulator {
+    fn push(&mut self, event: &SseEvent) -> Result<Vec<CompletionChunk>, ProviderError> {
+        let chunk: Value = serde_json::from_str(&event.data).map_err(|e| {
+            ProviderError::RequestFailed(format!("Invalid stream chunk: {}: {}", e, event.data))
+        })?;
+        self.push_chunk(&chunk)
+    }
+
+    fn finish(self) -> Value {
+        let tool_calls: Vec<Value> = self
+            .tool_calls
+            .into_iter()
+            .map(|(id, name, arguments)| {
+                json!({
+                    "id": id,
+                    "type": "function",
+                    "function": {"name": name, "arguments": arguments}
+                })
+            })
+            .collect();
+
+        let mut message = json!({"role": "assistant"});
+        if !self.reasoning.is_empty() {
+            message["reasoning_content"] = json!(self.reasoning);
+        }
+        if !self.content.is_empty() {
+            message["content"] = json!(self.content);
+        }
+        if !tool_calls.is_empty() {
+            message["tool_calls"] = json!(tool_calls);
+        }
+
+        let mut response = json!({"choices": [{"index": 0, "message": message}]});
+        if let Some(model) = self.model {
+            response["model"] = json!(model);
+        }
+        if let Some(usage) = self.usage {
+            response["usage"] = usage;
+        }
+        response
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::model::{ModelCapabilities, ReasoningEffort};
     use mcp_core::content::Content;
     use serde_json::json;
 
@@ -726,6 +1006,51 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_get_usage_cached_tokens() -> anyhow::Result<()> {
+        let response = json!({
+            "usage": {
+                "prompt_tokens": 2000,
+                "completion_tokens": 100,
+                "total_tokens": 2100,
+                "prompt_tokens_details": {"cached_tokens": 1920}
+            }
+        });
+
+        let usage = get_usage(&response)?;
+        assert_eq!(usage.input_tokens, Some(2000));
+        assert_eq!(usage.cache_creation_input_tokens, None);
+        assert_eq!(usage.cache_read_input_tokens, Some(1920));
+        Ok(())
+    }
+
+    #[test]
+    fn test_response_to_message_reasoning() -> anyhow::Result<()> {
+        let response = json!({
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "reasoning_content": "9.11 has fewer tenths than 9.8",
+                    "content": "9.8 is larger"
+                }
+            }]
+        });
+
+        let message = response_to_message(response)?;
+        assert_eq!(
+            message.content[0].as_thinking(),
+            Some("9.11 has fewer tenths than 9.8")
+        );
+        assert_eq!(message.as_concat_text(), "9.8 is larger");
+
+        // The reasoning is dropped when the message is sent back
+        let spec = format_messages(&[message], &ImageFormat::OpenAi);
+        assert_eq!(spec.len(), 1);
+        assert_eq!(spec[0]["content"], "9.8 is larger");
+        assert!(spec[0].get("reasoning_content").is_none());
+        Ok(())
+    }
+
     #[test]
     fn test_response_to_message_valid_toolrequest() -> anyhow::Result<()> {
         let response: Value = serde_json::from_str(OPENAI_TOOL_USE_RESPONSE)?;
@@ -815,6 +1140,9 @@ mod tests {
             context_limit: Some(4096),
             temperature: None,
             max_tokens: Some(1024),
+            thinking_budget: None,
+            capabilities: ModelCapabilities::default(),
+            ..ModelConfig::new("gpt-4o".to_string())
         };
         let request = create_request(&model_config, "system", &[], &[], &ImageFormat::OpenAi)?;
         let obj = request.as_object().unwrap();
@@ -845,6 +1173,9 @@ mod tests {
             context_limit: Some(4096),
             temperature: None,
             max_tokens: Some(1024),
+            thinking_budget: None,
+            capabilities: ModelCapabilities::default(),
+            ..ModelConfig::new("gpt-4o".to_string())
         };
         let request = create_request(&model_config, "system", &[], &[], &ImageFormat::OpenAi)?;
         let obj = request.as_object().unwrap();
@@ -867,6 +1198,29 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_create_request_without_system_prompt_support() -> anyhow::Result<()> {
+        let model_config = ModelConfig::new("o1-mini".to_string());
+        assert!(!model_config.capabilities.system_prompt);
+
+        let messages = [Message::user().with_text("Hello")];
+        let request = create_request(
+            &model_config,
+            "Be brief",
+            &messages,
+            &[],
+            &ImageFormat::OpenAi,
+        )?;
+
+        // The system prompt isn't dropped, it starts the first user message
+        let messages = request["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["role"], "user");
+        let content = messages[0]["content"].to_string();
+        assert!(content.find("Be brief").unwrap() < content.find("Hello").unwrap());
+        Ok(())
+    }
+
     #[test]
     fn test_create_request_o3_custom_reasoning_effort() -> anyhow::Result<()> {
         // Test custom reasoning effort for O3 model
@@ -876,6 +1230,9 @@ mod tests {
             context_limit: Some(4096),
             temperature: None,
             max_tokens: Some(1024),
+            thinking_budget: None,
+            capabilities: ModelCapabilities::default(),
+            ..ModelConfig::new("gpt-4o".to_string())
         };
         let request = create_request(&model_config, "system", &[], &[], &ImageFormat::OpenAi)?;
         let obj = request.as_object().unwrap();
@@ -897,4 +1254,109 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_create_request_generation_settings() -> anyhow::Result<()> {
+        let tools = [Tool::new("developer__shell", "Run a command", json!({}))];
+        let model_config = ModelConfig::new("gpt-4o".to_string())
+            .with_top_p(Some(0.5))
+            .with_stop_sequences(vec!["END".to_string()])
+            .with_seed(Some(7))
+            .with_frequency_penalty(Some(0.25))
+            .with_tool_choice(Some(ToolChoice::Tool("developer__shell".to_string())))
+            .with_parallel_tool_calls(Some(false));
+        let request = create_request(&model_config, "system", &[], &tools, &ImageFormat::OpenAi)?;
+        assert_eq!(request["top_p"], 0.5);
+        assert_eq!(request["stop"], json!(["END"]));
+        assert_eq!(request["seed"], 7);
+        assert_eq!(request["frequency_penalty"], 0.25);
+        assert!(request.get("presence_penalty").is_none());
+        assert_eq!(
+            request["tool_choice"],
+            json!({"type": "function", "function": {"name": "developer__shell"}})
+        );
+        assert_eq!(request["parallel_tool_calls"], false);
+
+        // Tool settings only make sense with tools
+        let request = create_request(&model_config, "system", &[], &[], &ImageFormat::OpenAi)?;
+        assert!(request.get("tool_choice").is_none());
+        assert!(request.get("parallel_tool_calls").is_none());
+
+        // An explicit reasoning effort wins over the model name
+        let model_config = ModelConfig::new("o3-mini-high".to_string())
+            .with_reasoning_effort(Some(ReasoningEffort::Low));
+        let request = create_request(&model_config, "system", &[], &[], &ImageFormat::OpenAi)?;
+        assert_eq!(request["model"], "o3-mini");
+        assert_eq!(request["reasoning_effort"], "low");
+
+        // Settings without an OpenAI equivalent are rejected
+        for model_config in [
+            ModelConfig::new("gpt-4o".to_string()).with_top_k(Some(40)),
+            ModelConfig::new("gpt-4o".to_string())
+                .with_reasoning_effort(Some(ReasoningEffort::High)),
+            ModelConfig::new("gpt-4o".to_string())
+                .with_tool_choice(Some(ToolChoice::Tool("missing".to_string()))),
+        ] {
+            assert!(
+                create_request(&model_config, "system", &[], &tools, &ImageFormat::OpenAi).is_err()
+            );
+        }
+        Ok(())
+    }
+
+    fn sse(data: Value) -> SseEvent {
+        SseEvent {
+            event: None,
+            data: data.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_stream_accumulator() -> anyhow::Result<()> {
+        let mut accumulator = ChatStreamAccumulator::default();
+        let events = [
+            json!({"model": "gpt-4o", "choices": [{"index": 0, "delta": {"role": "assistant", "content": "Let me "}}]}),
+            json!({"model": "gpt-4o", "choices": [{"index": 0, "delta": {"content": "check."}}]}),
+            json!({"model": "gpt-4o", "choices": [{"index": 0, "delta": {"tool_calls": [
+                {"index": 0, "id": "call_1", "type": "function", "function": {"name": "developer__shell", "arguments": ""}}
+            ]}}]}),
+            json!({"model": "gpt-4o", "choices": [{"index": 0, "delta": {"tool_calls": [
+                {"index": 0, "function": {"arguments": "{\"command\": "}}
+            ]}}]}),
+            json!({"model": "gpt-4o", "choices": [{"index": 0, "delta": {"tool_calls": [
+                {"index": 0, "function": {"arguments": "\"ls\"}"}}
+            ]}}]}),
+            json!({"model": "gpt-4o", "choices": [], "usage": {"prompt_tokens": 12, "completion_tokens": 8, "total_tokens": 20}}),
+        ];
+
+        let mut text = String::new();
+        for event in events {
+            for chunk in accumulator.push(&sse(event))? {
+                if let CompletionChunk::Text(delta) = chunk {
+                    text.push_str(&delta);
+                }
+            }
+        }
+        assert_eq!(text, "Let me check.");
+
+        let response = accumulator.finish();
+        let message = response_to_message(response.clone())?;
+        assert_eq!(message.as_concat_text(), "Let me check.");
+        let request = message.content[1].as_tool_request().unwrap();
+        let tool_call = request.tool_call.as_ref().unwrap();
+        assert_eq!(request.id, "call_1");
+        assert_eq!(tool_call.name, "developer__shell");
+        assert_eq!(tool_call.arguments, json!({"command": "ls"}));
+
+        let usage = get_usage(&response)?;
+        assert_eq!(usage.total_tokens, Some(20));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_accumulator_error() {
+        let mut accumulator = ChatStreamAccumulator::default();
+        let result = accumulator.push(&sse(json!({"error": {"message": "overloaded"}})));
+        assert!(matches!(result, Err(ProviderError::ServerError(_))));
+    }
 }
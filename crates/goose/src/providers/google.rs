@@ -1,16 +1,23 @@
 use super::errors::ProviderError;
 use crate::message::Message;
 use crate::model::ModelConfig;
-use crate::providers::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage};
-use crate::providers::formats::google::{create_request, get_usage, response_to_message};
+use crate::providers::base::{
+    complete_structured_with_prompt, CompletionStream, ConfigKey, Embeddings, Provider,
+    ProviderMetadata, ProviderUsage, Usage,
+};
+use crate::providers::formats::google::{
+    create_request, get_usage, response_to_message, set_response_schema, ContentStreamAccumulator,
+};
+use crate::providers::streaming::{response_error, stream_response};
 use crate::providers::utils::{
-    emit_debug_trace, handle_response_google_compat, unescape_json_values,
+    embed_in_batches, embedding_model, emit_debug_trace, get_embedding,
+    handle_response_google_compat, unescape_json_values,
 };
 use anyhow::Result;
 use async_trait::async_trait;
 use mcp_core::tool::Tool;
-use reqwest::Client;
-use serde_json::Value;
+use reqwest::{Client, Response};
+use serde_json::{json, Value};
 use std::time::Duration;
 use url::Url;
 
@@ -27,6 +34,10 @@ pub const GOOGLE_KNOWN_MODELS: &[&str] = &[
     "models/gemini-2.0-pro-exp-02-05",
 ];
 
+pub const GOOGLE_DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-004";
+/// The most requests one batchEmbedContents call accepts
+const GOOGLE_EMBEDDING_BATCH_SIZE: usize = 100;
+
 pub const GOOGLE_DOC_URL: &str = "https://ai.google/get-started/our-models/";
 
 #[derive(Debug, serde::Serialize)]
@@ -65,29 +76,61 @@ impl GoogleProvider {
         })
     }
 
-    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+    async fn send(&self, payload: &Value, stream: bool) -> Result<Response, ProviderError> {
         let base_url = Url::parse(&self.host)
             .map_err(|e| ProviderError::RequestFailed(format!("Invalid base URL: {e}")))?;
 
-        let url = base_url
+        let method = if stream {
+            "streamGenerateContent"
+        } else {
+            "generateContent"
+        };
+        let mut url = base_url
             .join(&format!(
-                "v1beta/models/{}:generateContent?key={}",
-                self.model.model_name, self.api_key
+                "v1beta/models/{}:{}",
+                self.model.model_name, method
             ))
             .map_err(|e| {
                 ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
             })?;
+        if stream {
+            url.query_pairs_mut().append_pair("alt", "sse");
+        }
+        url.query_pairs_mut().append_pair("key", &self.api_key);
 
         let response = self
             .client
             .post(url)
             .header("CONTENT_TYPE", "application/json")
-            .json(&payload)
+            .json(payload)
             .send()
             .await?;
+        Ok(response)
+    }
 
+    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+        let response = self.send(&payload, false).await?;
         handle_response_google_compat(response).await
     }
+
+    async fn complete_request(
+        &self,
+        payload: Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        // Make request
+        let response = self.post(payload.clone()).await?;
+
+        // Parse response
+        let message = response_to_message(unescape_json_values(&response))?;
+        let usage = get_usage(&response)?;
+        let model = match response.get("modelVersion") {
+            Some(model_version) => model_version.as_str().unwrap_or_default().to_string(),
+            None => self.model.model_name.clone(),
+        };
+        emit_debug_trace(self, &payload, &response, &usage);
+        let provider_usage = ProviderUsage::new(model, usage).with_provider("google");
+        Ok((message, provider_usage))
+    }
 }
 
 #[async_trait]
@@ -111,6 +154,90 @@ impl Provider for GoogleProvider {
         self.model.clone()
     }
 
+    /// Lists the models which can generate content, without the `models/` resource prefix
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        let base_url = Url::parse(&self.host)
+            .map_err(|e| ProviderError::RequestFailed(format!("Invalid base URL: {e}")))?;
+        let mut url = base_url.join("v1beta/models").map_err(|e| {
+            ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
+        })?;
+        url.query_pairs_mut()
+            .append_pair("pageSize", "1000")
+            .append_pair("key", &self.api_key);
+
+        let response = self.client.get(url).send().await?;
+        let response = handle_response_google_compat(response).await?;
+        let models = response
+            .get("models")
+            .and_then(|models| models.as_array())
+            .ok_or_else(|| {
+                ProviderError::RequestFailed("Model listing has no `models` array".to_string())
+            })?;
+
+        let mut names: Vec<String> = models
+            .iter()
+            .filter(|model| {
+                model["supportedGenerationMethods"]
+                    .as_array()
+                    .is_some_and(|methods| methods.iter().any(|m| m == "generateContent"))
+            })
+            .filter_map(|model| model["name"].as_str())
+            .map(|name| name.trim_start_matches("models/").to_string())
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Embeds with batchEmbedContents, which doesn't report token usage
+    async fn embed(&self, texts: &[String]) -> Result<Embeddings, ProviderError> {
+        let model = embedding_model(GOOGLE_DEFAULT_EMBEDDING_MODEL);
+        let model = model.trim_start_matches("models/").to_string();
+        let base_url = Url::parse(&self.host)
+            .map_err(|e| ProviderError::RequestFailed(format!("Invalid base URL: {e}")))?;
+        let mut url = base_url
+            .join(&format!("v1beta/models/{}:batchEmbedContents", model))
+            .map_err(|e| {
+                ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
+            })?;
+        url.query_pairs_mut().append_pair("key", &self.api_key);
+
+        let (vectors, usage) = embed_in_batches(texts, GOOGLE_EMBEDDING_BATCH_SIZE, |batch| {
+            let requests: Vec<Value> = batch
+                .iter()
+                .map(|text| {
+                    json!({
+                        "model": format!("models/{}", model),
+                        "content": {"parts": [{"text": text}]}
+                    })
+                })
+                .collect();
+            let request = self
+                .client
+                .post(url.clone())
+                .json(&json!({"requests": requests}));
+            async move {
+                let response = handle_response_google_compat(request.send().await?).await?;
+                let vectors = response["embeddings"]
+                    .as_array()
+                    .ok_or_else(|| {
+                        ProviderError::RequestFailed(
+                            "No embeddings in the Google response".to_string(),
+                        )
+                    })?
+                    .iter()
+                    .map(|embedding| get_embedding(&embedding["values"]))
+                    .collect::<Result<_, _>>()?;
+                Ok((vectors, Usage::default()))
+            }
+        })
+        .await?;
+
+        Ok(Embeddings {
+            vectors,
+            usage: ProviderUsage::new(model, usage).with_provider("google"),
+        })
+    }
+
     #[tracing::instrument(
         skip(self, system, messages, tools),
         fields(model_config, input, output, input_tokens, output_tokens, total_tokens)
@@ -122,19 +249,94 @@ impl Provider for GoogleProvider {
         tools: &[Tool],
     ) -> Result<(Message, ProviderUsage), ProviderError> {
         let payload = create_request(&self.model, system, messages, tools)?;
+        self.complete_request(payload).await
+    }
 
-        // Make request
-        let response = self.post(payload.clone()).await?;
+    /// Uses the responseSchema generation setting on models with JSON mode
+    async fn complete_structured(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        if !self.model.capabilities.json_mode {
+            return complete_structured_with_prompt(self, system, messages, schema).await;
+        }
+        let mut payload = create_request(&self.model, system, messages, &[])?;
+        set_response_schema(&mut payload, schema);
+        self.complete_request(payload).await
+    }
 
-        // Parse response
-        let message = response_to_message(unescape_json_values(&response))?;
-        let usage = get_usage(&response)?;
-        let model = match response.get("modelVersion") {
-            Some(model_version) => model_version.as_str().unwrap_or_default().to_string(),
-            None => self.model.model_name.clone(),
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<CompletionStream, ProviderError> {
+        let payload = create_request(&self.model, system, messages, tools)?;
+
+        let response = self.send(&payload, true).await?;
+        if !response.status().is_success() {
+            return Err(response_error(handle_response_google_compat(response)).await);
+        }
+
+        let model_config = self.model.clone();
+        Ok(stream_response(
+            response,
+            ContentStreamAccumulator::default(),
+            move |response| {
+                let message = response_to_message(unescape_json_values(&response))?;
+                let usage = get_usage(&response)?;
+                let model = match response.get("modelVersion") {
+                    Some(model_version) => model_version.as_str().unwrap_or_default().to_string(),
+                    None => model_config.model_name.clone(),
+                };
+                emit_debug_trace(&model_config, &payload, &response, &usage);
+                Ok((
+                    message,
+                    ProviderUsage::new(model, usage).with_provider("google"),
+                ))
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_embed() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(format!(
+                "/v1beta/models/{}:batchEmbedContents",
+                GOOGLE_DEFAULT_EMBEDDING_MODEL
+            )))
+            .and(query_param("key", "key"))
+            .and(body_partial_json(json!({"requests": [
+                {"content": {"parts": [{"text": "first"}]}},
+                {"content": {"parts": [{"text": "second"}]}}
+            ]})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "embeddings": [{"values": [1.0, 0.0]}, {"values": [0.0, 1.0]}]
+            })))
+            .mount(&server)
+            .await;
+
+        let provider = GoogleProvider {
+            client: Client::new(),
+            host: server.uri(),
+            api_key: "key".to_string(),
+            model: ModelConfig::new(GOOGLE_DEFAULT_MODEL.to_string()),
         };
-        emit_debug_trace(self, &payload, &response, &usage);
-        let provider_usage = ProviderUsage::new(model, usage);
-        Ok((message, provider_usage))
+        let embeddings = provider
+            .embed(&["first".to_string(), "second".to_string()])
+            .await?;
+        assert_eq!(embeddings.vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert_eq!(embeddings.usage.model, GOOGLE_DEFAULT_EMBEDDING_MODEL);
+        Ok(())
     }
 }
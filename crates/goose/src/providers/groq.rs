@@ -1,9 +1,11 @@
 use super::errors::ProviderError;
 use crate::message::Message;
-use crate::model::ModelConfig;
+use crate::model::{ModelCapabilities, ModelConfig};
 use crate::providers::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage, Usage};
 use crate::providers::formats::openai::{create_request, get_usage, response_to_message};
-use crate::providers::utils::get_model;
+use crate::providers::utils::{
+    get_model, get_model_names, get_retry_after, handle_response_openai_compat,
+};
 use anyhow::Result;
 use async_trait::async_trait;
 use mcp_core::Tool;
@@ -46,11 +48,12 @@ impl GroqProvider {
             .timeout(Duration::from_secs(600))
             .build()?;
 
+        let capabilities = groq_capabilities(model.capabilities.clone());
         Ok(Self {
             client,
             host,
             api_key,
-            model,
+            model: model.with_capabilities(capabilities),
         })
     }
 
@@ -70,6 +73,7 @@ impl GroqProvider {
             .await?;
 
         let status = response.status();
+        let retry_delay = get_retry_after(response.headers());
         let payload: Option<Value> = response.json().await.ok();
 
         match status {
@@ -82,7 +86,10 @@ impl GroqProvider {
                 Err(ProviderError::ContextLengthExceeded(format!("{:?}", payload)))
             }
             StatusCode::TOO_MANY_REQUESTS => {
-                Err(ProviderError::RateLimitExceeded(format!("{:?}", payload)))
+                Err(ProviderError::RateLimitExceeded {
+                    details: format!("{:?}", payload),
+                    retry_delay,
+                })
             }
             StatusCode::INTERNAL_SERVER_ERROR | StatusCode::SERVICE_UNAVAILABLE => {
                 Err(ProviderError::ServerError(format!("{:?}", payload)))
@@ -97,6 +104,14 @@ impl GroqProvider {
     }
 }
 
+/// Groq's API doesn't accept images, whichever model is used
+fn groq_capabilities(capabilities: ModelCapabilities) -> ModelCapabilities {
+    ModelCapabilities {
+        images: false,
+        ..capabilities
+    }
+}
+
 #[async_trait]
 impl Provider for GroqProvider {
     fn metadata() -> ProviderMetadata {
@@ -118,6 +133,23 @@ impl Provider for GroqProvider {
         self.model.clone()
     }
 
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        let base_url = Url::parse(&self.host)
+            .map_err(|e| ProviderError::RequestFailed(format!("Invalid base URL: {e}")))?;
+        let url = base_url.join("openai/v1/models").map_err(|e| {
+            ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
+        })?;
+
+        let response = self
+            .client
+            .get(url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .send()
+            .await?;
+        let response = handle_response_openai_compat(response).await?;
+        get_model_names(&response, "data", "id")
+    }
+
     #[tracing::instrument(
         skip(self, system, messages, tools),
         fields(model_config, input, output, input_tokens, output_tokens, total_tokens)
@@ -149,6 +181,9 @@ impl Provider for GroqProvider {
         };
         let model = get_model(&response);
         super::utils::emit_debug_trace(self, &payload, &response, &usage);
-        Ok((message, ProviderUsage::new(model, usage)))
+        Ok((
+            message,
+            ProviderUsage::new(model, usage).with_provider("groq"),
+        ))
     }
 }
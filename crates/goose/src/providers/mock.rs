@@ -0,0 +1,253 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use super::base::{Provider, ProviderMetadata, ProviderUsage, Usage};
+use super::errors::ProviderError;
+use crate::message::Message;
+use crate::model::ModelConfig;
+use mcp_core::tool::{Tool, ToolCall};
+
+/// One scripted answer
+pub enum MockResponse {
+    Reply(Message, Usage),
+    Error(ProviderError),
+}
+
+/// What the provider was sent in one call
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub system: String,
+    pub messages: Vec<Message>,
+    pub tools: Vec<Tool>,
+}
+
+impl MockRequest {
+    pub fn tool_names(&self) -> Vec<&str> {
+        self.tools.iter().map(|tool| tool.name.as_str()).collect()
+    }
+
+    /// Text of the newest message, usually what the user just said or a tool result
+    pub fn last_message_text(&self) -> String {
+        self.messages
+            .last()
+            .map(|message| message.as_concat_text())
+            .unwrap_or_default()
+    }
+}
+
+/// The requests a mock has received, which can be kept after the provider is handed to an agent
+#[derive(Debug, Clone, Default)]
+pub struct MockRequests(Arc<Mutex<Vec<MockRequest>>>);
+
+impl MockRequests {
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<MockRequest> {
+        self.0.lock().unwrap().get(index).cloned()
+    }
+
+    pub fn last(&self) -> Option<MockRequest> {
+        self.0.lock().unwrap().last().cloned()
+    }
+
+    pub fn all(&self) -> Vec<MockRequest> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+type Expectation = Box<dyn Fn(&MockRequest) + Send + Sync>;
+
+/// A provider which answers from a script, for testing agents and extensions without a model
+///
+/// Responses are queued with the `with_*` builders and returned in order, one per call. Once the
+/// script runs out, calls fail unless a default reply was set. Everything the provider is sent
+/// is kept in [`MockRequests`], and closures passed to [`MockProvider::expect`] are run against
+/// every request as it arrives.
+///
+/// Available to other crates through the `test-support` feature.
+pub struct MockProvider {
+    model_config: ModelConfig,
+    responses: Mutex<VecDeque<MockResponse>>,
+    default_reply: Option<Message>,
+    requests: MockRequests,
+    expectations: Vec<Expectation>,
+}
+
+impl MockProvider {
+    pub fn new(model: &str) -> Self {
+        Self {
+            model_config: ModelConfig::new(model.to_string()),
+            responses: Mutex::new(VecDeque::new()),
+            default_reply: None,
+            requests: MockRequests::default(),
+            expectations: Vec::new(),
+        }
+    }
+
+    pub fn with_model_config(mut self, model_config: ModelConfig) -> Self {
+        self.model_config = model_config;
+        self
+    }
+
+    pub fn with_reply(self, message: Message, usage: Usage) -> Self {
+        self.push(MockResponse::Reply(message, usage))
+    }
+
+    pub fn with_message(self, message: Message) -> Self {
+        self.with_reply(message, Usage::default())
+    }
+
+    pub fn with_text(self, text: &str) -> Self {
+        self.with_message(Message::assistant().with_text(text))
+    }
+
+    /// Queue a reply calling one tool, with an id unique within this mock
+    pub fn with_tool_request(self, name: &str, arguments: Value) -> Self {
+        let id = format!("mock_call_{}", self.responses.lock().unwrap().len());
+        self.with_message(
+            Message::assistant().with_tool_request(id, Ok(ToolCall::new(name, arguments))),
+        )
+    }
+
+    pub fn with_error(self, error: ProviderError) -> Self {
+        self.push(MockResponse::Error(error))
+    }
+
+    /// Answer with this text whenever the script has run out
+    pub fn with_default_text(mut self, text: &str) -> Self {
+        self.default_reply = Some(Message::assistant().with_text(text));
+        self
+    }
+
+    /// Check every request with a closure which panics on a mismatch, such as an `assert!`
+    pub fn expect(mut self, expectation: impl Fn(&MockRequest) + Send + Sync + 'static) -> Self {
+        self.expectations.push(Box::new(expectation));
+        self
+    }
+
+    pub fn requests(&self) -> MockRequests {
+        self.requests.clone()
+    }
+
+    /// How many scripted responses haven't been used yet
+    pub fn remaining(&self) -> usize {
+        self.responses.lock().unwrap().len()
+    }
+
+    fn push(self, response: MockResponse) -> Self {
+        self.responses.lock().unwrap().push_back(response);
+        self
+    }
+}
+
+#[async_trait]
+impl Provider for MockProvider {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::empty()
+    }
+
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let request = MockRequest {
+            system: system.to_string(),
+            messages: messages.to_vec(),
+            tools: tools.to_vec(),
+        };
+        for expectation in &self.expectations {
+            expectation(&request);
+        }
+        self.requests.0.lock().unwrap().push(request);
+
+        let response = self.responses.lock().unwrap().pop_front();
+        let usage = |usage| ProviderUsage::new(self.model_config.model_name.clone(), usage);
+        match response {
+            Some(MockResponse::Reply(message, reply_usage)) => Ok((message, usage(reply_usage))),
+            Some(MockResponse::Error(error)) => Err(error),
+            None => match &self.default_reply {
+                Some(message) => Ok((message.clone(), usage(Usage::default()))),
+                None => Err(ProviderError::ExecutionError(
+                    "MockProvider has no scripted responses left".to_string(),
+                )),
+            },
+        }
+    }
+
+    fn get_model_config(&self) -> ModelConfig {
+        self.model_config.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_scripted_responses() {
+        let provider = MockProvider::new("mock")
+            .with_tool_request("developer__shell", json!({"command": "ls"}))
+            .with_error(ProviderError::ContextLengthExceeded("too long".to_string()))
+            .with_text("Done")
+            .expect(|request| assert_eq!(request.system, "You are goose"));
+        let requests = provider.requests();
+        let tools = [Tool::new("developer__shell", "Run a command", json!({}))];
+        let messages = [Message::user().with_text("List the files")];
+
+        let (message, usage) = provider
+            .complete("You are goose", &messages, &tools)
+            .await
+            .unwrap();
+        assert!(message.is_tool_call());
+        assert_eq!(usage.model, "mock");
+
+        let result = provider.complete("You are goose", &messages, &[]).await;
+        assert!(matches!(
+            result,
+            Err(ProviderError::ContextLengthExceeded(_))
+        ));
+
+        let (message, _) = provider
+            .complete("You are goose", &messages, &[])
+            .await
+            .unwrap();
+        assert_eq!(message.as_concat_text(), "Done");
+        assert!(provider
+            .complete("You are goose", &messages, &[])
+            .await
+            .is_err());
+
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests.get(0).unwrap().tool_names(), ["developer__shell"]);
+        assert_eq!(
+            requests.last().unwrap().last_message_text(),
+            "List the files"
+        );
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "unexpected system prompt")]
+    async fn test_expectations_fail_the_test() {
+        let provider = MockProvider::new("mock")
+            .with_default_text("Hi")
+            .expect(|request| assert!(request.system.is_empty(), "unexpected system prompt"));
+        let _ = provider.complete("You are goose", &[], &[]).await;
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_are_not_supported() {
+        let result = MockProvider::new("mock").embed(&["text".to_string()]).await;
+        assert!(matches!(result, Err(ProviderError::NotSupported(_))));
+    }
+}
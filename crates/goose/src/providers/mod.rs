@@ -1,17 +1,28 @@
 pub mod anthropic;
 pub mod azure;
+pub mod azure_auth;
 pub mod base;
 pub mod bedrock;
+pub mod cache;
 pub mod databricks;
 pub mod errors;
 mod factory;
+pub mod fallback;
 pub mod formats;
 pub mod google;
 pub mod groq;
+#[cfg(any(test, feature = "test-support"))]
+pub mod mock;
 pub mod oauth;
 pub mod ollama;
 pub mod openai;
+pub mod openai_compatible;
 pub mod openrouter;
+pub mod pricing;
+pub mod replay;
+pub mod retry;
+pub mod streaming;
+pub mod toolshim;
 pub mod utils;
 
-pub use factory::{create, providers};
+pub use factory::{cached_models, create, default_model, list_models, providers};
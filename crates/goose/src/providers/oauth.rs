@@ -7,7 +7,13 @@ use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sha2::Digest;
-use std::{collections::HashMap, fs, net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{
+    collections::HashMap,
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use tokio::sync::{oneshot, Mutex as TokioMutex};
 use url::Url;
 
@@ -15,16 +21,80 @@ lazy_static! {
     static ref OAUTH_MUTEX: TokioMutex<()> = TokioMutex::new(());
 }
 
+const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// How the user signs in when there is no usable cached token
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OAuthGrant {
+    /// Open a browser and receive the code on a localhost redirect
+    #[default]
+    Browser,
+    /// Show a code to enter on another device, for machines without a browser
+    Device,
+}
+
+impl std::str::FromStr for OAuthGrant {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "browser" => Ok(Self::Browser),
+            "device" => Ok(Self::Device),
+            _ => Err(anyhow::anyhow!(
+                "Unknown OAuth flow {}, expected browser or device",
+                s
+            )),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct OidcEndpoints {
     authorization_endpoint: String,
     token_endpoint: String,
+    device_authorization_endpoint: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 struct TokenData {
     access_token: String,
     expires_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+}
+
+impl TokenData {
+    fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+    }
+
+    fn from_response(token_response: &Value) -> Result<Self> {
+        let access_token = token_response
+            .get("access_token")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("access_token not found in token response"))?
+            .to_string();
+
+        let expires_in = token_response
+            .get("expires_in")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(3600);
+
+        let expires_at = Utc::now() + chrono::Duration::seconds(expires_in as i64);
+
+        let refresh_token = token_response
+            .get("refresh_token")
+            .and_then(|v| v.as_str())
+            .map(String::from);
+
+        Ok(Self {
+            access_token,
+            expires_at: Some(expires_at),
+            refresh_token,
+        })
+    }
 }
 
 struct TokenCache {
@@ -42,31 +112,32 @@ fn get_base_path() -> PathBuf {
 
 impl TokenCache {
     fn new(host: &str, client_id: &str, scopes: &[String]) -> Self {
+        Self::in_dir(&get_base_path(), host, client_id, scopes)
+    }
+
+    /// A cache kept in the given directory rather than the config dir
+    fn in_dir(dir: &Path, host: &str, client_id: &str, scopes: &[String]) -> Self {
         let mut hasher = sha2::Sha256::new();
         hasher.update(host.as_bytes());
         hasher.update(client_id.as_bytes());
         hasher.update(scopes.join(",").as_bytes());
         let hash = format!("{:x}", hasher.finalize());
 
-        fs::create_dir_all(get_base_path()).unwrap();
-        let cache_path = get_base_path().join(format!("{}.json", hash));
+        fs::create_dir_all(dir).unwrap();
+        let cache_path = dir.join(format!("{}.json", hash));
 
         Self { cache_path }
     }
 
+    /// The cached token, even if it has expired, so its refresh token can still be used
+    fn load_cached(&self) -> Option<TokenData> {
+        let contents = fs::read_to_string(&self.cache_path).ok()?;
+        serde_json::from_str::<TokenData>(&contents).ok()
+    }
+
     fn load_token(&self) -> Option<TokenData> {
-        if let Ok(contents) = fs::read_to_string(&self.cache_path) {
-            if let Ok(token_data) = serde_json::from_str::<TokenData>(&contents) {
-                if let Some(expires_at) = token_data.expires_at {
-                    if expires_at > Utc::now() {
-                        return Some(token_data);
-                    }
-                } else {
-                    return Some(token_data);
-                }
-            }
-        }
-        None
+        self.load_cached()
+            .filter(|token_data| !token_data.is_expired())
     }
 
     fn save_token(&self, token_data: &TokenData) -> Result<()> {
@@ -109,12 +180,54 @@ async fn get_workspace_endpoints(host: &str) -> Result<OidcEndpoints> {
         .ok_or_else(|| anyhow::anyhow!("token_endpoint not found in OIDC configuration"))?
         .to_string();
 
+    let device_authorization_endpoint = oidc_config
+        .get("device_authorization_endpoint")
+        .and_then(|v| v.as_str())
+        .map(String::from);
+
     Ok(OidcEndpoints {
         authorization_endpoint,
         token_endpoint,
+        device_authorization_endpoint,
     })
 }
 
+async fn post_token_request(token_endpoint: &str, params: &[(&str, &str)]) -> Result<Value> {
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(token_endpoint)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .form(params)
+        .send()
+        .await?;
+
+    if !resp.status().is_success() {
+        let err_text = resp.text().await?;
+        return Err(anyhow::anyhow!("Token request failed: {}", err_text));
+    }
+
+    Ok(resp.json().await?)
+}
+
+async fn refresh_access_token(
+    endpoints: &OidcEndpoints,
+    client_id: &str,
+    refresh_token: &str,
+) -> Result<TokenData> {
+    let params = [
+        ("grant_type", "refresh_token"),
+        ("refresh_token", refresh_token),
+        ("client_id", client_id),
+    ];
+    let token_response = post_token_request(&endpoints.token_endpoint, &params).await?;
+    let mut token = TokenData::from_response(&token_response)?;
+    // Servers which don't rotate refresh tokens leave the old one valid
+    if token.refresh_token.is_none() {
+        token.refresh_token = Some(refresh_token.to_string());
+    }
+    Ok(token)
+}
+
 struct OAuthFlow {
     endpoints: OidcEndpoints,
     client_id: String,
@@ -173,40 +286,10 @@ impl OAuthFlow {
             ("client_id", &self.client_id),
         ];
 
-        let client = reqwest::Client::new();
-        let resp = client
-            .post(&self.endpoints.token_endpoint)
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .form(&params)
-            .send()
-            .await?;
-
-        if !resp.status().is_success() {
-            let err_text = resp.text().await?;
-            return Err(anyhow::anyhow!(
-                "Failed to exchange code for token: {}",
-                err_text
-            ));
-        }
-
-        let token_response: Value = resp.json().await?;
-        let access_token = token_response
-            .get("access_token")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow::anyhow!("access_token not found in token response"))?
-            .to_string();
-
-        let expires_in = token_response
-            .get("expires_in")
-            .and_then(|v| v.as_u64())
-            .unwrap_or(3600);
-
-        let expires_at = Utc::now() + chrono::Duration::seconds(expires_in as i64);
-
-        Ok(TokenData {
-            access_token,
-            expires_at: Some(expires_at),
-        })
+        let token_response = post_token_request(&self.endpoints.token_endpoint, &params)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to exchange code for token: {}", e))?;
+        TokenData::from_response(&token_response)
     }
 
     async fn execute(&self) -> Result<TokenData> {
@@ -285,33 +368,191 @@ impl OAuthFlow {
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct DeviceAuthorization {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    verification_uri_complete: Option<String>,
+    #[serde(default = "DeviceAuthorization::default_expires_in")]
+    expires_in: u64,
+    #[serde(default = "DeviceAuthorization::default_interval")]
+    interval: u64,
+}
+
+impl DeviceAuthorization {
+    fn default_expires_in() -> u64 {
+        600
+    }
+
+    fn default_interval() -> u64 {
+        5
+    }
+}
+
+/// The device authorization grant (RFC 8628), where the user approves the sign in from
+/// any browser while goose polls for the token
+struct DeviceCodeFlow {
+    endpoints: OidcEndpoints,
+    client_id: String,
+    scopes: Vec<String>,
+}
+
+impl DeviceCodeFlow {
+    fn new(endpoints: OidcEndpoints, client_id: String, scopes: Vec<String>) -> Self {
+        Self {
+            endpoints,
+            client_id,
+            scopes,
+        }
+    }
+
+    async fn request_device_code(&self) -> Result<DeviceAuthorization> {
+        let device_endpoint = self
+            .endpoints
+            .device_authorization_endpoint
+            .as_deref()
+            .ok_or_else(|| {
+                anyhow::anyhow!("device_authorization_endpoint not found in OIDC configuration")
+            })?;
+
+        let params = [
+            ("client_id", self.client_id.as_str()),
+            ("scope", &self.scopes.join(" ")),
+        ];
+
+        let client = reqwest::Client::new();
+        let resp = client.post(device_endpoint).form(&params).send().await?;
+
+        if !resp.status().is_success() {
+            let err_text = resp.text().await?;
+            return Err(anyhow::anyhow!(
+                "Failed to start device authorization: {}",
+                err_text
+            ));
+        }
+
+        Ok(resp.json().await?)
+    }
+
+    async fn poll_for_token(&self, device: &DeviceAuthorization) -> Result<TokenData> {
+        let params = [
+            ("grant_type", DEVICE_CODE_GRANT_TYPE),
+            ("device_code", &device.device_code),
+            ("client_id", &self.client_id),
+        ];
+        let deadline =
+            tokio::time::Instant::now() + std::time::Duration::from_secs(device.expires_in);
+        let mut interval = std::time::Duration::from_secs(device.interval);
+        let client = reqwest::Client::new();
+
+        loop {
+            if tokio::time::Instant::now() >= deadline {
+                return Err(anyhow::anyhow!("Authentication timed out"));
+            }
+            tokio::time::sleep(interval).await;
+
+            let resp = client
+                .post(&self.endpoints.token_endpoint)
+                .form(&params)
+                .send()
+                .await?;
+            let status = resp.status();
+            let body: Value = resp.json().await.unwrap_or_default();
+
+            if status.is_success() {
+                return TokenData::from_response(&body);
+            }
+
+            // Until the user approves, the token endpoint answers with an error code
+            match body.get("error").and_then(|v| v.as_str()) {
+                Some("authorization_pending") => {}
+                Some("slow_down") => interval += std::time::Duration::from_secs(5),
+                Some("access_denied") => return Err(anyhow::anyhow!("Authentication was denied")),
+                Some("expired_token") => return Err(anyhow::anyhow!("Authentication timed out")),
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Failed to get token with device code: {}",
+                        body
+                    ))
+                }
+            }
+        }
+    }
+
+    async fn execute(&self) -> Result<TokenData> {
+        let device = self.request_device_code().await?;
+
+        match &device.verification_uri_complete {
+            Some(uri) => println!("To sign in, open this URL on any device:\n{}", uri),
+            None => println!(
+                "To sign in, open this URL on any device:\n{}\nand enter the code {}",
+                device.verification_uri, device.user_code
+            ),
+        }
+
+        self.poll_for_token(&device).await
+    }
+}
+
 pub(crate) async fn get_oauth_token_async(
     host: &str,
     client_id: &str,
     redirect_url: &str,
     scopes: &[String],
+    grant: OAuthGrant,
+) -> Result<String> {
+    let token_cache = TokenCache::new(host, client_id, scopes);
+    get_oauth_token_with_cache(&token_cache, host, client_id, redirect_url, scopes, grant).await
+}
+
+async fn get_oauth_token_with_cache(
+    token_cache: &TokenCache,
+    host: &str,
+    client_id: &str,
+    redirect_url: &str,
+    scopes: &[String],
+    grant: OAuthGrant,
 ) -> Result<String> {
     // Acquire the global mutex to ensure only one OAuth flow runs at a time
     let _guard = OAUTH_MUTEX.lock().await;
 
-    let token_cache = TokenCache::new(host, client_id, scopes);
-
     // Try cache first
     if let Some(token) = token_cache.load_token() {
         return Ok(token.access_token);
     }
 
-    // Get endpoints and execute flow
     let endpoints = get_workspace_endpoints(host).await?;
-    let flow = OAuthFlow::new(
-        endpoints,
-        client_id.to_string(),
-        redirect_url.to_string(),
-        scopes.to_vec(),
-    );
+
+    // An expired token can often be renewed without signing in again
+    if let Some(refresh_token) = token_cache.load_cached().and_then(|t| t.refresh_token) {
+        match refresh_access_token(&endpoints, client_id, &refresh_token).await {
+            Ok(token) => {
+                token_cache.save_token(&token)?;
+                return Ok(token.access_token);
+            }
+            Err(e) => tracing::debug!("Failed to refresh OAuth token: {}", e),
+        }
+    }
 
     // Execute the OAuth flow and get token
-    let token = flow.execute().await?;
+    let token = match grant {
+        OAuthGrant::Browser => {
+            OAuthFlow::new(
+                endpoints,
+                client_id.to_string(),
+                redirect_url.to_string(),
+                scopes.to_vec(),
+            )
+            .execute()
+            .await?
+        }
+        OAuthGrant::Device => {
+            DeviceCodeFlow::new(endpoints, client_id.to_string(), scopes.to_vec())
+                .execute()
+                .await?
+        }
+    };
 
     // Cache and return
     token_cache.save_token(&token)?;
@@ -321,11 +562,26 @@ pub(crate) async fn get_oauth_token_async(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::providers::databricks::DatabricksAuth;
     use wiremock::{
-        matchers::{method, path},
+        matchers::{body_string_contains, method, path},
         Mock, MockServer, ResponseTemplate,
     };
 
+    async fn mock_oidc_server() -> MockServer {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/oidc/.well-known/oauth-authorization-server"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "authorization_endpoint": format!("{}/oidc/v1/authorize", mock_server.uri()),
+                "token_endpoint": format!("{}/oidc/v1/token", mock_server.uri()),
+                "device_authorization_endpoint": format!("{}/oidc/v1/device", mock_server.uri()),
+            })))
+            .mount(&mock_server)
+            .await;
+        mock_server
+    }
+
     #[tokio::test]
     async fn test_get_workspace_endpoints() -> Result<()> {
         let mock_server = MockServer::start().await;
@@ -363,6 +619,7 @@ mod tests {
         let token_data = TokenData {
             access_token: "test-token".to_string(),
             expires_at: Some(Utc::now() + chrono::Duration::hours(1)),
+            refresh_token: None,
         };
 
         cache.save_token(&token_data)?;
@@ -372,4 +629,156 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_device_code_flow() -> Result<()> {
+        let mock_server = mock_oidc_server().await;
+
+        Mock::given(method("POST"))
+            .and(path("/oidc/v1/device"))
+            .and(body_string_contains("client_id=test-client"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "device_code": "device-123",
+                "user_code": "ABCD-EFGH",
+                "verification_uri": "https://example.com/device",
+                "expires_in": 60,
+                "interval": 0
+            })))
+            .mount(&mock_server)
+            .await;
+        // The first poll happens before the user has approved the sign in
+        Mock::given(method("POST"))
+            .and(path("/oidc/v1/token"))
+            .respond_with(
+                ResponseTemplate::new(400)
+                    .set_body_json(serde_json::json!({"error": "authorization_pending"})),
+            )
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/oidc/v1/token"))
+            .and(body_string_contains("device_code=device-123"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "device-token",
+                "refresh_token": "refresh-123",
+                "expires_in": 3600
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let endpoints = get_workspace_endpoints(&mock_server.uri()).await?;
+        let flow = DeviceCodeFlow::new(
+            endpoints,
+            "test-client".to_string(),
+            vec!["all-apis".to_string()],
+        );
+        let token = flow.execute().await?;
+
+        assert_eq!(token.access_token, "device-token");
+        assert_eq!(token.refresh_token.as_deref(), Some("refresh-123"));
+        assert!(!token.is_expired());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_is_refreshed() -> Result<()> {
+        let mock_server = mock_oidc_server().await;
+
+        Mock::given(method("POST"))
+            .and(path("/oidc/v1/token"))
+            .and(body_string_contains("grant_type=refresh_token"))
+            .and(body_string_contains("refresh_token=refresh-123"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "refreshed-token",
+                "expires_in": 3600
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let dir = tempfile::TempDir::new()?;
+        let client_id = "test-client";
+        let scopes = vec!["all-apis".to_string()];
+        let cache = TokenCache::in_dir(dir.path(), &mock_server.uri(), client_id, &scopes);
+        cache.save_token(&TokenData {
+            access_token: "expired-token".to_string(),
+            expires_at: Some(Utc::now() - chrono::Duration::minutes(5)),
+            refresh_token: Some("refresh-123".to_string()),
+        })?;
+        assert!(cache.load_token().is_none());
+
+        let token = get_oauth_token_with_cache(
+            &cache,
+            &mock_server.uri(),
+            client_id,
+            "http://localhost:8020",
+            &scopes,
+            OAuthGrant::Device,
+        )
+        .await?;
+        assert_eq!(token, "refreshed-token");
+
+        // The refresh token is kept for next time when the server doesn't rotate it
+        let cached = cache.load_token().unwrap();
+        assert_eq!(cached.access_token, "refreshed-token");
+        assert_eq!(cached.refresh_token.as_deref(), Some("refresh-123"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_default_scopes_get_a_refresh_token() -> Result<()> {
+        let mock_server = mock_oidc_server().await;
+        let DatabricksAuth::OAuth {
+            client_id, scopes, ..
+        } = DatabricksAuth::oauth(mock_server.uri())
+        else {
+            unreachable!("DatabricksAuth::oauth always configures OAuth");
+        };
+
+        // Databricks only returns a refresh token to sign ins which ask for offline access
+        Mock::given(method("POST"))
+            .and(path("/oidc/v1/device"))
+            .and(body_string_contains("scope=all-apis+offline_access"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "device_code": "device-123",
+                "user_code": "ABCD-EFGH",
+                "verification_uri": "https://example.com/device",
+                "expires_in": 60,
+                "interval": 0
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/oidc/v1/token"))
+            .and(body_string_contains("device_code=device-123"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "device-token",
+                "refresh_token": "refresh-123",
+                "expires_in": 3600
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let dir = tempfile::TempDir::new()?;
+        let cache = TokenCache::in_dir(dir.path(), &mock_server.uri(), &client_id, &scopes);
+        let token = get_oauth_token_with_cache(
+            &cache,
+            &mock_server.uri(),
+            &client_id,
+            "http://localhost:8020",
+            &scopes,
+            OAuthGrant::Device,
+        )
+        .await?;
+        assert_eq!(token, "device-token");
+
+        let cached = cache.load_token().unwrap();
+        assert_eq!(cached.refresh_token.as_deref(), Some("refresh-123"));
+        Ok(())
+    }
 }
@@ -1,16 +1,26 @@
-use super::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage, Usage};
+use super::base::{
+    CompletionStream, ConfigKey, Embeddings, Provider, ProviderMetadata, ProviderUsage, Usage,
+};
 use super::errors::ProviderError;
-use super::utils::{get_model, handle_response_openai_compat};
+use super::streaming::{response_error, stream_json_lines};
+use super::utils::{
+    embed_in_batches, embedding_model, get_embedding, get_model, get_model_names,
+    handle_response_openai_compat,
+};
 use crate::message::Message;
 use crate::model::ModelConfig;
-use crate::providers::formats::openai::{create_request, get_usage, response_to_message};
+use crate::providers::formats::ollama::{
+    create_request, to_chat_completion, OllamaStreamAccumulator,
+};
+use crate::providers::formats::openai::{get_usage, response_to_message};
 use anyhow::Result;
 use async_trait::async_trait;
 use indoc::formatdoc;
 use mcp_core::tool::Tool;
-use reqwest::Client;
-use serde_json::Value;
+use reqwest::{Client, Response, StatusCode};
+use serde_json::{json, Value};
 use std::time::Duration;
+use tokio::sync::OnceCell;
 use url::Url;
 
 pub const OLLAMA_HOST: &str = "localhost";
@@ -18,14 +28,60 @@ pub const OLLAMA_DEFAULT_PORT: u16 = 11434;
 pub const OLLAMA_DEFAULT_MODEL: &str = "qwen2.5";
 // Ollama can run many models, we only provide the default
 pub const OLLAMA_KNOWN_MODELS: &[&str] = &[OLLAMA_DEFAULT_MODEL];
+pub const OLLAMA_DEFAULT_EMBEDDING_MODEL: &str = "nomic-embed-text";
+/// Ollama has no limit of its own, this keeps single requests to a reasonable size
+const OLLAMA_EMBEDDING_BATCH_SIZE: usize = 256;
 pub const OLLAMA_DOC_URL: &str = "https://ollama.com/library";
 
+/// What the Ollama server reports about a model through /api/show
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OllamaModelInfo {
+    /// The context length the model was trained with
+    pub context_length: Option<usize>,
+    /// Features like "tools" and "vision", only reported by recent Ollama versions
+    pub capabilities: Option<Vec<String>>,
+}
+
+impl OllamaModelInfo {
+    fn from_show_response(response: &Value) -> Self {
+        let context_length = response["model_info"].as_object().and_then(|info| {
+            info.iter()
+                .find(|(key, _)| key.ends_with(".context_length"))
+                .and_then(|(_, value)| value.as_u64())
+                .map(|length| length as usize)
+        });
+        let capabilities = response["capabilities"].as_array().map(|capabilities| {
+            capabilities
+                .iter()
+                .filter_map(|c| c.as_str().map(String::from))
+                .collect()
+        });
+        Self {
+            context_length,
+            capabilities,
+        }
+    }
+}
+
+/// A line of progress from /api/pull
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PullProgress {
+    pub status: String,
+    pub total: Option<u64>,
+    pub completed: Option<u64>,
+}
+
 #[derive(serde::Serialize)]
 pub struct OllamaProvider {
     #[serde(skip)]
     client: Client,
     host: String,
     model: ModelConfig,
+    /// Context window to load the model with, sent as the `num_ctx` option of each request.
+    /// Without it the server uses its default, which can be much smaller than the model's
+    num_ctx: Option<usize>,
+    #[serde(skip)]
+    model_info: OnceCell<OllamaModelInfo>,
 }
 
 impl Default for OllamaProvider {
@@ -42,6 +98,8 @@ impl OllamaProvider {
             .get("OLLAMA_HOST")
             .unwrap_or_else(|_| OLLAMA_HOST.to_string());
 
+        let num_ctx: Option<usize> = config.get("OLLAMA_NUM_CTX").ok();
+
         let client = Client::builder()
             .timeout(Duration::from_secs(600))
             .build()?;
@@ -50,74 +108,123 @@ impl OllamaProvider {
             client,
             host,
             model,
+            num_ctx,
+            model_info: OnceCell::new(),
         })
     }
 
-    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
-        // TODO: remove this later when the UI handles provider config refresh
-        // OLLAMA_HOST is sometimes just the 'host' or 'host:port' without a scheme
-        let base = if self.host.starts_with("http://") || self.host.starts_with("https://") {
-            self.host.clone()
-        } else {
-            format!("http://{}", self.host)
-        };
-
-        let mut base_url = Url::parse(&base)
-            .map_err(|e| ProviderError::RequestFailed(format!("Invalid base URL: {e}")))?;
+    /// Ask the server about the model, returning None if it hasn't been pulled
+    pub async fn show_model(&self) -> Result<Option<OllamaModelInfo>, ProviderError> {
+        let url = self.base_url()?.join("api/show").map_err(|e| {
+            ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
+        })?;
 
-        // Set the default port if missing
-        let explicit_default_port = self.host.ends_with(":80") || self.host.ends_with(":443");
-        if base_url.port().is_none() && !explicit_default_port {
-            base_url.set_port(Some(OLLAMA_DEFAULT_PORT)).map_err(|_| {
-                ProviderError::RequestFailed("Failed to set default port".to_string())
-            })?;
+        let response = self
+            .client
+            .post(url)
+            .json(&json!({"model": self.model.model_name}))
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
         }
+        let response = handle_response_openai_compat(response).await?;
+        Ok(Some(OllamaModelInfo::from_show_response(&response)))
+    }
 
-        let url = base_url.join("v1/chat/completions").map_err(|e| {
+    /// Download the model to the server, reporting progress as it goes
+    pub async fn pull_model(
+        &self,
+        mut on_progress: impl FnMut(&PullProgress),
+    ) -> Result<(), ProviderError> {
+        let url = self.base_url()?.join("api/pull").map_err(|e| {
             ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
         })?;
 
-        let response = self.client.post(url).json(&payload).send().await?;
-
-        handle_response_openai_compat(response).await
-    }
-}
+        let mut response = self
+            .client
+            .post(url)
+            .json(&json!({"model": self.model.model_name, "stream": true}))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(handle_response_openai_compat(response).await.unwrap_err());
+        }
 
-#[async_trait]
-impl Provider for OllamaProvider {
-    fn metadata() -> ProviderMetadata {
-        ProviderMetadata::new(
-            "ollama",
-            "Ollama",
-            "Local open source models",
-            OLLAMA_DEFAULT_MODEL,
-            OLLAMA_KNOWN_MODELS.iter().map(|&s| s.to_string()).collect(),
-            OLLAMA_DOC_URL,
-            vec![ConfigKey::new(
-                "OLLAMA_HOST",
-                true,
-                false,
-                Some(OLLAMA_HOST),
-            )],
-        )
+        // Progress arrives as one JSON object per line, with errors reported the same way
+        let mut buffer = String::new();
+        while let Some(chunk) = response.chunk().await? {
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+            while let Some(end) = buffer.find('\n') {
+                let line: String = buffer.drain(..=end).collect();
+                let Ok(value) = serde_json::from_str::<Value>(line.trim()) else {
+                    continue;
+                };
+                if let Some(error) = value.get("error").and_then(|e| e.as_str()) {
+                    return Err(ProviderError::RequestFailed(format!(
+                        "Failed to pull {}: {}",
+                        self.model.model_name, error
+                    )));
+                }
+                if let Ok(progress) = serde_json::from_value::<PullProgress>(value) {
+                    on_progress(&progress);
+                }
+            }
+        }
+        Ok(())
     }
 
-    fn get_model_config(&self) -> ModelConfig {
-        self.model.clone()
+    /// The model's details, fetched from the server on first use
+    async fn model_info(&self) -> Result<&OllamaModelInfo, ProviderError> {
+        self.model_info
+            .get_or_try_init(|| async {
+                self.show_model().await?.ok_or_else(|| {
+                    ProviderError::RequestFailed(format!(
+                        "The model {model} has not been pulled to the Ollama server. Run \
+                        `ollama pull {model}`, or choose it in `goose configure` to download it",
+                        model = self.model.model_name
+                    ))
+                })
+            })
+            .await
     }
 
-    #[tracing::instrument(
-        skip(self, system, messages, tools),
-        fields(model_config, input, output, input_tokens, output_tokens, total_tokens)
-    )]
-    async fn complete(
+    /// Build a request for the server's native chat API
+    async fn create_request(
         &self,
         system: &str,
         messages: &[Message],
         tools: &[Tool],
-    ) -> Result<(Message, ProviderUsage), ProviderError> {
+    ) -> Result<Value, ProviderError> {
+        self.model_info().await?;
+        let model_config = self.get_model_config();
+
+        // The server reports whether the model can call tools, so leave them out rather than
+        // sending them to a model which would reject them
+        let tools = if model_config.capabilities.tool_calling {
+            tools
+        } else {
+            tracing::warn!(
+                "{} does not support tool calling, sending the request without tools",
+                model_config.model_name
+            );
+            &[]
+        };
+
+        Ok(create_request(
+            &model_config,
+            &Self::modify_system(system),
+            messages,
+            tools,
+            self.num_ctx,
+        )?)
+    }
+
+    /// Replace the developer extension instructions with a condensed version, which works
+    /// better with smaller local models
+    fn modify_system(system: &str) -> String {
         // Transform the system message to replace developer instructions
-        let modified_system = if let Some(dev_section) = system.split("## developer").nth(1) {
+        if let Some(dev_section) = system.split("## developer").nth(1) {
             if let (Some(start_idx), Some(end_idx)) = (
                 dev_section.find("### Instructions"),
                 dev_section.find("operating system:"),
@@ -183,16 +290,144 @@ impl Provider for OllamaProvider {
             }
         } else {
             system.to_string()
+        }
+    }
+
+    fn base_url(&self) -> Result<Url, ProviderError> {
+        // TODO: remove this later when the UI handles provider config refresh
+        // OLLAMA_HOST is sometimes just the 'host' or 'host:port' without a scheme
+        let base = if self.host.starts_with("http://") || self.host.starts_with("https://") {
+            self.host.clone()
+        } else {
+            format!("http://{}", self.host)
         };
 
-        let payload = create_request(
-            &self.model,
-            &modified_system,
-            messages,
-            tools,
-            &super::utils::ImageFormat::OpenAi,
-        )?;
-        let response = self.post(payload.clone()).await?;
+        let mut base_url = Url::parse(&base)
+            .map_err(|e| ProviderError::RequestFailed(format!("Invalid base URL: {e}")))?;
+
+        // Set the default port if missing
+        let explicit_default_port = self.host.ends_with(":80") || self.host.ends_with(":443");
+        if base_url.port().is_none() && !explicit_default_port {
+            base_url.set_port(Some(OLLAMA_DEFAULT_PORT)).map_err(|_| {
+                ProviderError::RequestFailed("Failed to set default port".to_string())
+            })?;
+        }
+
+        Ok(base_url)
+    }
+
+    async fn send(&self, payload: &Value) -> Result<Response, ProviderError> {
+        let url = self.base_url()?.join("api/chat").map_err(|e| {
+            ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
+        })?;
+
+        Ok(self.client.post(url).json(payload).send().await?)
+    }
+
+    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+        let response = self.send(&payload).await?;
+        handle_response_openai_compat(response).await
+    }
+}
+
+#[async_trait]
+impl Provider for OllamaProvider {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::new(
+            "ollama",
+            "Ollama",
+            "Local open source models",
+            OLLAMA_DEFAULT_MODEL,
+            OLLAMA_KNOWN_MODELS.iter().map(|&s| s.to_string()).collect(),
+            OLLAMA_DOC_URL,
+            vec![
+                ConfigKey::new("OLLAMA_HOST", true, false, Some(OLLAMA_HOST)),
+                ConfigKey::new("OLLAMA_NUM_CTX", false, false, None),
+            ],
+        )
+    }
+
+    /// The model config, updated with what the server reports about the model once known
+    fn get_model_config(&self) -> ModelConfig {
+        let mut capabilities = self.model.capabilities.clone();
+        if let Some(info) = self.model_info.get() {
+            if let Some(context_length) = info.context_length {
+                capabilities.context_window = Some(context_length);
+            }
+            if let Some(features) = &info.capabilities {
+                capabilities.tool_calling = features.iter().any(|f| f == "tools");
+                capabilities.images = features.iter().any(|f| f == "vision");
+            }
+        }
+        // The model is loaded with the configured window, whatever it was trained with
+        if let Some(num_ctx) = self.num_ctx {
+            capabilities.context_window = Some(
+                capabilities
+                    .context_window
+                    .map_or(num_ctx, |window| window.min(num_ctx)),
+            );
+        }
+        self.model.clone().with_capabilities(capabilities)
+    }
+
+    /// Lists the models which have been pulled to the Ollama server
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        let url = self.base_url()?.join("api/tags").map_err(|e| {
+            ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
+        })?;
+
+        let response = self.client.get(url).send().await?;
+        let response = handle_response_openai_compat(response).await?;
+        get_model_names(&response, "models", "name")
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Embeddings, ProviderError> {
+        let url = self.base_url()?.join("api/embed").map_err(|e| {
+            ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
+        })?;
+
+        let model = embedding_model(OLLAMA_DEFAULT_EMBEDDING_MODEL);
+        let (vectors, usage) = embed_in_batches(texts, OLLAMA_EMBEDDING_BATCH_SIZE, |batch| {
+            let request = self
+                .client
+                .post(url.clone())
+                .json(&json!({"model": model, "input": batch}));
+            async move {
+                let response = handle_response_openai_compat(request.send().await?).await?;
+                let vectors = response["embeddings"]
+                    .as_array()
+                    .ok_or_else(|| {
+                        ProviderError::RequestFailed(
+                            "No embeddings in the Ollama response".to_string(),
+                        )
+                    })?
+                    .iter()
+                    .map(get_embedding)
+                    .collect::<Result<_, _>>()?;
+                let tokens = response["prompt_eval_count"].as_i64().map(|n| n as i32);
+                Ok((vectors, Usage::new(tokens, None, tokens)))
+            }
+        })
+        .await?;
+
+        Ok(Embeddings {
+            vectors,
+            usage: ProviderUsage::new(model, usage).with_provider("ollama"),
+        })
+    }
+
+    #[tracing::instrument(
+        skip(self, system, messages, tools),
+        fields(model_config, input, output, input_tokens, output_tokens, total_tokens)
+    )]
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let payload = self.create_request(system, messages, tools).await?;
+        let response = to_chat_completion(&self.post(payload.clone()).await?);
 
         // Parse response
         let message = response_to_message(response.clone())?;
@@ -206,6 +441,229 @@ impl Provider for OllamaProvider {
         };
         let model = get_model(&response);
         super::utils::emit_debug_trace(self, &payload, &response, &usage);
-        Ok((message, ProviderUsage::new(model, usage)))
+        Ok((
+            message,
+            ProviderUsage::new(model, usage).with_provider("ollama"),
+        ))
+    }
+
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<CompletionStream, ProviderError> {
+        let mut payload = self.create_request(system, messages, tools).await?;
+        payload["stream"] = json!(true);
+
+        let response = self.send(&payload).await?;
+        if !response.status().is_success() {
+            return Err(response_error(handle_response_openai_compat(response)).await);
+        }
+
+        let model_config = self.model.clone();
+        Ok(stream_json_lines(
+            response,
+            OllamaStreamAccumulator::default(),
+            move |response| {
+                let message = response_to_message(response.clone())?;
+                let usage = get_usage(&response).unwrap_or_else(|e| {
+                    tracing::debug!("Failed to get usage data: {}", e);
+                    Usage::default()
+                });
+                let model = get_model(&response);
+                super::utils::emit_debug_trace(&model_config, &payload, &response, &usage);
+                Ok((
+                    message,
+                    ProviderUsage::new(model, usage).with_provider("ollama"),
+                ))
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::base::CompletionChunk;
+    use futures::StreamExt;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn provider(server: &MockServer, num_ctx: Option<usize>) -> OllamaProvider {
+        OllamaProvider {
+            client: Client::new(),
+            host: server.uri(),
+            model: ModelConfig::new("qwen2.5".to_string()),
+            num_ctx,
+            model_info: OnceCell::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_show_sets_context_and_capabilities() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/show"))
+            .and(body_partial_json(json!({"model": "qwen2.5"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "model_info": {
+                    "general.architecture": "qwen2",
+                    "qwen2.context_length": 32768
+                },
+                "capabilities": ["completion"]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "model": "qwen2.5",
+                "message": {"role": "assistant", "content": "Hello"},
+                "done": true,
+                "prompt_eval_count": 10,
+                "eval_count": 2
+            })))
+            .mount(&server)
+            .await;
+
+        let provider = provider(&server, Some(8192));
+        let tool = Tool::new(
+            "developer__shell",
+            "Run a command",
+            json!({"type": "object"}),
+        );
+        let messages = [Message::user().with_text("Hi")];
+        provider
+            .complete("", &messages, std::slice::from_ref(&tool))
+            .await?;
+        let (message, usage) = provider.complete("", &messages, &[tool]).await?;
+        assert_eq!(message.as_concat_text(), "Hello");
+        assert_eq!(usage.usage.total_tokens, Some(12));
+
+        // The configured window is smaller than the model's, and the model can't call tools
+        let config = provider.get_model_config();
+        assert_eq!(config.context_limit(), 8192);
+        assert!(!config.capabilities.tool_calling);
+        assert!(!config.capabilities.images);
+
+        let requests = server.received_requests().await.unwrap();
+        let chat: Value = serde_json::from_slice(&requests[1].body)?;
+        assert_eq!(chat["options"]["num_ctx"], json!(8192));
+        assert!(chat.get("tools").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/show"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "capabilities": ["completion", "tools"]
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .and(body_partial_json(json!({
+                "stream": true,
+                "options": {"top_k": 40, "num_ctx": 4096}
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_string(concat!(
+                "{\"model\":\"qwen2.5\",\"message\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"done\":false}\n",
+                "{\"model\":\"qwen2.5\",\"message\":{\"role\":\"assistant\",\"content\":\"lo\"},\"done\":false}\n",
+                "{\"model\":\"qwen2.5\",\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,",
+                "\"prompt_eval_count\":10,\"eval_count\":2}\n",
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut provider = provider(&server, Some(4096));
+        provider.model = provider.model.with_top_k(Some(40));
+        let mut stream = provider
+            .stream("", &[Message::user().with_text("Hi")], &[])
+            .await?;
+        let mut text = String::new();
+        let mut done = None;
+        while let Some(chunk) = stream.next().await {
+            match chunk? {
+                CompletionChunk::Text(delta) => text.push_str(&delta),
+                CompletionChunk::Done(message, usage) => done = Some((message, usage)),
+                _ => {}
+            }
+        }
+        assert_eq!(text, "Hello");
+        let (message, usage) = done.unwrap();
+        assert_eq!(message.as_concat_text(), "Hello");
+        assert_eq!(usage.usage.total_tokens, Some(12));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_missing_model_can_be_pulled() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/show"))
+            .respond_with(
+                ResponseTemplate::new(404)
+                    .set_body_json(json!({"error": "model 'qwen2.5' not found"})),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/pull"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(concat!(
+                "{\"status\":\"pulling manifest\"}\n",
+                "{\"status\":\"pulling 2bada8a74506\",\"total\":4000,\"completed\":1000}\n",
+                "{\"status\":\"pulling 2bada8a74506\",\"total\":4000,\"completed\":4000}\n",
+                "{\"status\":\"success\"}\n",
+            )))
+            .mount(&server)
+            .await;
+
+        let provider = provider(&server, None);
+        assert_eq!(provider.show_model().await?, None);
+        let error = provider
+            .complete("", &[Message::user().with_text("Hi")], &[])
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("ollama pull qwen2.5"));
+
+        let mut progress = Vec::new();
+        provider
+            .pull_model(|p| progress.push((p.status.clone(), p.completed)))
+            .await?;
+        assert_eq!(progress.len(), 4);
+        assert_eq!(progress[2].1, Some(4000));
+        assert_eq!(progress[3].0, "success");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_embed() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/embed"))
+            .and(body_partial_json(json!({
+                "model": OLLAMA_DEFAULT_EMBEDDING_MODEL,
+                "input": ["first", "second"]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "model": OLLAMA_DEFAULT_EMBEDDING_MODEL,
+                "embeddings": [[1.0, 0.0], [0.0, 1.0]],
+                "prompt_eval_count": 4
+            })))
+            .mount(&server)
+            .await;
+
+        let embeddings = provider(&server, None)
+            .embed(&["first".to_string(), "second".to_string()])
+            .await?;
+        assert_eq!(embeddings.vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert_eq!(embeddings.usage.usage.input_tokens, Some(4));
+        Ok(())
     }
 }
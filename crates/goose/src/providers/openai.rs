@@ -1,13 +1,23 @@
 use anyhow::Result;
 use async_trait::async_trait;
-use reqwest::Client;
-use serde_json::Value;
+use reqwest::{Client, RequestBuilder, Response};
+use serde_json::{json, Value};
 use std::time::Duration;
 
-use super::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage, Usage};
+use super::base::{
+    complete_structured_with_prompt, CompletionStream, ConfigKey, Embeddings, Provider,
+    ProviderMetadata, ProviderUsage, Usage,
+};
 use super::errors::ProviderError;
-use super::formats::openai::{create_request, get_usage, response_to_message};
-use super::utils::{emit_debug_trace, get_model, handle_response_openai_compat, ImageFormat};
+use super::formats::openai::{
+    create_embeddings_request, create_request, embeddings_from_response, get_usage,
+    response_to_message, set_response_schema, ChatStreamAccumulator,
+};
+use super::streaming::{response_error, stream_response};
+use super::utils::{
+    embed_in_batches, embedding_model, emit_debug_trace, get_model, get_model_names,
+    handle_response_openai_compat, ImageFormat,
+};
 use crate::message::Message;
 use crate::model::ModelConfig;
 use mcp_core::tool::Tool;
@@ -23,6 +33,10 @@ pub const OPEN_AI_KNOWN_MODELS: &[&str] = &[
 
 pub const OPEN_AI_DOC_URL: &str = "https://platform.openai.com/docs/models";
 
+pub const OPEN_AI_DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+/// The most inputs OpenAI accepts in one embeddings request
+const OPEN_AI_EMBEDDING_BATCH_SIZE: usize = 2048;
+
 #[derive(Debug, serde::Serialize)]
 pub struct OpenAiProvider {
     #[serde(skip)]
@@ -64,17 +78,44 @@ impl OpenAiProvider {
         })
     }
 
-    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+    async fn send(&self, payload: &Value) -> Result<Response, ProviderError> {
         let base_url = url::Url::parse(&self.host)
             .map_err(|e| ProviderError::RequestFailed(format!("Invalid base URL: {e}")))?;
         let url = base_url.join("v1/chat/completions").map_err(|e| {
             ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
         })?;
 
-        let mut request = self
-            .client
-            .post(url)
-            .header("Authorization", format!("Bearer {}", self.api_key));
+        let request = self.authorize(self.client.post(url));
+        Ok(request.json(payload).send().await?)
+    }
+
+    async fn complete_request(
+        &self,
+        payload: Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        // Make request
+        let response = self.post(payload.clone()).await?;
+
+        // Parse response
+        let message = response_to_message(response.clone())?;
+        let usage = match get_usage(&response) {
+            Ok(usage) => usage,
+            Err(ProviderError::UsageError(e)) => {
+                tracing::debug!("Failed to get usage data: {}", e);
+                Usage::default()
+            }
+            Err(e) => return Err(e),
+        };
+        let model = get_model(&response);
+        emit_debug_trace(self, &payload, &response, &usage);
+        Ok((
+            message,
+            ProviderUsage::new(model, usage).with_provider("openai"),
+        ))
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        let mut request = request.header("Authorization", format!("Bearer {}", self.api_key));
 
         // Add organization header if present
         if let Some(org) = &self.organization {
@@ -86,8 +127,11 @@ impl OpenAiProvider {
             request = request.header("OpenAI-Project", project);
         }
 
-        let response = request.json(&payload).send().await?;
+        request
+    }
 
+    async fn post(&self, payload: Value) -> Result<Value, ProviderError> {
+        let response = self.send(&payload).await?;
         handle_response_openai_compat(response).await
     }
 }
@@ -118,6 +162,43 @@ impl Provider for OpenAiProvider {
         self.model.clone()
     }
 
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        let base_url = url::Url::parse(&self.host)
+            .map_err(|e| ProviderError::RequestFailed(format!("Invalid base URL: {e}")))?;
+        let url = base_url.join("v1/models").map_err(|e| {
+            ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
+        })?;
+
+        let response = self.authorize(self.client.get(url)).send().await?;
+        let response = handle_response_openai_compat(response).await?;
+        get_model_names(&response, "data", "id")
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Embeddings, ProviderError> {
+        let base_url = url::Url::parse(&self.host)
+            .map_err(|e| ProviderError::RequestFailed(format!("Invalid base URL: {e}")))?;
+        let url = base_url.join("v1/embeddings").map_err(|e| {
+            ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
+        })?;
+
+        let model = embedding_model(OPEN_AI_DEFAULT_EMBEDDING_MODEL);
+        let (vectors, usage) = embed_in_batches(texts, OPEN_AI_EMBEDDING_BATCH_SIZE, |batch| {
+            let request = self
+                .authorize(self.client.post(url.clone()))
+                .json(&create_embeddings_request(&model, &batch));
+            async move {
+                let response = handle_response_openai_compat(request.send().await?).await?;
+                embeddings_from_response(&response)
+            }
+        })
+        .await?;
+
+        Ok(Embeddings {
+            vectors,
+            usage: ProviderUsage::new(model, usage).with_provider("openai"),
+        })
+    }
+
     #[tracing::instrument(
         skip(self, system, messages, tools),
         fields(model_config, input, output, input_tokens, output_tokens, total_tokens)
@@ -129,22 +210,143 @@ impl Provider for OpenAiProvider {
         tools: &[Tool],
     ) -> Result<(Message, ProviderUsage), ProviderError> {
         let payload = create_request(&self.model, system, messages, tools, &ImageFormat::OpenAi)?;
+        self.complete_request(payload).await
+    }
 
-        // Make request
-        let response = self.post(payload.clone()).await?;
+    /// Uses the json_schema response format on models with JSON mode
+    async fn complete_structured(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        if !self.model.capabilities.json_mode {
+            return complete_structured_with_prompt(self, system, messages, schema).await;
+        }
+        let mut payload = create_request(&self.model, system, messages, &[], &ImageFormat::OpenAi)?;
+        set_response_schema(&mut payload, schema);
+        self.complete_request(payload).await
+    }
 
-        // Parse response
-        let message = response_to_message(response.clone())?;
-        let usage = match get_usage(&response) {
-            Ok(usage) => usage,
-            Err(ProviderError::UsageError(e)) => {
-                tracing::debug!("Failed to get usage data: {}", e);
-                Usage::default()
-            }
-            Err(e) => return Err(e),
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<CompletionStream, ProviderError> {
+        let mut payload =
+            create_request(&self.model, system, messages, tools, &ImageFormat::OpenAi)?;
+        payload["stream"] = json!(true);
+        payload["stream_options"] = json!({"include_usage": true});
+
+        let response = self.send(&payload).await?;
+        if !response.status().is_success() {
+            return Err(response_error(handle_response_openai_compat(response)).await);
+        }
+
+        let model_config = self.model.clone();
+        Ok(stream_response(
+            response,
+            ChatStreamAccumulator::default(),
+            move |response| {
+                let message = response_to_message(response.clone())?;
+                let usage = get_usage(&response).unwrap_or_else(|e| {
+                    tracing::debug!("Failed to get usage data: {}", e);
+                    Usage::default()
+                });
+                let model = get_model(&response);
+                emit_debug_trace(&model_config, &payload, &response, &usage);
+                Ok((
+                    message,
+                    ProviderUsage::new(model, usage).with_provider("openai"),
+                ))
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_json, body_partial_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_embed() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .and(header("Authorization", "Bearer key"))
+            .and(body_json(json!({
+                "model": OPEN_AI_DEFAULT_EMBEDDING_MODEL,
+                "input": ["first", "second"]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "data": [
+                    {"object": "embedding", "index": 1, "embedding": [0.0, 1.0]},
+                    {"object": "embedding", "index": 0, "embedding": [1.0, 0.0]}
+                ],
+                "model": OPEN_AI_DEFAULT_EMBEDDING_MODEL,
+                "usage": {"prompt_tokens": 4, "total_tokens": 4}
+            })))
+            .mount(&server)
+            .await;
+
+        let provider = OpenAiProvider {
+            client: Client::new(),
+            host: server.uri(),
+            api_key: "key".to_string(),
+            organization: None,
+            project: None,
+            model: ModelConfig::new(OPEN_AI_DEFAULT_MODEL.to_string()),
         };
-        let model = get_model(&response);
-        emit_debug_trace(self, &payload, &response, &usage);
-        Ok((message, ProviderUsage::new(model, usage)))
+        let embeddings = provider
+            .embed(&["first".to_string(), "second".to_string()])
+            .await?;
+        // Returned in the order the texts were given, not the order of the response
+        assert_eq!(embeddings.vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert_eq!(embeddings.usage.model, OPEN_AI_DEFAULT_EMBEDDING_MODEL);
+        assert_eq!(embeddings.usage.usage.input_tokens, Some(4));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_complete_structured() -> Result<()> {
+        let schema = json!({"type": "object", "properties": {"files": {"type": "integer"}}});
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(body_partial_json(json!({
+                "response_format": {
+                    "type": "json_schema",
+                    "json_schema": {"name": "final_output", "schema": schema, "strict": false}
+                }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "model": "gpt-4o",
+                "choices": [{"message": {"role": "assistant", "content": "{\"files\": 2}"}}],
+                "usage": {"prompt_tokens": 20, "completion_tokens": 5, "total_tokens": 25}
+            })))
+            .mount(&server)
+            .await;
+
+        let provider = OpenAiProvider {
+            client: Client::new(),
+            host: server.uri(),
+            api_key: "key".to_string(),
+            organization: None,
+            project: None,
+            model: ModelConfig::new("gpt-4o".to_string()),
+        };
+        let (message, _) = provider
+            .complete_structured(
+                "system",
+                &[Message::user().with_text("How many files?")],
+                &schema,
+            )
+            .await?;
+        assert_eq!(message.as_concat_text(), r#"{"files": 2}"#);
+        Ok(())
     }
 }
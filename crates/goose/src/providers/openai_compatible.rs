@@ -0,0 +1,355 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+use url::Url;
+
+use super::base::{CompletionStream, ConfigKey, Provider, ProviderMetadata, ProviderUsage, Usage};
+use super::errors::ProviderError;
+use super::formats::openai::{
+    create_request, get_usage, response_to_message, ChatStreamAccumulator,
+};
+use super::streaming::{response_error, stream_response};
+use super::utils::{
+    emit_debug_trace, get_model, get_model_names, handle_response_openai_compat, ImageFormat,
+};
+use crate::config::{Config, ConfigError};
+use crate::message::Message;
+use crate::model::ModelConfig;
+use mcp_core::tool::Tool;
+
+pub const OPENAI_COMPATIBLE_CONFIG_KEY: &str = "GOOSE_OPENAI_COMPATIBLE_PROVIDERS";
+
+fn default_chat_path() -> String {
+    "v1/chat/completions".to_string()
+}
+
+fn default_models_path() -> String {
+    "v1/models".to_string()
+}
+
+fn default_auth_header() -> String {
+    "Authorization".to_string()
+}
+
+fn default_auth_scheme() -> String {
+    "Bearer".to_string()
+}
+
+fn default_stream_usage() -> bool {
+    true
+}
+
+/// The settings for one named OpenAI compatible server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAiCompatibleConfig {
+    /// The server's address, e.g. http://localhost:8000
+    pub base_url: String,
+    /// Path of the chat completions endpoint, relative to the base url
+    #[serde(default = "default_chat_path")]
+    pub chat_path: String,
+    /// Path of the model listing endpoint, relative to the base url
+    #[serde(default = "default_models_path")]
+    pub models_path: String,
+    /// Name of the secret holding the api key, for servers which need one
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Header which carries the api key
+    #[serde(default = "default_auth_header")]
+    pub auth_header: String,
+    /// Written before the api key in the auth header, leave empty to send the bare key
+    #[serde(default = "default_auth_scheme")]
+    pub auth_scheme: String,
+    /// Extra headers sent with every request
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Model to use when none is configured
+    #[serde(default)]
+    pub default_model: Option<String>,
+    /// Models to offer when the server can't list them
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Ask for token usage at the end of a stream, which some servers reject
+    #[serde(default = "default_stream_usage")]
+    pub stream_usage: bool,
+}
+
+/// A provider for any server speaking the OpenAI chat completions API, such as vLLM,
+/// LM Studio, llama.cpp or an internal gateway
+///
+/// Each server is registered under a name, which is then used as the provider name, for example
+///
+/// ```yaml
+/// GOOSE_PROVIDER: vllm
+/// GOOSE_OPENAI_COMPATIBLE_PROVIDERS:
+///   vllm:
+///     base_url: http://localhost:8000
+///     default_model: Qwen/Qwen2.5-Coder-32B-Instruct
+///   gateway:
+///     base_url: https://llm.internal.example.com
+///     chat_path: openai/chat/completions
+///     api_key: GATEWAY_API_KEY
+///     auth_header: X-Api-Key
+///     auth_scheme: ""
+///     headers:
+///       X-Team: platform
+/// ```
+#[derive(Debug, serde::Serialize)]
+pub struct OpenAiCompatibleProvider {
+    #[serde(skip)]
+    client: Client,
+    name: String,
+    #[serde(skip)]
+    config: OpenAiCompatibleConfig,
+    #[serde(skip)]
+    api_key: Option<String>,
+    model: ModelConfig,
+}
+
+impl OpenAiCompatibleProvider {
+    /// The servers registered in config, by name, none if the setting is missing
+    pub fn instances() -> Result<HashMap<String, OpenAiCompatibleConfig>> {
+        match Config::global().get(OPENAI_COMPATIBLE_CONFIG_KEY) {
+            Ok(instances) => Ok(instances),
+            Err(ConfigError::NotFound(_)) => Ok(HashMap::new()),
+            Err(e) => Err(anyhow::anyhow!(
+                "Invalid {}: {}",
+                OPENAI_COMPATIBLE_CONFIG_KEY,
+                e
+            )),
+        }
+    }
+
+    /// Metadata describing one registered server, so it can be offered like a built in provider
+    pub fn instance_metadata(name: &str, config: &OpenAiCompatibleConfig) -> ProviderMetadata {
+        let config_keys = config
+            .api_key
+            .iter()
+            .map(|key| ConfigKey::new(key, true, true, None))
+            .collect();
+        ProviderMetadata::new(
+            name,
+            name,
+            &format!("OpenAI compatible server at {}", config.base_url),
+            config.default_model.as_deref().unwrap_or_default(),
+            config.models.clone(),
+            "",
+            config_keys,
+        )
+    }
+
+    pub fn from_env(name: &str, model: ModelConfig) -> Result<Self> {
+        let config = Self::instances()?.remove(name).ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} has no entry named {}",
+                OPENAI_COMPATIBLE_CONFIG_KEY,
+                name
+            )
+        })?;
+        let api_key = match &config.api_key {
+            Some(key) => Some(Config::global().get_secret(key)?),
+            None => None,
+        };
+        let client = Client::builder()
+            .timeout(Duration::from_secs(600))
+            .build()?;
+
+        Ok(Self {
+            client,
+            name: name.to_string(),
+            config,
+            api_key,
+            model,
+        })
+    }
+
+    fn url(&self, path: &str) -> Result<Url, ProviderError> {
+        let base_url = Url::parse(&self.config.base_url)
+            .map_err(|e| ProviderError::RequestFailed(format!("Invalid base URL: {e}")))?;
+        base_url.join(path).map_err(|e| {
+            ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
+        })
+    }
+
+    fn authorize(&self, mut request: RequestBuilder) -> RequestBuilder {
+        if let Some(api_key) = &self.api_key {
+            let value = if self.config.auth_scheme.is_empty() {
+                api_key.clone()
+            } else {
+                format!("{} {}", self.config.auth_scheme, api_key)
+            };
+            request = request.header(&self.config.auth_header, value);
+        }
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+        request
+    }
+
+    async fn send(&self, payload: &Value) -> Result<Response, ProviderError> {
+        let url = self.url(&self.config.chat_path)?;
+        let request = self.authorize(self.client.post(url));
+        Ok(request.json(payload).send().await?)
+    }
+}
+
+#[async_trait]
+impl Provider for OpenAiCompatibleProvider {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::new(
+            "openai_compatible",
+            "OpenAI Compatible",
+            "Any server speaking the OpenAI chat completions API, registered by name",
+            "",
+            vec![],
+            "",
+            vec![],
+        )
+    }
+
+    fn get_model_config(&self) -> ModelConfig {
+        self.model.clone()
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        let url = self.url(&self.config.models_path)?;
+        let response = self.authorize(self.client.get(url)).send().await?;
+        let response = handle_response_openai_compat(response).await?;
+        get_model_names(&response, "data", "id")
+    }
+
+    #[tracing::instrument(
+        skip(self, system, messages, tools),
+        fields(model_config, input, output, input_tokens, output_tokens, total_tokens)
+    )]
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let payload = create_request(&self.model, system, messages, tools, &ImageFormat::OpenAi)?;
+
+        let response = handle_response_openai_compat(self.send(&payload).await?).await?;
+
+        let message = response_to_message(response.clone())?;
+        let usage = get_usage(&response).unwrap_or_else(|e| {
+            tracing::debug!("Failed to get usage data: {}", e);
+            Usage::default()
+        });
+        let model = get_model(&response);
+        emit_debug_trace(self, &payload, &response, &usage);
+        Ok((
+            message,
+            ProviderUsage::new(model, usage).with_provider(&self.name),
+        ))
+    }
+
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<CompletionStream, ProviderError> {
+        let mut payload =
+            create_request(&self.model, system, messages, tools, &ImageFormat::OpenAi)?;
+        payload["stream"] = json!(true);
+        if self.config.stream_usage {
+            payload["stream_options"] = json!({"include_usage": true});
+        }
+
+        let response = self.send(&payload).await?;
+        if !response.status().is_success() {
+            return Err(response_error(handle_response_openai_compat(response)).await);
+        }
+
+        let model_config = self.model.clone();
+        let name = self.name.clone();
+        Ok(stream_response(
+            response,
+            ChatStreamAccumulator::default(),
+            move |response| {
+                let message = response_to_message(response.clone())?;
+                let usage = get_usage(&response).unwrap_or_else(|e| {
+                    tracing::debug!("Failed to get usage data: {}", e);
+                    Usage::default()
+                });
+                let model = get_model(&response);
+                emit_debug_trace(&model_config, &payload, &response, &usage);
+                Ok((
+                    message,
+                    ProviderUsage::new(model, usage).with_provider(&name),
+                ))
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_config_is_reported() {
+        std::env::set_var(
+            OPENAI_COMPATIBLE_CONFIG_KEY,
+            r#"{"vllm": {"base_url": "http://localhost:8000"}, "gateway": {"chat_path": "chat"}}"#,
+        );
+        let result = crate::providers::create("vllm", ModelConfig::new("llama".to_string()));
+        std::env::remove_var(OPENAI_COMPATIBLE_CONFIG_KEY);
+
+        // The entry missing its base_url is named, rather than every server being unknown
+        let error = result
+            .err()
+            .expect("the config should be rejected")
+            .to_string();
+        assert!(error.contains(OPENAI_COMPATIBLE_CONFIG_KEY), "{}", error);
+        assert!(error.contains("base_url"), "{}", error);
+    }
+
+    #[test]
+    fn test_config_defaults() -> Result<()> {
+        let config: OpenAiCompatibleConfig =
+            serde_json::from_value(json!({"base_url": "http://localhost:1234"}))?;
+        assert_eq!(config.chat_path, "v1/chat/completions");
+        assert_eq!(config.models_path, "v1/models");
+        assert_eq!(config.auth_header, "Authorization");
+        assert_eq!(config.auth_scheme, "Bearer");
+        assert!(config.api_key.is_none());
+        assert!(config.stream_usage);
+        Ok(())
+    }
+
+    #[test]
+    fn test_authorize_with_custom_header() -> Result<()> {
+        let config: OpenAiCompatibleConfig = serde_json::from_value(json!({
+            "base_url": "https://llm.example.com/gateway/",
+            "chat_path": "openai/chat/completions",
+            "auth_header": "X-Api-Key",
+            "auth_scheme": "",
+            "headers": {"X-Team": "platform"}
+        }))?;
+        let provider = OpenAiCompatibleProvider {
+            client: Client::new(),
+            name: "gateway".to_string(),
+            config,
+            api_key: Some("secret".to_string()),
+            model: ModelConfig::new("llama".to_string()),
+        };
+
+        let url = provider.url(&provider.config.chat_path)?;
+        assert_eq!(
+            url.as_str(),
+            "https://llm.example.com/gateway/openai/chat/completions"
+        );
+
+        let request = provider.authorize(provider.client.post(url)).build()?;
+        assert_eq!(request.headers()["X-Api-Key"], "secret");
+        assert_eq!(request.headers()["X-Team"], "platform");
+        assert!(request.headers().get("Authorization").is_none());
+        Ok(())
+    }
+}
@@ -7,8 +7,8 @@ use std::time::Duration;
 use super::base::{ConfigKey, Provider, ProviderMetadata, ProviderUsage, Usage};
 use super::errors::ProviderError;
 use super::utils::{
-    emit_debug_trace, get_model, handle_response_google_compat, handle_response_openai_compat,
-    is_google_model,
+    emit_debug_trace, get_model, get_model_names, handle_response_google_compat,
+    handle_response_openai_compat, is_google_model,
 };
 use crate::message::Message;
 use crate::model::ModelConfig;
@@ -211,6 +211,25 @@ impl Provider for OpenRouterProvider {
         self.model.clone()
     }
 
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        let base_url = Url::parse(&self.host)
+            .map_err(|e| ProviderError::RequestFailed(format!("Invalid base URL: {e}")))?;
+        let url = base_url.join("api/v1/models").map_err(|e| {
+            ProviderError::RequestFailed(format!("Failed to construct endpoint URL: {e}"))
+        })?;
+
+        let response = self
+            .client
+            .get(url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("HTTP-Referer", "https://github.com/block/goose")
+            .header("X-Title", "Goose")
+            .send()
+            .await?;
+        let response = handle_response_openai_compat(response).await?;
+        get_model_names(&response, "data", "id")
+    }
+
     #[tracing::instrument(
         skip(self, system, messages, tools),
         fields(model_config, input, output, input_tokens, output_tokens, total_tokens)
@@ -239,6 +258,9 @@ impl Provider for OpenRouterProvider {
         };
         let model = get_model(&response);
         emit_debug_trace(self, &payload, &response, &usage);
-        Ok((message, ProviderUsage::new(model, usage)))
+        Ok((
+            message,
+            ProviderUsage::new(model, usage).with_provider("openrouter"),
+        ))
     }
 }
@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use super::base::{ProviderUsage, Usage};
+use crate::config::Config;
+
+/// Prices in US dollars per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    pub input: f64,
+    pub output: f64,
+    /// Rate for input tokens written to the prompt cache, defaults to the input rate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_write: Option<f64>,
+    /// Rate for input tokens read from the prompt cache, defaults to the input rate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read: Option<f64>,
+}
+
+impl ModelPricing {
+    /// The cost in US dollars of the given usage
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        let cache_write = usage.cache_creation_input_tokens.unwrap_or(0).max(0) as f64;
+        let cache_read = usage.cache_read_input_tokens.unwrap_or(0).max(0) as f64;
+        // Input tokens include those written to and read from the cache
+        let input =
+            (usage.input_tokens.unwrap_or(0).max(0) as f64 - cache_write - cache_read).max(0.0);
+        let output = usage.output_tokens.unwrap_or(0).max(0) as f64;
+
+        (input * self.input
+            + cache_write * self.cache_write.unwrap_or(self.input)
+            + cache_read * self.cache_read.unwrap_or(self.input)
+            + output * self.output)
+            / 1_000_000.0
+    }
+}
+
+/// Model prices by provider name and then model name
+pub type PricingTable = HashMap<String, HashMap<String, ModelPricing>>;
+
+/// Looks up what a model costs, from the bundled price list with overrides from
+/// GOOSE_PRICING, for example
+///
+/// ```yaml
+/// GOOSE_PRICING:
+///   openai:
+///     gpt-4o:
+///       input: 2.5
+///       output: 10.0
+///       cache_read: 1.25
+/// ```
+///
+/// Model names match the longest listed prefix, so `claude-3-5-sonnet` also prices
+/// `claude-3-5-sonnet-20241022`.
+#[derive(Debug, Clone, Default)]
+pub struct PricingRegistry {
+    table: PricingTable,
+}
+
+impl PricingRegistry {
+    /// The prices bundled with goose
+    pub fn bundled() -> Self {
+        let table = serde_json::from_str(include_str!("pricing.json"))
+            .expect("the bundled pricing table is valid");
+        Self { table }
+    }
+
+    /// The bundled prices with any overrides from GOOSE_PRICING applied
+    pub fn from_config() -> Self {
+        let registry = Self::bundled();
+        match Config::global().get::<PricingTable>("GOOSE_PRICING") {
+            Ok(overrides) => registry.with_overrides(overrides),
+            Err(_) => registry,
+        }
+    }
+
+    pub fn with_overrides(mut self, overrides: PricingTable) -> Self {
+        for (provider, models) in overrides {
+            self.table.entry(provider).or_default().extend(models);
+        }
+        self
+    }
+
+    /// Find the pricing for a model of the named provider
+    pub fn lookup(&self, provider: &str, model: &str) -> Option<&ModelPricing> {
+        // Some APIs report model names with a resource prefix, e.g. models/gemini-2.0-flash
+        let model = model.strip_prefix("models/").unwrap_or(model);
+
+        self.table
+            .get(provider)?
+            .iter()
+            .filter(|(name, _)| model.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, pricing)| pricing)
+    }
+
+    /// The cost in US dollars of a provider call, if the model's pricing is known
+    ///
+    /// The same model name can be priced differently by each provider, so calls without a
+    /// provider are not priced
+    pub fn cost(&self, usage: &ProviderUsage) -> Option<f64> {
+        self.lookup(usage.provider.as_deref()?, &usage.model)
+            .map(|pricing| pricing.cost(&usage.usage))
+    }
+}
+
+/// The registry loaded from config, shared for the lifetime of the process
+pub fn registry() -> &'static PricingRegistry {
+    static REGISTRY: OnceLock<PricingRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(PricingRegistry::from_config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_uses_longest_prefix() {
+        let registry = PricingRegistry::bundled();
+
+        let mini = registry.lookup("openai", "gpt-4o-mini-2024-07-18").unwrap();
+        assert_eq!(mini.input, 0.15);
+        let full = registry.lookup("openai", "gpt-4o-2024-08-06").unwrap();
+        assert_eq!(full.input, 2.5);
+
+        let sonnet = registry
+            .lookup("anthropic", "claude-3-5-sonnet-20241022")
+            .unwrap();
+        assert_eq!(sonnet.output, 15.0);
+        assert!(registry
+            .lookup("google", "models/gemini-2.0-flash")
+            .is_some());
+        assert!(registry.lookup("google", "gpt-4o").is_none());
+        assert!(registry.lookup("ollama", "llama3.2").is_none());
+    }
+
+    #[test]
+    fn test_cost_with_cache_tokens() {
+        let pricing = ModelPricing {
+            input: 3.0,
+            output: 15.0,
+            cache_write: Some(3.75),
+            cache_read: Some(0.3),
+        };
+        let usage = Usage::new(Some(1_000_000), Some(100_000), Some(1_100_000))
+            .with_cache_tokens(Some(200_000), Some(600_000));
+
+        // 200k uncached input, 200k cache writes, 600k cache reads and 100k output
+        let cost = pricing.cost(&usage);
+        assert!((cost - (0.6 + 0.75 + 0.18 + 1.5)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_overrides_replace_and_add_models() {
+        let overrides: PricingTable = serde_json::from_value(serde_json::json!({
+            "openai": {
+                "gpt-4o": {"input": 1.0, "output": 2.0},
+                "my-finetune": {"input": 4.0, "output": 8.0}
+            }
+        }))
+        .unwrap();
+        let registry = PricingRegistry::bundled().with_overrides(overrides);
+
+        assert_eq!(registry.lookup("openai", "gpt-4o").unwrap().input, 1.0);
+        assert_eq!(registry.lookup("openai", "my-finetune").unwrap().input, 4.0);
+        assert!(registry.lookup("openai", "gpt-4o-mini").is_some());
+
+        let usage = ProviderUsage::new(
+            "my-finetune".to_string(),
+            Usage::new(Some(500_000), Some(0), Some(500_000)),
+        )
+        .with_provider("openai");
+        assert_eq!(registry.cost(&usage), Some(2.0));
+
+        // Without the provider there is no telling whose prices apply
+        let usage = ProviderUsage::new("gpt-4o".to_string(), Usage::default());
+        assert_eq!(registry.cost(&usage), None);
+    }
+}
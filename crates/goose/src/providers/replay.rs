@@ -0,0 +1,401 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Digest;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use super::base::{Embeddings, Provider, ProviderMetadata, ProviderUsage};
+use super::errors::ProviderError;
+use crate::config::Config;
+use crate::message::{Message, MessageContent};
+use crate::model::ModelConfig;
+use mcp_core::tool::Tool;
+
+pub const REPLAY_PROVIDER_NAME: &str = "replay";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayMode {
+    /// Answer from the cassette without touching the network
+    #[default]
+    Replay,
+    /// Call the real provider and write every exchange to the cassette
+    Record,
+}
+
+/// The GOOSE_REPLAY settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayConfig {
+    /// File holding the recorded exchanges
+    pub cassette: PathBuf,
+    #[serde(default)]
+    pub mode: ReplayMode,
+    /// Provider to record from, only needed when recording
+    #[serde(default)]
+    pub provider: Option<String>,
+}
+
+/// One request and the response it got
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub hash: String,
+    /// The normalized request, kept to explain mismatches
+    pub request: Value,
+    pub message: Message,
+    pub usage: ProviderUsage,
+}
+
+/// A recorded session, stored as JSON
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    /// Config of the recorded model, so replays see the same limits and capabilities
+    #[serde(default)]
+    pub model: Option<ModelConfig>,
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            anyhow::anyhow!("Failed to read the cassette {}: {}", path.display(), e)
+        })?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// The request with everything that changes between otherwise identical runs taken out:
+/// message timestamps, the date in the system prompt, and tool call ids, which are replaced by
+/// their order of appearance
+pub fn normalize_request(system: &str, messages: &[Message], tools: &[Tool]) -> Value {
+    static DATE_TIME: OnceLock<Regex> = OnceLock::new();
+    let date_time =
+        DATE_TIME.get_or_init(|| Regex::new(r"\d{4}-\d{2}-\d{2}[ T]\d{2}:\d{2}:\d{2}").unwrap());
+
+    let mut ids: HashMap<String, String> = HashMap::new();
+    let mut normalize_id = |id: &mut String| {
+        let next = format!("tool_{}", ids.len());
+        *id = ids.entry(id.clone()).or_insert(next).clone();
+    };
+
+    let messages: Vec<Message> = messages
+        .iter()
+        .cloned()
+        .map(|mut message| {
+            message.created = 0;
+            for content in &mut message.content {
+                match content {
+                    MessageContent::ToolRequest(request) => normalize_id(&mut request.id),
+                    MessageContent::ToolResponse(response) => normalize_id(&mut response.id),
+                    _ => {}
+                }
+            }
+            message
+        })
+        .collect();
+
+    json!({
+        "system": date_time.replace_all(system, "<date>"),
+        "messages": messages,
+        "tools": tools,
+    })
+}
+
+/// A structured output request, normalized like any other and told apart by its schema
+pub fn normalize_structured_request(system: &str, messages: &[Message], schema: &Value) -> Value {
+    let mut request = normalize_request(system, messages, &[]);
+    request["output_schema"] = schema.clone();
+    request
+}
+
+pub fn request_hash(request: &Value) -> String {
+    format!("{:x}", sha2::Sha256::digest(request.to_string().as_bytes()))
+}
+
+/// A provider which records a real provider's responses to a cassette, or replays them
+/// offline, so agent and extension flows can be tested without network access or credentials
+///
+/// ```yaml
+/// GOOSE_PROVIDER: replay
+/// GOOSE_REPLAY:
+///   cassette: tests/cassettes/developer_shell.json
+///   mode: record        # or replay, the default
+///   provider: anthropic # only needed to record
+/// ```
+///
+/// Requests are matched by a hash of their normalized content, and a request with no unused
+/// recording is an error rather than a guess.
+pub struct ReplayProvider {
+    path: PathBuf,
+    /// The provider being recorded, none when replaying
+    recording: Option<Box<dyn Provider + Send + Sync>>,
+    model: ModelConfig,
+    cassette: Mutex<Cassette>,
+    /// Which recorded interactions have been replayed
+    used: Mutex<Vec<bool>>,
+}
+
+impl ReplayProvider {
+    pub fn recording(path: PathBuf, provider: Box<dyn Provider + Send + Sync>) -> Self {
+        let model = provider.get_model_config();
+        let cassette = Cassette {
+            model: Some(model.clone()),
+            interactions: Vec::new(),
+        };
+        Self {
+            path,
+            recording: Some(provider),
+            model,
+            cassette: Mutex::new(cassette),
+            used: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn replaying(path: PathBuf, model: ModelConfig) -> Result<Self> {
+        let cassette = Cassette::load(&path)?;
+        let model = cassette.model.clone().unwrap_or(model);
+        let used = vec![false; cassette.interactions.len()];
+        Ok(Self {
+            path,
+            recording: None,
+            model,
+            cassette: Mutex::new(cassette),
+            used: Mutex::new(used),
+        })
+    }
+
+    pub fn from_env(model: ModelConfig) -> Result<Self> {
+        let config: ReplayConfig = Config::global()
+            .get("GOOSE_REPLAY")
+            .map_err(|_| anyhow::anyhow!("GOOSE_REPLAY must be set to use the replay provider"))?;
+
+        match config.mode {
+            ReplayMode::Replay => Self::replaying(config.cassette, model),
+            ReplayMode::Record => {
+                let provider = config.provider.ok_or_else(|| {
+                    anyhow::anyhow!("GOOSE_REPLAY needs a provider to record from")
+                })?;
+                if provider == REPLAY_PROVIDER_NAME {
+                    return Err(anyhow::anyhow!("The replay provider cannot record itself"));
+                }
+                Ok(Self::recording(
+                    config.cassette,
+                    super::create(&provider, model)?,
+                ))
+            }
+        }
+    }
+
+    fn replay(
+        &self,
+        request: &Value,
+        hash: &str,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let cassette = self.cassette.lock().unwrap();
+        let mut used = self.used.lock().unwrap();
+        let position = (0..used.len()).find(|&i| !used[i] && cassette.interactions[i].hash == hash);
+
+        match position {
+            Some(i) => {
+                used[i] = true;
+                let interaction = &cassette.interactions[i];
+                Ok((interaction.message.clone(), interaction.usage.clone()))
+            }
+            None => {
+                let expected = used
+                    .iter()
+                    .position(|used| !used)
+                    .map(|i| &cassette.interactions[i]);
+                let detail = match expected {
+                    Some(expected) => format!(
+                        "the next recorded request was {}:\n{}\nbut got:\n{}",
+                        expected.hash,
+                        serde_json::to_string_pretty(&expected.request).unwrap_or_default(),
+                        serde_json::to_string_pretty(request).unwrap_or_default()
+                    ),
+                    None => "every recorded request has already been replayed".to_string(),
+                };
+                Err(ProviderError::ExecutionError(format!(
+                    "No recording in {} matches request {}, {}. Record the cassette again if the request is expected to change",
+                    self.path.display(),
+                    hash,
+                    detail
+                )))
+            }
+        }
+    }
+
+    fn record(
+        &self,
+        hash: String,
+        request: Value,
+        message: &Message,
+        usage: &ProviderUsage,
+    ) -> Result<(), ProviderError> {
+        let mut cassette = self.cassette.lock().unwrap();
+        cassette.interactions.push(Interaction {
+            hash,
+            request,
+            message: message.clone(),
+            usage: usage.clone(),
+        });
+        // Saved after every exchange so an interrupted recording keeps what it has
+        cassette.save(&self.path)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Provider for ReplayProvider {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::empty()
+    }
+
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let request = normalize_request(system, messages, tools);
+        let hash = request_hash(&request);
+
+        let Some(provider) = &self.recording else {
+            return self.replay(&request, &hash);
+        };
+        let (message, usage) = provider.complete(system, messages, tools).await?;
+        self.record(hash, request, &message, &usage)?;
+        Ok((message, usage))
+    }
+
+    async fn complete_structured(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let request = normalize_structured_request(system, messages, schema);
+        let hash = request_hash(&request);
+
+        let Some(provider) = &self.recording else {
+            return self.replay(&request, &hash);
+        };
+        let (message, usage) = provider
+            .complete_structured(system, messages, schema)
+            .await?;
+        self.record(hash, request, &message, &usage)?;
+        Ok((message, usage))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        Ok(vec![self.model.model_name.clone()])
+    }
+
+    /// Embeddings pass through while recording but aren't kept in the cassette
+    async fn embed(&self, texts: &[String]) -> Result<Embeddings, ProviderError> {
+        match &self.recording {
+            Some(provider) => provider.embed(texts).await,
+            None => Err(ProviderError::NotSupported(
+                "embeddings are not recorded, so they cannot be replayed".to_string(),
+            )),
+        }
+    }
+
+    fn get_model_config(&self) -> ModelConfig {
+        self.model.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::ToolRequest;
+    use crate::providers::base::Usage;
+    use crate::providers::mock::MockProvider;
+    use mcp_core::tool::ToolCall;
+
+    fn recorded_provider() -> MockProvider {
+        MockProvider::new("counting")
+            .with_model_config(
+                ModelConfig::new("counting".to_string()).with_context_limit(Some(1000)),
+            )
+            .with_reply(
+                Message::assistant().with_text("3 messages"),
+                Usage::new(Some(10), Some(2), None),
+            )
+    }
+
+    fn conversation(tool_id: &str) -> Vec<Message> {
+        vec![
+            Message::user().with_text("List the files"),
+            Message::assistant().with_content(MessageContent::ToolRequest(ToolRequest {
+                id: tool_id.to_string(),
+                tool_call: Ok(ToolCall::new("developer__shell", json!({"command": "ls"}))),
+            })),
+            Message::user().with_tool_response(tool_id, Ok(vec![])),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("cassettes/session.json");
+        let provider = recorded_provider();
+        let requests = provider.requests();
+
+        let recorder = ReplayProvider::recording(path.clone(), Box::new(provider));
+        recorder
+            .complete("It is 2025-01-01 09:00:00", &conversation("call_1"), &[])
+            .await?;
+
+        // The replayed run happens at another time and gets different tool call ids
+        let replayer = ReplayProvider::replaying(path, ModelConfig::new("other".to_string()))?;
+        let (message, usage) = replayer
+            .complete("It is 2025-02-03 17:30:00", &conversation("call_9"), &[])
+            .await?;
+
+        assert_eq!(message.as_concat_text(), "3 messages");
+        assert_eq!(usage.usage.input_tokens, Some(10));
+        assert_eq!(requests.len(), 1);
+        assert_eq!(replayer.get_model_config().model_name, "counting");
+        assert_eq!(replayer.get_model_config().context_limit(), 1000);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replay_fails_on_mismatch() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("session.json");
+        let recorder = ReplayProvider::recording(path.clone(), Box::new(recorded_provider()));
+        let messages = [Message::user().with_text("Hello")];
+        recorder.complete("system", &messages, &[]).await?;
+
+        let replayer = ReplayProvider::replaying(path, ModelConfig::new("other".to_string()))?;
+        let error = replayer
+            .complete("system", &[Message::user().with_text("Goodbye")], &[])
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Goodbye"));
+        assert!(error.to_string().contains("Hello"));
+
+        // Each recording answers once
+        replayer.complete("system", &messages, &[]).await?;
+        let error = replayer
+            .complete("system", &messages, &[])
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("already been replayed"));
+        Ok(())
+    }
+}
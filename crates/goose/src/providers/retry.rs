@@ -0,0 +1,328 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use rand::Rng;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::base::{CompletionStream, Embeddings, Provider, ProviderMetadata, ProviderUsage};
+use super::errors::ProviderError;
+use crate::config::Config;
+use crate::message::Message;
+use crate::model::ModelConfig;
+use mcp_core::tool::Tool;
+
+pub const DEFAULT_MAX_ATTEMPTS: usize = 3;
+const DEFAULT_INITIAL_DELAY: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// How transient provider errors are retried
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// Total number of attempts, including the first one. 1 disables retries
+    pub max_attempts: usize,
+    /// Backoff before the first retry, doubled on every subsequent one
+    pub initial_delay: Duration,
+    /// Upper bound on the delay, including delays requested by the provider
+    pub max_delay: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_delay: DEFAULT_INITIAL_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Load the retry settings, using GOOSE_PROVIDER_MAX_ATTEMPTS for the number of attempts
+    pub fn from_config() -> Self {
+        let max_attempts = Config::global()
+            .get("GOOSE_PROVIDER_MAX_ATTEMPTS")
+            .unwrap_or(DEFAULT_MAX_ATTEMPTS);
+        Self {
+            max_attempts: max_attempts.max(1),
+            ..Default::default()
+        }
+    }
+
+    /// The delay before retrying after the given failed attempt (starting at 1), or None if the
+    /// error should not be retried
+    fn delay_for(&self, attempt: usize, error: &ProviderError) -> Option<Duration> {
+        let requested = match error {
+            ProviderError::RateLimitExceeded { retry_delay, .. } => *retry_delay,
+            ProviderError::ServerError(_) => None,
+            _ => return None,
+        };
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        // Respect the provider's requested delay up to the cap, so a long Retry-After can't stall
+        // the turn, otherwise use exponential backoff with jitter so that concurrent sessions
+        // don't all retry at once
+        if let Some(requested) = requested {
+            return Some(requested.min(self.max_delay));
+        }
+        let exponent = (attempt - 1).min(16) as i32;
+        let backoff = self
+            .initial_delay
+            .mul_f64(2f64.powi(exponent))
+            .min(self.max_delay);
+        Some(backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0)))
+    }
+}
+
+/// Details about a retry, passed to the listener before waiting
+#[derive(Debug, Clone)]
+pub struct RetryNotice {
+    /// The attempt that failed, starting at 1
+    pub attempt: usize,
+    pub max_attempts: usize,
+    pub delay: Duration,
+    pub error: String,
+}
+
+pub type RetryListener = Arc<dyn Fn(&RetryNotice) + Send + Sync>;
+
+/// Wraps a provider to retry rate limits and server errors with exponential backoff
+pub struct RetryProvider {
+    inner: Box<dyn Provider + Send + Sync>,
+    config: RetryConfig,
+    listener: Option<RetryListener>,
+}
+
+impl RetryProvider {
+    pub fn new(inner: Box<dyn Provider + Send + Sync>, config: RetryConfig) -> Self {
+        Self {
+            inner,
+            config,
+            listener: None,
+        }
+    }
+
+    /// Get notified before each retry, for example to show the delay to the user
+    pub fn with_listener(mut self, listener: RetryListener) -> Self {
+        self.listener = Some(listener);
+        self
+    }
+
+    /// Wait out the delay for a failed attempt, or return the error if it should not be retried
+    async fn wait_before_retry(
+        &self,
+        attempt: usize,
+        error: ProviderError,
+    ) -> Result<(), ProviderError> {
+        let Some(delay) = self.config.delay_for(attempt, &error) else {
+            return Err(error);
+        };
+
+        tracing::warn!(
+            "Provider request failed on attempt {}/{}, retrying in {:?}: {}",
+            attempt,
+            self.config.max_attempts,
+            delay,
+            error
+        );
+        if let Some(listener) = &self.listener {
+            listener(&RetryNotice {
+                attempt,
+                max_attempts: self.config.max_attempts,
+                delay,
+                error: error.to_string(),
+            });
+        }
+
+        tokio::time::sleep(delay).await;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Provider for RetryProvider {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::empty()
+    }
+
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let mut attempt = 1;
+        loop {
+            let error = match self.inner.complete(system, messages, tools).await {
+                Ok(result) => return Ok(result),
+                Err(error) => error,
+            };
+            self.wait_before_retry(attempt, error).await?;
+            attempt += 1;
+        }
+    }
+
+    /// Only establishing the stream is retried, since chunks may already have been shown
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<CompletionStream, ProviderError> {
+        let mut attempt = 1;
+        loop {
+            let error = match self.inner.stream(system, messages, tools).await {
+                Ok(stream) => return Ok(stream),
+                Err(error) => error,
+            };
+            self.wait_before_retry(attempt, error).await?;
+            attempt += 1;
+        }
+    }
+
+    async fn complete_structured(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        let mut attempt = 1;
+        loop {
+            let error = match self
+                .inner
+                .complete_structured(system, messages, schema)
+                .await
+            {
+                Ok(result) => return Ok(result),
+                Err(error) => error,
+            };
+            self.wait_before_retry(attempt, error).await?;
+            attempt += 1;
+        }
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        self.inner.list_models().await
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Embeddings, ProviderError> {
+        let mut attempt = 1;
+        loop {
+            let error = match self.inner.embed(texts).await {
+                Ok(embeddings) => return Ok(embeddings),
+                Err(error) => error,
+            };
+            self.wait_before_retry(attempt, error).await?;
+            attempt += 1;
+        }
+    }
+
+    fn get_model_config(&self) -> ModelConfig {
+        self.inner.get_model_config()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::mock::{MockProvider, MockRequests};
+    use std::sync::Mutex;
+
+    /// Fails with the errors in order, then succeeds
+    fn flaky(errors: Vec<ProviderError>) -> (Box<dyn Provider + Send + Sync>, MockRequests) {
+        let provider = errors
+            .into_iter()
+            .fold(MockProvider::new("mock"), MockProvider::with_error)
+            .with_text("done");
+        let requests = provider.requests();
+        (Box::new(provider), requests)
+    }
+
+    fn fast_config(max_attempts: usize) -> RetryConfig {
+        RetryConfig {
+            max_attempts,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_errors() {
+        let (inner, requests) = flaky(vec![
+            ProviderError::ServerError("overloaded".to_string()),
+            ProviderError::RateLimitExceeded {
+                details: "slow down".to_string(),
+                retry_delay: Some(Duration::from_millis(3)),
+            },
+        ]);
+        let notices = Arc::new(Mutex::new(Vec::new()));
+        let recorded = notices.clone();
+        let provider = RetryProvider::new(inner, fast_config(3)).with_listener(Arc::new(
+            move |notice: &RetryNotice| recorded.lock().unwrap().push(notice.clone()),
+        ));
+
+        let (message, _) = provider.complete("", &[], &[]).await.unwrap();
+        assert_eq!(message.as_concat_text(), "done");
+        assert_eq!(requests.len(), 3);
+
+        let notices = notices.lock().unwrap();
+        assert_eq!(notices.len(), 2);
+        assert_eq!(notices[0].attempt, 1);
+        assert!(notices[0].delay <= Duration::from_millis(1));
+        // The delay requested by the provider is used as is
+        assert_eq!(notices[1].attempt, 2);
+        assert_eq!(notices[1].delay, Duration::from_millis(3));
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let (inner, requests) = flaky(vec![
+            ProviderError::ServerError("1".to_string()),
+            ProviderError::ServerError("2".to_string()),
+            ProviderError::ServerError("3".to_string()),
+        ]);
+        let provider = RetryProvider::new(inner, fast_config(2));
+
+        let result = provider.complete("", &[], &[]).await;
+        assert!(matches!(result, Err(ProviderError::ServerError(msg)) if msg == "2"));
+        assert_eq!(requests.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_other_errors() {
+        let (inner, requests) = flaky(vec![ProviderError::ContextLengthExceeded(
+            "too long".to_string(),
+        )]);
+        let provider = RetryProvider::new(inner, fast_config(3));
+
+        let result = provider.complete("", &[], &[]).await;
+        assert!(matches!(
+            result,
+            Err(ProviderError::ContextLengthExceeded(_))
+        ));
+        assert_eq!(requests.len(), 1);
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let config = RetryConfig {
+            max_attempts: 20,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(8),
+        };
+        let error = ProviderError::ServerError("overloaded".to_string());
+        for attempt in 1..20 {
+            let delay = config.delay_for(attempt, &error).unwrap();
+            assert!(delay <= Duration::from_secs(8));
+        }
+        assert!(config.delay_for(20, &error).is_none());
+
+        let error = ProviderError::RateLimitExceeded {
+            details: "slow down".to_string(),
+            retry_delay: Some(Duration::from_secs(3600)),
+        };
+        assert_eq!(config.delay_for(1, &error), Some(Duration::from_secs(8)));
+    }
+}
@@ -0,0 +1,342 @@
+use futures::StreamExt;
+use reqwest::Response;
+use serde_json::Value;
+use std::future::Future;
+
+use super::base::{CompletionChunk, CompletionStream, ProviderUsage};
+use super::errors::ProviderError;
+use crate::message::Message;
+
+/// A single server-sent event
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SseEvent {
+    /// The `event:` field, if one was sent
+    pub event: Option<String>,
+    pub data: String,
+}
+
+/// Incremental parser for `text/event-stream` bodies
+///
+/// Network chunks don't line up with events, so partial lines are buffered until complete.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: String,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    /// Feed the next chunk of the body, returning any events it completed
+    pub fn feed(&mut self, chunk: &str) -> Vec<SseEvent> {
+        self.buffer.push_str(chunk);
+        let mut events = Vec::new();
+
+        while let Some(end) = self.buffer.find('\n') {
+            let line: String = self.buffer.drain(..=end).collect();
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                // A blank line dispatches the event
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        event: self.event.take(),
+                        data: self.data.join("\n"),
+                    });
+                }
+                self.event = None;
+                self.data.clear();
+                continue;
+            }
+
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "event" => self.event = Some(value.to_string()),
+                "data" => self.data.push(value.to_string()),
+                // Comments (empty field) and unused fields such as id and retry
+                _ => {}
+            }
+        }
+
+        events
+    }
+
+    /// Flush a final event which was not followed by a blank line
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let rest = std::mem::take(&mut self.buffer);
+            self.feed(&format!("{}\n", rest));
+        }
+        if self.data.is_empty() {
+            return None;
+        }
+        Some(SseEvent {
+            event: self.event.take(),
+            data: std::mem::take(&mut self.data).join("\n"),
+        })
+    }
+}
+
+/// Incremental parser for newline delimited JSON bodies, such as Ollama's native API streams
+///
+/// Each non-empty line is returned as the data of an event, so the same accumulators can be
+/// used as for event streams.
+#[derive(Debug, Default)]
+pub struct JsonLinesParser {
+    buffer: String,
+}
+
+impl JsonLinesParser {
+    /// Feed the next chunk of the body, returning any lines it completed
+    pub fn feed(&mut self, chunk: &str) -> Vec<SseEvent> {
+        self.buffer.push_str(chunk);
+        let mut events = Vec::new();
+
+        while let Some(end) = self.buffer.find('\n') {
+            let line: String = self.buffer.drain(..=end).collect();
+            let line = line.trim();
+            if !line.is_empty() {
+                events.push(SseEvent {
+                    event: None,
+                    data: line.to_string(),
+                });
+            }
+        }
+
+        events
+    }
+
+    /// Flush a final line which was not followed by a newline
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        let rest = std::mem::take(&mut self.buffer);
+        let rest = rest.trim();
+        (!rest.is_empty()).then(|| SseEvent {
+            event: None,
+            data: rest.to_string(),
+        })
+    }
+}
+
+/// The parsers which split a streaming body into events
+trait EventParser: Default + Send + 'static {
+    fn feed(&mut self, chunk: &str) -> Vec<SseEvent>;
+    fn finish(&mut self) -> Option<SseEvent>;
+}
+
+impl EventParser for SseParser {
+    fn feed(&mut self, chunk: &str) -> Vec<SseEvent> {
+        SseParser::feed(self, chunk)
+    }
+
+    fn finish(&mut self) -> Option<SseEvent> {
+        SseParser::finish(self)
+    }
+}
+
+impl EventParser for JsonLinesParser {
+    fn feed(&mut self, chunk: &str) -> Vec<SseEvent> {
+        JsonLinesParser::feed(self, chunk)
+    }
+
+    fn finish(&mut self) -> Option<SseEvent> {
+        JsonLinesParser::finish(self)
+    }
+}
+
+/// Builds a complete response from the events of a streaming API
+///
+/// Implemented for each API format, so that the final response can be converted with the same
+/// code as a non-streaming response.
+pub trait StreamAccumulator: Send + 'static {
+    /// Process one event, returning the chunks to forward to the caller
+    fn push(&mut self, event: &SseEvent) -> Result<Vec<CompletionChunk>, ProviderError>;
+
+    /// The accumulated response, in the same format as the non-streaming API
+    fn finish(self) -> Value;
+}
+
+/// Convert an error response into the provider's usual error for that status
+pub async fn response_error(
+    handled: impl Future<Output = Result<Value, ProviderError>>,
+) -> ProviderError {
+    match handled.await {
+        Err(error) => error,
+        Ok(payload) => ProviderError::RequestFailed(format!("Unexpected response: {}", payload)),
+    }
+}
+
+/// Decodes UTF-8 arriving in chunks, which can split a multi-byte character
+#[derive(Debug, Default)]
+struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    /// Decode what has arrived so far, holding back a character which is still incomplete.
+    /// Invalid bytes are replaced with U+FFFD rather than stalling the stream
+    fn decode(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+        let mut text = String::new();
+        let mut rest = self.pending.as_slice();
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    rest = &[];
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    // Safe to unwrap, the bytes up to valid_up_to are valid UTF-8
+                    text.push_str(std::str::from_utf8(valid).unwrap());
+                    match e.error_len() {
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        // The bytes end partway through a character, which the next chunk completes
+                        None => {
+                            rest = after;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.pending = rest.to_vec();
+        text
+    }
+
+    /// Whatever is left once the stream ends, which can only be a truncated character
+    fn finish(self) -> String {
+        String::from_utf8_lossy(&self.pending).into_owned()
+    }
+}
+
+/// Stream a successful event-stream response through an accumulator
+///
+/// Chunks are forwarded as they arrive, and once the stream ends the accumulated response is
+/// passed to `finish` to produce the final `CompletionChunk::Done`.
+pub fn stream_response<A, F>(response: Response, accumulator: A, finish: F) -> CompletionStream
+where
+    A: StreamAccumulator,
+    F: FnOnce(Value) -> Result<(Message, ProviderUsage), ProviderError> + Send + 'static,
+{
+    stream_events::<SseParser, A, F>(response, accumulator, finish)
+}
+
+/// Stream a successful newline delimited JSON response through an accumulator, in the same way
+/// as `stream_response`
+pub fn stream_json_lines<A, F>(response: Response, accumulator: A, finish: F) -> CompletionStream
+where
+    A: StreamAccumulator,
+    F: FnOnce(Value) -> Result<(Message, ProviderUsage), ProviderError> + Send + 'static,
+{
+    stream_events::<JsonLinesParser, A, F>(response, accumulator, finish)
+}
+
+fn stream_events<P, A, F>(response: Response, mut accumulator: A, finish: F) -> CompletionStream
+where
+    P: EventParser,
+    A: StreamAccumulator,
+    F: FnOnce(Value) -> Result<(Message, ProviderUsage), ProviderError> + Send + 'static,
+{
+    Box::pin(async_stream::try_stream! {
+        let mut body = response.bytes_stream();
+        let mut parser = P::default();
+        let mut decoder = Utf8Decoder::default();
+
+        while let Some(bytes) = body.next().await {
+            let text = decoder.decode(&bytes?);
+            for event in parser.feed(&text) {
+                // OpenAI compatible APIs end the stream with a sentinel rather than closing it
+                if event.data == "[DONE]" {
+                    continue;
+                }
+                for chunk in accumulator.push(&event)? {
+                    yield chunk;
+                }
+            }
+        }
+        parser.feed(&decoder.finish());
+        if let Some(event) = parser.finish().filter(|e| e.data != "[DONE]") {
+            for chunk in accumulator.push(&event)? {
+                yield chunk;
+            }
+        }
+
+        let (message, usage) = finish(accumulator.finish())?;
+        yield CompletionChunk::Done(message, usage);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoder_joins_split_characters() {
+        let mut decoder = Utf8Decoder::default();
+        let bytes = "data: é\n\n".as_bytes();
+        assert_eq!(decoder.decode(&bytes[..7]), "data: ");
+        assert_eq!(decoder.decode(&bytes[7..]), "é\n\n");
+        assert_eq!(decoder.finish(), "");
+    }
+
+    #[test]
+    fn test_decoder_replaces_invalid_bytes() {
+        let mut decoder = Utf8Decoder::default();
+        assert_eq!(decoder.decode(b"data: a\xffb"), "data: a\u{FFFD}b");
+        // The stream carries on past the bad byte
+        assert_eq!(decoder.decode(b"c\n\n"), "c\n\n");
+        assert_eq!(decoder.decode(b"\xe2\x82"), "");
+        assert_eq!(decoder.finish(), "\u{FFFD}");
+    }
+
+    #[test]
+    fn test_parser_handles_split_chunks() {
+        let mut parser = SseParser::default();
+        assert!(parser.feed("event: message_start\ndata: {\"a\"").is_empty());
+        let events = parser.feed(": 1}\n\ndata: second\r\n\r\n");
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: Some("message_start".to_string()),
+                    data: "{\"a\": 1}".to_string(),
+                },
+                SseEvent {
+                    event: None,
+                    data: "second".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parser_joins_data_lines_and_skips_comments() {
+        let mut parser = SseParser::default();
+        let events = parser.feed(": keep-alive\n\ndata: first\ndata: second\nid: 1\n\n");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "first\nsecond");
+    }
+
+    #[test]
+    fn test_parser_flushes_unterminated_event() {
+        let mut parser = SseParser::default();
+        assert!(parser.feed("data: [DONE]").is_empty());
+        assert_eq!(parser.finish().unwrap().data, "[DONE]");
+        assert!(parser.finish().is_none());
+    }
+
+    #[test]
+    fn test_json_lines_parser() {
+        let mut parser = JsonLinesParser::default();
+        assert!(parser.feed("{\"a\":").is_empty());
+        let events = parser.feed(" 1}\n\n{\"b\": 2}\r\n{\"c\"");
+        let data: Vec<&str> = events.iter().map(|e| e.data.as_str()).collect();
+        assert_eq!(data, vec!["{\"a\": 1}", "{\"b\": 2}"]);
+        assert_eq!(parser.finish().unwrap().data, "{\"c\"");
+        assert!(parser.finish().is_none());
+    }
+}
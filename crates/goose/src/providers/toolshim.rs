@@ -0,0 +1,565 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use indoc::formatdoc;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::OnceLock;
+
+use super::base::{
+    CompletionChunk, CompletionStream, Embeddings, Provider, ProviderMetadata, ProviderUsage, Usage,
+};
+use super::errors::ProviderError;
+use super::pricing;
+use super::utils::tool_messages_as_text;
+use crate::config::Config;
+use crate::message::{Message, MessageContent};
+use crate::model::ModelConfig;
+use mcp_core::tool::{Tool, ToolCall};
+use mcp_core::ToolError;
+
+/// The small model which pulls tool calls out of a reply the main model wrote as prose
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterpreterConfig {
+    pub provider: String,
+    pub model: String,
+}
+
+/// Which models have their tool calls emulated, from GOOSE_TOOLSHIM
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ToolShimConfig {
+    /// Model names, or parts of them, which get the shim
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Optional second model for replies which look like they call a tool, but have no call
+    /// which can be parsed from them directly
+    #[serde(default)]
+    pub interpreter: Option<InterpreterConfig>,
+}
+
+impl ToolShimConfig {
+    pub fn from_config() -> Self {
+        Config::global().get("GOOSE_TOOLSHIM").unwrap_or_default()
+    }
+
+    pub fn applies_to(&self, model_name: &str) -> bool {
+        self.models
+            .iter()
+            .any(|model| model_name.contains(model.as_str()))
+    }
+}
+
+/// Emulates tool calling for models without native support
+///
+/// The tools are described in the system prompt and the model is asked to reply with a JSON
+/// object to call one. Those objects are parsed out of the reply and turned into tool requests,
+/// and earlier tool requests and responses in the conversation are sent back as plain text.
+/// Enabled per model, for example
+///
+/// ```yaml
+/// GOOSE_TOOLSHIM:
+///   models:
+///     - deepseek-r1
+///     - llama3.2-vision
+///   interpreter:
+///     provider: ollama
+///     model: mistral-nemo
+/// ```
+pub struct ToolShimProvider {
+    inner: Box<dyn Provider + Send + Sync>,
+    interpreter: Option<Box<dyn Provider + Send + Sync>>,
+}
+
+impl ToolShimProvider {
+    pub fn new(
+        inner: Box<dyn Provider + Send + Sync>,
+        interpreter: Option<Box<dyn Provider + Send + Sync>>,
+    ) -> Self {
+        Self { inner, interpreter }
+    }
+
+    /// Wrap the provider if GOOSE_TOOLSHIM enables the shim for its model
+    pub fn wrap_if_enabled(
+        provider: Box<dyn Provider + Send + Sync>,
+    ) -> Result<Box<dyn Provider + Send + Sync>> {
+        let config = ToolShimConfig::from_config();
+        if !config.applies_to(&provider.get_model_config().model_name) {
+            return Ok(provider);
+        }
+
+        // The interpreter isn't shimmed itself, which would recurse if its model were listed too
+        let interpreter = match config.interpreter {
+            Some(interpreter) => Some(super::factory::create_provider(
+                &interpreter.provider,
+                ModelConfig::new(interpreter.model),
+            )?),
+            None => None,
+        };
+        Ok(Box::new(Self::new(provider, interpreter)))
+    }
+
+    /// Ask the interpreter model to find the tool calls in a reply, along with its usage
+    async fn interpret(
+        &self,
+        text: &str,
+        tools: &[Tool],
+    ) -> (Vec<ToolCall>, Option<ProviderUsage>) {
+        let Some(interpreter) = &self.interpreter else {
+            return (Vec::new(), None);
+        };
+
+        let system = formatdoc! {r#"
+            You extract tool calls from text written by another assistant. The available tools are:
+
+            {tools}
+
+            Reply with only a JSON array of the calls the text asks for, each like
+            {{"name": "tool_name", "arguments": {{...}}}}. Reply with [] if it asks for none.
+        "#, tools = describe_tools(tools)};
+        match interpreter
+            .complete(&system, &[Message::user().with_text(text)], &[])
+            .await
+        {
+            Ok((message, usage)) => (parse_tool_calls(&message.as_concat_text()).1, Some(usage)),
+            Err(e) => {
+                tracing::warn!("The tool call interpreter failed: {}", e);
+                (Vec::new(), None)
+            }
+        }
+    }
+}
+
+fn describe_tools(tools: &[Tool]) -> String {
+    tools
+        .iter()
+        .map(|tool| {
+            format!(
+                "- {}: {}\n  arguments schema: {}",
+                tool.name, tool.description, tool.input_schema
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The system prompt with instructions for calling tools through JSON in the reply
+fn shim_system_prompt(system: &str, tools: &[Tool]) -> String {
+    formatdoc! {r#"
+        {system}
+
+        # Tools
+
+        You can use these tools:
+
+        {tools}
+
+        To use a tool, reply with a JSON object in a fenced code block and nothing after it:
+
+        ```json
+        {{"name": "tool_name", "arguments": {{"argument": "value"}}}}
+        ```
+
+        The result will be sent back to you in the next message. Answer normally, without JSON,
+        when no tool is needed.
+    "#, tools = describe_tools(tools)}
+}
+
+/// Fenced code blocks, capturing their contents
+fn fenced_blocks() -> &'static Regex {
+    static FENCED: OnceLock<Regex> = OnceLock::new();
+    FENCED.get_or_init(|| Regex::new(r"(?s)```(?:json)?\s*\n?(.*?)```").unwrap())
+}
+
+/// Split a reply into its text and the tool calls written as JSON in it
+///
+/// Calls are looked for in fenced code blocks, and then in the whole reply if it is only JSON.
+/// An object with a `name` is a call, with its `arguments` or `parameters`, and arrays of them
+/// are several calls.
+pub fn parse_tool_calls(text: &str) -> (String, Vec<ToolCall>) {
+    let mut calls = Vec::new();
+    let mut remaining = text.to_string();
+    for block in fenced_blocks().captures_iter(text) {
+        let found = serde_json::from_str(block[1].trim())
+            .map(|value| calls_from_value(&value))
+            .unwrap_or_default();
+        if !found.is_empty() {
+            calls.extend(found);
+            remaining = remaining.replacen(&block[0], "", 1);
+        }
+    }
+
+    if calls.is_empty() {
+        if let Ok(value) = serde_json::from_str::<Value>(text.trim()) {
+            calls = calls_from_value(&value);
+            if !calls.is_empty() {
+                remaining.clear();
+            }
+        }
+    }
+
+    (remaining.trim().to_string(), calls)
+}
+
+/// Whether a reply with no calls parsed from it still looks like it tries to make one, with
+/// JSON which isn't a call or by naming one of the tools
+///
+/// Only these replies are worth a call to the interpreter, since most replies without calls
+/// are answers.
+fn looks_like_call(text: &str, tools: &[Tool]) -> bool {
+    let trimmed = text.trim_start();
+    let has_json = trimmed.starts_with('{')
+        || trimmed.starts_with('[')
+        || fenced_blocks()
+            .captures_iter(text)
+            .any(|block| block[1].trim_start().starts_with(['{', '[']));
+    // Models often leave out the extension prefix, e.g. "shell" for "developer__shell"
+    let names_tool = tools.iter().any(|tool| {
+        let short_name = tool.name.rsplit("__").next().unwrap_or(&tool.name);
+        text.contains(tool.name.as_str()) || text.contains(short_name)
+    });
+    has_json || names_tool
+}
+
+/// Add the interpreter's usage to the reply's
+///
+/// The total is reported under the main model, so each is priced against its own model first.
+fn add_usage(usage: ProviderUsage, interpreter: ProviderUsage) -> ProviderUsage {
+    let registry = pricing::registry();
+    let cost = sum_optional(
+        usage.cost.or_else(|| registry.cost(&usage)),
+        interpreter.cost.or_else(|| registry.cost(&interpreter)),
+    );
+    let tokens = Usage {
+        input_tokens: sum_optional(usage.usage.input_tokens, interpreter.usage.input_tokens),
+        output_tokens: sum_optional(usage.usage.output_tokens, interpreter.usage.output_tokens),
+        total_tokens: sum_optional(usage.usage.total_tokens, interpreter.usage.total_tokens),
+        ..usage.usage.clone()
+    };
+    ProviderUsage {
+        usage: tokens,
+        cost,
+        ..usage
+    }
+}
+
+fn sum_optional<T: std::ops::Add<Output = T> + Default>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (None, None) => None,
+        (a, b) => Some(a.unwrap_or_default() + b.unwrap_or_default()),
+    }
+}
+
+fn calls_from_value(value: &Value) -> Vec<ToolCall> {
+    match value {
+        Value::Array(items) => items.iter().flat_map(calls_from_value).collect(),
+        Value::Object(object) => match object.get("name").and_then(|name| name.as_str()) {
+            Some(name) => {
+                let arguments = object
+                    .get("arguments")
+                    .or_else(|| object.get("parameters"))
+                    .cloned()
+                    .unwrap_or_else(|| Value::Object(Default::default()));
+                vec![ToolCall::new(name, arguments)]
+            }
+            None => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+#[async_trait]
+impl Provider for ToolShimProvider {
+    fn metadata() -> ProviderMetadata {
+        ProviderMetadata::empty()
+    }
+
+    async fn complete(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        if tools.is_empty() {
+            return self.inner.complete(system, messages, tools).await;
+        }
+
+        let system = shim_system_prompt(system, tools);
+        let (message, mut usage) = self
+            .inner
+            .complete(&system, &tool_messages_as_text(messages), &[])
+            .await?;
+
+        let text = message.as_concat_text();
+        let (remaining, mut calls) = parse_tool_calls(&text);
+        let remaining = if calls.is_empty() && looks_like_call(&text, tools) {
+            let (interpreted, interpreter_usage) = self.interpret(&text, tools).await;
+            calls = interpreted;
+            if let Some(interpreter_usage) = interpreter_usage {
+                usage = add_usage(usage, interpreter_usage);
+            }
+            text
+        } else {
+            remaining
+        };
+        if calls.is_empty() {
+            return Ok((message, usage));
+        }
+
+        // Keep any reasoning, but replace the text with what's left around the calls
+        let mut content: Vec<MessageContent> = message
+            .content
+            .into_iter()
+            .filter(|content| !matches!(content, MessageContent::Text(_)))
+            .collect();
+        if !remaining.is_empty() {
+            content.push(MessageContent::text(remaining));
+        }
+        for call in calls {
+            let id = format!("toolshim_{}", nanoid::nanoid!(12));
+            let tool_call = if tools.iter().any(|tool| tool.name == call.name) {
+                Ok(call)
+            } else {
+                Err(ToolError::NotFound(format!(
+                    "There is no tool named {}",
+                    call.name
+                )))
+            };
+            content.push(MessageContent::tool_request(id, tool_call));
+        }
+
+        Ok((
+            Message {
+                content,
+                ..Message::assistant()
+            },
+            usage,
+        ))
+    }
+
+    /// Streams straight from the wrapped model when there are no tools, otherwise the whole
+    /// reply is needed to find the calls in it
+    async fn stream(
+        &self,
+        system: &str,
+        messages: &[Message],
+        tools: &[Tool],
+    ) -> Result<CompletionStream, ProviderError> {
+        if tools.is_empty() {
+            return self.inner.stream(system, messages, tools).await;
+        }
+        let (message, usage) = self.complete(system, messages, tools).await?;
+        Ok(Box::pin(futures::stream::once(async move {
+            Ok(CompletionChunk::Done(message, usage))
+        })))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        self.inner.list_models().await
+    }
+
+    /// Structured output calls no tools, so it needs no shim
+    async fn complete_structured(
+        &self,
+        system: &str,
+        messages: &[Message],
+        schema: &Value,
+    ) -> Result<(Message, ProviderUsage), ProviderError> {
+        self.inner
+            .complete_structured(system, messages, schema)
+            .await
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Embeddings, ProviderError> {
+        self.inner.embed(texts).await
+    }
+
+    /// The wrapped model's config, which can now call tools
+    fn get_model_config(&self) -> ModelConfig {
+        let config = self.inner.get_model_config();
+        let mut capabilities = config.capabilities.clone();
+        capabilities.tool_calling = true;
+        config.with_capabilities(capabilities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::mock::MockProvider;
+    use serde_json::json;
+
+    fn shell_tool() -> Tool {
+        Tool::new(
+            "developer__shell",
+            "Run a shell command",
+            json!({"type": "object", "properties": {"command": {"type": "string"}}}),
+        )
+    }
+
+    #[test]
+    fn test_parse_tool_calls() {
+        let (text, calls) = parse_tool_calls(
+            "Let me look.\n```json\n{\"name\": \"developer__shell\", \"arguments\": {\"command\": \"ls\"}}\n```",
+        );
+        assert_eq!(text, "Let me look.");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].name, "developer__shell");
+        assert_eq!(calls[0].arguments, json!({"command": "ls"}));
+
+        // A bare array of calls, using "parameters"
+        let (text, calls) =
+            parse_tool_calls(r#"[{"name": "a", "parameters": {"x": 1}}, {"name": "b"}]"#);
+        assert!(text.is_empty());
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[1].arguments, json!({}));
+
+        // Code which isn't a call is left alone
+        let reply = "Here you go:\n```json\n{\"status\": \"ok\"}\n```";
+        let (text, calls) = parse_tool_calls(reply);
+        assert_eq!(text, reply);
+        assert!(calls.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_emulates_tool_calls() -> Result<()> {
+        let inner = MockProvider::new("deepseek-r1").with_text(
+            "```json\n{\"name\": \"developer__shell\", \"arguments\": {\"command\": \"ls\"}}\n```",
+        );
+        let requests = inner.requests();
+        let provider = ToolShimProvider::new(Box::new(inner), None);
+        assert!(provider.get_model_config().capabilities.tool_calling);
+
+        let history = vec![
+            Message::user().with_text("What's here?"),
+            Message::assistant().with_tool_request(
+                "1",
+                Ok(ToolCall::new("developer__shell", json!({"command": "pwd"}))),
+            ),
+            Message::user().with_tool_response("1", Ok(vec![mcp_core::Content::text("/tmp")])),
+        ];
+        let (message, _) = provider
+            .complete("You are goose", &history, &[shell_tool()])
+            .await?;
+
+        let request = message.content[0].as_tool_request().unwrap();
+        let call = request.tool_call.as_ref().unwrap();
+        assert_eq!(call.name, "developer__shell");
+        assert_eq!(call.arguments, json!({"command": "ls"}));
+
+        // The model was sent the tools in its prompt and the history as text
+        let request = requests.get(0).unwrap();
+        assert!(request.tools.is_empty());
+        assert!(request
+            .system
+            .contains("developer__shell: Run a shell command"));
+        let messages = &request.messages;
+        assert!(messages[1].content[0]
+            .as_text()
+            .unwrap()
+            .contains("\"pwd\""));
+        assert_eq!(messages[2].content[0].as_text(), Some("Tool result:\n/tmp"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_emulates_tool_calls() -> Result<()> {
+        use futures::StreamExt;
+
+        let inner = MockProvider::new("deepseek-r1")
+            .with_text("{\"name\": \"developer__shell\", \"arguments\": {\"command\": \"ls\"}}")
+            .with_text("Hello");
+        let provider = ToolShimProvider::new(Box::new(inner), None);
+        let messages = [Message::user().with_text("What's here?")];
+
+        let chunks: Vec<_> = provider
+            .stream("", &messages, &[shell_tool()])
+            .await?
+            .collect()
+            .await;
+        let Some(Ok(CompletionChunk::Done(message, _))) = chunks.last() else {
+            panic!("the stream did not finish with a message");
+        };
+        assert!(message.is_tool_call());
+
+        // Without tools the wrapped model's stream is used as is
+        let chunks: Vec<_> = provider.stream("", &messages, &[]).await?.collect().await;
+        let Some(Ok(CompletionChunk::Done(message, _))) = chunks.last() else {
+            panic!("the stream did not finish with a message");
+        };
+        assert_eq!(message.as_concat_text(), "Hello");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_interpreter_and_unknown_tools() -> Result<()> {
+        let inner = MockProvider::new("deepseek-r1")
+            .with_reply(
+                Message::assistant().with_text("I'll run ls with the shell tool."),
+                Usage::new(Some(100), Some(10), Some(110)),
+            )
+            .with_text("Sure, here is a haiku.");
+        let interpreter = MockProvider::new("llama3.2").with_reply(
+            Message::assistant().with_text(
+                r#"[{"name": "developer__shell", "arguments": {"command": "ls"}}, {"name": "rm_rf"}]"#,
+            ),
+            Usage::new(Some(20), Some(5), Some(25)),
+        );
+        let interpreter_requests = interpreter.requests();
+        let provider = ToolShimProvider::new(Box::new(inner), Some(Box::new(interpreter)));
+
+        let messages = [Message::user().with_text("What's here?")];
+        let (message, usage) = provider.complete("", &messages, &[shell_tool()]).await?;
+        assert_eq!(
+            message.content[0].as_text(),
+            Some("I'll run ls with the shell tool.")
+        );
+        assert!(message.content[1]
+            .as_tool_request()
+            .unwrap()
+            .tool_call
+            .is_ok());
+        assert!(matches!(
+            message.content[2].as_tool_request().unwrap().tool_call,
+            Err(ToolError::NotFound(_))
+        ));
+        assert_eq!(
+            interpreter_requests.get(0).unwrap().messages[0].as_concat_text(),
+            "I'll run ls with the shell tool."
+        );
+        // The interpreter's tokens count towards the reply
+        assert_eq!(usage.model, "deepseek-r1");
+        assert_eq!(usage.usage.input_tokens, Some(120));
+        assert_eq!(usage.usage.total_tokens, Some(135));
+
+        // Answers which don't look like calls are returned as they are, without interpreting
+        let (message, _) = provider.complete("", &messages, &[shell_tool()]).await?;
+        assert_eq!(message.as_concat_text(), "Sure, here is a haiku.");
+        assert!(!message.is_tool_call());
+        assert_eq!(interpreter_requests.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_looks_like_call() {
+        let tools = [shell_tool()];
+        assert!(looks_like_call("{\"tool\": \"developer__shell\"", &tools));
+        assert!(looks_like_call(
+            "Running:\n```json\n{\"cmd\": \"ls\"}\n```",
+            &tools
+        ));
+        assert!(looks_like_call("I'll use shell to list the files.", &tools));
+        assert!(!looks_like_call("The answer is 42.", &tools));
+        assert!(!looks_like_call("```rust\nfn main() {}\n```", &tools));
+    }
+
+    #[test]
+    fn test_applies_to() {
+        let config = ToolShimConfig {
+            models: vec!["deepseek-r1".to_string()],
+            interpreter: None,
+        };
+        assert!(config.applies_to("deepseek-r1:14b"));
+        assert!(!config.applies_to("llama3.2"));
+        assert!(!ToolShimConfig::default().applies_to("deepseek-r1"));
+    }
+}
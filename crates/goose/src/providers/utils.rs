@@ -3,14 +3,18 @@ use super::errors::GoogleErrorCode;
 use anyhow::Result;
 use base64::Engine;
 use regex::Regex;
+use reqwest::header::HeaderMap;
 use reqwest::{Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
 use std::io::Read;
 use std::path::Path;
+use std::time::Duration;
 
+use crate::message::{Message, MessageContent};
 use crate::providers::errors::ProviderError;
 use mcp_core::content::ImageContent;
+use mcp_core::role::Role;
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum ImageFormat {
@@ -38,11 +42,35 @@ pub fn convert_image(image: &ImageContent, image_format: &ImageFormat) -> Value
     }
 }
 
+/// Parse how long a rate limited request should wait before retrying
+///
+/// Supports `retry-after-ms` (OpenAI and Azure) as well as the standard `retry-after` header,
+/// which can be either a number of seconds or an HTTP date. Values which aren't a finite
+/// duration are ignored.
+pub fn get_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name)?.to_str().ok().map(str::trim);
+    let seconds = |secs: f64| Duration::try_from_secs_f64(secs.max(0.0)).ok();
+
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return seconds(ms / 1000.0);
+    }
+
+    let value = header("retry-after")?;
+    if let Ok(secs) = value.parse::<f64>() {
+        return seconds(secs);
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.signed_duration_since(chrono::Utc::now());
+    Some(delay.to_std().unwrap_or(Duration::ZERO))
+}
+
 /// Handle response from OpenAI compatible endpoints
 /// Error codes: https://platform.openai.com/docs/guides/error-codes
 /// Context window exceeded: https://community.openai.com/t/help-needed-tackling-context-length-limits-in-openai-models/617543
 pub async fn handle_response_openai_compat(response: Response) -> Result<Value, ProviderError> {
     let status = response.status();
+    let retry_delay = get_retry_after(response.headers());
     // Try to parse the response body as JSON (if applicable)
     let payload = match response.json::<Value>().await {
         Ok(json) => json,
@@ -80,7 +108,10 @@ pub async fn handle_response_openai_compat(response: Response) -> Result<Value,
             Err(ProviderError::RequestFailed(format!("{:?}", payload)))
         }
         StatusCode::TOO_MANY_REQUESTS => {
-            Err(ProviderError::RateLimitExceeded(format!("{:?}", payload)))
+            Err(ProviderError::RateLimitExceeded {
+                details: format!("{:?}", payload),
+                retry_delay,
+            })
         }
         StatusCode::INTERNAL_SERVER_ERROR | StatusCode::SERVICE_UNAVAILABLE => {
             Err(ProviderError::ServerError(format!("{:?}", payload)))
@@ -145,6 +176,7 @@ fn get_google_final_status(status: StatusCode, payload: Option<&Value>) -> Statu
 /// - `Err(ProviderError)`: Describes the failure reason.
 pub async fn handle_response_google_compat(response: Response) -> Result<Value, ProviderError> {
     let status = response.status();
+    let retry_delay = get_retry_after(response.headers());
     let payload: Option<Value> = response.json().await.ok();
     let final_status = get_google_final_status(status, payload.as_ref());
 
@@ -171,7 +203,10 @@ pub async fn handle_response_google_compat(response: Response) -> Result<Value,
             Err(ProviderError::RequestFailed(format!("Request failed with status: {}. Message: {}", final_status, error_msg)))
         }
         StatusCode::TOO_MANY_REQUESTS => {
-            Err(ProviderError::RateLimitExceeded(format!("{:?}", payload)))
+            Err(ProviderError::RateLimitExceeded {
+                details: format!("{:?}", payload),
+                retry_delay,
+            })
         }
         StatusCode::INTERNAL_SERVER_ERROR | StatusCode::SERVICE_UNAVAILABLE => {
             Err(ProviderError::ServerError(format!("{:?}", payload)))
@@ -185,6 +220,63 @@ pub async fn handle_response_google_compat(response: Response) -> Result<Value,
     }
 }
 
+/// The embedding model to use, GOOSE_EMBEDDING_MODEL or else the provider's default
+pub fn embedding_model(default: &str) -> String {
+    crate::config::Config::global()
+        .get("GOOSE_EMBEDDING_MODEL")
+        .unwrap_or_else(|_| default.to_string())
+}
+
+/// Embed texts in batches of at most `batch_size`, as embedding APIs limit how many inputs one
+/// request may carry, adding up the usage of every batch
+pub async fn embed_in_batches<F, Fut>(
+    texts: &[String],
+    batch_size: usize,
+    mut embed_batch: F,
+) -> Result<(Vec<Vec<f32>>, Usage), ProviderError>
+where
+    F: FnMut(Vec<String>) -> Fut,
+    Fut: std::future::Future<Output = Result<(Vec<Vec<f32>>, Usage), ProviderError>>,
+{
+    let mut vectors = Vec::with_capacity(texts.len());
+    let mut usage = Usage::default();
+    for batch in texts.chunks(batch_size.max(1)) {
+        let (batch_vectors, batch_usage) = embed_batch(batch.to_vec()).await?;
+        if batch_vectors.len() != batch.len() {
+            return Err(ProviderError::RequestFailed(format!(
+                "Expected {} embeddings but got {}",
+                batch.len(),
+                batch_vectors.len()
+            )));
+        }
+        vectors.extend(batch_vectors);
+        usage.input_tokens = add_tokens(usage.input_tokens, batch_usage.input_tokens);
+        usage.total_tokens = add_tokens(usage.total_tokens, batch_usage.total_tokens);
+    }
+    Ok((vectors, usage))
+}
+
+fn add_tokens(a: Option<i32>, b: Option<i32>) -> Option<i32> {
+    match (a, b) {
+        (None, None) => None,
+        (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+    }
+}
+
+/// Read a JSON array of numbers as an embedding
+pub fn get_embedding(value: &Value) -> Result<Vec<f32>, ProviderError> {
+    value
+        .as_array()
+        .ok_or_else(|| ProviderError::RequestFailed("Embedding is not an array".to_string()))?
+        .iter()
+        .map(|v| {
+            v.as_f64().map(|v| v as f32).ok_or_else(|| {
+                ProviderError::RequestFailed("Embedding contains a non-number".to_string())
+            })
+        })
+        .collect()
+}
+
 pub fn sanitize_function_name(name: &str) -> String {
     let re = Regex::new(r"[^a-zA-Z0-9_-]").unwrap();
     re.replace_all(name, "_").to_string()
@@ -208,6 +300,27 @@ pub fn get_model(data: &Value) -> String {
     }
 }
 
+/// Extract the sorted model names from a model listing, e.g. `{"data": [{"id": "gpt-4o"}]}`
+pub fn get_model_names(
+    data: &Value,
+    list_key: &str,
+    name_key: &str,
+) -> Result<Vec<String>, ProviderError> {
+    let entries = data
+        .get(list_key)
+        .and_then(|list| list.as_array())
+        .ok_or_else(|| {
+            ProviderError::RequestFailed(format!("Model listing has no `{}` array", list_key))
+        })?;
+    let mut names: Vec<String> = entries
+        .iter()
+        .filter_map(|entry| entry.get(name_key).and_then(|name| name.as_str()))
+        .map(|name| name.to_string())
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
 /// Check if a file is actually an image by examining its magic bytes
 fn is_image_file(path: &Path) -> bool {
     if let Ok(mut file) = std::fs::File::open(path) {
@@ -344,11 +457,135 @@ pub fn emit_debug_trace<T: serde::Serialize>(
     );
 }
 
+/// Rewrite tool requests and responses as text, for a model which doesn't accept them
+pub fn tool_messages_as_text(messages: &[Message]) -> Vec<Message> {
+    messages
+        .iter()
+        .map(|message| {
+            let content = message
+                .content
+                .iter()
+                .filter_map(|content| match content {
+                    MessageContent::ToolRequest(request) => {
+                        let call = request.tool_call.as_ref().ok()?;
+                        let json = json!({"name": call.name, "arguments": call.arguments});
+                        Some(MessageContent::text(format!("```json\n{}\n```", json)))
+                    }
+                    MessageContent::ToolResponse(response) => {
+                        Some(MessageContent::text(match &response.tool_result {
+                            Ok(_) => format!(
+                                "Tool result:\n{}",
+                                content.as_tool_response_text().unwrap_or_default()
+                            ),
+                            Err(e) => format!("The tool call failed: {}", e),
+                        }))
+                    }
+                    content => Some(content.clone()),
+                })
+                .collect();
+            Message {
+                content,
+                ..message.clone()
+            }
+        })
+        .collect()
+}
+
+/// Move the system prompt to the start of the first user message, for models which don't
+/// accept a separate one
+///
+/// It is joined to the message's first text rather than added alongside it, since some formats
+/// only send one text per message
+pub fn system_prompt_as_user_text(system: &str, messages: &[Message]) -> Vec<Message> {
+    let mut messages = messages.to_vec();
+    let Some(first) = messages.iter_mut().find(|m| m.role == Role::User) else {
+        messages.insert(0, Message::user().with_text(system));
+        return messages;
+    };
+    match first.content.iter_mut().find_map(|c| match c {
+        MessageContent::Text(text) => Some(text),
+        _ => None,
+    }) {
+        Some(text) => text.text = format!("{}\n\n{}", system, text.text),
+        None => first.content.insert(0, MessageContent::text(system)),
+    }
+    messages
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use serde_json::json;
 
+    #[tokio::test]
+    async fn test_embed_in_batches() {
+        let texts: Vec<String> = ["a", "b", "c"].iter().map(|t| t.to_string()).collect();
+        let mut batches = Vec::new();
+        let (vectors, usage) = embed_in_batches(&texts, 2, |batch| {
+            batches.push(batch.clone());
+            let vectors = batch.iter().map(|text| vec![text.len() as f32]).collect();
+            async move { Ok((vectors, Usage::new(Some(2), None, Some(2)))) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(batches, vec![vec!["a", "b"], vec!["c"]]);
+        assert_eq!(vectors.len(), 3);
+        assert_eq!(usage.input_tokens, Some(4));
+
+        // A batch answered with the wrong number of vectors is an error rather than misaligned
+        let result = embed_in_batches(&texts, 3, |_| async { Ok((vec![], Usage::default())) });
+        assert!(result.await.is_err());
+    }
+
+    #[test]
+    fn test_get_model_names() {
+        let listing = json!({
+            "object": "list",
+            "data": [{"id": "gpt-4o-mini"}, {"id": "gpt-4o"}, {"object": "model"}]
+        });
+        assert_eq!(
+            get_model_names(&listing, "data", "id").unwrap(),
+            vec!["gpt-4o", "gpt-4o-mini"]
+        );
+        assert!(get_model_names(&listing, "models", "name").is_err());
+    }
+
+    #[test]
+    fn test_get_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(get_retry_after(&headers), None);
+
+        headers.insert("retry-after", "7".parse().unwrap());
+        assert_eq!(get_retry_after(&headers), Some(Duration::from_secs(7)));
+
+        // The millisecond variant is more precise so it takes priority
+        headers.insert("retry-after-ms", "1500".parse().unwrap());
+        assert_eq!(get_retry_after(&headers), Some(Duration::from_millis(1500)));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "retry-after",
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(get_retry_after(&headers), Some(Duration::ZERO));
+
+        let later = (chrono::Utc::now() + chrono::Duration::seconds(120)).to_rfc2822();
+        headers.insert("retry-after", later.parse().unwrap());
+        let delay = get_retry_after(&headers).unwrap();
+        assert!(delay > Duration::from_secs(110) && delay <= Duration::from_secs(120));
+
+        headers.insert("retry-after", "soon".parse().unwrap());
+        assert_eq!(get_retry_after(&headers), None);
+
+        // Numbers which don't fit in a Duration are ignored rather than panicking
+        for value in ["inf", "1e30", "NaN"] {
+            headers.insert("retry-after", value.parse().unwrap());
+            assert!(get_retry_after(&headers).is_none_or(|delay| delay == Duration::ZERO));
+        }
+        headers.insert("retry-after-ms", "inf".parse().unwrap());
+        assert_eq!(get_retry_after(&headers), None);
+    }
+
     #[test]
     fn test_detect_image_path() {
         // Create a temporary PNG file with valid PNG magic numbers
@@ -1,115 +1,220 @@
+use base64::Engine;
+use etcetera::{choose_app_strategy, AppStrategy};
 use include_dir::{include_dir, Dir};
 use mcp_core::Tool;
 use std::error::Error;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokenizers::tokenizer::Tokenizer;
+use tracing::{info, warn};
 
-use crate::message::Message;
+use crate::config::Config;
+use crate::message::{Message, MessageContent};
+use crate::model::GPT_4O_TOKENIZER;
 
 // The embedded directory with all possible tokenizer files.
-// If one of them doesn’t exist, we’ll download it at startup.
+// Tokenizers which aren't embedded are looked up on disk, and downloaded if allowed.
 static TOKENIZER_FILES: Dir = include_dir!("$CARGO_MANIFEST_DIR/../../tokenizer_files");
 
-/// The `TokenCounter` now stores exactly one `Tokenizer`.
+/// A rough average for English text and code, used when no tokenizer can be loaded
+const CHARS_PER_TOKEN: usize = 4;
+/// Images are scaled down to fit this many pixels on their long edge before being tokenized
+const MAX_IMAGE_EDGE: f64 = 1568.0;
+const PIXELS_PER_IMAGE_TOKEN: f64 = 750.0;
+/// The count for an image whose size can't be read, which is what the largest images cost
+const DEFAULT_IMAGE_TOKENS: usize = 1600;
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The `TokenCounter` stores at most one `Tokenizer`, and estimates from the length of the
+/// text when it has none.
 pub struct TokenCounter {
-    tokenizer: Tokenizer,
+    tokenizer: Option<Tokenizer>,
 }
 
 impl TokenCounter {
-    /// Creates a new `TokenCounter` using the given HuggingFace tokenizer name.
+    /// Creates a new `TokenCounter` using the given HuggingFace tokenizer name. If it can't be
+    /// loaded, the embedded GPT-4o tokenizer is used instead, and the heuristic counter as a last
+    /// resort.
     ///
     /// * `tokenizer_name` might look like "Xenova--gpt-4o"
     ///   or "Qwen--Qwen2.5-Coder-32B-Instruct", etc.
     pub fn new(tokenizer_name: &str) -> Self {
-        match Self::load_from_embedded(tokenizer_name) {
-            Ok(tokenizer) => Self { tokenizer },
-            Err(e) => {
-                println!(
-                    "Tokenizer '{}' not found in embedded dir: {}",
-                    tokenizer_name, e
+        let error = match Self::try_new(tokenizer_name) {
+            Ok(counter) => return counter,
+            Err(e) => e,
+        };
+        match Self::load_from_embedded(GPT_4O_TOKENIZER) {
+            Some(Ok(tokenizer)) => {
+                info!(
+                    "Tokenizer '{}' is not available, counting with {}: {}",
+                    tokenizer_name, GPT_4O_TOKENIZER, error
                 );
-                println!("Attempting to download tokenizer and load...");
-                // Fallback to download tokenizer and load from disk
-                match Self::download_and_load(tokenizer_name) {
-                    Ok(counter) => counter,
-                    Err(e) => panic!("Failed to initialize tokenizer: {}", e),
+                Self {
+                    tokenizer: Some(tokenizer),
                 }
             }
+            _ => {
+                warn!(
+                    "Failed to load tokenizer '{}', token counts will be estimated: {}",
+                    tokenizer_name, error
+                );
+                Self::heuristic()
+            }
         }
     }
 
-    /// Load tokenizer bytes from the embedded directory (via `include_dir!`).
-    fn load_from_embedded(tokenizer_name: &str) -> Result<Tokenizer, Box<dyn Error>> {
-        let tokenizer_file_path = format!("{}/tokenizer.json", tokenizer_name);
-        let file = TOKENIZER_FILES
-            .get_file(&tokenizer_file_path)
-            .ok_or_else(|| {
-                format!(
-                    "Tokenizer file not found in embedded: {}",
-                    tokenizer_file_path
-                )
-            })?;
-        let contents = file.contents();
-        let tokenizer = Tokenizer::from_bytes(contents)
-            .map_err(|e| format!("Failed to parse tokenizer bytes: {}", e))?;
-        Ok(tokenizer)
+    /// Creates a new `TokenCounter`, looking for the tokenizer in this order:
+    ///
+    /// 1. The tokenizers embedded in the binary
+    /// 2. GOOSE_TOKENIZER_DIR, if set, as `<dir>/<tokenizer_name>/tokenizer.json`
+    /// 3. The goose cache directory, where downloaded tokenizers are kept
+    ///
+    /// If it isn't found and GOOSE_TOKENIZER_DOWNLOAD is true, it is downloaded from Hugging
+    /// Face into the cache directory in the background, for the next counter to use.
+    pub fn try_new(tokenizer_name: &str) -> Result<Self, Box<dyn Error>> {
+        let config = Config::global();
+        let mut dirs = Vec::new();
+        if let Ok(dir) = config.get::<String>("GOOSE_TOKENIZER_DIR") {
+            dirs.push(PathBuf::from(dir));
+        }
+        let cache_dir = choose_app_strategy(crate::config::APP_STRATEGY.clone())
+            .map(|strategy| strategy.in_cache_dir("tokenizers"))
+            .ok();
+        dirs.extend(cache_dir.clone());
+
+        let error = match Self::load(tokenizer_name, &dirs) {
+            Ok(tokenizer) => {
+                return Ok(Self {
+                    tokenizer: Some(tokenizer),
+                })
+            }
+            Err(e) => e,
+        };
+        let download = config
+            .get::<bool>("GOOSE_TOKENIZER_DOWNLOAD")
+            .unwrap_or(false);
+        if let Some(cache_dir) = cache_dir.filter(|_| download) {
+            Self::download_in_background(tokenizer_name, cache_dir.join(tokenizer_name));
+        }
+        Err(error)
+    }
+
+    /// A counter which estimates from the length of the text, for when there is no tokenizer
+    pub fn heuristic() -> Self {
+        Self { tokenizer: None }
+    }
+
+    /// Whether counts are estimated rather than coming from a tokenizer
+    pub fn is_heuristic(&self) -> bool {
+        self.tokenizer.is_none()
     }
 
-    /// Fallback: If not found in embedded, we look in `base_dir` on disk.
-    /// If not on disk, we download from Hugging Face, then load from disk.
-    fn download_and_load(tokenizer_name: &str) -> Result<Self, Box<dyn Error>> {
-        let local_dir = std::env::temp_dir().join(tokenizer_name);
-        let local_json_path = local_dir.join("tokenizer.json");
-
-        // If the file doesn't already exist, we download from HF
-        if !Path::new(&local_json_path).exists() {
-            eprintln!("Tokenizer file not on disk, downloading…");
-            let repo_id = tokenizer_name.replace("--", "/");
-            // e.g. "Xenova--llama3-tokenizer" -> "Xenova/llama3-tokenizer"
-            Self::download_tokenizer(&repo_id, &local_dir)?;
+    /// Load the tokenizer from the embedded files or from `dirs`
+    fn load(tokenizer_name: &str, dirs: &[PathBuf]) -> Result<Tokenizer, Box<dyn Error>> {
+        if let Some(tokenizer) = Self::load_from_embedded(tokenizer_name) {
+            return tokenizer;
         }
+        for dir in dirs {
+            let path = dir.join(tokenizer_name).join("tokenizer.json");
+            if path.exists() {
+                return Self::load_from_file(&path);
+            }
+        }
+        Err(format!(
+            "Tokenizer '{}' is not embedded or in {:?}",
+            tokenizer_name, dirs
+        )
+        .into())
+    }
+
+    /// Load tokenizer bytes from the embedded directory (via `include_dir!`), None if the
+    /// tokenizer isn't embedded.
+    fn load_from_embedded(tokenizer_name: &str) -> Option<Result<Tokenizer, Box<dyn Error>>> {
+        let tokenizer_file_path = format!("{}/tokenizer.json", tokenizer_name);
+        let file = TOKENIZER_FILES.get_file(&tokenizer_file_path)?;
+        Some(
+            Tokenizer::from_bytes(file.contents())
+                .map_err(|e| format!("Failed to parse tokenizer bytes: {}", e).into()),
+        )
+    }
 
-        // Load from disk
-        let file_content = fs::read(&local_json_path)?;
+    fn load_from_file(path: &Path) -> Result<Tokenizer, Box<dyn Error>> {
+        let file_content = fs::read(path)?;
         let tokenizer = Tokenizer::from_bytes(&file_content)
-            .map_err(|e| format!("Failed to parse tokenizer after download: {}", e))?;
+            .map_err(|e| format!("Failed to parse tokenizer {}: {}", path.display(), e))?;
+        Ok(tokenizer)
+    }
 
-        Ok(Self { tokenizer })
+    /// Download from Hugging Face into the local directory on a thread of its own, so creating
+    /// a counter never waits on the network
+    fn download_in_background(tokenizer_name: &str, download_dir: PathBuf) {
+        // e.g. "Xenova--llama3-tokenizer" -> "Xenova/llama3-tokenizer"
+        let repo_id = tokenizer_name.replace("--", "/");
+        std::thread::spawn(move || {
+            info!(
+                "Downloading tokenizer {} to {}",
+                repo_id,
+                download_dir.display()
+            );
+            if let Err(e) = Self::download_tokenizer(&repo_id, &download_dir) {
+                warn!("Failed to download tokenizer {}: {}", repo_id, e);
+            }
+        });
     }
 
-    /// Download from Hugging Face into the local directory if not already present.
-    /// Synchronous version using a blocking runtime for simplicity.
     fn download_tokenizer(repo_id: &str, download_dir: &Path) -> Result<(), Box<dyn Error>> {
-        fs::create_dir_all(download_dir)?;
-
         let file_url = format!(
             "https://huggingface.co/{}/resolve/main/tokenizer.json",
             repo_id
         );
-        let file_path = download_dir.join("tokenizer.json");
-
-        // Blocking for example: just spawn a short-lived runtime
         let content = tokio::runtime::Runtime::new()?.block_on(async {
-            let response = reqwest::get(&file_url).await?;
+            let client = reqwest::Client::builder()
+                .timeout(DOWNLOAD_TIMEOUT)
+                .build()?;
+            let response = client.get(&file_url).send().await?;
             if !response.status().is_success() {
                 let error_msg =
                     format!("Failed to download tokenizer: status {}", response.status());
                 return Err(Box::<dyn Error>::from(error_msg));
             }
-            let bytes = response.bytes().await?;
-            Ok(bytes)
+            Ok(response.bytes().await?)
         })?;
 
-        fs::write(&file_path, content)?;
+        // Write to a temporary file first, so a partial download is never loaded
+        fs::create_dir_all(download_dir)?;
+        let partial_path = download_dir.join("tokenizer.json.part");
+        fs::write(&partial_path, content)?;
+        fs::rename(partial_path, download_dir.join("tokenizer.json"))?;
 
         Ok(())
     }
 
     /// Count tokens for a piece of text using our single tokenizer.
     pub fn count_tokens(&self, text: &str) -> usize {
-        let encoding = self.tokenizer.encode(text, false).unwrap();
-        encoding.len()
+        match self
+            .tokenizer
+            .as_ref()
+            .map(|tokenizer| tokenizer.encode(text, false))
+        {
+            Some(Ok(encoding)) => encoding.len(),
+            _ => text.chars().count().div_ceil(CHARS_PER_TOKEN),
+        }
+    }
+
+    /// Count tokens for a base64 encoded image, from its size after it is scaled down to what
+    /// the providers accept
+    pub fn count_image_tokens(&self, data: &str, mime_type: &str) -> usize {
+        let dimensions = base64::prelude::BASE64_STANDARD
+            .decode(data)
+            .ok()
+            .and_then(|bytes| image_dimensions(&bytes, mime_type));
+        let Some((width, height)) = dimensions else {
+            return DEFAULT_IMAGE_TOKENS;
+        };
+        let (width, height) = (width as f64, height as f64);
+        let scale = (MAX_IMAGE_EDGE / width.max(height)).min(1.0);
+        ((width * scale) * (height * scale) / PIXELS_PER_IMAGE_TOKEN).ceil() as usize
     }
 
     pub fn count_tokens_for_tools(&self, tools: &[Tool]) -> usize {
@@ -185,6 +290,8 @@ impl TokenCounter {
                 // content can either be text response or tool request
                 if let Some(content_text) = content.as_text() {
                     num_tokens += self.count_tokens(content_text);
+                } else if let Some(thinking) = content.as_thinking() {
+                    num_tokens += self.count_tokens(thinking);
                 } else if let Some(tool_request) = content.as_tool_request() {
                     // TODO: count tokens for tool request
                     let tool_call = tool_request.tool_call.as_ref().unwrap();
@@ -193,10 +300,21 @@ impl TokenCounter {
                         tool_request.id, tool_call.name, tool_call.arguments
                     );
                     num_tokens += self.count_tokens(&text);
-                } else if let Some(tool_response_text) = content.as_tool_response_text() {
-                    num_tokens += self.count_tokens(&tool_response_text);
+                } else if let Some(tool_response) = content.as_tool_response() {
+                    if let Some(tool_response_text) = content.as_tool_response_text() {
+                        num_tokens += self.count_tokens(&tool_response_text);
+                    }
+                    if let Ok(contents) = &tool_response.tool_result {
+                        num_tokens += contents
+                            .iter()
+                            .filter_map(|content| content.as_image())
+                            .map(|(data, mime_type)| self.count_image_tokens(data, mime_type))
+                            .sum::<usize>();
+                    }
+                } else if let MessageContent::Image(image) = content {
+                    num_tokens += self.count_image_tokens(&image.data, &image.mime_type);
                 } else {
-                    // unsupported content type such as image - pass
+                    // unsupported content type - pass
                     continue;
                 }
             }
@@ -231,6 +349,37 @@ impl TokenCounter {
     }
 }
 
+/// Read the width and height from the header of a PNG, GIF or JPEG image
+fn image_dimensions(bytes: &[u8], mime_type: &str) -> Option<(u32, u32)> {
+    let be16 = |at: usize| Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+    let be32 = |at: usize| Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    let le16 = |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+
+    match mime_type {
+        // The IHDR chunk always comes first, just after the signature
+        "image/png" => Some((be32(16)?, be32(20)?)),
+        "image/gif" => Some((le16(6)?, le16(8)?)),
+        "image/jpeg" | "image/jpg" => {
+            // Walk the segments until a start of frame marker
+            let mut at = 2;
+            loop {
+                if *bytes.get(at)? != 0xFF {
+                    return None;
+                }
+                let marker = *bytes.get(at + 1)?;
+                let length = be16(at + 2)? as usize;
+                let is_frame =
+                    matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+                if is_frame {
+                    return Some((be16(at + 7)?, be16(at + 5)?));
+                }
+                at += 2 + length;
+            }
+        }
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -275,6 +424,7 @@ mod tests {
             Message {
                 role: Role::User,
                 created: 0,
+                pinned: false,
                 content: vec![MessageContent::text(
                     "What's the weather like in San Francisco?",
                 )],
@@ -282,6 +432,7 @@ mod tests {
             Message {
                 role: Role::Assistant,
                 created: 1,
+                pinned: false,
                 content: vec![MessageContent::text(
                     "Looks like it's 60 degrees Fahrenheit in San Francisco.",
                 )],
@@ -289,6 +440,7 @@ mod tests {
             Message {
                 role: Role::User,
                 created: 2,
+                pinned: false,
                 content: vec![MessageContent::text("How about New York?")],
             },
         ];
@@ -324,12 +476,73 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
-    fn test_panic_if_provided_tokenizer_doesnt_exist() {
-        // This should panic because the tokenizer doesn't exist
-        // in the embedded directory and the download fails
+    fn test_fallback_if_provided_tokenizer_doesnt_exist() {
+        // The tokenizer isn't embedded or on disk, and can't be downloaded
+        let error = TokenCounter::load("nonexistent-tokenizer", &[]).unwrap_err();
+        assert!(error.to_string().contains("is not embedded"));
+        assert!(!TokenCounter::new("nonexistent-tokenizer").is_heuristic());
+
+        let counter = TokenCounter::heuristic();
+        assert!(counter.is_heuristic());
+        assert_eq!(counter.count_tokens("Hello, how are you?"), 5);
+        assert_eq!(counter.count_tokens(""), 0);
+    }
+
+    #[test]
+    fn test_load_from_tokenizer_dir() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let tokenizer_dir = dir.path().join("local--tokenizer");
+        fs::create_dir_all(&tokenizer_dir)?;
+        let embedded = TOKENIZER_FILES
+            .get_file(format!("{}/tokenizer.json", GPT_4O_TOKENIZER))
+            .unwrap();
+        fs::write(tokenizer_dir.join("tokenizer.json"), embedded.contents())?;
+
+        let dirs = [dir.path().join("missing"), dir.path().to_path_buf()];
+        assert!(TokenCounter::load("local--tokenizer", &dirs).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_image_tokens() {
+        let counter = TokenCounter::heuristic();
+        let encode = |bytes: &[u8]| base64::prelude::BASE64_STANDARD.encode(bytes);
+
+        // A PNG signature and IHDR chunk for a 200x150 image
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend(200u32.to_be_bytes());
+        png.extend(150u32.to_be_bytes());
+        assert_eq!(counter.count_image_tokens(&encode(&png), "image/png"), 40);
+
+        // A JPEG with an APP0 segment before its start of frame, 3136x1000 pixels is scaled to
+        // 1568x500
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00];
+        jpeg.extend([0xFF, 0xC0, 0x00, 0x11, 0x08]);
+        jpeg.extend(1000u16.to_be_bytes());
+        jpeg.extend(3136u16.to_be_bytes());
+        assert_eq!(
+            counter.count_image_tokens(&encode(&jpeg), "image/jpeg"),
+            1046
+        );
+
+        assert_eq!(
+            counter.count_image_tokens("not base64!", "image/png"),
+            DEFAULT_IMAGE_TOKENS
+        );
+        assert_eq!(
+            counter.count_image_tokens(&encode(b"RIFF"), "image/webp"),
+            DEFAULT_IMAGE_TOKENS
+        );
 
-        TokenCounter::new("nonexistent-tokenizer");
+        let messages = vec![Message::user()
+            .with_text("What is this?")
+            .with_image(encode(&png), "image/png")];
+        let without_image =
+            counter.count_chat_tokens("", &[Message::user().with_text("What is this?")], &[]);
+        assert_eq!(
+            counter.count_chat_tokens("", &messages, &[]),
+            without_image + 40
+        );
     }
 
     // Optional test to confirm that fallback download works if not found in embedded:
@@ -337,8 +550,13 @@ mod tests {
     #[test]
     #[ignore]
     fn test_download_tokenizer_successfully_if_not_embedded() {
-        let non_embedded_key = "openai-community/gpt2";
-        let counter = TokenCounter::new(non_embedded_key);
+        let dir = tempfile::tempdir().unwrap();
+        TokenCounter::download_tokenizer("openai-community/gpt2", &dir.path().join("gpt2"))
+            .unwrap();
+        let tokenizer = TokenCounter::load("gpt2", &[dir.path().to_path_buf()]).unwrap();
+        let counter = TokenCounter {
+            tokenizer: Some(tokenizer),
+        };
 
         // If it downloads successfully, we can do a quick count to ensure it's valid
         let text = "print('hello world')";
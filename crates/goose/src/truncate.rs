@@ -1,10 +1,21 @@
-use crate::message::Message;
+use crate::config::Config;
+use crate::message::{Message, MessageContent};
 use anyhow::{anyhow, Result};
-use mcp_core::Role;
+use mcp_core::{Content, Role};
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
-use tracing::debug;
+use tracing::{debug, warn};
+
+/// Tool responses of at least this many tokens are replaced with a stub before any message is
+/// removed
+const MIN_ELIDED_TOKENS: usize = 1000;
+/// What a stub for an elided tool response is counted as
+const ELIDED_STUB_TOKENS: usize = 10;
 
 /// Trait representing a truncation strategy
+///
+/// Every strategy keeps the first user message and pinned messages, along with the other half of
+/// any tool call or response among them.
 pub trait TruncationStrategy {
     /// Determines the indices of messages to remove to fit within the context limit.
     ///
@@ -23,8 +34,52 @@ pub trait TruncationStrategy {
 
 /// Strategy to truncate messages by removing the oldest first
 pub struct OldestFirstTruncation;
-/// Strategy to truncate messages explicitly
-pub struct ExplicitTruncation;
+/// Strategy to truncate whole turns, a user's request and everything up to their next one,
+/// removing the oldest first
+pub struct TurnTruncation;
+/// Strategy to truncate messages in an order given by the caller, for example the messages the
+/// user chose to forget. Messages which aren't listed are never removed, so from config, where
+/// the order is empty, large tool responses are elided but nothing else is dropped
+#[derive(Debug, Clone, Default)]
+pub struct ExplicitTruncation {
+    pub order: Vec<usize>,
+}
+
+/// The truncation strategies which can be chosen with GOOSE_TRUNCATION_STRATEGY
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TruncationStrategyName {
+    #[default]
+    OldestFirst,
+    Turns,
+    /// Never drop messages, only elide tool responses, and report when that isn't enough
+    Explicit,
+}
+
+impl TruncationStrategyName {
+    /// The strategy set in GOOSE_TRUNCATION_STRATEGY, oldest first if not set
+    pub fn from_config() -> Self {
+        match Config::global().get::<TruncationStrategyName>("GOOSE_TRUNCATION_STRATEGY") {
+            Ok(name) => name,
+            Err(crate::config::ConfigError::NotFound(_)) => Self::default(),
+            Err(e) => {
+                warn!(
+                    "Invalid GOOSE_TRUNCATION_STRATEGY, removing oldest first: {}",
+                    e
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub fn strategy(self) -> Box<dyn TruncationStrategy + Send + Sync> {
+        match self {
+            Self::OldestFirst => Box::new(OldestFirstTruncation),
+            Self::Turns => Box::new(TurnTruncation),
+            Self::Explicit => Box::new(ExplicitTruncation::default()),
+        }
+    }
+}
 
 impl TruncationStrategy for OldestFirstTruncation {
     fn determine_indices_to_remove(
@@ -33,46 +88,187 @@ impl TruncationStrategy for OldestFirstTruncation {
         token_counts: &[usize],
         context_limit: usize,
     ) -> Result<HashSet<usize>> {
-        let mut indices_to_remove = HashSet::new();
-        let mut total_tokens: usize = token_counts.iter().sum();
-        let mut tool_ids_to_remove = HashSet::new();
+        let groups = (0..messages.len()).map(|i| vec![i]);
+        Ok(remove_in_order(
+            messages,
+            token_counts,
+            context_limit,
+            groups,
+        ))
+    }
+}
 
+impl TruncationStrategy for TurnTruncation {
+    fn determine_indices_to_remove(
+        &self,
+        messages: &[Message],
+        token_counts: &[usize],
+        context_limit: usize,
+    ) -> Result<HashSet<usize>> {
+        // Tool responses come from the user role too, so a turn starts at a text request
+        let mut turns: Vec<Vec<usize>> = Vec::new();
         for (i, message) in messages.iter().enumerate() {
-            if total_tokens <= context_limit {
-                break;
+            match turns.last_mut() {
+                Some(turn) if !is_user_request(message) => turn.push(i),
+                _ => turns.push(vec![i]),
             }
+        }
+        Ok(remove_in_order(
+            messages,
+            token_counts,
+            context_limit,
+            turns,
+        ))
+    }
+}
 
-            // Remove the message
-            indices_to_remove.insert(i);
+impl TruncationStrategy for ExplicitTruncation {
+    fn determine_indices_to_remove(
+        &self,
+        messages: &[Message],
+        token_counts: &[usize],
+        context_limit: usize,
+    ) -> Result<HashSet<usize>> {
+        if let Some(index) = self.order.iter().find(|&&i| i >= messages.len()) {
+            return Err(anyhow!("No message at index {} to remove", index));
+        }
+        let groups = self.order.iter().map(|&i| vec![i]);
+        Ok(remove_in_order(
+            messages,
+            token_counts,
+            context_limit,
+            groups,
+        ))
+    }
+}
+
+fn is_user_request(message: &Message) -> bool {
+    message.role == Role::User && message.has_only_text_content()
+}
+
+/// The indices of the other messages with the same tool calls as the message at `index`
+fn tool_pairs(messages: &[Message], index: usize) -> Vec<usize> {
+    let tool_ids = messages[index].get_tool_ids();
+    if tool_ids.is_empty() {
+        return Vec::new();
+    }
+    messages
+        .iter()
+        .enumerate()
+        .filter(|(i, message)| {
+            *i != index
+                && message
+                    .get_tool_ids()
+                    .iter()
+                    .any(|id| tool_ids.contains(id))
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// The messages no strategy removes: the first user request, pinned messages and their tool pairs
+fn protected_indices(messages: &[Message]) -> HashSet<usize> {
+    let mut protected: HashSet<usize> = messages
+        .iter()
+        .enumerate()
+        .filter(|(_, message)| message.pinned)
+        .map(|(i, _)| i)
+        .collect();
+    protected.extend(messages.iter().position(is_user_request));
+    let pairs: Vec<usize> = protected
+        .iter()
+        .flat_map(|&i| tool_pairs(messages, i))
+        .collect();
+    protected.extend(pairs);
+    protected
+}
+
+/// Remove groups of messages in the given order until the rest fit within the context limit,
+/// skipping protected messages and taking tool calls and responses out together
+fn remove_in_order(
+    messages: &[Message],
+    token_counts: &[usize],
+    context_limit: usize,
+    groups: impl IntoIterator<Item = Vec<usize>>,
+) -> HashSet<usize> {
+    let protected = protected_indices(messages);
+    let mut indices_to_remove = HashSet::new();
+    let mut total_tokens: usize = token_counts.iter().sum();
+
+    for group in groups {
+        if total_tokens <= context_limit {
+            break;
+        }
+        let with_pairs: Vec<usize> = group
+            .iter()
+            .flat_map(|&i| std::iter::once(i).chain(tool_pairs(messages, i)))
+            .collect();
+        for i in with_pairs {
+            if protected.contains(&i) || !indices_to_remove.insert(i) {
+                continue;
+            }
             total_tokens -= token_counts[i];
             debug!(
-                "OldestFirst: Removing message at index {}. Tokens removed: {}",
+                "Removing message at index {}. Tokens removed: {}",
                 i, token_counts[i]
             );
+        }
+    }
 
-            // If it's a ToolRequest or ToolResponse, mark its pair for removal
-            if message.is_tool_call() || message.is_tool_response() {
-                message.get_tool_ids().iter().for_each(|id| {
-                    tool_ids_to_remove.insert((i, id.to_string()));
-                });
-            }
+    indices_to_remove
+}
+
+/// Replace large tool responses with a stub, oldest first, until the messages fit within the
+/// context limit. Returns the total tokens afterwards
+fn elide_tool_responses(
+    messages: &mut [Message],
+    token_counts: &mut [usize],
+    context_limit: usize,
+) -> usize {
+    let mut total_tokens: usize = token_counts.iter().sum();
+    for (message, token_count) in messages.iter_mut().zip(token_counts.iter_mut()) {
+        if total_tokens <= context_limit {
+            break;
+        }
+        if message.pinned || !message.is_tool_response() || *token_count < MIN_ELIDED_TOKENS {
+            continue;
         }
 
-        // Now, find and remove paired ToolResponses or ToolRequests
-        for (i, message) in messages.iter().enumerate() {
-            let message_tool_ids = message.get_tool_ids();
-            // Find the other part of the pair - same tool_id but different message index
-            for (message_idx, tool_id) in &tool_ids_to_remove {
-                if message_idx != &i && message_tool_ids.contains(tool_id.as_str()) {
-                    indices_to_remove.insert(i);
-                    // No need to check other tool_ids for this message since it's already marked
-                    break;
-                }
+        // Split the message's tokens between its responses by the length of their text
+        let lengths: Vec<usize> = message
+            .content
+            .iter()
+            .map(|content| content.as_tool_response_text().map_or(0, |text| text.len()))
+            .collect();
+        let total_length = lengths.iter().sum::<usize>().max(1);
+        let mut elided = 0;
+        for (content, length) in message.content.iter_mut().zip(lengths) {
+            let MessageContent::ToolResponse(response) = content else {
+                continue;
+            };
+            if response.tool_result.is_err() {
+                continue;
             }
+            let tokens = *token_count * length / total_length;
+            response.tool_result = Ok(vec![Content::text(format!(
+                "output elided, {} tokens",
+                tokens
+            ))]);
+            elided += 1;
+        }
+        if elided == 0 {
+            continue;
         }
 
-        Ok(indices_to_remove)
+        let new_count = (ELIDED_STUB_TOKENS * elided).min(*token_count);
+        debug!(
+            "Elided tool responses of {} tokens to {} tokens",
+            token_count, new_count
+        );
+        total_tokens -= *token_count - new_count;
+        *token_count = new_count;
     }
+    total_tokens
 }
 
 /// Truncates the messages to fit within the model's context window.
@@ -81,7 +277,7 @@ impl TruncationStrategy for OldestFirstTruncation {
 /// - messages: The vector of messages in the conversation.
 /// - token_counts: A parallel vector containing the token count for each message.
 /// - context_limit: The maximum allowed context length in tokens.
-/// - strategy: The truncation strategy to use. Only option is OldestFirstTruncation.
+/// - strategy: The truncation strategy to use, after large tool responses have been elided.
 pub fn truncate_messages(
     messages: &mut Vec<Message>,
     token_counts: &mut Vec<usize>,
@@ -117,11 +313,17 @@ pub fn truncate_messages(
         return Ok(()); // No truncation needed
     }
 
-    // Step 2: Determine indices to remove based on strategy
+    // Step 2: Elide large tool responses, which is often enough without removing any turns
+    total_tokens = elide_tool_responses(messages, token_counts, context_limit);
+    if total_tokens <= context_limit {
+        return Ok(());
+    }
+
+    // Step 3: Determine indices to remove based on strategy
     let indices_to_remove =
         strategy.determine_indices_to_remove(messages, token_counts, context_limit)?;
 
-    // Step 3: Remove the marked messages
+    // Step 4: Remove the marked messages
     // Vectorize the set and sort in reverse order to avoid shifting indices when removing
     let mut indices_to_remove = indices_to_remove.iter().cloned().collect::<Vec<usize>>();
     indices_to_remove.sort_unstable_by(|a, b| b.cmp(a));
@@ -134,7 +336,7 @@ pub fn truncate_messages(
         }
     }
 
-    // Step 4: Ensure the last message is a user message with TextContent only
+    // Step 5: Ensure the last message is a user message with TextContent only
     while let Some(last_msg) = messages.last() {
         if last_msg.role != Role::User || !last_msg.has_only_text_content() {
             let _ = messages.pop().ok_or(anyhow!("Failed to pop message"))?;
@@ -147,7 +349,7 @@ pub fn truncate_messages(
         }
     }
 
-    // Step 5: Check first msg is a User message with TextContent only
+    // Step 6: Check first msg is a User message with TextContent only
     while let Some(first_msg) = messages.first() {
         if first_msg.role != Role::User || !first_msg.has_only_text_content() {
             let _ = messages.remove(0);
@@ -213,6 +415,40 @@ mod tests {
         (Message::user().with_tool_response(id, Ok(result)), tokens)
     }
 
+    // Helper function to pin a message created by one of the helpers above
+    fn pinned((message, tokens): (Message, usize)) -> (Message, usize) {
+        (message.with_pinned(true), tokens)
+    }
+
+    // The messages and token counts left by a strategy
+    type Truncated = (TruncationStrategyName, Vec<Message>, Vec<usize>);
+
+    // Helper function to run truncation with each configurable strategy which removes messages on
+    // its own
+    fn truncate_with_each_strategy(
+        messages: &[Message],
+        token_counts: &[usize],
+        context_limit: usize,
+    ) -> Result<Vec<Truncated>> {
+        [
+            TruncationStrategyName::OldestFirst,
+            TruncationStrategyName::Turns,
+        ]
+        .into_iter()
+        .map(|name| {
+            let mut messages = messages.to_vec();
+            let mut token_counts = token_counts.to_vec();
+            truncate_messages(
+                &mut messages,
+                &mut token_counts,
+                context_limit,
+                name.strategy().as_ref(),
+            )?;
+            Ok((name, messages, token_counts))
+        })
+        .collect()
+    }
+
     // Helper function to create messages with alternating user and assistant
     // text messages of a fixed token count
     fn create_messages_with_counts(
@@ -465,4 +701,142 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_keeps_first_user_message_and_pinned_messages() -> Result<()> {
+        let (messages, token_counts): (Vec<Message>, Vec<usize>) = vec![
+            user_text(0, 10),
+            assistant_text(1, 10),
+            user_text(2, 10),
+            pinned(assistant_text(3, 10)),
+            user_text(4, 10),
+            assistant_text(5, 10),
+            user_text(6, 10),
+        ]
+        .into_iter()
+        .unzip();
+
+        for (name, truncated, counts) in truncate_with_each_strategy(&messages, &token_counts, 40)?
+        {
+            assert!(counts.iter().sum::<usize>() <= 40, "{:?}", name);
+            assert_eq!(truncated.first(), messages.first(), "{:?}", name);
+            assert!(truncated.contains(&messages[3]), "{:?}", name);
+            assert_eq!(truncated.last(), messages.last(), "{:?}", name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_pinned_tool_request_keeps_its_response() -> Result<()> {
+        let tool_call = ToolCall::new("read_file", json!({"path": "notes.md"}));
+        let (messages, token_counts): (Vec<Message>, Vec<usize>) = vec![
+            user_text(0, 10),
+            pinned(assistant_tool_request("notes", tool_call, 10)),
+            user_tool_response("notes", vec![Content::text("Notes")], 10),
+            assistant_text(1, 10),
+            user_text(2, 10),
+            assistant_text(3, 10),
+            user_text(4, 10),
+        ]
+        .into_iter()
+        .unzip();
+
+        for (name, truncated, _) in truncate_with_each_strategy(&messages, &token_counts, 40)? {
+            assert!(truncated.contains(&messages[1]), "{:?}", name);
+            assert!(truncated.contains(&messages[2]), "{:?}", name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_large_tool_responses_are_elided_first() -> Result<()> {
+        let tool_call = ToolCall::new("shell", json!({"command": "cat big.log"}));
+        let (messages, token_counts): (Vec<Message>, Vec<usize>) = vec![
+            user_text(0, 10),
+            assistant_tool_request("log", tool_call, 10),
+            user_tool_response("log", vec![Content::text("log line\n".repeat(1000))], 2000),
+            assistant_text(1, 10),
+            user_text(2, 10),
+        ]
+        .into_iter()
+        .unzip();
+
+        for (name, truncated, counts) in truncate_with_each_strategy(&messages, &token_counts, 100)?
+        {
+            // Every message is kept, with the output replaced by a stub
+            assert_eq!(truncated.len(), 5, "{:?}", name);
+            assert_eq!(counts[2], ELIDED_STUB_TOKENS);
+            let response = truncated[2].content[0].as_tool_response().unwrap();
+            assert_eq!(
+                response.tool_result.as_ref().unwrap(),
+                &vec![Content::text("output elided, 2000 tokens")]
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_turn_truncation_removes_whole_turns() -> Result<()> {
+        let (messages, token_counts) = create_messages_with_counts(3, 10, true);
+
+        let mut oldest_first = (messages.clone(), token_counts.clone());
+        truncate_messages(
+            &mut oldest_first.0,
+            &mut oldest_first.1,
+            35,
+            &OldestFirstTruncation,
+        )?;
+        assert_eq!(
+            oldest_first.0,
+            vec![
+                messages[0].clone(),
+                messages[3].clone(),
+                messages[4].clone()
+            ]
+        );
+
+        let mut turns = (messages.clone(), token_counts.clone());
+        truncate_messages(&mut turns.0, &mut turns.1, 35, &TurnTruncation)?;
+        assert_eq!(turns.0, vec![messages[0].clone(), messages[4].clone()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_explicit_truncation() -> Result<()> {
+        let (messages, token_counts) = create_messages_with_counts(3, 10, true);
+
+        let mut truncated = (messages.clone(), token_counts.clone());
+        let strategy = ExplicitTruncation {
+            order: vec![3, 1, 2],
+        };
+        truncate_messages(&mut truncated.0, &mut truncated.1, 40, &strategy)?;
+        assert_eq!(truncated.0.len(), 4);
+        assert!(!truncated.0.contains(&messages[3]));
+
+        // Messages which aren't listed, or are protected, stay even if they don't fit
+        let strategy = ExplicitTruncation { order: vec![0] };
+        let mut truncated = (messages.clone(), token_counts.clone());
+        assert!(truncate_messages(&mut truncated.0, &mut truncated.1, 40, &strategy).is_err());
+
+        // Configured, nothing is listed, so the conversation is never cut short behind the user's back
+        let strategy = TruncationStrategyName::Explicit.strategy();
+        let mut truncated = (messages.clone(), token_counts.clone());
+        assert!(
+            truncate_messages(&mut truncated.0, &mut truncated.1, 40, strategy.as_ref()).is_err()
+        );
+
+        let strategy = ExplicitTruncation { order: vec![7] };
+        let mut truncated = (messages, token_counts);
+        assert!(truncate_messages(&mut truncated.0, &mut truncated.1, 40, &strategy).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_strategy_names() {
+        let name: TruncationStrategyName = serde_json::from_value(json!("turns")).unwrap();
+        assert_eq!(name, TruncationStrategyName::Turns);
+        let name: TruncationStrategyName = serde_json::from_value(json!("explicit")).unwrap();
+        assert_eq!(name, TruncationStrategyName::Explicit);
+        assert!(serde_json::from_value::<TruncationStrategyName>(json!("newest_first")).is_err());
+    }
 }